@@ -0,0 +1,692 @@
+use std::{
+    collections::HashSet,
+    fs,
+    io::{self, ErrorKind, Read, Write},
+    net::Shutdown,
+    os::unix::net::{UnixListener, UnixStream},
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crossbeam_channel::{Sender, TrySendError};
+use serde_json::{json, Value};
+
+use crate::{
+    codec,
+    connection::{Connection, Socket},
+    event_handler::{Context as EventContext, EventCallbackHandle},
+    models::{
+        message::payload_length, payload::Payload, Command, Event, EventData, Message, OpCode,
+    },
+    sync::{AtomicBool, Mutex, Ordering},
+    targets::CONNECTION,
+    Client, DiscordError, Result,
+};
+
+/// Directory the broker's socket is created in, within the usual IPC directory
+const BROKER_DIRECTORY: &str = "discord-presence-broker";
+
+/// Name of the broker's socket, which matches Discord's so clients find it like any other
+const SOCKET_NAME: &str = "discord-ipc-0";
+
+/// How often the broker checks for new clients
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How long a connecting client waits for the broker to be ready, before being disconnected to retry later
+const READY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long a connecting client has to send its handshake, before being disconnected
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How many messages may be waiting to be written to a client, before it is disconnected for not reading them
+const PEER_QUEUE_CAPACITY: usize = 256;
+
+/// Error code Discord uses for errors without a more specific code
+const UNKNOWN_ERROR: u64 = 1000;
+
+/// Close code Discord uses when a client identifies with the wrong application
+const INVALID_CLIENT_ID: u64 = 4000;
+
+/// The directory [`Broker::start`] listens in, and [`Client::use_broker`] connects to
+#[must_use]
+pub fn default_dir() -> PathBuf {
+    Socket::ipc_path().join(BROKER_DIRECTORY)
+}
+
+/// A client connected to the broker
+struct Peer {
+    id: u64,
+    /// Messages waiting to be written by the client's writer thread, so a client that stops reading holds up no one else
+    outgoing: Sender<Vec<u8>>,
+    /// Used to disconnect the client, which unblocks the thread reading from it
+    stream: UnixStream,
+    /// Events the client subscribed to, along with the arguments it subscribed with
+    subscriptions: Mutex<Vec<(Event, Value)>>,
+    /// Process ID of the last activity the client set, so it can be cleared once the client leaves
+    pid: Mutex<Option<u64>>,
+}
+
+impl Peer {
+    /// Starts writing to a client on a thread of its own
+    fn new(id: u64, stream: &UnixStream) -> Result<Arc<Self>> {
+        let (outgoing, queued) = crossbeam_channel::bounded::<Vec<u8>>(PEER_QUEUE_CAPACITY);
+        let mut writer = stream.try_clone()?;
+
+        thread::Builder::new()
+            .name("discord-presence-broker-writer".to_owned())
+            .spawn(move || {
+                // Ends once the client is gone, and with it the last sender
+                for bytes in queued {
+                    if let Err(why) = writer.write_all(&bytes) {
+                        debug!(target: CONNECTION, "Failed to write to broker client {id}: {why}");
+                        writer.shutdown(Shutdown::Both).ok();
+                        break;
+                    }
+                }
+            })?;
+
+        Ok(Arc::new(Self {
+            id,
+            outgoing,
+            stream: stream.try_clone()?,
+            subscriptions: Mutex::new(Vec::new()),
+            pid: Mutex::new(None),
+        }))
+    }
+
+    /// Queues a message to be written, disconnecting the client if too many are already waiting
+    fn send(&self, message: &Message) -> Result<()> {
+        match self.outgoing.try_send(message.encode()?) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => {
+                warn!(target: CONNECTION, "Disconnecting broker client {}, as it stopped reading", self.id);
+                self.disconnect();
+                Err(DiscordError::ConnectionClosed)
+            }
+            Err(TrySendError::Disconnected(_)) => Err(DiscordError::ConnectionClosed),
+        }
+    }
+
+    fn disconnect(&self) {
+        self.stream.shutdown(Shutdown::Both).ok();
+    }
+
+    /// Whether the client subscribed to an event with arguments matching its data
+    fn is_subscribed(&self, kind: Event, data: &Value) -> bool {
+        self.subscriptions
+            .lock()
+            .iter()
+            .any(|(event, args)| *event == kind && args_match(args, data))
+    }
+}
+
+/// Whether an event's data matches the arguments of a subscription, such as the channel of `MESSAGE_CREATE`
+///
+/// Arguments the data does not carry cannot be told apart, so they are not compared.
+fn args_match(args: &Value, data: &Value) -> bool {
+    let Value::Object(args) = args else {
+        return true;
+    };
+
+    args.iter()
+        .all(|(key, value)| data.get(key).map_or(true, |actual| actual == value))
+}
+
+/// State shared between the broker and the threads serving its clients
+struct Shared {
+    client_id: u64,
+    peers: Mutex<Vec<Arc<Peer>>>,
+    /// Subscriptions made on Discord, by event and arguments
+    subscribed: Mutex<HashSet<(Event, String)>>,
+    running: AtomicBool,
+}
+
+/// Shares one connection to Discord between several processes on the machine
+///
+/// Discord limits how many RPC connections it accepts, so applications made of several processes
+/// can have one of them run the broker, and the others connect to it with [`Client::use_broker`].
+/// The broker speaks the same protocol as Discord, so those clients work as if connected to Discord directly:
+///
+/// - Commands are relayed to Discord, and their responses back to the client that sent them
+/// - Events are relayed to every client subscribed to them with matching arguments, such as the same channel
+/// - The activity a client set is cleared when it disconnects
+///
+/// Every client must use the broker's application ID. Events are relayed as the client understood them,
+/// so fields it does not model are dropped.
+///
+/// The broker listens on a Unix socket, and stops listening when dropped.
+pub struct Broker {
+    client: Option<Client>,
+    path: PathBuf,
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+    _handlers: [EventCallbackHandle; 2],
+}
+
+impl Broker {
+    /// Connects to Discord with the given application ID, and listens for clients in [`default_dir`]
+    ///
+    /// # Errors
+    /// - Another broker is already listening
+    /// - The socket could not be created
+    pub fn start(client_id: u64) -> Result<Self> {
+        Self::start_in(client_id, default_dir())
+    }
+
+    /// Connects to Discord with the given application ID, and listens for clients in `ipc_dir`
+    ///
+    /// Clients connect to it with [`Client::set_ipc_dir`].
+    ///
+    /// # Errors
+    /// - Another broker is already listening
+    /// - The socket could not be created
+    pub fn start_in<P: Into<PathBuf>>(client_id: u64, ipc_dir: P) -> Result<Self> {
+        let ipc_dir = ipc_dir.into();
+        fs::create_dir_all(&ipc_dir)?;
+
+        let path = ipc_dir.join(SOCKET_NAME);
+        let listener = bind(&path)?;
+        listener.set_nonblocking(true)?;
+
+        let shared = Arc::new(Shared {
+            client_id,
+            peers: Mutex::new(Vec::new()),
+            subscribed: Mutex::new(HashSet::new()),
+            running: AtomicBool::new(true),
+        });
+
+        let mut client = Client::new(client_id);
+        let events = client.on_any_event({
+            let shared = shared.clone();
+            move |ctx| broadcast(&shared, &ctx)
+        });
+        let ready = client.on_ready({
+            let shared = shared.clone();
+            let client = client.clone();
+            move |_| resubscribe(&mut client.clone(), &shared)
+        });
+        client.start();
+
+        let thread = thread::Builder::new()
+            .name("discord-presence-broker".to_owned())
+            .spawn({
+                let shared = shared.clone();
+                let client = client.clone();
+                move || accept(&listener, &client, &shared)
+            })?;
+
+        Ok(Self {
+            client: Some(client),
+            path,
+            shared,
+            thread: Some(thread),
+            _handlers: [events, ready],
+        })
+    }
+
+    /// Path of the socket the broker listens on
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The client connected to Discord, which the broker's own process can use too
+    #[must_use]
+    pub fn client(&self) -> Option<&Client> {
+        self.client.as_ref()
+    }
+
+    /// The number of clients connected to the broker
+    #[must_use]
+    pub fn peer_count(&self) -> usize {
+        self.shared.peers.lock().len()
+    }
+}
+
+impl Drop for Broker {
+    fn drop(&mut self) {
+        self.shared.running.store(false, Ordering::Relaxed);
+
+        // Unblocks the threads reading from each client
+        for peer in self.shared.peers.lock().iter() {
+            peer.disconnect();
+        }
+
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+
+        fs::remove_file(&self.path).ok();
+
+        if let Some(client) = self.client.take() {
+            client.shutdown().ok();
+        }
+    }
+}
+
+/// Binds the socket, replacing it if it was left behind by a broker that exited
+fn bind(path: &Path) -> Result<UnixListener> {
+    match UnixListener::bind(path) {
+        Err(why) if why.kind() == ErrorKind::AddrInUse => {
+            if UnixStream::connect(path).is_ok() {
+                return Err(why.into());
+            }
+
+            debug!(target: CONNECTION, "Replacing stale broker socket {}", path.display());
+            fs::remove_file(path)?;
+            Ok(UnixListener::bind(path)?)
+        }
+        result => Ok(result?),
+    }
+}
+
+fn accept(listener: &UnixListener, client: &Client, shared: &Arc<Shared>) {
+    let mut next_id = 0;
+
+    while shared.running.load(Ordering::Relaxed) {
+        let stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(why) if why.kind() == ErrorKind::WouldBlock => {
+                thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+            Err(why) => {
+                error!(target: CONNECTION, "Broker stopped accepting clients: {why}");
+                break;
+            }
+        };
+
+        next_id += 1;
+        let id = next_id;
+        let client = client.clone();
+        let shared = shared.clone();
+
+        let spawned = thread::Builder::new()
+            .name("discord-presence-broker-peer".to_owned())
+            .spawn(move || {
+                if let Err(why) = serve(stream, client, &shared, id) {
+                    debug!(target: CONNECTION, "Broker client {id} disconnected: {why}");
+                }
+            });
+
+        if let Err(why) = spawned {
+            error!(target: CONNECTION, "Failed to serve broker client: {why}");
+        }
+    }
+}
+
+fn serve(mut stream: UnixStream, mut client: Client, shared: &Shared, id: u64) -> Result<()> {
+    stream.set_nonblocking(false)?;
+
+    // A client that connects without saying anything would otherwise hold its thread forever
+    stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+    let handshake = read_message(&mut stream)?;
+    stream.set_read_timeout(None)?;
+
+    let args: Value = serde_json::from_str(&handshake.payload)?;
+    if handshake.opcode != OpCode::Handshake
+        || args["client_id"].as_str().and_then(|id| id.parse().ok()) != Some(shared.client_id)
+    {
+        let close = Message::new(
+            OpCode::Close,
+            json!({ "code": INVALID_CLIENT_ID, "message": "Invalid Client ID" }),
+        )?;
+        stream.write_all(&close.encode()?)?;
+        return Ok(());
+    }
+
+    let ready = wait_for_ready(&client)?;
+    let ready = Payload {
+        cmd: Command::Dispatch,
+        args: None,
+        data: Some(serde_json::to_value(ready)?),
+        evt: Some(Event::Ready),
+        nonce: None,
+    };
+    stream.write_all(&Message::new(OpCode::Frame, ready)?.encode()?)?;
+
+    let peer = Peer::new(id, &stream)?;
+    shared.peers.lock().push(peer.clone());
+    trace!(target: CONNECTION, "Broker client {id} connected");
+
+    let result = relay(&mut stream, &mut client, shared, &peer);
+
+    shared.peers.lock().retain(|other| other.id != id);
+    let pid = *peer.pid.lock();
+    if let Some(pid) = pid {
+        let cleared = client.execute::<_, Value>(Command::SetActivity, json!({ "pid": pid }), None);
+        if let Err(why) = cleared {
+            warn!(target: CONNECTION, "Failed to clear the activity of broker client {id}: {why}");
+        }
+    }
+
+    result
+}
+
+fn wait_for_ready(client: &Client) -> Result<crate::models::ReadyEvent> {
+    let deadline = Instant::now() + READY_TIMEOUT;
+
+    loop {
+        if let Some(ready) = client.ready_info() {
+            return Ok(ready);
+        }
+
+        if Instant::now() >= deadline {
+            return Err(io::Error::new(ErrorKind::TimedOut, "Discord is not connected").into());
+        }
+
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+fn relay(stream: &mut UnixStream, client: &mut Client, shared: &Shared, peer: &Peer) -> Result<()> {
+    loop {
+        let message = read_message(stream)?;
+
+        match message.opcode {
+            OpCode::Frame => {
+                let payload: Payload<Value> = codec::decode(&message.payload)?;
+                let response = execute(client, shared, peer, payload);
+                peer.send(&Message::new(OpCode::Frame, response)?)?;
+            }
+            OpCode::Ping => peer.send(&Message {
+                opcode: OpCode::Pong,
+                payload: message.payload,
+            })?,
+            OpCode::Close => return Ok(()),
+            OpCode::Handshake | OpCode::Pong => {}
+        }
+    }
+}
+
+/// Relays a command to Discord, and returns the response addressed to the peer
+///
+/// Subscriptions are shared between peers, so they are only made on Discord once per connection, and never undone.
+fn execute(
+    client: &mut Client,
+    shared: &Shared,
+    peer: &Peer,
+    payload: Payload<Value>,
+) -> Payload<Value> {
+    let Payload {
+        cmd,
+        args,
+        evt,
+        nonce,
+        ..
+    } = payload;
+    let args = args.unwrap_or(Value::Null);
+
+    let respond = |data| Payload {
+        cmd,
+        args: None,
+        data: Some(data),
+        evt,
+        nonce: nonce.clone(),
+    };
+
+    match (cmd, evt) {
+        (Command::Subscribe, Some(evt)) => {
+            let mut subscriptions = peer.subscriptions.lock();
+            if !subscriptions.contains(&(evt, args.clone())) {
+                subscriptions.push((evt, args.clone()));
+            }
+            drop(subscriptions);
+
+            let subscription = (evt, args.to_string());
+            if shared.subscribed.lock().contains(&subscription) {
+                return respond(json!({ "evt": evt }));
+            }
+        }
+        (Command::Unsubscribe, Some(evt)) => {
+            peer.subscriptions
+                .lock()
+                .retain(|(event, subscribed)| *event != evt || *subscribed != args);
+            return respond(json!({ "evt": evt }));
+        }
+        (Command::SetActivity, _) => *peer.pid.lock() = args["pid"].as_u64(),
+        _ => {}
+    }
+
+    match client.execute::<_, Value>(cmd, &args, evt) {
+        Ok(mut response) => {
+            if let (Command::Subscribe, Some(evt)) = (cmd, evt) {
+                shared.subscribed.lock().insert((evt, args.to_string()));
+            }
+
+            response.nonce = nonce;
+            response
+        }
+        Err(why) => {
+            // Discord's own error is passed on as it is, so the peer sees the same error it would without the broker
+            let (code, message) = match why {
+                DiscordError::CommandFailed {
+                    code: Some(code),
+                    message,
+                } => (u64::from(code), message),
+                why => (UNKNOWN_ERROR, why.to_string()),
+            };
+
+            Payload {
+                evt: Some(Event::Error),
+                ..respond(json!({ "code": code, "message": message }))
+            }
+        }
+    }
+}
+
+/// Subscribes to everything the peers are subscribed to again, as subscriptions do not carry over between connections
+fn resubscribe(client: &mut Client, shared: &Shared) {
+    shared.subscribed.lock().clear();
+
+    let mut wanted = Vec::new();
+    for peer in shared.peers.lock().iter() {
+        for subscription in peer.subscriptions.lock().iter() {
+            if !wanted.contains(subscription) {
+                wanted.push(subscription.clone());
+            }
+        }
+    }
+
+    for (evt, args) in wanted {
+        match client.execute::<_, Value>(Command::Subscribe, &args, Some(evt)) {
+            Ok(_) => {
+                shared.subscribed.lock().insert((evt, args.to_string()));
+            }
+            Err(why) => {
+                warn!(target: CONNECTION, "Failed to subscribe to {evt:?} again for broker clients: {why}");
+            }
+        }
+    }
+}
+
+/// Relays an event to every peer subscribed to it
+///
+/// Messages are only queued for each peer, so a peer that stops reading is disconnected rather than holding up the rest.
+fn broadcast(shared: &Shared, ctx: &EventContext) {
+    let data = event_value(&ctx.event);
+    let payload = Payload {
+        cmd: Command::Dispatch,
+        args: None,
+        data: Some(data.clone()),
+        evt: Some(ctx.kind),
+        nonce: None,
+    };
+
+    let message = match Message::new(OpCode::Frame, payload) {
+        Ok(message) => message,
+        Err(why) => {
+            error!(target: CONNECTION, "Failed to relay {:?} event: {why}", ctx.kind);
+            return;
+        }
+    };
+
+    for peer in shared.peers.lock().iter() {
+        if !peer.is_subscribed(ctx.kind, &data) {
+            continue;
+        }
+
+        if let Err(why) = peer.send(&message) {
+            debug!(target: CONNECTION, "Failed to relay event to broker client {}: {why}", peer.id);
+        }
+    }
+}
+
+/// The data of an event, as Discord sent it
+fn event_value(event: &EventData) -> Value {
+    // Event data serializes tagged with its variant, around the data itself
+    match serde_json::to_value(event) {
+        Ok(Value::Object(tagged)) if tagged.len() == 1 => tagged
+            .into_iter()
+            .next()
+            .map_or(Value::Null, |(_, data)| data),
+        _ => Value::Null,
+    }
+}
+
+fn read_message(stream: &mut UnixStream) -> Result<Message> {
+    let mut header = [0; 8];
+    match stream.read_exact(&mut header) {
+        Err(why) if why.kind() == ErrorKind::UnexpectedEof => {
+            return Err(DiscordError::ConnectionClosed)
+        }
+        result => result?,
+    }
+
+    let len = payload_length(header)?;
+    let mut bytes = header.to_vec();
+    bytes.resize(header.len() + len, 0);
+    stream.read_exact(&mut bytes[header.len()..])?;
+
+    Message::decode(&bytes)
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use crate::models::ActivityJoinEvent;
+
+    fn broker_dir(name: &str) -> PathBuf {
+        crate::connection::test_socket_path(&format!("broker-{name}"))
+    }
+
+    #[test]
+    fn rejects_other_applications() {
+        let broker = Broker::start_in(1, broker_dir("reject")).unwrap();
+
+        let mut stream = UnixStream::connect(broker.path()).unwrap();
+        let handshake = Message::new(OpCode::Handshake, json!({ "v": 1, "client_id": "2" }));
+        stream
+            .write_all(&handshake.unwrap().encode().unwrap())
+            .unwrap();
+
+        let response = read_message(&mut stream).unwrap();
+        assert_eq!(response.opcode, OpCode::Close);
+        assert_eq!(broker.peer_count(), 0);
+    }
+
+    #[test]
+    fn only_one_broker_listens() {
+        let dir = broker_dir("single");
+        let broker = Broker::start_in(1, &dir).unwrap();
+        assert!(Broker::start_in(1, &dir).is_err());
+
+        // A socket left behind by a broker that exited is replaced
+        drop(broker);
+        drop(UnixListener::bind(dir.join(SOCKET_NAME)).unwrap());
+        assert!(Broker::start_in(1, &dir).is_ok());
+    }
+
+    fn shared(peers: Vec<Arc<Peer>>) -> Shared {
+        Shared {
+            client_id: 1,
+            peers: Mutex::new(peers),
+            subscribed: Mutex::new(HashSet::new()),
+            running: AtomicBool::new(true),
+        }
+    }
+
+    fn join_event(secret: &str) -> EventContext {
+        EventContext::new(
+            Event::ActivityJoin,
+            EventData::ActivityJoin(ActivityJoinEvent::new().secret(secret)),
+            0,
+            None,
+        )
+    }
+
+    #[test]
+    fn subscriptions_are_forgotten_on_reconnecting() {
+        let (stream, _remote) = UnixStream::pair().unwrap();
+        let peer = Peer::new(1, &stream).unwrap();
+        peer.subscriptions
+            .lock()
+            .push((Event::ActivityJoin, Value::Null));
+        let shared = shared(vec![peer]);
+        shared
+            .subscribed
+            .lock()
+            .insert((Event::ActivityJoin, Value::Null.to_string()));
+
+        // Subscribing again fails while not connected, so the next peer to subscribe is forwarded to Discord
+        resubscribe(&mut Client::new(1), &shared);
+        assert!(shared.subscribed.lock().is_empty());
+    }
+
+    #[test]
+    fn peers_that_stop_reading_hold_up_no_one() {
+        let (stuck, _stuck_remote) = UnixStream::pair().unwrap();
+        let (reading, mut reading_remote) = UnixStream::pair().unwrap();
+        let stuck = Peer::new(1, &stuck).unwrap();
+        let reading = Peer::new(2, &reading).unwrap();
+        for peer in [&stuck, &reading] {
+            peer.subscriptions
+                .lock()
+                .push((Event::ActivityJoin, Value::Null));
+        }
+        let shared = shared(vec![stuck, reading]);
+
+        // Enough to fill the socket buffer and the queue of the peer that is not reading
+        for _ in 0..10_000 {
+            broadcast(&shared, &join_event("secret"));
+            let message = read_message(&mut reading_remote).unwrap();
+            assert_eq!(message.opcode, OpCode::Frame);
+        }
+    }
+
+    #[test]
+    fn subscriptions_match_their_args() {
+        let data = json!({ "channel_id": "1", "message": {} });
+
+        assert!(args_match(&Value::Null, &data));
+        assert!(args_match(&json!({ "channel_id": "1" }), &data));
+        assert!(!args_match(&json!({ "channel_id": "2" }), &data));
+        // Arguments the event does not carry are not compared
+        assert!(args_match(&json!({ "guild_id": "3" }), &data));
+    }
+
+    #[test]
+    fn handshakes_time_out() {
+        let broker = Broker::start_in(1, broker_dir("handshake")).unwrap();
+        let mut stream = UnixStream::connect(broker.path()).unwrap();
+        stream
+            .set_read_timeout(Some(HANDSHAKE_TIMEOUT * 2))
+            .unwrap();
+
+        // Disconnected without a handshake, rather than waiting for one forever
+        let mut byte = [0];
+        assert_eq!(stream.read(&mut byte).unwrap(), 0);
+    }
+
+    #[test]
+    fn event_data_is_untagged() {
+        let event = EventData::ActivityJoin(ActivityJoinEvent {
+            secret: Some("secret".to_owned()),
+        });
+
+        assert_eq!(event_value(&event), json!({ "secret": "secret" }));
+        assert_eq!(event_value(&EventData::Unknown(json!(1))), json!(1));
+    }
+}
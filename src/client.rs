@@ -1,24 +1,52 @@
 use std::{
+    collections::hash_map::DefaultHasher,
+    future::Future,
+    hash::{Hash, Hasher},
+    num::NonZeroU64,
+    path::PathBuf,
+    pin::Pin,
     sync::{atomic::Ordering, Arc},
-    thread::{JoinHandle, Thread},
-    time::Duration,
+    task::{Context as TaskContext, Poll, Waker},
+    thread::{self, JoinHandle, Thread},
+    time::{Duration, Instant, SystemTime},
 };
 
+#[cfg(feature = "voice")]
+use crate::models::VoiceSettings;
+#[cfg(feature = "commerce")]
+use crate::models::{Entitlement, Sku};
+#[cfg(feature = "keyring")]
+use crate::token_store::TokenStore;
+#[cfg(feature = "wsl")]
+use crate::wsl::Bridge;
 use crate::{
-    connection::Manager as ConnectionManager,
-    event_handler::{Context as EventContext, EventCallbackHandle, HandlerRegistry},
+    connection::{
+        Manager as ConnectionManager, Priority, DEFAULT_RESPONSE_TIMEOUT,
+        DEFAULT_WRITE_PROBE_INTERVAL, RPC_VERSION,
+    },
+    diagnostics::ConnectDiagnostics,
+    event_handler::{
+        ConnectionState, Context as EventContext, DispatchMode, EventCallbackHandle, HandlerInfo,
+        HandlerRegistry, Ping, SlowHandler, StateChange, DEFAULT_SLOW_HANDLER_THRESHOLD,
+    },
     models::{
-        commands::{Subscription, SubscriptionArgs},
-        message::Message,
+        commands::{
+            AuthenticateArgs, AuthenticateResponse, AuthorizeArgs, AuthorizeResponse, OAuthScope,
+            Subscription, SubscriptionArgs,
+        },
         payload::Payload,
         rich_presence::{
-            Activity, CloseActivityRequestArgs, SendActivityJoinInviteArgs, SetActivityArgs,
+            AcceptActivityInviteArgs, Activity, ActivityInviteEvent, CloseActivityRequestArgs,
+            SendActivityJoinInviteArgs, SetActivityArgs,
         },
-        Command, Event, OpCode,
+        Command, ErrorEvent, Event, PartialUser, ReadyEvent, RpcServerConfiguration, Snowflake,
     },
-    DiscordError, Result,
+    nonce::NonceGenerator,
+    sync::{AtomicBool, Mutex},
+    targets::{CONNECTION, DISPATCH},
+    utils, DiscordError, Result,
 };
-use crossbeam_channel::Sender;
+use crossbeam_channel::{RecvTimeoutError, Sender};
 use serde::{de::DeserializeOwned, Serialize};
 use serde_json::Value;
 
@@ -41,7 +69,7 @@ macro_rules! event_handler_function {
 
 /// Wrapper around the [`JoinHandle`] returned by [`Client::start`]
 #[allow(clippy::module_name_repetitions)]
-pub struct ClientThread(JoinHandle<()>, Sender<()>);
+pub struct ClientThread(JoinHandle<()>, Sender<()>, ConnectionManager);
 
 impl ClientThread {
     // Ignore missing error docs because it's an alias of `join`
@@ -74,6 +102,7 @@ impl ClientThread {
     pub fn stop(self) -> Result<()> {
         // Attempt to send the message to stop the thread
         self.1.send(())?;
+        self.2.wake();
 
         self.join().map_err(|_| DiscordError::EventLoopError)?;
 
@@ -86,41 +115,806 @@ impl ClientThread {
     }
 }
 
+/// Capacities of the channels between the client and its connection thread
+///
+/// `None` leaves a channel unbounded, which is the default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ChannelCapacities {
+    /// Commands waiting to be sent. Sending blocks while the channel is full.
+    pub outbound: Option<usize>,
+    /// High priority commands waiting to be sent, such as clearing the activity. Sending blocks while the channel is full.
+    pub priority_outbound: Option<usize>,
+}
+
+/// Delay before retrying a failed connection, unless configured otherwise
+const DEFAULT_ERROR_SLEEP: Duration = Duration::from_secs(5);
+
+/// Everything that can be tuned about a [`Client`], for [`Client::with_config`]
+///
+/// Each setting defaults to the same value as the matching setter on [`Client`], which can still change it later.
+/// [`Client::clear_on_exit`] is left out, as it installs a handler for the whole process, which can fail.
+///
+/// # Examples
+///
+/// ```
+/// # use std::time::Duration;
+/// # use discord_presence::client::{Client, ClientConfig};
+/// let config = ClientConfig::new(1003450375732482138)
+///     .connection_attempts(Some(3))
+///     .offline_queue(16, Duration::from_secs(30))
+///     .compatibility_mode(true);
+///
+/// let client = Client::with_config(config);
+/// ```
+#[derive(Clone)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct ClientConfig {
+    client_id: u64,
+    error_sleep: Duration,
+    connection_attempts: Option<usize>,
+    channel_capacities: ChannelCapacities,
+    offline_queue: Option<(usize, Duration)>,
+    activity_debounce: Option<Duration>,
+    skip_unchanged_activity: bool,
+    compatibility_mode: bool,
+    auto_subscribe: bool,
+    ipc_dir: Option<PathBuf>,
+    rpc_version: u32,
+    slow_handler_threshold: Option<Duration>,
+    write_probe_interval: Option<Duration>,
+    response_timeout: Duration,
+    clear_on_drop: bool,
+    localizer: Option<Arc<Localizer>>,
+    nonce_generator: Option<Arc<NonceGenerator>>,
+    #[cfg(feature = "wsl")]
+    wsl_bridge: Option<Bridge>,
+}
+
+impl std::fmt::Debug for ClientConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut config = f.debug_struct("ClientConfig");
+        config
+            .field("client_id", &self.client_id)
+            .field("error_sleep", &self.error_sleep)
+            .field("connection_attempts", &self.connection_attempts)
+            .field("channel_capacities", &self.channel_capacities)
+            .field("offline_queue", &self.offline_queue)
+            .field("activity_debounce", &self.activity_debounce)
+            .field("skip_unchanged_activity", &self.skip_unchanged_activity)
+            .field("compatibility_mode", &self.compatibility_mode)
+            .field("auto_subscribe", &self.auto_subscribe)
+            .field("ipc_dir", &self.ipc_dir)
+            .field("rpc_version", &self.rpc_version)
+            .field("slow_handler_threshold", &self.slow_handler_threshold)
+            .field("write_probe_interval", &self.write_probe_interval)
+            .field("response_timeout", &self.response_timeout)
+            .field("clear_on_drop", &self.clear_on_drop)
+            .field("localizer", &self.localizer.is_some())
+            .field("nonce_generator", &self.nonce_generator.is_some());
+        #[cfg(feature = "wsl")]
+        config.field("wsl_bridge", &self.wsl_bridge);
+        config.finish()
+    }
+}
+
+impl ClientConfig {
+    /// Creates the default configuration for the given application ID, checking that it looks like a Discord ID first
+    ///
+    /// # Errors
+    /// - [`DiscordError::MalformedClientId`] if the ID is invalid. See [`IntoClientId`].
+    pub fn try_new<I: IntoClientId>(client_id: I) -> Result<Self> {
+        Ok(Self::new(client_id.into_client_id()?))
+    }
+
+    /// Creates the default configuration for the given application ID
+    #[must_use]
+    pub fn new(client_id: u64) -> Self {
+        Self {
+            client_id,
+            error_sleep: DEFAULT_ERROR_SLEEP,
+            connection_attempts: None,
+            channel_capacities: ChannelCapacities::default(),
+            offline_queue: None,
+            activity_debounce: None,
+            skip_unchanged_activity: false,
+            compatibility_mode: false,
+            auto_subscribe: true,
+            ipc_dir: None,
+            rpc_version: RPC_VERSION,
+            slow_handler_threshold: Some(DEFAULT_SLOW_HANDLER_THRESHOLD),
+            write_probe_interval: Some(DEFAULT_WRITE_PROBE_INTERVAL),
+            response_timeout: DEFAULT_RESPONSE_TIMEOUT,
+            clear_on_drop: false,
+            localizer: None,
+            nonce_generator: None,
+            #[cfg(feature = "wsl")]
+            wsl_bridge: None,
+        }
+    }
+
+    /// How long to wait before retrying a failed connection. Defaults to 5 seconds.
+    #[must_use]
+    pub fn error_sleep(mut self, error_sleep: Duration) -> Self {
+        self.error_sleep = error_sleep;
+        self
+    }
+
+    /// How many times to retry a failed connection before giving up, or `None` to retry forever, which is the default
+    #[must_use]
+    pub fn connection_attempts(mut self, attempts: Option<usize>) -> Self {
+        self.connection_attempts = attempts;
+        self
+    }
+
+    /// Capacities of the channels between the client and its connection thread. See [`ChannelCapacities`].
+    #[must_use]
+    pub fn channel_capacities(mut self, capacities: ChannelCapacities) -> Self {
+        self.channel_capacities = capacities;
+        self
+    }
+
+    /// Queues commands issued before the client is ready. See [`Client::set_offline_queue`].
+    #[must_use]
+    pub fn offline_queue(mut self, capacity: usize, ttl: Duration) -> Self {
+        self.offline_queue = Some((capacity, ttl));
+        self
+    }
+
+    /// Debounces activity updates. See [`Client::set_activity_debounce`].
+    #[must_use]
+    pub fn activity_debounce(mut self, window: Option<Duration>) -> Self {
+        self.activity_debounce = window;
+        self
+    }
+
+    /// Skips activity updates identical to the last one sent. See [`Client::set_skip_unchanged_activity`].
+    #[must_use]
+    pub fn skip_unchanged_activity(mut self, enabled: bool) -> Self {
+        self.skip_unchanged_activity = enabled;
+        self
+    }
+
+    /// Accepts third-party RPC servers. See [`Client::set_compatibility_mode`].
+    #[must_use]
+    pub fn compatibility_mode(mut self, enabled: bool) -> Self {
+        self.compatibility_mode = enabled;
+        self
+    }
+
+    /// Subscribes to activity events automatically. See [`Client::set_auto_subscribe`].
+    #[must_use]
+    pub fn auto_subscribe(mut self, enabled: bool) -> Self {
+        self.auto_subscribe = enabled;
+        self
+    }
+
+    /// The RPC version to ask for in the handshake. See [`Client::set_rpc_version`].
+    #[must_use]
+    pub fn rpc_version(mut self, version: u32) -> Self {
+        self.rpc_version = version;
+        self
+    }
+
+    /// How long event handlers may run before they are reported as slow. See [`Client::set_slow_handler_threshold`].
+    #[must_use]
+    pub fn slow_handler_threshold(mut self, threshold: Option<Duration>) -> Self {
+        self.slow_handler_threshold = threshold;
+        self
+    }
+
+    /// How often an idle connection is checked for being half-open. See [`Client::set_write_probe_interval`].
+    #[must_use]
+    pub fn write_probe_interval(mut self, interval: Option<Duration>) -> Self {
+        self.write_probe_interval = interval;
+        self
+    }
+
+    /// How long commands wait for their response. See [`Client::set_response_timeout`].
+    #[must_use]
+    pub fn response_timeout(mut self, timeout: Duration) -> Self {
+        self.response_timeout = timeout;
+        self
+    }
+
+    /// Clears the activity when the client is shut down or dropped. See [`Client::set_clear_on_drop`].
+    #[must_use]
+    pub fn clear_on_drop(mut self, enabled: bool) -> Self {
+        self.clear_on_drop = enabled;
+        self
+    }
+
+    /// Overrides the directory containing Discord's socket. See [`Client::set_ipc_dir`].
+    #[must_use]
+    pub fn ipc_dir(mut self, ipc_dir: Option<PathBuf>) -> Self {
+        self.ipc_dir = ipc_dir;
+        self
+    }
+
+    /// Translates the text of every activity. See [`Client::set_localizer`].
+    #[must_use]
+    pub fn localizer<F>(mut self, localizer: F) -> Self
+    where
+        F: Fn(&str, &str) -> Option<String> + Send + Sync + 'static,
+    {
+        self.localizer = Some(Arc::new(localizer));
+        self
+    }
+
+    /// Generates the nonce of every command. See [`Client::set_nonce_generator`].
+    #[must_use]
+    pub fn nonce_generator<F>(mut self, generator: F) -> Self
+    where
+        F: Fn() -> String + Send + Sync + 'static,
+    {
+        self.nonce_generator = Some(Arc::new(generator));
+        self
+    }
+
+    /// Connects through a bridge when running in WSL. See [`Client::set_wsl_bridge`].
+    #[cfg(feature = "wsl")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "wsl")))]
+    #[must_use]
+    pub fn wsl_bridge(mut self, bridge: Option<Bridge>) -> Self {
+        self.wsl_bridge = bridge;
+        self
+    }
+
+    /// Connects to a [`Broker`](crate::broker::Broker) instead of Discord. See [`Client::use_broker`].
+    #[cfg(all(unix, feature = "broker"))]
+    #[cfg_attr(docsrs, doc(cfg(all(unix, feature = "broker"))))]
+    #[must_use]
+    pub fn use_broker(self) -> Self {
+        self.ipc_dir(Some(crate::broker::default_dir()))
+    }
+}
+
+/// An application ID given as a number or a string, which is checked before a client is created.
+/// See [`Client::try_new`].
+pub trait IntoClientId {
+    /// Converts the ID into a number, checking that it looks like a Discord ID
+    ///
+    /// # Errors
+    /// - [`DiscordError::MalformedClientId`] if it is not a number, or not one Discord could have made
+    fn into_client_id(self) -> Result<u64>;
+}
+
+impl IntoClientId for Snowflake {
+    fn into_client_id(self) -> Result<u64> {
+        if self.is_plausible() {
+            Ok(self.get())
+        } else {
+            Err(DiscordError::MalformedClientId(self.to_string()))
+        }
+    }
+}
+
+impl IntoClientId for u64 {
+    fn into_client_id(self) -> Result<u64> {
+        Snowflake::new(self).into_client_id()
+    }
+}
+
+impl IntoClientId for NonZeroU64 {
+    fn into_client_id(self) -> Result<u64> {
+        self.get().into_client_id()
+    }
+}
+
+impl IntoClientId for &str {
+    fn into_client_id(self) -> Result<u64> {
+        self.trim()
+            .parse::<Snowflake>()
+            .map_err(|_| DiscordError::MalformedClientId(self.to_owned()))?
+            .into_client_id()
+    }
+}
+
+impl IntoClientId for String {
+    fn into_client_id(self) -> Result<u64> {
+        self.as_str().into_client_id()
+    }
+}
+
+/// Locale used for localization until Discord reports the user's locale
+pub const DEFAULT_LOCALE: &str = "en-US";
+
+/// Translates activity text, given the text as a key and a locale. See [`Client::set_localizer`].
+pub type Localizer = dyn Fn(&str, &str) -> Option<String> + Send + Sync;
+
+/// How long [`Client::clear_on_exit`] and [`Client::set_clear_on_drop`] wait for the activity to be cleared before giving up
+pub const EXIT_CLEAR_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long Discord shows an "Ask to Join" request before it expires
+pub const JOIN_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// An "Ask to Join" request that has not been answered yet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JoinRequest {
+    /// The user asking to join
+    pub user: PartialUser,
+    /// When the request was received
+    pub received_at: SystemTime,
+    /// When the request expires, after which it can no longer be answered
+    pub expires_at: SystemTime,
+}
+
+/// The activity last sent and the one Discord shows, when they differ. See [`Client::presence_drift`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PresenceDrift {
+    /// The activity last sent, or `None` if it was cleared
+    pub intended: Option<Activity>,
+    /// The activity Discord last acknowledged, or `None` if it shows none
+    pub shown: Option<Activity>,
+}
+
+/// Counters describing the client's connection to Discord, from [`Client::stats`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ConnectionStats {
+    /// Bytes written to Discord, including frame headers
+    pub bytes_sent: u64,
+    /// Frames written to Discord
+    pub frames_sent: u64,
+    /// Bytes read from Discord, including frame headers
+    pub bytes_received: u64,
+    /// Frames read from Discord
+    pub frames_received: u64,
+    /// How many times the client connected again after its first connection
+    pub reconnects: u64,
+    /// How long the current connection has been up, or `None` while disconnected
+    pub uptime: Option<Duration>,
+    /// When the activity was last sent to Discord
+    pub activity_sent_at: Option<SystemTime>,
+}
+
+/// A command sent without blocking, which resolves once Discord responds to it
+///
+/// The command is sent whether or not the future is polled. Dropping the future or timing out cancels it: the command is
+/// not sent if it has not been yet, and otherwise the client stops waiting for its response, dropping it once it arrives.
+#[must_use = "the command is sent regardless, but its response is only available by awaiting it"]
+pub struct CommandFuture<T> {
+    state: Arc<Mutex<CommandState<T>>>,
+    cancelled: Arc<AtomicBool>,
+    /// Dropped along with the future, which stops the thread waiting for the result
+    _cancel: Sender<()>,
+}
+
+/// An activity update sent by [`Client::set_activity_async`]
+pub type ActivityUpdate = CommandFuture<Payload<Activity>>;
+
+struct CommandState<T> {
+    result: Option<Result<T>>,
+    waker: Option<Waker>,
+}
+
+impl<T: Send + 'static> CommandFuture<T> {
+    /// Runs `command` on a thread of its own, resolving with its result or [`DiscordError::TimeoutError`] after `timeout`.
+    ///
+    /// `command` is passed a flag that is set once its result is no longer wanted, which it has to give up on.
+    fn spawn<F>(timeout: Duration, command: F) -> Self
+    where
+        F: FnOnce(&Arc<AtomicBool>) -> Result<T> + Send + 'static,
+    {
+        let state = Arc::new(Mutex::new(CommandState {
+            result: None,
+            waker: None,
+        }));
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let (cancel, cancel_rx) = crossbeam_channel::bounded(0);
+        let (tx, rx) = crossbeam_channel::bounded(1);
+
+        let command_cancelled = cancelled.clone();
+        utils::spawn("command", move || tx.send(command(&command_cancelled)).ok());
+
+        let waiter_state = state.clone();
+        let waiter_cancelled = cancelled.clone();
+        utils::spawn("command-wait", move || {
+            let result = crossbeam_channel::select! {
+                recv(rx) -> result => result.unwrap_or(Err(DiscordError::ThreadError)),
+                recv(cancel_rx) -> _ => return,
+                default(timeout) => {
+                    // Otherwise the command thread keeps waiting for a response the future no longer resolves with
+                    waiter_cancelled.store(true, Ordering::Release);
+                    Err(DiscordError::TimeoutError(RecvTimeoutError::Timeout))
+                }
+            };
+
+            let mut state = waiter_state.lock();
+            state.result = Some(result);
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        });
+
+        Self {
+            state,
+            cancelled,
+            _cancel: cancel,
+        }
+    }
+}
+
+impl<T> std::fmt::Debug for CommandFuture<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CommandFuture")
+            .field("done", &self.state.lock().result.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> Future for CommandFuture<T> {
+    type Output = Result<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Self::Output> {
+        let mut state = self.state.lock();
+        if let Some(result) = state.result.take() {
+            return Poll::Ready(result);
+        }
+
+        state.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl<T> Drop for CommandFuture<T> {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Release);
+    }
+}
+
+#[derive(Default)]
+struct ActivityDebounce {
+    window: Option<Duration>,
+    last_sent: Option<Instant>,
+    /// The latest deferred update, and the priority it was made with
+    pending: Option<(SetActivityArgs, Priority)>,
+}
+
 #[derive(Clone)]
 /// The Discord client
 pub struct Client {
     connection_manager: ConnectionManager,
     event_handler_registry: Arc<HandlerRegistry>,
     thread: Option<Arc<ClientThread>>,
+    /// The connection generation and hash of the last activity that was sent
+    last_activity: Arc<Mutex<Option<(u64, u64)>>>,
+    skip_unchanged_activity: Arc<AtomicBool>,
+    clear_on_drop: Arc<AtomicBool>,
+    activity_debounce: Arc<Mutex<ActivityDebounce>>,
+    localizer: Arc<Mutex<Option<Arc<Localizer>>>>,
+}
+
+impl Drop for Client {
+    // Only the last handle to a started client clears the activity, as the others are still using the connection
+    fn drop(&mut self) {
+        let last = self
+            .thread
+            .as_ref()
+            .is_some_and(|thread| Arc::strong_count(thread) == 1);
+
+        if last && self.should_clear_on_drop() {
+            self.clear_before_stopping();
+        }
+    }
+}
+
+impl std::fmt::Debug for Client {
+    // Only counts are shown, so activities and the secrets in queued commands are never printed
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Client")
+            .field("client_id", &self.connection_manager.client_id())
+            .field("state", &self.connection_manager.state())
+            .field("generation", &self.connection_manager.generation())
+            .field("started", &self.thread.is_some())
+            .field(
+                "queued_commands",
+                &self.connection_manager.queued_commands(),
+            )
+            .field("handlers", &*self.event_handler_registry)
+            .finish_non_exhaustive()
+    }
 }
 
 impl Client {
     /// Creates a new `Client` with default error sleep duration of 5 seconds, and no limit on connection attempts
+    ///
+    /// The ID is not checked, so an invalid one only fails once Discord rejects the handshake.
+    /// Use [`Client::try_new`] to catch it straight away.
     #[must_use]
     pub fn new(client_id: u64) -> Self {
-        Self::with_error_config(client_id, Duration::from_secs(5), None)
+        Self::with_config(ClientConfig::new(client_id))
+    }
+
+    /// Creates a new `Client` like [`Client::new`], after checking that the application ID looks like a Discord ID.
+    ///
+    /// The ID can be given as a number or a string, such as one read from configuration.
+    ///
+    /// # Examples
+    /// ```
+    /// # use discord_presence::{Client, DiscordError};
+    /// assert!(Client::try_new("1003450375732482138").is_ok());
+    /// assert!(matches!(
+    ///     Client::try_new("my-app"),
+    ///     Err(DiscordError::MalformedClientId(_))
+    /// ));
+    /// ```
+    ///
+    /// # Errors
+    /// - [`DiscordError::MalformedClientId`] if the ID is invalid. See [`IntoClientId`].
+    pub fn try_new<I: IntoClientId>(client_id: I) -> Result<Self> {
+        Ok(Self::with_config(ClientConfig::try_new(client_id)?))
     }
 
     /// Creates a new `Client` with a custom error sleep duration, and number of attempts
     #[must_use]
+    #[deprecated(
+        note = "use `Client::with_config` with `ClientConfig::error_sleep` and `ClientConfig::connection_attempts`"
+    )]
     pub fn with_error_config(
         client_id: u64,
         sleep_duration: Duration,
         attempts: Option<usize>,
     ) -> Self {
+        Self::with_config(
+            ClientConfig::new(client_id)
+                .error_sleep(sleep_duration)
+                .connection_attempts(attempts),
+        )
+    }
+
+    /// Creates a new `Client` with every setting taken from `config`
+    #[must_use]
+    pub fn with_config(config: ClientConfig) -> Self {
         let event_handler_registry = Arc::new(HandlerRegistry::new());
         let connection_manager = ConnectionManager::new(
-            client_id,
+            config.client_id,
             event_handler_registry.clone(),
-            sleep_duration,
-            attempts,
+            config.error_sleep,
+            config.connection_attempts,
+            config.channel_capacities,
         );
 
-        Self {
+        let client = Self {
             connection_manager,
             event_handler_registry,
             thread: None,
+            last_activity: Arc::new(Mutex::new(None)),
+            skip_unchanged_activity: Arc::new(AtomicBool::new(false)),
+            clear_on_drop: Arc::new(AtomicBool::new(false)),
+            activity_debounce: Arc::new(Mutex::new(ActivityDebounce::default())),
+            localizer: Arc::new(Mutex::new(None)),
+        };
+
+        if let Some((capacity, ttl)) = config.offline_queue {
+            client.set_offline_queue(capacity, ttl);
         }
+        client.set_activity_debounce(config.activity_debounce);
+        client.set_skip_unchanged_activity(config.skip_unchanged_activity);
+        client.set_compatibility_mode(config.compatibility_mode);
+        client.set_auto_subscribe(config.auto_subscribe);
+        client.set_ipc_dir(config.ipc_dir);
+        client.set_rpc_version(config.rpc_version);
+        client.set_slow_handler_threshold(config.slow_handler_threshold);
+        client.set_write_probe_interval(config.write_probe_interval);
+        client.set_response_timeout(config.response_timeout);
+        client.set_clear_on_drop(config.clear_on_drop);
+        *client.localizer.lock() = config.localizer;
+        client
+            .connection_manager
+            .set_nonce_generator(config.nonce_generator);
+        #[cfg(feature = "wsl")]
+        client.set_wsl_bridge(config.wsl_bridge);
+
+        client
+    }
+
+    /// Enables queueing commands issued before the client is ready, or disables it if `capacity` is `0`. Disabled by default.
+    ///
+    /// Queued commands return [`DiscordError::Queued`], and are sent in order once the handshake completes.
+    /// If the queue is full, the oldest command is dropped, and commands older than `ttl` are dropped instead of being sent.
+    pub fn set_offline_queue(&self, capacity: usize, ttl: Duration) {
+        self.connection_manager.set_offline_queue(capacity, ttl);
+    }
+
+    /// Sets the debounce window for activity updates, or disables debouncing if `None`. Disabled by default.
+    ///
+    /// Activity updates made within the window of the last one sent are deferred until the window ends,
+    /// at which point only the latest of them is sent.
+    ///
+    /// This is separate from Discord's own rate limit, and is intended to smooth out bursts of updates.
+    pub fn set_activity_debounce(&self, window: Option<Duration>) {
+        self.activity_debounce.lock().window = window;
+    }
+
+    /// Enables or disables skipping activity updates identical to the last one sent on the current connection. Disabled by default.
+    ///
+    /// Skipped updates return [`DiscordError::Unchanged`] instead of being sent again.
+    pub fn set_skip_unchanged_activity(&self, enabled: bool) {
+        self.skip_unchanged_activity
+            .store(enabled, Ordering::Relaxed);
+    }
+
+    #[must_use]
+    /// Why the last attempt to connect to Discord failed, or `None` if it succeeded or none was made yet.
+    ///
+    /// The same report is included in the message of the [`Event::Error`] event fired when connecting fails.
+    pub fn connect_diagnostics(&self) -> Option<ConnectDiagnostics> {
+        self.connection_manager.connect_diagnostics()
+    }
+
+    #[must_use]
+    /// Checks whether Discord can be connected to, by trying every candidate socket now.
+    ///
+    /// This opens a separate connection, which is closed straight away, so it can be used whether or not the client is started.
+    pub fn diagnose(&self) -> ConnectDiagnostics {
+        self.connection_manager.diagnose()
+    }
+
+    /// Enables compatibility with third-party RPC servers, such as arRPC used by Vesktop and other web-based clients. Disabled by default.
+    ///
+    /// In compatibility mode, every socket name and location those servers may use is tried, and handshake responses
+    /// that do not describe the server are accepted. Applies from the next time the client connects.
+    pub fn set_compatibility_mode(&self, enabled: bool) {
+        self.connection_manager.set_compatibility_mode(enabled);
+    }
+
+    /// Enables or disables subscribing to activity events automatically. Enabled by default.
+    ///
+    /// While enabled, the client subscribes to [`Event::ActivityJoin`], [`Event::ActivitySpectate`],
+    /// [`Event::ActivityJoinRequest`] and [`Event::ActivityInvite`] once a handler is registered for them,
+    /// or an activity with the matching secrets is set,
+    /// so those handlers fire without calling [`Client::subscribe`]. The subscriptions are made again on every connection.
+    ///
+    /// Disabling it leaves subscribing to the application, and stops renewing the automatic subscriptions on later connections.
+    pub fn set_auto_subscribe(&self, enabled: bool) {
+        self.connection_manager.set_auto_subscribe(enabled);
+    }
+
+    /// Subscribes to an activity event automatically, if automatic subscriptions are enabled
+    fn subscribe_automatically(&self, event: Event) {
+        let activity_event = matches!(
+            event,
+            Event::ActivityJoin
+                | Event::ActivitySpectate
+                | Event::ActivityJoinRequest
+                | Event::ActivityInvite
+        );
+
+        if activity_event {
+            if let Err(why) = self.connection_manager.subscribe_automatically(event) {
+                warn!(target: CONNECTION, "Failed to subscribe to {event:?}: {why}");
+            }
+        }
+    }
+
+    /// Sets the RPC protocol version asked for in the handshake. Defaults to 1, the only version Discord supports.
+    ///
+    /// If Discord does not support the version, connecting fails with [`DiscordError::UnsupportedProtocol`].
+    /// Applies from the next time the client connects.
+    pub fn set_rpc_version(&self, version: u32) {
+        self.connection_manager.set_rpc_version(version);
+    }
+
+    /// Sets how often an idle connection is written to, to tell whether it is still open, or stops checking if `None`.
+    /// Defaults to 30 seconds.
+    ///
+    /// A connection Discord crashed on can stay open without ever becoming readable, particularly a named pipe on Windows.
+    /// Writing to it fails once it is closed, and a write stuck for longer than the interval means nothing is reading it,
+    /// so either way the client reconnects rather than waiting on a dead connection.
+    pub fn set_write_probe_interval(&self, interval: Option<Duration>) {
+        self.connection_manager.set_write_probe_interval(interval);
+    }
+
+    /// Sets how long commands wait for their response before failing with [`DiscordError::TimeoutError`].
+    /// Defaults to 30 seconds.
+    ///
+    /// `AUTHORIZE` is not affected, as Discord only responds once the user answers its prompt.
+    pub fn set_response_timeout(&self, timeout: Duration) {
+        self.connection_manager.set_response_timeout(timeout);
+    }
+
+    /// Clears the activity when the client is shut down, or when the last handle to the started client is dropped.
+    /// Disabled by default.
+    ///
+    /// Clearing waits for up to [`EXIT_CLEAR_TIMEOUT`], regardless of the debounce window, and is skipped if the client
+    /// is not connected. Without this, the activity stays visible until Discord notices the socket has closed.
+    pub fn set_clear_on_drop(&self, enabled: bool) {
+        self.clear_on_drop.store(enabled, Ordering::Release);
+    }
+
+    /// Connects through a bridge to Discord on Windows when running in WSL, or stops doing so if `None`.
+    ///
+    /// The bridge is only used when [`is_wsl`](crate::wsl::is_wsl) detects WSL, so the same configuration works on native Linux.
+    /// Applies from the next time the client connects.
+    #[cfg(feature = "wsl")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "wsl")))]
+    pub fn set_wsl_bridge(&self, bridge: Option<Bridge>) {
+        self.connection_manager.set_wsl_bridge(bridge);
+    }
+
+    #[must_use]
+    /// The directory the client looks for Discord's socket in when it next connects.
+    ///
+    /// On Unix, this is the first of `XDG_RUNTIME_DIR` and `TMPDIR` that is set, or `/tmp` otherwise,
+    /// unless overridden with [`Client::set_ipc_dir`].
+    pub fn ipc_dir(&self) -> PathBuf {
+        self.connection_manager.ipc_dir()
+    }
+
+    /// Overrides the directory containing Discord's socket, or restores the default if `None`.
+    ///
+    /// Useful on multi-user machines where the environment points at another user's directory, such as under `sudo`.
+    /// Applies from the next time the client connects.
+    pub fn set_ipc_dir(&self, ipc_dir: Option<PathBuf>) {
+        self.connection_manager.set_ipc_dir(ipc_dir);
+    }
+
+    /// Connects to the [`Broker`](crate::broker::Broker) started by another process, sharing its connection to Discord,
+    /// instead of connecting to Discord directly.
+    ///
+    /// Applies from the next time the client connects. Use [`Client::set_ipc_dir`] for brokers listening elsewhere.
+    #[cfg(all(unix, feature = "broker"))]
+    #[cfg_attr(docsrs, doc(cfg(all(unix, feature = "broker"))))]
+    pub fn use_broker(&self) {
+        self.set_ipc_dir(Some(crate::broker::default_dir()));
+    }
+
+    /// Translates the text of every activity sent from now on into the user's locale.
+    ///
+    /// The localizer is called with each text field of the activity as the key, along with the locale from [`Client::locale`],
+    /// and fields it returns `None` for are sent as they are. See [`Activity::localize`].
+    pub fn set_localizer<F>(&self, localizer: F)
+    where
+        F: Fn(&str, &str) -> Option<String> + Send + Sync + 'static,
+    {
+        *self.localizer.lock() = Some(Arc::new(localizer));
+    }
+
+    /// Stops translating activity text
+    pub fn remove_localizer(&self) {
+        *self.localizer.lock() = None;
+    }
+
+    /// Generates the nonce of every command sent from now on with `generator`, instead of a random UUID.
+    ///
+    /// Discord echoes the nonce back in its response, so nonces that are ordered or carry the process ID make
+    /// frames easier to match up with other logs and captures. See [`nonce`](crate::nonce) for common generators.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use discord_presence::{nonce::Sequential, Client};
+    /// let client = Client::new(1003450375732482138);
+    /// let nonces = Sequential::new();
+    /// client.set_nonce_generator(move || nonces.next());
+    /// ```
+    pub fn set_nonce_generator<F>(&self, generator: F)
+    where
+        F: Fn() -> String + Send + Sync + 'static,
+    {
+        self.connection_manager
+            .set_nonce_generator(Some(Arc::new(generator)));
+    }
+
+    /// Goes back to generating nonces as random UUIDs
+    pub fn remove_nonce_generator(&self) {
+        self.connection_manager.set_nonce_generator(None);
+    }
+
+    #[must_use]
+    /// The configuration of the RPC server, as reported in the `READY` payload, such as the CDN host to build avatar URLs with
+    ///
+    /// Returns `None` until the first handshake completes, or if Discord did not report one.
+    pub fn server_config(&self) -> Option<RpcServerConfiguration> {
+        self.ready_info().and_then(|ready| ready.config)
+    }
+
+    #[must_use]
+    /// The user's locale, as reported in the `READY` payload, or [`DEFAULT_LOCALE`] if Discord has not reported one
+    pub fn locale(&self) -> String {
+        self.ready_info()
+            .and_then(|ready| ready.user)
+            .and_then(|user| user.locale)
+            .unwrap_or_else(|| DEFAULT_LOCALE.to_owned())
     }
 
     // TODO: Add examples
@@ -135,7 +929,11 @@ impl Client {
 
         let thread = self.connection_manager.start(rx);
 
-        self.thread = Some(Arc::new(ClientThread(thread, tx)));
+        self.thread = Some(Arc::new(ClientThread(
+            thread,
+            tx,
+            self.connection_manager.clone(),
+        )));
     }
 
     /// Shutdown the client and its thread
@@ -144,17 +942,127 @@ impl Client {
     /// - The internal connection thread ran into an error
     /// - The client was not started, or has already been shutdown
     pub fn shutdown(self) -> Result<()> {
+        if self.should_clear_on_drop() {
+            self.clear_before_stopping();
+        }
+
+        self.stop()?;
+        self.block_on()
+    }
+
+    fn should_clear_on_drop(&self) -> bool {
+        self.clear_on_drop.load(Ordering::Acquire) && self.connection_manager.is_ready()
+    }
+
+    /// A handle sharing the connection, without keeping the connection thread alive
+    pub(crate) fn detached(&self) -> Self {
+        let mut client = self.clone();
+        client.thread = None;
+        client
+    }
+
+    /// Tells the connection thread to stop, without waiting for it
+    fn stop(&self) -> Result<()> {
         if let Some(thread) = self.thread.as_ref() {
             thread.1.send(())?;
+            thread.2.wake();
 
-            crate::READY.store(false, Ordering::Relaxed);
+            self.connection_manager.reset_ready();
 
-            self.block_on()
+            Ok(())
         } else {
             Err(DiscordError::NotStarted)
         }
     }
 
+    /// Clears the activity and stops the client when the process is interrupted with Ctrl+C, or sent SIGTERM or SIGHUP
+    /// on Unix, then exits the process with the conventional code for the signal: 130 for Ctrl+C, 143 for SIGTERM and
+    /// 129 for SIGHUP.
+    ///
+    /// The activity is cleared right away, regardless of the debounce window, and the process exits without it if
+    /// Discord does not respond within [`EXIT_CLEAR_TIMEOUT`].
+    ///
+    /// Without this, the activity stays visible until Discord notices the socket has closed.
+    /// Only one such handler can be installed per process, including by other libraries.
+    ///
+    /// # Errors
+    /// - [`DiscordError::ExitHookError`] if a Ctrl+C handler is already installed, or installing it failed
+    ///
+    /// # Examples
+    /// ```no_run
+    /// # use discord_presence::Client;
+    /// let mut client = Client::new(1003450375732482138);
+    /// client.start();
+    /// client.clear_on_exit()?;
+    ///
+    /// client.block_on()?;
+    /// # Ok::<(), discord_presence::DiscordError>(())
+    /// ```
+    #[cfg(feature = "exit-hook")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "exit-hook")))]
+    pub fn clear_on_exit(&self) -> Result<()> {
+        let client = self.clone();
+        let terminated = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let hung_up = Arc::new(std::sync::atomic::AtomicBool::new(false));
+
+        let signal_terminated = terminated.clone();
+        let signal_hung_up = hung_up.clone();
+        ctrlc::set_handler(move || {
+            info!(target: CONNECTION, "Exiting, clearing the activity");
+            client.clear_before_stopping();
+            if let Err(why) = client.stop() {
+                warn!(target: CONNECTION, "Failed to stop the client: {why}");
+            }
+
+            let code = if signal_terminated.load(Ordering::Acquire) {
+                143
+            } else if signal_hung_up.load(Ordering::Acquire) {
+                129
+            } else {
+                130
+            };
+            std::process::exit(code);
+        })?;
+
+        // Registered after the Ctrl+C handler, which they chain to, so it can tell which signal was received
+        #[cfg(unix)]
+        {
+            signal_hook::flag::register(signal_hook::consts::SIGTERM, terminated)?;
+            signal_hook::flag::register(signal_hook::consts::SIGHUP, hung_up)?;
+        }
+
+        Ok(())
+    }
+
+    /// Clears the activity without waiting for the debounce window, giving up after [`EXIT_CLEAR_TIMEOUT`]
+    fn clear_before_stopping(&self) {
+        let mut client = self.detached();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = crossbeam_channel::bounded(1);
+
+        // A deferred update would otherwise be sent after the activity is cleared
+        client.activity_debounce.lock().pending = None;
+
+        let clear_cancelled = cancelled.clone();
+        utils::spawn("exit-clear", move || {
+            let result = client.send_activity(
+                SetActivityArgs::default(),
+                Priority::High,
+                Some(&clear_cancelled),
+            );
+            tx.send(result).ok();
+        });
+
+        match rx.recv_timeout(EXIT_CLEAR_TIMEOUT) {
+            Ok(Ok(_) | Err(DiscordError::Unchanged)) => {}
+            Ok(Err(why)) => warn!(target: CONNECTION, "Failed to clear the activity: {why}"),
+            Err(_) => {
+                cancelled.store(true, Ordering::Release);
+                warn!(target: CONNECTION, "Timed out clearing the activity");
+            }
+        }
+    }
+
     /// Block indefinitely until the client shuts down
     ///
     /// This is nearly the same as [`Client::shutdown()`],
@@ -167,109 +1075,638 @@ impl Client {
     pub fn block_on(mut self) -> Result<()> {
         let thread = self.unwrap_thread()?;
 
-        // If into_inner succeeds, await the thread completing.
-        // Otherwise, the thread will be dropped and shut down anyway
-        thread.join().map_err(|_| DiscordError::ThreadError)?;
+        // If into_inner succeeds, await the thread completing.
+        // Otherwise, the thread will be dropped and shut down anyway
+        thread.join().map_err(|_| DiscordError::ThreadError)?;
+
+        Ok(())
+    }
+
+    fn unwrap_thread(&mut self) -> Result<ClientThread> {
+        if let Some(thread) = self.thread.take() {
+            let thread = Arc::try_unwrap(thread).map_err(|_| DiscordError::ThreadInUse)?;
+
+            Ok(thread)
+        } else {
+            Err(DiscordError::NotStarted)
+        }
+    }
+
+    #[must_use]
+    /// Check if the client is ready
+    pub fn is_ready() -> bool {
+        crate::READY.load(Ordering::Acquire)
+    }
+
+    #[must_use]
+    /// The number of times the client has (re)connected to Discord
+    ///
+    /// Compare against [`EventContext::generation`] to tell whether an event was received on the current connection.
+    pub fn connection_generation(&self) -> u64 {
+        self.connection_manager.generation()
+    }
+
+    #[must_use]
+    /// The current state of the connection to Discord
+    ///
+    /// Use [`Client::on_state_change`] to be told whenever it changes.
+    pub fn connection_state(&self) -> ConnectionState {
+        self.connection_manager.state()
+    }
+
+    /// Passes an event to the event handlers as if Discord had sent it, with `data` as the event's `data` field,
+    /// so handlers can be tested without Discord or a connection.
+    ///
+    /// The event is tracked like a real one, so a simulated [`Event::ActivityJoinRequest`] shows up in
+    /// [`Client::join_requests`], and a simulated [`Event::Error`] in [`Client::last_error`].
+    /// Handlers run on threads of their own, so this returns before they have finished.
+    ///
+    /// # Examples
+    /// ```
+    /// # use discord_presence::{models::EventData, Client, Event};
+    /// # use serde_json::json;
+    /// let mut client = Client::new(1003450375732482138);
+    /// let (tx, rx) = std::sync::mpsc::channel();
+    /// client
+    ///     .on_activity_join(move |ctx| {
+    ///         tx.send(ctx.event.clone()).unwrap();
+    ///     })
+    ///     .persist();
+    ///
+    /// client.simulate_event(Event::ActivityJoin, json!({ "secret": "match-1" }));
+    ///
+    /// let EventData::ActivityJoin(join) = rx.recv().unwrap() else {
+    ///     panic!("expected a join event");
+    /// };
+    /// assert_eq!(join.secret.as_deref(), Some("match-1"));
+    /// ```
+    #[cfg(feature = "test-util")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "test-util")))]
+    pub fn simulate_event(&self, event: Event, data: Value) {
+        self.connection_manager.simulate(event, data);
+    }
+
+    #[must_use]
+    /// The most recent error, either from failing to connect or reported by Discord through the [`Event::Error`] event
+    pub fn last_error(&self) -> Option<ErrorEvent> {
+        self.connection_manager.last_error()
+    }
+
+    #[must_use]
+    /// Counters describing the connection to Discord, such as the traffic so far and how long it has been up
+    ///
+    /// The handshake is not counted as traffic.
+    pub fn stats(&self) -> ConnectionStats {
+        self.connection_manager.stats()
+    }
+
+    #[must_use]
+    /// The data of the most recent `READY` payload, including the user and the RPC server configuration
+    ///
+    /// Returns `None` until the first handshake completes.
+    pub fn ready_info(&self) -> Option<ReadyEvent> {
+        self.connection_manager.ready_info()
+    }
+
+    pub(crate) fn execute<A, E>(
+        &mut self,
+        cmd: Command,
+        args: A,
+        evt: Option<Event>,
+    ) -> Result<Payload<E>>
+    where
+        A: Serialize + Send + Sync,
+        E: Serialize + DeserializeOwned + Send + Sync,
+    {
+        self.execute_with_priority(cmd, args, evt, Priority::Normal, None)
+    }
+
+    fn execute_with_priority<A, E>(
+        &mut self,
+        cmd: Command,
+        args: A,
+        evt: Option<Event>,
+        priority: Priority,
+        cancelled: Option<&Arc<AtomicBool>>,
+    ) -> Result<Payload<E>>
+    where
+        A: Serialize + Send + Sync,
+        E: Serialize + DeserializeOwned + Send + Sync,
+    {
+        let required = match (cmd, evt) {
+            (Command::Subscribe, Some(evt)) => evt.required_scopes(),
+            _ => cmd.required_scopes(),
+        };
+        self.connection_manager.check_scopes(required)?;
+
+        self.connection_manager
+            .execute_unless_cancelled(cmd, args, evt, priority, cancelled)
+    }
+
+    fn execute_activity(
+        &mut self,
+        args: SetActivityArgs,
+        priority: Priority,
+        cancelled: Option<&Arc<AtomicBool>>,
+    ) -> Result<Payload<Activity>> {
+        let mut debounce = self.activity_debounce.lock();
+
+        if let Some(window) = debounce.window {
+            let elapsed = debounce.last_sent.map(|last_sent| last_sent.elapsed());
+
+            if let Some(elapsed) = elapsed.filter(|elapsed| *elapsed < window) {
+                // Only schedule a flush if one is not already pending
+                if debounce.pending.replace((args, priority)).is_none() {
+                    let mut client = self.detached();
+
+                    utils::spawn("debounce", move || {
+                        thread::sleep(window.saturating_sub(elapsed));
+
+                        let pending = {
+                            let mut debounce = client.activity_debounce.lock();
+                            debounce.last_sent = Some(Instant::now());
+                            debounce.pending.take()
+                        };
+
+                        // Sent with the priority of the update that replaced the others, not the first one
+                        if let Some((args, priority)) = pending {
+                            match client.send_activity(args, priority, None) {
+                                Ok(_) | Err(DiscordError::Unchanged) => {}
+                                Err(why) => error!(
+                                    target: CONNECTION,
+                                    "Failed to send debounced activity: {why}"
+                                ),
+                            }
+                        }
+                    });
+                }
+
+                return Err(DiscordError::Debounced);
+            }
+
+            // A deferred update is older than this one, so the flush must not send it afterwards
+            debounce.pending = None;
+            debounce.last_sent = Some(Instant::now());
+        }
+
+        drop(debounce);
+
+        self.send_activity(args, priority, cancelled)
+    }
+
+    fn send_activity(
+        &mut self,
+        args: SetActivityArgs,
+        priority: Priority,
+        cancelled: Option<&Arc<AtomicBool>>,
+    ) -> Result<Payload<Activity>> {
+        // Localized when sent, so deferred updates use the latest locale
+        let localizer = self.localizer.lock().clone();
+        let args = match localizer {
+            Some(localizer) => {
+                let locale = self.locale();
+                args.map_activity(|activity| activity.localize(&locale, &*localizer))
+            }
+            None => args,
+        };
+        let args = args.try_map_activity(Activity::validated)?;
+
+        // Discord only sends join and spectate events to applications that subscribed to them
+        if let Some(secrets) = args
+            .activity()
+            .and_then(|activity| activity.secrets.as_ref())
+        {
+            if secrets.join.is_some() {
+                self.subscribe_automatically(Event::ActivityJoin);
+                self.subscribe_automatically(Event::ActivityJoinRequest);
+            }
+            if secrets.spectate.is_some() {
+                self.subscribe_automatically(Event::ActivitySpectate);
+            }
+        }
+
+        let mut hasher = DefaultHasher::new();
+        args.hash(&mut hasher);
+        let activity = (self.connection_generation(), hasher.finish());
+
+        if self.skip_unchanged_activity.load(Ordering::Relaxed)
+            && *self.last_activity.lock() == Some(activity)
+        {
+            return Err(DiscordError::Unchanged);
+        }
+
+        let response = self.execute_with_priority(
+            Command::SetActivity,
+            args.clone(),
+            None,
+            priority,
+            cancelled,
+        )?;
+        *self.last_activity.lock() = Some(activity);
+        self.connection_manager.record_activity(args);
+
+        Ok(response)
+    }
+
+    /// Set the users current activity
+    ///
+    /// # Errors
+    /// - [`DiscordError::InvalidActivity`] if Discord would reject the activity or show it wrongly. See [`Activity::validated`]
+    /// - [`DiscordError::Unchanged`] if the activity is identical to the last one sent on this connection. See [`Client::set_skip_unchanged_activity`]
+    /// - [`DiscordError::Debounced`] if the update was deferred. See [`Client::set_activity_debounce`]
+    /// - See [`DiscordError`] for more info
+    pub fn set_activity<F>(&mut self, f: F) -> Result<Payload<Activity>>
+    where
+        F: FnOnce(Activity) -> Activity,
+    {
+        self.execute_activity(SetActivityArgs::new(f), Priority::Normal, None)
+    }
+
+    /// Set the users current activity without blocking, returning a future that resolves once Discord responds.
+    ///
+    /// The update is sent on a thread of its own, so the future works with any async runtime, and it resolves with
+    /// [`DiscordError::TimeoutError`] if Discord does not respond within `timeout`.
+    /// Behaves like [`Client::set_activity`] otherwise, so the future resolves straight away with an error if the update
+    /// was queued, debounced, or unchanged.
+    ///
+    /// Dropping the future, such as when it loses a `select!`, or timing out cancels the update if it has not been sent yet,
+    /// and otherwise stops waiting for Discord's response.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// # use std::time::Duration;
+    /// # use discord_presence::Client;
+    /// # async fn example() -> discord_presence::Result<()> {
+    /// let mut drpc = Client::new(1003450375732482138);
+    /// drpc.start();
+    ///
+    /// drpc.set_activity_async(|a| a.state("In a match"), Duration::from_secs(5))
+    ///     .await?;
+    /// println!("Discord is showing the match");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_activity_async<F>(&self, f: F, timeout: Duration) -> ActivityUpdate
+    where
+        F: FnOnce(Activity) -> Activity,
+    {
+        let mut client = self.detached();
+        let args = SetActivityArgs::new(f);
+
+        CommandFuture::spawn(timeout, move |cancelled| {
+            client.execute_activity(args, Priority::Normal, Some(cancelled))
+        })
+    }
+
+    /// Set the users current activity from a prebuilt [`Activity`]
+    ///
+    /// Accepts both owned and borrowed activities, e.g. ones loaded from a config file.
+    ///
+    /// # Errors
+    /// - [`DiscordError::InvalidActivity`] if Discord would reject the activity or show it wrongly. See [`Activity::validated`]
+    /// - [`DiscordError::Unchanged`] if the activity is identical to the last one sent on this connection. See [`Client::set_skip_unchanged_activity`]
+    /// - [`DiscordError::Debounced`] if the update was deferred. See [`Client::set_activity_debounce`]
+    /// - See [`DiscordError`] for more info
+    pub fn set_activity_struct<A>(&mut self, activity: A) -> Result<Payload<Activity>>
+    where
+        A: Into<Activity>,
+    {
+        self.execute_activity(
+            SetActivityArgs::from(activity.into()),
+            Priority::Normal,
+            None,
+        )
+    }
+
+    /// Clear the users current activity
+    ///
+    /// # Errors
+    /// - [`DiscordError::Unchanged`] if the activity has already been cleared on this connection. See [`Client::set_skip_unchanged_activity`]
+    /// - [`DiscordError::Debounced`] if the update was deferred. See [`Client::set_activity_debounce`]
+    /// - See [`DiscordError`] for more info
+    pub fn clear_activity(&mut self) -> Result<Payload<Activity>> {
+        // Clearing the activity is usually the final cleanup, so it should not wait on a backlog
+        self.execute_activity(SetActivityArgs::default(), Priority::High, None)
+    }
+
+    // NOTE: Not sure what the actual response values of
+    //       SEND_ACTIVITY_JOIN_INVITE and CLOSE_ACTIVITY_REQUEST are,
+    //       they are not documented.
+    /// Send an invite to a user to join a game
+    ///
+    /// This resolves the user's pending join request, if any. See [`Client::join_requests`].
+    ///
+    /// # Errors
+    /// - See [`DiscordError`] for more info
+    pub fn send_activity_join_invite<I: Into<Snowflake>>(
+        &mut self,
+        user_id: I,
+    ) -> Result<Payload<Value>> {
+        let user_id = user_id.into();
+        let response = self.execute(
+            Command::SendActivityJoinInvite,
+            SendActivityJoinInviteArgs::new(user_id),
+            None,
+        )?;
+
+        self.connection_manager.resolve_join_request(user_id);
+        Ok(response)
+    }
+
+    /// Accept an invite to join a game, received through [`Client::on_activity_invite`]
+    ///
+    /// Discord then launches the game if needed, and sends it [`Event::ActivityJoin`] with the secret to join with.
+    ///
+    /// # Errors
+    /// - [`DiscordError::NoneError`] if the invite does not say who sent it or in which message
+    /// - See [`DiscordError`] for more info
+    pub fn accept_activity_invite(
+        &mut self,
+        invite: &ActivityInviteEvent,
+    ) -> Result<Payload<Value>> {
+        let args = into_error!(
+            AcceptActivityInviteArgs::from_invite(invite),
+            String::from("The invite does not say who sent it or in which message")
+        )?;
+
+        self.execute(Command::AcceptActivityInvite, args, None)
+    }
+
+    /// Close request to join a game
+    ///
+    /// This resolves the user's pending join request, if any. See [`Client::join_requests`].
+    ///
+    /// # Errors
+    /// - See [`DiscordError`] for more info
+    pub fn close_activity_request<I: Into<Snowflake>>(
+        &mut self,
+        user_id: I,
+    ) -> Result<Payload<Value>> {
+        let user_id = user_id.into();
+        let response = self.execute(
+            Command::CloseActivityRequest,
+            CloseActivityRequestArgs::new(user_id),
+            None,
+        )?;
+
+        self.connection_manager.resolve_join_request(user_id);
+        Ok(response)
+    }
+
+    #[must_use]
+    /// The activity Discord last acknowledged, as it echoed it back, or `None` if it shows none
+    ///
+    /// Discord fills in fields of its own, such as the application's name.
+    /// The activity is cleared when the connection is lost, as Discord stops showing it then.
+    pub fn shown_activity(&self) -> Option<Activity> {
+        self.connection_manager.shown_activity()
+    }
+
+    #[must_use]
+    /// Compares the activity last sent with the one Discord last acknowledged, returning both if they differ.
+    /// See [`Activity::shows_same_as`] for what is compared.
+    ///
+    /// They differ for a moment while an update is on its way, and after reconnecting until the activity is sent again.
+    pub fn presence_drift(&self) -> Option<PresenceDrift> {
+        let intended = self.connection_manager.intended_activity();
+        let shown = self.connection_manager.shown_activity();
+
+        let same = match (&intended, &shown) {
+            (Some(intended), Some(shown)) => intended.shows_same_as(shown),
+            (intended, shown) => intended.is_none() && shown.is_none(),
+        };
+
+        (!same).then_some(PresenceDrift { intended, shown })
+    }
+
+    #[must_use]
+    /// The "Ask to Join" requests that have not been answered or expired yet, oldest first
+    ///
+    /// Requests are resolved by [`Client::send_activity_join_invite`], [`Client::close_activity_request`],
+    /// or by answering them from their handler with [`EventContext::accept`] and [`EventContext::decline`].
+    pub fn join_requests(&self) -> Vec<JoinRequest> {
+        self.connection_manager.join_requests()
+    }
+
+    /// Sends any command and waits for its response, returning the response's data as `R`.
+    ///
+    /// This reaches commands without a method of their own, with the nonce, framing and deserialization handled
+    /// like any other command. Use [`serde_json::Value`] as `R` to take the data as it is.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// # use discord_presence::{models::Command, Client};
+    /// # use serde::{Deserialize, Serialize};
+    /// #[derive(Serialize)]
+    /// struct GetGuildArgs {
+    ///     guild_id: String,
+    /// }
+    ///
+    /// #[derive(Serialize, Deserialize)]
+    /// struct Guild {
+    ///     id: String,
+    ///     name: String,
+    /// }
+    ///
+    /// let mut drpc = Client::new(1003450375732482138);
+    /// drpc.start();
+    ///
+    /// let args = GetGuildArgs {
+    ///     guild_id: String::from("199737254929760256"),
+    /// };
+    /// let guild: Guild = drpc.send_command(Command::GetGuild, args)?;
+    /// println!("Looking at {}", guild.name);
+    /// # Ok::<_, discord_presence::DiscordError>(())
+    /// ```
+    ///
+    /// # Errors
+    /// - [`DiscordError::CommandFailed`] with Discord's error code and message, if Discord rejects the command
+    /// - [`DiscordError::NoneError`] if the response has no data
+    /// - See [`DiscordError`] for more info
+    pub fn send_command<A, R>(&mut self, cmd: Command, args: A) -> Result<R>
+    where
+        A: Serialize + Send + Sync,
+        R: Serialize + DeserializeOwned + Send + Sync,
+    {
+        let response: Payload<R> = self.execute(cmd, args, None)?;
+        into_error!(
+            response.data,
+            format!("The response to {cmd:?} has no data")
+        )
+    }
+
+    /// Authenticates with an `OAuth2` access token, which some commands and events require
+    ///
+    /// The token is kept, and used to authenticate again every time the client reconnects, so that authenticated
+    /// subscriptions keep working. Should Discord reject it by then, for example because it expired, it is forgotten
+    /// and the handler set with [`Client::on_access_token_expired`] is called.
+    ///
+    /// # Errors
+    /// - [`DiscordError::AuthenticationFailed`] if Discord rejects the token
+    /// - See [`DiscordError`] for more info
+    pub fn authenticate<S>(&mut self, access_token: S) -> Result<AuthenticateResponse>
+    where
+        S: Into<String>,
+    {
+        let access_token = access_token.into();
+        let args = AuthenticateArgs::new().access_token(access_token.clone());
+        let response: Payload<AuthenticateResponse> =
+            self.execute(Command::Authenticate, args, None)?;
+        let response = into_error!(
+            response.data,
+            String::from("The response to AUTHENTICATE has no data")
+        )?;
+        self.connection_manager.set_access_token(Some(access_token));
+        self.connection_manager
+            .set_granted_scopes(Some(response.scopes.iter().copied().collect()));
 
-        Ok(())
+        Ok(response)
     }
 
-    fn unwrap_thread(&mut self) -> Result<ClientThread> {
-        if let Some(thread) = self.thread.take() {
-            let thread = Arc::try_unwrap(thread).map_err(|_| DiscordError::ThreadInUse)?;
-
-            Ok(thread)
-        } else {
-            Err(DiscordError::NotStarted)
-        }
+    /// Asks the user to authorize the application with the given scopes, returning an `OAuth2` authorization code
+    ///
+    /// The code is exchanged for an access token through Discord's HTTP API, which takes the application's client secret,
+    /// so that is best left to a server. The access token can then be passed to [`Client::authenticate`].
+    ///
+    /// This blocks until the user answers Discord's prompt, for up to 5 minutes, while other commands carry on as usual.
+    ///
+    /// # Errors
+    /// - [`DiscordError::AuthorizationFailed`] if the user declines, or Discord rejects the request
+    /// - [`DiscordError::TimeoutError`] if the user does not answer in time
+    /// - See [`DiscordError`] for more info
+    pub fn authorize<S>(&mut self, scopes: S) -> Result<String>
+    where
+        S: IntoIterator<Item = OAuthScope>,
+    {
+        let args = AuthorizeArgs::new(self.connection_manager.client_id(), scopes);
+        let response: Payload<AuthorizeResponse> = self.execute(Command::Authorize, args, None)?;
+        let response = into_error!(
+            response.data,
+            String::from("The response to AUTHORIZE has no data")
+        )?;
+        into_error!(
+            response.code,
+            String::from("The response to AUTHORIZE has no code")
+        )
     }
 
     #[must_use]
-    /// Check if the client is ready
-    pub fn is_ready() -> bool {
-        crate::READY.load(Ordering::Relaxed)
+    /// The scopes granted by the last successful [`Client::authenticate`], or `None` if it has not been called
+    ///
+    /// Once known, commands and subscriptions that need a scope outside of these fail with [`DiscordError::MissingScope`]
+    /// before they are sent. See [`Command::required_scopes`] and [`Event::required_scopes`].
+    pub fn granted_scopes(&self) -> Option<Vec<OAuthScope>> {
+        self.connection_manager
+            .granted_scopes()
+            .map(|scopes| scopes.into_iter().collect())
     }
 
-    fn execute<A, E>(&mut self, cmd: Command, args: A, evt: Option<Event>) -> Result<Payload<E>>
+    /// Authenticates with the access token kept in `store` for this application, returning `None` if there is none
+    ///
+    /// Expired tokens are not sent, and are left in the store for the application to refresh.
+    ///
+    /// # Errors
+    /// - [`DiscordError::AuthenticationFailed`] if Discord rejects the token
+    /// - The store could not be read
+    /// - See [`DiscordError`] for more info
+    #[cfg(feature = "keyring")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "keyring")))]
+    pub fn authenticate_stored<S>(&mut self, store: &S) -> Result<Option<AuthenticateResponse>>
     where
-        A: Serialize + Send + Sync,
-        E: Serialize + DeserializeOwned + Send + Sync,
+        S: TokenStore + ?Sized,
     {
-        if !crate::READY.load(Ordering::Relaxed) {
-            return Err(DiscordError::NotStarted);
+        let tokens = store.load(self.connection_manager.client_id())?;
+        match tokens {
+            Some(tokens) if !tokens.is_expired() => {
+                self.authenticate(tokens.access_token).map(Some)
+            }
+            _ => Ok(None),
         }
+    }
 
-        trace!("Executing command: {:?}", cmd);
-
-        let message = Message::new(
-            OpCode::Frame,
-            Payload::with_nonce(cmd, Some(args), None, evt),
-        );
-        self.connection_manager.send(message?)?;
-        let Message { payload, .. } = self.connection_manager.recv()?;
-        let response: Payload<E> = serde_json::from_str(&payload)?;
+    /// Stops authenticating again on reconnects, with the access token passed to [`Client::authenticate`]
+    ///
+    /// The current connection stays authenticated.
+    pub fn forget_access_token(&self) {
+        self.connection_manager.set_access_token(None);
+    }
 
-        match response.evt {
-            Some(Event::Error) => Err(DiscordError::SubscriptionFailed),
-            _ => Ok(response),
-        }
+    /// Sets the handler called when Discord rejects the stored access token on reconnecting, replacing any previous one
+    ///
+    /// The client carries on without authentication. The handler runs on its own thread, so it can fetch a new token
+    /// and pass it to [`Client::authenticate`].
+    pub fn on_access_token_expired<F>(&self, handler: F)
+    where
+        F: Fn(ErrorEvent) + Send + Sync + 'static,
+    {
+        self.connection_manager
+            .set_token_expired_hook(Some(Arc::new(handler)));
     }
 
-    /// Set the users current activity
+    /// Gets the user's voice settings, including the input and output devices to pick from
     ///
     /// # Errors
+    /// - [`DiscordError::MissingScope`] if the access token lacks the `rpc.voice.read` scope
     /// - See [`DiscordError`] for more info
-    pub fn set_activity<F>(&mut self, f: F) -> Result<Payload<Activity>>
-    where
-        F: FnOnce(Activity) -> Activity,
-    {
-        self.execute(Command::SetActivity, SetActivityArgs::new(f), None)
+    #[cfg(feature = "voice")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "voice")))]
+    pub fn get_voice_settings(&mut self) -> Result<VoiceSettings> {
+        self.send_command(
+            Command::GetVoiceSettings,
+            Value::Object(serde_json::Map::new()),
+        )
     }
 
-    /// Clear the users current activity
+    /// Gets the SKUs of the application in the Discord store
     ///
     /// # Errors
     /// - See [`DiscordError`] for more info
-    pub fn clear_activity(&mut self) -> Result<Payload<Activity>> {
-        self.execute(Command::SetActivity, SetActivityArgs::default(), None)
+    #[cfg(feature = "commerce")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "commerce")))]
+    pub fn get_skus(&mut self) -> Result<Vec<Sku>> {
+        self.send_command(Command::GetSkus, Value::Object(serde_json::Map::new()))
     }
 
-    // NOTE: Not sure what the actual response values of
-    //       SEND_ACTIVITY_JOIN_INVITE and CLOSE_ACTIVITY_REQUEST are,
-    //       they are not documented.
-    /// Send an invite to a user to join a game
+    /// Gets the user's entitlements to the application's SKUs
     ///
     /// # Errors
     /// - See [`DiscordError`] for more info
-    pub fn send_activity_join_invite(&mut self, user_id: u64) -> Result<Payload<Value>> {
-        self.execute(
-            Command::SendActivityJoinInvite,
-            SendActivityJoinInviteArgs::new(user_id),
-            None,
+    #[cfg(feature = "commerce")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "commerce")))]
+    pub fn get_entitlements(&mut self) -> Result<Vec<Entitlement>> {
+        self.send_command(
+            Command::GetEntitlements,
+            Value::Object(serde_json::Map::new()),
         )
     }
 
-    /// Close request to join a game
+    /// Checks whether the user owns the SKU, such as a DLC, according to their entitlements
     ///
     /// # Errors
     /// - See [`DiscordError`] for more info
-    pub fn close_activity_request(&mut self, user_id: u64) -> Result<Payload<Value>> {
-        self.execute(
-            Command::CloseActivityRequest,
-            CloseActivityRequestArgs::new(user_id),
-            None,
-        )
+    #[cfg(feature = "commerce")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "commerce")))]
+    pub fn has_entitlement<I: Into<Snowflake>>(&mut self, sku_id: I) -> Result<bool> {
+        let sku_id = sku_id.into();
+        let entitlements = self.get_entitlements()?;
+
+        Ok(entitlements
+            .iter()
+            .any(|entitlement| entitlement.grants(sku_id)))
     }
 
     /// Subscribe to a given event
     ///
+    /// Once the last handler for the event is removed, the client unsubscribes from it,
+    /// so the subscription has to be made again for handlers registered later.
+    ///
     /// # Errors
     /// - See [`DiscordError`] for more info
     pub fn subscribe<F>(&mut self, evt: Event, f: F) -> Result<Payload<Subscription>>
     where
         F: FnOnce(SubscriptionArgs) -> SubscriptionArgs,
     {
-        self.execute(Command::Subscribe, f(SubscriptionArgs::new()), Some(evt))
+        self.execute_subscription(Command::Subscribe, evt, f(SubscriptionArgs::new()))
     }
 
     /// Unsubscribe from a given event
@@ -280,11 +1717,61 @@ impl Client {
     where
         F: FnOnce(SubscriptionArgs) -> SubscriptionArgs,
     {
-        self.execute(Command::Unsubscribe, f(SubscriptionArgs::new()), Some(evt))
+        self.execute_subscription(Command::Unsubscribe, evt, f(SubscriptionArgs::new()))
+    }
+
+    /// Subscribes to each of the given events that can be subscribed to, skipping the others.
+    ///
+    /// This is intended for debugging tools that log everything, so every subscription is attempted even if some fail,
+    /// such as those that need a channel ID. The outcome of each subscription is returned alongside its event.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use discord_presence::{Client, Event};
+    /// let mut drpc = Client::new(1003450375732482138);
+    /// drpc.on_any_event(|ctx| println!("{:?}: {:?}", ctx.kind, ctx.event))
+    ///     .persist();
+    /// drpc.start();
+    ///
+    /// for (event, result) in drpc.subscribe_all(Event::all()) {
+    ///     if let Err(why) = result {
+    ///         eprintln!("Could not subscribe to {event:?}: {why}");
+    ///     }
+    /// }
+    /// ```
+    pub fn subscribe_all<I>(&mut self, events: I) -> Vec<(Event, Result<Payload<Subscription>>)>
+    where
+        I: IntoIterator<Item = Event>,
+    {
+        events
+            .into_iter()
+            .filter(|event| event.is_subscribable())
+            .map(|event| (event, self.subscribe(event, |args| args)))
+            .collect()
+    }
+
+    fn execute_subscription(
+        &mut self,
+        cmd: Command,
+        evt: Event,
+        args: SubscriptionArgs,
+    ) -> Result<Payload<Subscription>> {
+        let result = self.execute(cmd, args.clone(), Some(evt));
+        if let Ok(_) | Err(DiscordError::Queued) = result {
+            self.connection_manager.track_subscription(cmd, evt, args);
+        }
+
+        result
     }
 
     /// Listens for a given event, and returns a handle that unregisters the listener when it is dropped.
     ///
+    /// Listening for an activity event subscribes to it automatically. See [`Client::set_auto_subscribe`].
+    ///
+    /// Handlers run on their own thread, without any of the client's locks held,
+    /// so they can safely send commands such as [`Client::set_activity`] through a clone of the client.
+    ///
     /// # Examples
     ///
     /// ```no_run
@@ -347,9 +1834,198 @@ impl Client {
     where
         F: Fn(EventContext) + 'static + Send + Sync,
     {
+        self.subscribe_automatically(event);
         self.event_handler_registry.register(event, handler)
     }
 
+    /// Listens for a given event, attaching a label to the handler.
+    ///
+    /// The label is reported by [`Client::handlers`], which helps track down leaked handlers.
+    /// Behaves like [`Client::on_event`] otherwise.
+    pub fn on_event_labeled<S, F>(&self, event: Event, label: S, handler: F) -> EventCallbackHandle
+    where
+        S: Into<String>,
+        F: Fn(EventContext) + 'static + Send + Sync,
+    {
+        self.subscribe_automatically(event);
+        self.event_handler_registry
+            .register_labeled(event, label, handler)
+    }
+
+    /// Listens for a given event with a handler that can fail.
+    ///
+    /// Errors returned by the handler are logged and passed on to the [`Event::Error`] handlers, such as those registered
+    /// with [`Client::on_error`], with a message naming the event. Behaves like [`Client::on_event`] otherwise.
+    ///
+    /// # Examples
+    /// ```
+    /// # use discord_presence::{Client, Event, models::EventData};
+    /// let mut drpc = Client::new(1003450375732482138);
+    ///
+    /// drpc.on_event_fallible(Event::ActivityJoin, |ctx| {
+    ///     let EventData::ActivityJoin(event) = ctx.event else {
+    ///         return Ok(());
+    ///     };
+    ///     let lobby: u64 = event.secret.unwrap_or_default().parse()?;
+    ///     println!("Joining lobby {lobby}");
+    ///     Ok::<_, std::num::ParseIntError>(())
+    /// })
+    /// .persist();
+    /// ```
+    pub fn on_event_fallible<F, E>(&self, event: Event, handler: F) -> EventCallbackHandle
+    where
+        F: Fn(EventContext) -> std::result::Result<(), E> + 'static + Send + Sync,
+        E: std::fmt::Display,
+    {
+        self.subscribe_automatically(event);
+        self.event_handler_registry
+            .register_fallible(event, handler)
+    }
+
+    /// Listens for every event, regardless of its kind.
+    ///
+    /// The fired event is available as [`EventContext::kind`].
+    /// Behaves like [`Client::on_event`] otherwise.
+    pub fn on_any_event<F>(&self, handler: F) -> EventCallbackHandle
+    where
+        F: Fn(EventContext) + 'static + Send + Sync,
+    {
+        self.event_handler_registry.register_wildcard(handler)
+    }
+
+    /// Listens for every change of the connection state, along with why it changed.
+    ///
+    /// This covers connecting, completing the handshake, losing the connection, retrying and stopping,
+    /// which is simpler than listening for the individual events when mirroring the connection status in a UI.
+    /// Handlers are called in the order the changes happened, on a thread of their own.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use discord_presence::{Client, event_handler::ConnectionState};
+    /// let mut drpc = Client::new(1003450375732482138);
+    ///
+    /// drpc.on_state_change(|change| {
+    ///     if change.new == ConnectionState::Connected {
+    ///         println!("Connected to Discord");
+    ///     }
+    /// })
+    /// .persist();
+    /// ```
+    pub fn on_state_change<F>(&self, handler: F) -> EventCallbackHandle
+    where
+        F: Fn(StateChange) + 'static + Send + Sync,
+    {
+        self.event_handler_registry.register_state_handler(handler)
+    }
+
+    /// Sets how the handlers for `event` are run. Defaults to [`DispatchMode::Concurrent`].
+    ///
+    /// With [`DispatchMode::Sequential`], the handlers registered for `event` run one after another on a single thread,
+    /// in the order they were registered, which suits handlers that share mutable state. A slow handler then holds up
+    /// later events of the same kind. Handlers registered with [`Client::on_any_event`] always run concurrently.
+    pub fn set_dispatch_mode(&self, event: Event, mode: DispatchMode) {
+        self.event_handler_registry.set_dispatch_mode(event, mode);
+    }
+
+    #[must_use]
+    /// How the handlers for `event` are run. See [`Client::set_dispatch_mode`].
+    pub fn dispatch_mode(&self, event: Event) -> DispatchMode {
+        self.event_handler_registry.dispatch_mode(event)
+    }
+
+    /// Sets how long event handlers may run before they are reported as slow, or stops timing them if `None`.
+    /// Defaults to one second.
+    ///
+    /// Every handler runs on a thread of its own, so handlers that take long to finish pile up threads as events keep
+    /// arriving. Slow handlers are logged as a warning, and passed to the hook set with [`Client::on_slow_handler`],
+    /// once while they are still running past the threshold and again once they finish.
+    pub fn set_slow_handler_threshold(&self, threshold: Option<Duration>) {
+        self.event_handler_registry
+            .set_slow_handler_threshold(threshold);
+    }
+
+    /// Calls `hook` whenever an event handler runs for longer than the threshold set with
+    /// [`Client::set_slow_handler_threshold`], such as to record it in metrics. Replaces any previous hook.
+    ///
+    /// Handlers that never return are still reported, with [`SlowHandler::running`] set.
+    pub fn on_slow_handler<F>(&self, hook: F)
+    where
+        F: Fn(&SlowHandler) + 'static + Send + Sync,
+    {
+        self.event_handler_registry
+            .set_slow_handler_hook(Some(Arc::new(hook)));
+    }
+
+    /// Stops calling the hook set with [`Client::on_slow_handler`]. Slow handlers are still logged.
+    pub fn remove_slow_handler_hook(&self) {
+        self.event_handler_registry.set_slow_handler_hook(None);
+    }
+
+    /// Listens for pings from Discord.
+    ///
+    /// Pings are answered with a pong by the client before the handler is called,
+    /// so this is only needed to keep track of whether the connection is alive.
+    pub fn on_ping<F>(&self, handler: F) -> EventCallbackHandle
+    where
+        F: Fn(Ping) + 'static + Send + Sync,
+    {
+        self.event_handler_registry.register_ping_handler(handler)
+    }
+
+    /// Listens for a given event, only calling the handler when `filter` returns `true`.
+    ///
+    /// Behaves like [`Client::on_event`] otherwise.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use discord_presence::{models::EventData, Client, Event};
+    /// let mut drpc = Client::new(1003450375732482138);
+    /// let _join = drpc.on_event_filtered(
+    ///     Event::ActivityJoin,
+    ///     |ctx| matches!(&ctx.event, EventData::ActivityJoin(e) if e.secret.as_deref() == Some("party-1")),
+    ///     |_ctx| println!("Someone joined party-1!"),
+    /// );
+    /// ```
+    pub fn on_event_filtered<P, F>(
+        &self,
+        event: Event,
+        filter: P,
+        handler: F,
+    ) -> EventCallbackHandle
+    where
+        P: Fn(&EventContext) -> bool + 'static + Send + Sync,
+        F: Fn(EventContext) + 'static + Send + Sync,
+    {
+        self.subscribe_automatically(event);
+        self.event_handler_registry
+            .register_filtered(event, filter, handler)
+    }
+
+    #[must_use]
+    /// The number of handlers registered for the given event
+    ///
+    /// This does not include handlers registered with [`Client::on_any_event`].
+    pub fn handler_count(&self, event: Event) -> usize {
+        self.event_handler_registry.count(event)
+    }
+
+    #[must_use]
+    /// Lists every registered handler, including the labels supplied with [`Client::on_event_labeled`]
+    pub fn handlers(&self) -> Vec<HandlerInfo> {
+        self.event_handler_registry.info()
+    }
+
+    // The number of removed handlers is informational, so ignoring it is fine
+    #[allow(clippy::must_use_candidate)]
+    /// Removes all handlers for the given event, returning how many were removed.
+    ///
+    /// Any [`EventCallbackHandle`]s for the removed handlers become no-ops.
+    pub fn clear_handlers(&self, event: Event) -> usize {
+        self.event_handler_registry.clear(event)
+    }
+
     /// Block the current thread until the event is fired
     ///
     /// Returns the context the event was fired in
@@ -358,9 +2034,6 @@ impl Client {
     ///
     /// # Errors
     /// - Channel disconnected
-    ///
-    /// # Panics
-    /// - Panics if the channel is disconnected for whatever reason.
     pub fn block_until_event(&mut self, event: Event) -> Result<crate::event_handler::Context> {
         // TODO: Use bounded channel
         let (tx, rx) = crossbeam_channel::unbounded::<crate::event_handler::Context>();
@@ -368,7 +2041,7 @@ impl Client {
         let handler = move |info| {
             // dbg!("Blocked until at ", std::time::SystemTime::now());
             if let Err(e) = tx.send(info) {
-                error!("{e}");
+                error!(target: DISPATCH, "{e}");
             }
         };
 
@@ -391,9 +2064,41 @@ impl Client {
     event_handler_function!(on_activity_join_request, Event::ActivityJoinRequest);
 
     event_handler_function!(on_activity_spectate, Event::ActivitySpectate);
+
+    event_handler_function!(on_activity_invite, Event::ActivityInvite);
+
+    #[cfg(feature = "messages")]
+    event_handler_function!(on_message_create, Event::MessageCreate);
+
+    #[cfg(feature = "messages")]
+    event_handler_function!(on_message_update, Event::MessageUpdate);
+
+    #[cfg(feature = "messages")]
+    event_handler_function!(on_message_delete, Event::MessageDelete);
+
+    #[cfg(feature = "voice")]
+    event_handler_function!(on_speaking_start, Event::SpeakingStart);
+
+    #[cfg(feature = "voice")]
+    event_handler_function!(on_speaking_stop, Event::SpeakingStop);
+
+    #[cfg(feature = "voice")]
+    event_handler_function!(on_voice_state_create, Event::VoiceStateCreate);
+
+    #[cfg(feature = "voice")]
+    event_handler_function!(on_voice_state_update, Event::VoiceStateUpdate);
+
+    #[cfg(feature = "voice")]
+    event_handler_function!(on_voice_state_delete, Event::VoiceStateDelete);
+
+    #[cfg(feature = "notifications")]
+    event_handler_function!(on_notification_create, Event::NotificationCreate);
+
+    #[cfg(feature = "voice")]
+    event_handler_function!(on_voice_connection_status, Event::VoiceConnectionStatus);
 }
 
-#[cfg(test)]
+#[cfg(all(test, not(loom)))]
 mod tests {
     use super::*;
 
@@ -405,4 +2110,272 @@ mod tests {
 
         assert!(Client::is_ready());
     }
+
+    #[test]
+    #[cfg(feature = "test-util")]
+    fn simulated_events_are_tracked() {
+        let client = Client::new(1_003_450_375_732_482_138);
+        client.simulate_event(
+            Event::ActivityJoinRequest,
+            serde_json::json!({ "user": { "id": "175928847299117063", "username": "test" } }),
+        );
+        client.simulate_event(
+            Event::Error,
+            serde_json::json!({ "code": 4000, "message": "Invalid Client ID" }),
+        );
+
+        assert_eq!(
+            client.join_requests()[0].user.id,
+            Some(Snowflake::new(175_928_847_299_117_063))
+        );
+        assert_eq!(client.last_error().and_then(|error| error.code), Some(4000));
+    }
+
+    #[test]
+    fn config_applies_settings() {
+        let config = ClientConfig::new(1_003_450_375_732_482_138)
+            .activity_debounce(Some(Duration::from_secs(1)))
+            .ipc_dir(Some(PathBuf::from("/run/user/1000")));
+
+        let client = Client::with_config(config);
+        assert_eq!(client.ipc_dir(), PathBuf::from("/run/user/1000"));
+        assert_eq!(
+            client.activity_debounce.lock().window,
+            Some(Duration::from_secs(1))
+        );
+    }
+
+    #[test]
+    fn config_applies_hooks() {
+        let config = ClientConfig::new(1_003_450_375_732_482_138)
+            .localizer(|key, _| (key == "menu").then(|| String::from("Main menu")))
+            .nonce_generator(|| String::from("nonce"))
+            .clear_on_drop(true);
+        let debug = format!("{config:?}");
+        assert!(debug.contains("localizer: true"));
+        assert!(debug.contains("nonce_generator: true"));
+
+        let client = Client::with_config(config);
+        assert!(client.localizer.lock().is_some());
+        assert!(client.clear_on_drop.load(Ordering::Acquire));
+    }
+
+    #[test]
+    fn debounces_activity_updates() {
+        let mut client = Client::new(1_003_450_375_732_482_138);
+        client.set_activity_debounce(Some(Duration::from_secs(60)));
+        client.activity_debounce.lock().last_sent = Some(Instant::now());
+
+        assert!(matches!(
+            client.set_activity(|a| a.state("first")),
+            Err(DiscordError::Debounced)
+        ));
+        assert!(matches!(
+            client.clear_activity(),
+            Err(DiscordError::Debounced)
+        ));
+
+        let pending = client.activity_debounce.lock().pending.take();
+        assert_eq!(pending, Some((SetActivityArgs::default(), Priority::High)));
+    }
+
+    #[test]
+    fn unchanged_activities_are_only_skipped_when_enabled() {
+        let mut client = Client::new(1_003_450_375_732_482_138);
+        let mut hasher = DefaultHasher::new();
+        SetActivityArgs::new(|a| a.state("same")).hash(&mut hasher);
+        *client.last_activity.lock() = Some((client.connection_generation(), hasher.finish()));
+
+        assert!(matches!(
+            client.set_activity(|a| a.state("same")),
+            Err(DiscordError::NotStarted)
+        ));
+
+        client.set_skip_unchanged_activity(true);
+        assert!(matches!(
+            client.set_activity(|a| a.state("same")),
+            Err(DiscordError::Unchanged)
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "exit-hook")]
+    fn exit_clears_bypass_the_debounce() {
+        let client = Client::new(1_003_450_375_732_482_138);
+        client.set_activity_debounce(Some(Duration::from_secs(60)));
+        {
+            let mut debounce = client.activity_debounce.lock();
+            debounce.last_sent = Some(Instant::now());
+            debounce.pending = Some((SetActivityArgs::new(|a| a.state("stale")), Priority::Normal));
+        }
+
+        client.clear_before_stopping();
+        assert!(client.activity_debounce.lock().pending.is_none());
+    }
+
+    #[test]
+    fn direct_updates_replace_pending_ones() {
+        let mut client = Client::new(1_003_450_375_732_482_138);
+        client.set_activity_debounce(Some(Duration::from_secs(60)));
+        {
+            let mut debounce = client.activity_debounce.lock();
+            debounce.last_sent = Instant::now().checked_sub(Duration::from_secs(120));
+            debounce.pending = Some((SetActivityArgs::new(|a| a.state("stale")), Priority::Normal));
+        }
+
+        // Sent directly as the window has passed, before the flush thread wakes up
+        assert!(matches!(
+            client.set_activity(|a| a.state("newer")),
+            Err(DiscordError::NotStarted)
+        ));
+        assert!(client.activity_debounce.lock().pending.is_none());
+    }
+
+    #[test]
+    fn debug_redacts_secrets() {
+        let mut client = Client::new(1_003_450_375_732_482_138);
+        client.set_offline_queue(8, Duration::from_secs(60));
+        let _ready = client.on_ready(|_| {});
+
+        assert!(matches!(
+            client.set_activity(|a| a.party(|p| p.id("party")).secrets(|s| s.join("hunter2"))),
+            Err(DiscordError::Queued)
+        ));
+
+        let debug = format!("{client:?}");
+        assert!(debug.contains("client_id: 1003450375732482138"));
+        assert!(debug.contains("queued_commands: 1"));
+        assert!(debug.contains("handlers: {Ready: 1}"));
+        assert!(!debug.contains("hunter2"));
+    }
+
+    #[test]
+    fn reports_presence_drift() {
+        let client = Client::new(1_003_450_375_732_482_138);
+        assert_eq!(client.presence_drift(), None);
+
+        let activity = Activity::new()
+            .state("In a match")
+            .party(|p| p.id("party").size((1, 4)));
+        let args = SetActivityArgs::new(|_| activity.clone());
+        client.connection_manager.record_activity(args);
+        let drift = client.presence_drift().unwrap();
+        assert_eq!(drift.intended.as_ref(), Some(&activity));
+        assert_eq!(drift.shown, None);
+
+        let acknowledgement = |data: Option<Value>, evt: Option<Event>| Payload {
+            cmd: Command::SetActivity,
+            args: None,
+            data,
+            evt,
+            nonce: None,
+        };
+        // Discord fills in fields of its own, which are not drift
+        let echoed = serde_json::json!({
+            "state": "In a match",
+            "party": { "id": "party", "size": [1, 4] },
+            "name": "Test Game",
+            "application_id": "1003450375732482138",
+            "type": 0,
+        });
+        client
+            .connection_manager
+            .activity_acknowledged(&acknowledgement(Some(echoed), None));
+        assert_eq!(
+            client.shown_activity().and_then(|shown| shown.state),
+            Some(String::from("In a match"))
+        );
+        assert_eq!(client.presence_drift(), None);
+
+        client
+            .connection_manager
+            .activity_acknowledged(&acknowledgement(
+                Some(serde_json::json!({ "code": 4000, "message": "Invalid payload" })),
+                Some(Event::Error),
+            ));
+        assert_eq!(client.presence_drift(), None);
+
+        client
+            .connection_manager
+            .activity_acknowledged(&acknowledgement(None, None));
+        assert_eq!(client.shown_activity(), None);
+        assert!(client.presence_drift().is_some());
+    }
+
+    #[test]
+    fn validates_client_ids() {
+        const ID: u64 = 1_003_450_375_732_482_138;
+
+        assert_eq!(ID.into_client_id().unwrap(), ID);
+        assert_eq!(NonZeroU64::new(ID).unwrap().into_client_id().unwrap(), ID);
+        assert_eq!(" 1003450375732482138\n".into_client_id().unwrap(), ID);
+        assert_eq!(ID.to_string().into_client_id().unwrap(), ID);
+
+        for invalid in ["", "0", "42", "-1003450375732482138", "my-app"] {
+            assert!(
+                matches!(
+                    invalid.into_client_id(),
+                    Err(DiscordError::MalformedClientId(ref id)) if id == invalid
+                ),
+                "{invalid:?} was accepted"
+            );
+        }
+        assert!(u64::MAX.into_client_id().is_err());
+    }
+
+    #[test]
+    fn activity_updates_resolve() {
+        struct Unpark(Thread);
+
+        impl std::task::Wake for Unpark {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+
+        let client = Client::new(1_003_450_375_732_482_138);
+        let mut update = client.set_activity_async(|a| a.state("test"), Duration::from_secs(5));
+
+        let waker = Waker::from(Arc::new(Unpark(thread::current())));
+        let mut cx = TaskContext::from_waker(&waker);
+        let result = loop {
+            match Pin::new(&mut update).poll(&mut cx) {
+                Poll::Ready(result) => break result,
+                Poll::Pending => thread::park_timeout(Duration::from_secs(5)),
+            }
+        };
+
+        // Without an offline queue, updates fail until the client is started
+        assert!(matches!(result, Err(DiscordError::NotStarted)));
+    }
+
+    #[test]
+    fn timed_out_commands_are_cancelled() {
+        let (tx, rx) = crossbeam_channel::bounded(1);
+        let future = CommandFuture::spawn(Duration::from_millis(10), move |cancelled| {
+            // Stands in for a command whose response never arrives
+            while !cancelled.load(Ordering::Acquire) {
+                thread::sleep(Duration::from_millis(1));
+            }
+            tx.send(()).unwrap();
+            Err::<(), _>(DiscordError::Cancelled)
+        });
+
+        rx.recv_timeout(Duration::from_secs(1)).unwrap();
+        // The result is stored just after the command is cancelled
+        let deadline = Instant::now() + Duration::from_secs(1);
+        while future.state.lock().result.is_none() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert!(matches!(
+            future.state.lock().result,
+            Some(Err(DiscordError::TimeoutError(_)))
+        ));
+    }
+
+    #[test]
+    fn locale_defaults_until_ready() {
+        let client = Client::new(1_003_450_375_732_482_138);
+        assert_eq!(client.locale(), DEFAULT_LOCALE);
+    }
 }
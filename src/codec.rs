@@ -0,0 +1,99 @@
+use crate::{targets::CODEC, Result};
+use serde::de::DeserializeOwned;
+
+/// Decodes incoming payloads
+///
+/// Every frame received from Discord goes through here, so it is kept swappable for faster parsers.
+pub(crate) trait Codec {
+    /// Deserializes a JSON payload
+    fn decode<T: DeserializeOwned>(payload: &str) -> Result<T>;
+}
+
+/// Decodes payloads with `serde_json`
+#[cfg_attr(feature = "simd-json", allow(dead_code))]
+pub(crate) struct SerdeJson;
+
+impl Codec for SerdeJson {
+    fn decode<T: DeserializeOwned>(payload: &str) -> Result<T> {
+        Ok(serde_json::from_str(payload)?)
+    }
+}
+
+/// Decodes payloads with `simd-json`
+#[cfg(feature = "simd-json")]
+pub(crate) struct SimdJson;
+
+#[cfg(feature = "simd-json")]
+thread_local! {
+    /// The payload being parsed and the parser's own buffers, reused so decoding stops allocating once they fit
+    static SCRATCH: std::cell::RefCell<(Vec<u8>, simd_json::Buffers)> =
+        std::cell::RefCell::new((Vec::new(), simd_json::Buffers::default()));
+}
+
+#[cfg(feature = "simd-json")]
+impl Codec for SimdJson {
+    fn decode<T: DeserializeOwned>(payload: &str) -> Result<T> {
+        // simd-json parses in place, and some payloads are decoded more than once, so a copy is parsed instead
+        SCRATCH.with(|scratch| {
+            let (ref mut bytes, ref mut buffers) = *scratch.borrow_mut();
+            bytes.clear();
+            bytes.extend_from_slice(payload.as_bytes());
+            Ok(simd_json::serde::from_slice_with_buffers(bytes, buffers)?)
+        })
+    }
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(feature = "simd-json")] {
+        type Json = SimdJson;
+    } else {
+        type Json = SerdeJson;
+    }
+}
+
+/// Deserializes a JSON payload with the codec selected by the enabled features
+pub(crate) fn decode<T: DeserializeOwned>(payload: &str) -> Result<T> {
+    Json::decode(payload).map_err(|why| {
+        debug!(target: CODEC, "Failed to decode {} byte payload: {why}", payload.len());
+        why
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{payload::Payload, Command};
+    use serde_json::Value;
+
+    const PAYLOAD: &str = r#"{"cmd":"DISPATCH","evt":"READY","data":{"v":1},"nonce":null}"#;
+
+    fn decodes_payload<C: Codec>() {
+        let payload: Payload<Value> = C::decode(PAYLOAD).unwrap();
+
+        assert_eq!(payload.cmd, Command::Dispatch);
+        assert_eq!(payload.data, Some(serde_json::json!({ "v": 1 })));
+        assert!(C::decode::<Payload<Value>>("{").is_err());
+    }
+
+    #[test]
+    fn serde_json_decodes_payload() {
+        decodes_payload::<SerdeJson>();
+    }
+
+    #[cfg(feature = "simd-json")]
+    #[test]
+    fn simd_json_decodes_payload() {
+        decodes_payload::<SimdJson>();
+    }
+
+    #[cfg(feature = "simd-json")]
+    #[test]
+    fn simd_json_reuses_its_buffer() {
+        let buffer = || SCRATCH.with(|scratch| scratch.borrow().0.as_ptr());
+
+        SimdJson::decode::<Payload<Value>>(PAYLOAD).unwrap();
+        let first = buffer();
+        SimdJson::decode::<Payload<Value>>(PAYLOAD).unwrap();
+        assert_eq!(buffer(), first);
+    }
+}
@@ -1,110 +1,910 @@
+#[cfg(feature = "wsl")]
+use crate::wsl::Bridge;
 use crate::{
+    codec,
+    diagnostics::{ConnectDiagnostics, ConnectFailure, Sandbox},
     error::{DiscordError, Result},
-    models::message::{Message, OpCode},
+    models::{
+        message::{Message, OpCode},
+        payload::Payload,
+        CloseCode, ErrorEvent, Event,
+    },
+    targets::CONNECTION,
     utils,
 };
 use bytes::BytesMut;
-use serde_json::json;
+use crossbeam_channel::unbounded;
+use serde::Serialize;
+use serde_json::{json, Value as JsonValue};
 use std::{
-    io::{Read, Write},
+    io::{self, Read, Write},
+    iter,
     marker::Sized,
-    path::PathBuf,
-    thread, time,
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
 };
 
-/// Wait for a non-blocking connection until it's complete.
+/// Directories within the IPC path where Discord, or a reimplementation of it such as arRPC, may place its socket
+const COMPAT_SUBDIRECTORIES: [&str; 3] = [
+    "app/com.discordapp.Discord",
+    "app/dev.vencord.Vesktop",
+    "snap.discord",
+];
+
+/// Number of socket names to try in compatibility mode, as servers pick the first free one
+pub(super) const COMPAT_SOCKET_COUNT: u8 = 10;
+
+/// How long a candidate socket has to respond to the handshake when probed
+pub(super) const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// The RPC protocol version sent in the handshake, unless configured otherwise
+pub const RPC_VERSION: u32 = 1;
+
+/// Where to look for the socket to connect to, and how to handshake with it
+#[derive(Debug, Clone)]
+pub struct ConnectOptions {
+    /// Whether to try every socket name and location used by third-party RPC servers
+    pub compatibility: bool,
+    /// Directory containing the socket, overriding [`Connection::ipc_path`]
+    pub ipc_dir: Option<PathBuf>,
+    /// The RPC protocol version to ask for in the handshake
+    pub rpc_version: u32,
+}
+
+impl Default for ConnectOptions {
+    fn default() -> Self {
+        Self {
+            compatibility: false,
+            ipc_dir: None,
+            rpc_version: RPC_VERSION,
+        }
+    }
+}
+
+/// Turns Discord closing the connection during the handshake into the error it stands for
+fn handshake_error(why: DiscordError, client_id: u64, rpc_version: u32) -> DiscordError {
+    let DiscordError::Closed(ref event) = why else {
+        return why;
+    };
+
+    match event.close_code() {
+        Some(CloseCode::InvalidClientId) => DiscordError::InvalidClientId(client_id),
+        Some(CloseCode::InvalidVersion) => {
+            DiscordError::UnsupportedProtocol(format!("RPC version {rpc_version} is not supported"))
+        }
+        _ => why,
+    }
+}
+
+/// Checks that the handshake response is a `READY` event,
+/// which third-party servers in compatibility mode may leave out
+fn validate_handshake(message: &Message, client_id: u64, options: &ConnectOptions) -> Result<()> {
+    if message.opcode != OpCode::Frame {
+        return Err(DiscordError::UnexpectedHandshake(format!(
+            "expected a frame in response to the handshake, got {:?}",
+            message.opcode
+        )));
+    }
+
+    let payload: Payload<JsonValue> = codec::decode(&message.payload).map_err(|_| {
+        DiscordError::UnexpectedHandshake(String::from(
+            "the handshake response is not a valid payload",
+        ))
+    })?;
+
+    match (payload.evt, payload.data) {
+        (Some(Event::Ready), Some(_)) => Ok(()),
+        _ if options.compatibility => Ok(()),
+        (Some(Event::Error), data) => {
+            let error = data
+                .and_then(|data| serde_json::from_value::<ErrorEvent>(data).ok())
+                .unwrap_or_default();
+            // Only an explicit rejection is fatal, anything else may go differently on the next attempt
+            match error.code.and_then(CloseCode::from_code) {
+                Some(CloseCode::InvalidClientId) => Err(DiscordError::InvalidClientId(client_id)),
+                Some(CloseCode::InvalidVersion) => Err(DiscordError::UnsupportedProtocol(format!(
+                    "RPC version {} is not supported",
+                    options.rpc_version
+                ))),
+                _ => Err(DiscordError::UnexpectedHandshake(format!(
+                    "the handshake failed: {}",
+                    error.message.as_deref().unwrap_or("no reason given")
+                ))),
+            }
+        }
+        (evt, _) => Err(DiscordError::UnexpectedHandshake(format!(
+            "expected a READY event in response to the handshake, got {evt:?}"
+        ))),
+    }
+}
+
+/// Wait for a non-blocking connection until it's complete, blocking on the connection between attempts.
 macro_rules! try_until_done {
-    [ $e:expr ] => {
+    [ $conn:ident, $e:expr ] => {
         loop {
             match $e {
                 Ok(v) => break v,
                 Err(why) => if !why.io_would_block() { return Err(why); },
             }
 
-            thread::sleep(time::Duration::from_millis(500));
+            $conn.wait(WAIT_INTERVAL)?;
         }
+    };
+    [ $conn:ident, $e:expr, $deadline:expr ] => {
+        loop {
+            match $e {
+                Ok(v) => break v,
+                Err(why) => if !why.io_would_block() { return Err(why); },
+            }
+
+            let now = Instant::now();
+            if now >= $deadline {
+                return Err(io::Error::from(io::ErrorKind::TimedOut).into());
+            }
+
+            $conn.wait($deadline - now)?;
+        }
+    };
+}
+
+/// Treats a frame the socket could only take part of as sent, as the rest is written by later calls
+fn committed(result: Result<()>) -> Result<()> {
+    match result {
+        Err(why) if why.io_would_block() => Ok(()),
+        result => result,
     }
 }
 
+/// Logs a frame going in `direction`, including its (redacted) payload with the `frame-logging` feature
+fn log_frame(direction: &str, opcode: OpCode, payload: &[u8]) {
+    trace!(target: CONNECTION, "{direction} {opcode:?} frame, {} bytes", payload.len());
+
+    #[cfg(feature = "frame-logging")]
+    trace!(
+        target: crate::targets::FRAMES,
+        "{direction} {opcode:?} {}",
+        utils::redact_json(&String::from_utf8_lossy(payload))
+    );
+}
+
+/// How many bytes are read from the socket at a time
+const READ_CHUNK_SIZE: usize = 1024;
+
+/// The most bytes handed to the socket in a single write, as some platforms fail or truncate writes
+/// larger than the pipe's buffer instead of taking part of them
+const WRITE_CHUNK_SIZE: usize = 64 * 1024;
+
+/// The longest a connection waits for at once without a deadline, and how long connections that cannot wait for readiness sleep
+const WAIT_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Buffers kept for the lifetime of a connection, so frames can be sent and received without allocating
+#[derive(Default)]
+pub struct Buffers {
+    read: BytesMut,
+    write: Vec<u8>,
+    /// How much of `write` the socket has taken
+    written: usize,
+}
+
+impl Buffers {
+    /// Whether part of a frame is still waiting to be written, as the socket could not take all of it at once
+    #[must_use]
+    pub fn has_pending_write(&self) -> bool {
+        self.written < self.write.len()
+    }
+}
+
+/// Interrupts [`Connection::wait`] from another thread
+pub trait Wake: Send + Sync {
+    /// Wakes the thread waiting on the connection
+    fn wake(&self);
+}
+
+/// A connection to Discord's IPC socket, speaking its framing of opcodes and JSON payloads
+///
+/// This is what [`Client`](crate::Client) is built on, for custom protocols and proxies that need the
+/// handshake and framing without the client's event handling. Frames are exchanged with [`Connection::send`]
+/// and [`Connection::recv`], and the socket is non-blocking, so [`Connection::recv`] may fail with
+/// [`io::ErrorKind::WouldBlock`] until [`Connection::wait`] reports it is readable.
+///
+/// # Examples
+/// ```no_run
+/// use discord_presence::connection::{ConnectOptions, Connection, Message, OpCode, Socket};
+/// use serde_json::json;
+/// use std::time::Duration;
+///
+/// let (mut socket, ready) = Socket::connect(&ConnectOptions::default(), 1_003_450_375_732_482_138)?;
+/// println!("Connected: {}", ready.payload);
+///
+/// let frame = json!({ "cmd": "GET_GUILDS", "args": {}, "nonce": "1" });
+/// socket.send(&Message::new(OpCode::Frame, frame)?)?;
+///
+/// let response = loop {
+///     match socket.recv() {
+///         Ok(message) if message.opcode == OpCode::Frame => break message,
+///         Ok(_) => {}
+///         Err(why) if why.io_would_block() => socket.wait(Duration::from_secs(1))?,
+///         Err(why) => return Err(why),
+///     }
+/// };
+/// println!("Received: {}", response.payload);
+/// # Ok::<(), discord_presence::DiscordError>(())
+/// ```
 pub trait Connection: Sized {
+    /// The underlying stream frames are read from and written to
     type Socket: Write + Read;
 
-    /// The internally stored socket connection.
-    fn socket(&mut self) -> &mut Self::Socket;
+    /// The internally stored socket connection, and the buffers used to read and write it.
+    fn socket(&mut self) -> (&mut Self::Socket, &mut Buffers);
 
     /// The base path were the socket is located.
     fn ipc_path() -> PathBuf;
 
-    /// Establish a new connection to the server.
-    fn connect() -> Result<Self>;
+    /// Establish a new connection to the server at the given socket path.
+    ///
+    /// # Errors
+    /// - [`DiscordError::IoError`] if nothing is listening at `path`
+    fn connect_to(path: &Path) -> Result<Self>;
+
+    /// Establish a new connection to the server and perform the handshake, probing every candidate socket at once.
+    ///
+    /// Sockets that accept a connection are handshaken with concurrently, each within a few seconds,
+    /// and the first to respond is kept. Trying many sockets therefore takes no longer than trying one.
+    /// In compatibility mode, every socket name and location used by Discord and its reimplementations is tried.
+    ///
+    /// # Errors
+    /// - [`DiscordError::Closed`], [`DiscordError::InvalidClientId`], [`DiscordError::UnsupportedProtocol`] or
+    ///   [`DiscordError::UnexpectedHandshake`] if no handshake succeeded, and Discord rejected the handshake on one of the sockets
+    /// - [`DiscordError::DiscordNotFound`], reporting why each socket failed, if no handshake succeeded
+    fn connect(options: &ConnectOptions, client_id: u64) -> Result<(Self, Message)>
+    where
+        Self: Send + 'static,
+    {
+        let mut diagnostics = ConnectDiagnostics::default();
+        let paths = Self::socket_paths(options);
+
+        let (tx, rx) = unbounded();
+        for path in &paths {
+            let connection = match Self::connect_to(path) {
+                Ok(connection) => connection,
+                Err(why) => {
+                    trace!(target: CONNECTION, "Failed to connect to {}: {why}", path.display());
+                    diagnostics
+                        .failures
+                        .push(ConnectFailure::new(path.clone(), &why));
+                    continue;
+                }
+            };
+
+            let tx = tx.clone();
+            let path = path.clone();
+            let options = options.clone();
+            thread::Builder::new()
+                .name("discord-presence-probe".to_owned())
+                .spawn(move || {
+                    let mut connection = connection;
+                    let result = connection
+                        .handshake(client_id, &options, PROBE_TIMEOUT)
+                        .map(|message| (connection, message));
+
+                    // The receiver is gone once another socket won, which closes this connection
+                    tx.send((path, result)).ok();
+                })?;
+        }
+        drop(tx);
+
+        // Discord saying why it refused the handshake is more useful than which sockets failed
+        let mut rejected = None;
+        for (path, result) in rx {
+            match result {
+                Ok(connected) => {
+                    trace!(target: CONNECTION, "Handshake completed on {}", path.display());
+                    return Ok(connected);
+                }
+                Err(why) => {
+                    trace!(target: CONNECTION, "Handshake failed on {}: {why}", path.display());
+                    diagnostics.failures.push(ConnectFailure::new(path, &why));
+                    if let DiscordError::Closed(_)
+                    | DiscordError::InvalidClientId(_)
+                    | DiscordError::UnsupportedProtocol(_)
+                    | DiscordError::UnexpectedHandshake(_) = why
+                    {
+                        rejected.get_or_insert(why);
+                    }
+                }
+            }
+        }
+
+        if let Some(rejected) = rejected {
+            return Err(rejected);
+        }
+
+        // Probes finish in any order, but failures are reported in the order the sockets are tried
+        diagnostics
+            .failures
+            .sort_by_key(|failure| paths.iter().position(|path| *path == failure.path));
+        diagnostics.sandbox = Sandbox::detect();
+        Err(DiscordError::DiscordNotFound(diagnostics))
+    }
+
+    /// Establish a new connection through a bridge to Discord, rather than Discord's own socket.
+    ///
+    /// Connections that can only reach Unix sockets reject TCP bridges.
+    ///
+    /// # Errors
+    /// - [`DiscordError::IoError`] if the bridge cannot be reached, or is not supported by this connection
+    #[cfg(feature = "wsl")]
+    fn connect_bridge(bridge: &Bridge) -> Result<Self> {
+        match bridge {
+            Bridge::Unix(path) => Self::connect_to(path),
+            Bridge::Tcp(_) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "TCP bridges are not supported by this connection",
+            )
+            .into()),
+        }
+    }
+
+    /// Tries each candidate socket without keeping the connection, reporting which one could be connected to,
+    /// or why none could be.
+    #[must_use]
+    fn diagnose(options: &ConnectOptions) -> ConnectDiagnostics {
+        let mut diagnostics = ConnectDiagnostics::default();
+        drop(Self::try_connect(options, &mut diagnostics));
+        diagnostics
+    }
+
+    /// Tries each candidate socket in turn, recording the outcome in `diagnostics`.
+    fn try_connect(options: &ConnectOptions, diagnostics: &mut ConnectDiagnostics) -> Option<Self> {
+        for path in Self::socket_paths(options) {
+            match Self::connect_to(&path) {
+                Ok(connection) => {
+                    diagnostics.connected = Some(path);
+                    return Some(connection);
+                }
+                Err(why) => {
+                    trace!(target: CONNECTION, "Failed to connect to {}: {why}", path.display());
+                    diagnostics.failures.push(ConnectFailure::new(path, &why));
+                }
+            }
+        }
+
+        diagnostics.sandbox = Sandbox::detect();
+        None
+    }
+
+    /// Block until the socket is readable, the connection is woken, or `timeout` elapses.
+    /// While part of a frame is waiting to be written, the socket becoming writable also ends the wait.
+    ///
+    /// Connections that cannot wait for readiness sleep for a fixed interval instead, or until `timeout` if sooner.
+    ///
+    /// # Errors
+    /// - [`DiscordError::IoError`] if waiting on the socket failed
+    fn wait(&mut self, timeout: Duration) -> Result<()> {
+        thread::sleep(timeout.min(WAIT_INTERVAL));
+        Ok(())
+    }
+
+    /// Create a handle that interrupts [`Connection::wait`], if supported.
+    ///
+    /// # Errors
+    /// - [`DiscordError::IoError`] if the handle could not be registered with the socket
+    fn waker(&self) -> Result<Option<Arc<dyn Wake>>> {
+        Ok(None)
+    }
+
+    /// The directory containing the socket: the override from `options` if set, or [`Connection::ipc_path`] otherwise.
+    fn ipc_dir(options: &ConnectOptions) -> PathBuf {
+        options.ipc_dir.clone().unwrap_or_else(Self::ipc_path)
+    }
 
     /// The full socket path.
-    fn socket_path(n: u8) -> PathBuf {
+    #[must_use]
+    fn socket_path(ipc_dir: &Path, n: u8) -> PathBuf {
         let socket_path = format!("discord-ipc-{n}");
-        let base_path = Self::ipc_path().join(socket_path.clone());
+        let base_path = ipc_dir.join(socket_path.clone());
 
         if base_path.exists() {
             base_path
         } else {
             // This fixes issues with Unix implementations
-            Self::ipc_path()
+            ipc_dir
                 .join("app")
                 .join("com.discordapp.Discord")
                 .join(socket_path)
         }
     }
 
-    /// Perform a handshake on this socket connection.
-    /// Will block until complete.
-    fn handshake(&mut self, client_id: u64) -> Result<Message> {
+    /// The socket paths to try connecting to, in order.
+    #[must_use]
+    fn socket_paths(options: &ConnectOptions) -> Vec<PathBuf> {
+        let base_path = Self::ipc_dir(options);
+        if !options.compatibility {
+            return vec![Self::socket_path(&base_path, 0)];
+        }
+
+        (0..COMPAT_SOCKET_COUNT)
+            .flat_map(|n| {
+                let socket_path = format!("discord-ipc-{n}");
+                let base_path = base_path.clone();
+
+                iter::once(base_path.join(&socket_path)).chain(
+                    COMPAT_SUBDIRECTORIES
+                        .iter()
+                        .map(move |directory| base_path.join(directory).join(&socket_path)),
+                )
+            })
+            .collect()
+    }
+
+    /// Perform a handshake on this socket connection, asking for the RPC version in `options`.
+    /// Will block until complete, or fail with [`io::ErrorKind::TimedOut`] once `timeout` elapses.
+    ///
+    /// # Errors
+    /// - [`DiscordError::InvalidClientId`] if Discord does not know the client ID
+    /// - [`DiscordError::UnsupportedProtocol`] if Discord does not support the RPC version
+    /// - [`DiscordError::UnexpectedHandshake`] if the response is not a `READY` event
+    fn handshake(
+        &mut self,
+        client_id: u64,
+        options: &ConnectOptions,
+        timeout: Duration,
+    ) -> Result<Message> {
+        let deadline = Instant::now() + timeout;
         let hs = json![{
             "client_id": client_id.to_string(),
-            "v": 1,
+            "v": options.rpc_version,
             "nonce": utils::nonce()
         }];
 
-        let msg = Message::new(OpCode::Handshake, hs)?;
-        try_until_done!(self.send(&msg));
-        let msg = try_until_done!(self.recv());
+        try_until_done!(self, self.send_payload(OpCode::Handshake, &hs), deadline);
+        // Pings are answered by `recv`, the response is the first frame that is not one
+        let msg = loop {
+            let msg = try_until_done!(
+                self,
+                self.recv()
+                    .map_err(|why| handshake_error(why, client_id, options.rpc_version)),
+                deadline
+            );
+            if msg.opcode != OpCode::Ping {
+                break msg;
+            }
+        };
 
+        validate_handshake(&msg, client_id, options)?;
         Ok(msg)
     }
 
     /// Ping the server and get a pong response.
     /// Will block until complete.
+    ///
+    /// # Errors
+    /// - [`DiscordError::IoError`] if the connection was lost
+    /// - [`DiscordError::Closed`] if Discord closed the connection
     fn ping(&mut self) -> Result<OpCode> {
-        let message = Message::new(OpCode::Ping, json![{}])?;
-        try_until_done!(self.send(&message));
-        let response = try_until_done!(self.recv());
+        try_until_done!(self, self.send_payload(OpCode::Ping, &json![{}]));
+        let response = try_until_done!(self, self.recv());
         Ok(response.opcode)
     }
 
-    /// Send a message to the server.
-    fn send(&mut self, message: &Message) -> Result<()> {
-        match message.encode() {
-            Err(why) => error!("{:?}", why),
-            Ok(bytes) => {
-                self.socket().write_all(&bytes)?;
+    /// Write what is left of a frame the socket could not take all of at once.
+    ///
+    /// # Errors
+    /// - [`DiscordError::IoError`] of kind [`io::ErrorKind::WouldBlock`] if the socket still cannot take all of it
+    /// - [`DiscordError::IoError`] if the connection was lost
+    fn flush_pending(&mut self) -> Result<()> {
+        let (socket, buffers) = self.socket();
+        while buffers.has_pending_write() {
+            let end = buffers.write.len().min(buffers.written + WRITE_CHUNK_SIZE);
+            match socket.write(&buffers.write[buffers.written..end]) {
+                Ok(0) => return Err(io::Error::from(io::ErrorKind::WriteZero).into()),
+                Ok(n) => buffers.written += n,
+                Err(why) if why.kind() == io::ErrorKind::Interrupted => {}
+                Err(why) => return Err(why.into()),
             }
-        };
-        trace!("-> {:?}", message);
+        }
+
+        buffers.write.clear();
+        buffers.written = 0;
         Ok(())
     }
 
+    /// Send a message to the server.
+    ///
+    /// Whatever part of the frame the socket cannot take yet is written by later calls, such as to [`Connection::recv`],
+    /// so the message counts as sent once this succeeds.
+    ///
+    /// # Errors
+    /// - [`DiscordError::IoError`] of kind [`io::ErrorKind::WouldBlock`] if an earlier frame is still being written,
+    ///   in which case none of the message was sent
+    /// - [`DiscordError::IoError`] if the connection was lost
+    fn send(&mut self, message: &Message) -> Result<()> {
+        self.flush_pending()?;
+
+        let (_, buffers) = self.socket();
+        message.encode_into(&mut buffers.write)?;
+        log_frame("->", message.opcode, message.payload.as_bytes());
+        committed(self.flush_pending())
+    }
+
+    /// Serialize a payload straight into the write buffer and send it to the server.
+    ///
+    /// Like [`Connection::send`], the message counts as sent once this succeeds.
+    ///
+    /// # Errors
+    /// - [`DiscordError::JsonError`] if the payload could not be serialized
+    /// - [`DiscordError::IoError`] of kind [`io::ErrorKind::WouldBlock`] if an earlier frame is still being written,
+    ///   in which case none of the message was sent
+    /// - [`DiscordError::IoError`] if the connection was lost
+    fn send_payload<T: Serialize>(&mut self, opcode: OpCode, payload: &T) -> Result<()> {
+        self.flush_pending()?;
+
+        let (_, buffers) = self.socket();
+        if let Err(why) = Message::encode_payload_into(opcode, payload, &mut buffers.write) {
+            // Nothing has been written yet, so a half serialized payload can be dropped
+            buffers.write.clear();
+            return Err(why);
+        }
+        log_frame("->", opcode, &buffers.write[8..]);
+        committed(self.flush_pending())
+    }
+
     /// Receive a message from the server.
+    ///
+    /// Pings are answered with a pong carrying the same payload before they are returned,
+    /// so the server does not drop the connection for being unresponsive.
+    ///
+    /// # Errors
+    /// - [`DiscordError::IoError`] if the connection was lost, or no frame is ready yet
+    /// - [`DiscordError::ConnectionClosed`] if the server hung up
+    /// - [`DiscordError::Closed`] if Discord closed the connection
     fn recv(&mut self) -> Result<Message> {
-        let mut buf = BytesMut::new();
-        buf.resize(1024, 0);
-        let n = self.socket().read(&mut buf)?;
-        trace!("Received {} bytes", n);
+        // Reading does not have to wait for an earlier frame to be written
+        committed(self.flush_pending())?;
+
+        let (socket, buffers) = self.socket();
+        let buf = &mut buffers.read;
+        // A previous read may have already received this frame, or part of it
+        let message = loop {
+            if let Some(message) = Message::decode_buffered(buf)? {
+                break message;
+            }
+
+            let received = buf.len();
+            buf.resize(received + READ_CHUNK_SIZE, 0);
+            let n = match socket.read(&mut buf[received..]) {
+                Ok(n) => n,
+                Err(why) => {
+                    // Keep what was received so far for the next call, such as after a `WouldBlock`
+                    buf.truncate(received);
+                    return Err(why.into());
+                }
+            };
+            buf.truncate(received + n);
+            trace!(target: CONNECTION, "Received {} bytes", n);
+
+            if n == 0 {
+                return Err(DiscordError::ConnectionClosed);
+            }
+        };
+
+        log_frame("<-", message.opcode, message.payload.as_bytes());
 
-        if n == 0 {
-            return Err(DiscordError::ConnectionClosed);
+        if message.opcode == OpCode::Close {
+            // A close frame without a readable reason still closes the connection
+            let event = codec::decode(&message.payload).unwrap_or_default();
+            return Err(DiscordError::Closed(event));
         }
 
-        let message = Message::decode(&buf[..n])?;
-        trace!("<- {:?}", message);
+        if message.opcode == OpCode::Ping {
+            let pong = Message {
+                opcode: OpCode::Pong,
+                payload: message.payload.clone(),
+            };
+            match self.send(&pong) {
+                // Losing the ping would be worse, the server pings again if it still expects an answer
+                Err(why) if why.io_would_block() => {
+                    trace!(target: CONNECTION, "Could not answer ping, an earlier frame is still being written");
+                }
+                result => result?,
+            }
+        }
 
         Ok(message)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::Socket;
+
+    #[test]
+    fn validates_handshake_responses() {
+        use crate::models::CloseEvent;
+
+        let options = ConnectOptions::default();
+        let response = |payload| Message::new(OpCode::Frame, payload).unwrap();
+
+        let ready = response(json!({ "cmd": "DISPATCH", "evt": "READY", "data": {} }));
+        assert!(validate_handshake(&ready, 0, &options).is_ok());
+
+        let error = response(json!({
+            "cmd": "DISPATCH",
+            "evt": "ERROR",
+            "data": { "code": 4000, "message": "Invalid Client ID" },
+        }));
+        assert!(matches!(
+            validate_handshake(&error, 1, &options),
+            Err(DiscordError::InvalidClientId(1))
+        ));
+
+        let version = response(json!({
+            "cmd": "DISPATCH",
+            "evt": "ERROR",
+            "data": { "code": 4004, "message": "Invalid version" },
+        }));
+        let why = validate_handshake(&version, 1, &options).unwrap_err();
+        assert!(matches!(why, DiscordError::UnsupportedProtocol(_)));
+        assert!(why.should_break());
+
+        // Anything else unexpected is retried
+        let bare = response(json!({ "cmd": "DISPATCH", "evt": "READY" }));
+        let why = validate_handshake(&bare, 0, &options).unwrap_err();
+        assert!(matches!(why, DiscordError::UnexpectedHandshake(_)));
+        assert!(!why.should_break());
+        let options = ConnectOptions {
+            compatibility: true,
+            ..ConnectOptions::default()
+        };
+        assert!(validate_handshake(&bare, 0, &options).is_ok());
+
+        let closed = DiscordError::Closed(CloseEvent::new().code(4004));
+        assert!(matches!(
+            handshake_error(closed, 0, 2),
+            DiscordError::UnsupportedProtocol(ref reason) if reason == "RPC version 2 is not supported"
+        ));
+    }
+
+    #[test]
+    fn compatibility_mode_tries_more_sockets() {
+        let base_path = Socket::ipc_path();
+        let mut options = ConnectOptions::default();
+        assert_eq!(
+            Socket::socket_paths(&options),
+            [Socket::socket_path(&base_path, 0)]
+        );
+
+        options.compatibility = true;
+        let paths = Socket::socket_paths(&options);
+        assert_eq!(paths[0], base_path.join("discord-ipc-0"));
+        assert!(paths.contains(&base_path.join("app/dev.vencord.Vesktop/discord-ipc-0")));
+        assert!(paths.contains(&base_path.join("discord-ipc-9")));
+    }
+
+    #[test]
+    fn ipc_dir_can_be_overridden() {
+        let options = ConnectOptions {
+            ipc_dir: Some(PathBuf::from("/run/user/1000")),
+            ..ConnectOptions::default()
+        };
+
+        assert_eq!(Socket::ipc_dir(&options), Path::new("/run/user/1000"));
+        assert!(Socket::socket_paths(&options)[0].starts_with("/run/user/1000"));
+    }
+
+    #[test]
+    #[cfg(all(unix, not(feature = "io-uring")))]
+    fn probes_sockets_concurrently() {
+        use crate::connection::test_socket_path;
+        use std::os::unix::net::UnixListener;
+
+        let ipc_dir = test_socket_path("probe");
+        std::fs::create_dir_all(&ipc_dir).unwrap();
+
+        // Accepts connections, but never answers the handshake
+        let _silent = UnixListener::bind(ipc_dir.join("discord-ipc-0")).unwrap();
+        let server = UnixListener::bind(ipc_dir.join("discord-ipc-1")).unwrap();
+        thread::spawn(move || {
+            let (mut stream, _) = server.accept().unwrap();
+            stream.read_exact(&mut [0; 8]).ok();
+            let ready = json!({ "cmd": "DISPATCH", "evt": "READY" });
+            let ready = Message::new(OpCode::Frame, ready).unwrap();
+            stream.write_all(&ready.encode().unwrap()).unwrap();
+            thread::sleep(Duration::from_secs(1));
+        });
+
+        let options = ConnectOptions {
+            compatibility: true,
+            ipc_dir: Some(ipc_dir.clone()),
+            ..ConnectOptions::default()
+        };
+        let started = Instant::now();
+        let result = Socket::connect(&options, 0);
+        std::fs::remove_dir_all(&ipc_dir).ok();
+
+        let (_, message) = result.unwrap();
+        assert_eq!(message.opcode, OpCode::Frame);
+        assert!(started.elapsed() < PROBE_TIMEOUT);
+    }
+
+    #[test]
+    #[cfg(all(unix, not(feature = "io-uring")))]
+    fn pings_are_answered() {
+        let (mut socket, mut stream) = crate::connection::socket_pair("ping");
+        let ping = Message::new(OpCode::Ping, json!({ "n": 1 })).unwrap();
+        stream.write_all(&ping.encode().unwrap()).unwrap();
+
+        let message = loop {
+            match socket.recv() {
+                Err(why) if why.io_would_block() => thread::sleep(Duration::from_millis(10)),
+                result => break result.unwrap(),
+            }
+        };
+        assert_eq!(message, ping);
+
+        let mut frame = vec![0; 8 + ping.payload.len()];
+        stream.read_exact(&mut frame).unwrap();
+        let answer = Message::decode(&frame).unwrap();
+        assert_eq!(answer.opcode, OpCode::Pong);
+        assert_eq!(answer.payload, ping.payload);
+    }
+
+    /// A stream taking at most `limit` bytes per write, which is interrupted or blocked on every third write
+    #[derive(Default)]
+    struct Trickle {
+        limit: usize,
+        received: Vec<u8>,
+        writes: usize,
+        largest_write: usize,
+    }
+
+    impl Read for Trickle {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::WouldBlock.into())
+        }
+    }
+
+    impl Write for Trickle {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.writes += 1;
+            self.largest_write = self.largest_write.max(buf.len());
+            match self.writes % 6 {
+                2 => Err(io::ErrorKind::Interrupted.into()),
+                5 => Err(io::ErrorKind::WouldBlock.into()),
+                _ => {
+                    let n = buf.len().min(self.limit);
+                    self.received.extend_from_slice(&buf[..n]);
+                    Ok(n)
+                }
+            }
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[derive(Default)]
+    struct TrickleConnection {
+        stream: Trickle,
+        buffers: Buffers,
+    }
+
+    impl Connection for TrickleConnection {
+        type Socket = Trickle;
+
+        fn socket(&mut self) -> (&mut Trickle, &mut Buffers) {
+            (&mut self.stream, &mut self.buffers)
+        }
+
+        fn ipc_path() -> PathBuf {
+            PathBuf::new()
+        }
+
+        fn connect_to(_: &Path) -> Result<Self> {
+            Ok(Self::default())
+        }
+    }
+
+    #[test]
+    fn large_frames_are_written_in_chunks() {
+        let mut conn = TrickleConnection::default();
+        conn.stream.limit = 48 * 1024;
+
+        let frames = [
+            Message::new(OpCode::Frame, json!({ "data": "x".repeat(300 * 1024) })).unwrap(),
+            Message::new(OpCode::Frame, json!({ "data": "y".repeat(700 * 1024) })).unwrap(),
+            Message::new(OpCode::Frame, json!({})).unwrap(),
+        ];
+        for frame in &frames {
+            loop {
+                match conn.send(frame) {
+                    Err(why) if why.io_would_block() => {}
+                    result => break result.unwrap(),
+                }
+            }
+        }
+        while conn.buffers.has_pending_write() {
+            committed(conn.flush_pending()).unwrap();
+        }
+
+        let expected: Vec<u8> = frames.iter().flat_map(|f| f.encode().unwrap()).collect();
+        assert_eq!(conn.stream.received, expected);
+        assert!(conn.stream.largest_write <= WRITE_CHUNK_SIZE);
+
+        let mut received = BytesMut::from(&conn.stream.received[..]);
+        for frame in &frames {
+            assert_eq!(
+                &Message::decode_buffered(&mut received).unwrap().unwrap(),
+                frame
+            );
+        }
+        assert!(received.is_empty());
+    }
+
+    #[test]
+    #[cfg(all(unix, not(feature = "io-uring")))]
+    fn blocked_writes_are_finished_later() {
+        let (mut socket, mut stream) = crate::connection::socket_pair("blocked");
+
+        // Larger than the socket's buffer, so only part of it can be written until the server reads
+        let large = Message::new(OpCode::Frame, json!({ "data": "x".repeat(1 << 20) })).unwrap();
+        socket.send(&large).unwrap();
+        assert!(socket.socket().1.has_pending_write());
+
+        let small = Message::new(OpCode::Frame, json!({})).unwrap();
+        assert!(socket.send(&small).unwrap_err().io_would_block());
+
+        let length = 8 + large.payload.len();
+        let reader = thread::spawn(move || {
+            let mut frame = vec![0; length];
+            stream.read_exact(&mut frame).unwrap();
+            Message::decode(&frame).unwrap()
+        });
+        loop {
+            match socket.flush_pending() {
+                Err(why) if why.io_would_block() => socket.wait(Duration::from_millis(10)).unwrap(),
+                result => break result.unwrap(),
+            }
+        }
+
+        assert_eq!(reader.join().unwrap(), large);
+        assert!(!socket.socket().1.has_pending_write());
+    }
+
+    #[test]
+    #[cfg(all(unix, not(feature = "io-uring")))]
+    fn invalid_client_ids_are_reported() {
+        use crate::connection::test_socket_path;
+        use std::os::unix::net::UnixListener;
+
+        let ipc_dir = test_socket_path("close");
+        std::fs::create_dir_all(&ipc_dir).unwrap();
+
+        let server = UnixListener::bind(ipc_dir.join("discord-ipc-0")).unwrap();
+        thread::spawn(move || {
+            let (mut stream, _) = server.accept().unwrap();
+            stream.read_exact(&mut [0; 8]).ok();
+            let close = json!({ "code": 4000, "message": "Invalid Client ID" });
+            let close = Message::new(OpCode::Close, close).unwrap();
+            stream.write_all(&close.encode().unwrap()).unwrap();
+            thread::sleep(Duration::from_secs(1));
+        });
+
+        let options = ConnectOptions {
+            ipc_dir: Some(ipc_dir.clone()),
+            ..ConnectOptions::default()
+        };
+        let result = Socket::connect(&options, 0);
+        std::fs::remove_dir_all(&ipc_dir).ok();
+
+        let Err(why) = result else {
+            panic!("Expected the connection to be closed");
+        };
+        assert!(matches!(why, DiscordError::InvalidClientId(0)));
+        assert!(why.should_break());
+    }
+}
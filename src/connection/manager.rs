@@ -1,33 +1,210 @@
-use super::{Connection, Socket};
+#[cfg(feature = "wsl")]
+use super::base::PROBE_TIMEOUT;
+use super::{ConnectOptions, Connection, Socket, Wake, RPC_VERSION};
 use crate::{
+    client::{ChannelCapacities, ConnectionStats, JoinRequest, JOIN_REQUEST_TIMEOUT},
+    codec,
+    diagnostics::ConnectDiagnostics,
     error::{DiscordError, Result},
-    event_handler::HandlerRegistry,
-    models::{payload::Payload, ErrorEvent, Event, Message},
+    event_handler::{
+        ConnectionState, Context as EventContext, HandlerRegistry, Ping, StateChange,
+        StateChangeReason,
+    },
+    models::{
+        payload::Payload, Activity, AuthenticateArgs, Command, ErrorEvent, Event, EventData,
+        Message, OAuthScope, OpCode, PartialUser, ReadyEvent, SetActivityArgs, Snowflake,
+        SubscriptionArgs,
+    },
+    nonce::NonceGenerator,
+    sync::{AtomicBool, AtomicU64, Mutex, Ordering},
+    targets::{CONNECTION, DISPATCH},
+    utils,
 };
-use crossbeam_channel::{unbounded, Receiver, Sender};
-use parking_lot::Mutex;
-use serde_json::Value as JsonValue;
+#[cfg(feature = "wsl")]
+use crate::{
+    diagnostics::{ConnectFailure, Sandbox},
+    wsl::{self, Bridge},
+};
+use crossbeam_channel::{bounded, unbounded, Receiver, RecvTimeoutError, SendError, Sender};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Value as JsonValue};
 use std::{
-    io::ErrorKind,
-    sync::{atomic::Ordering, Arc},
+    collections::{HashMap, HashSet, VecDeque},
+    io::{self, ErrorKind},
+    path::PathBuf,
+    sync::Arc,
     thread,
-    time::{self, Duration},
+    time::{self, Duration, Instant, SystemTime},
 };
 
 type Tx = Sender<Message>;
+type TokenExpiredHook = dyn Fn(ErrorEvent) + Send + Sync;
 type Rx = Receiver<Message>;
+type Lane = Receiver<Outgoing>;
+
+/// A message waiting in an outbound lane
+struct Outgoing {
+    message: Message,
+    /// Set once the message is no longer wanted, so it is dropped instead of being written
+    cancelled: Option<Arc<AtomicBool>>,
+}
+
+impl Outgoing {
+    fn is_cancelled(&self) -> bool {
+        self.cancelled
+            .as_ref()
+            .is_some_and(|cancelled| cancelled.load(Ordering::Acquire))
+    }
+}
+
+fn channel<T>(capacity: Option<usize>) -> (Sender<T>, Receiver<T>) {
+    capacity.map_or_else(unbounded, bounded)
+}
+
+/// How long the connection waits for incoming data, if it is not woken up sooner
+const IDLE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long the connection loop may go without running before the system is assumed to have been suspended,
+/// well beyond the longest it waits for at once
+const SUSPEND_THRESHOLD: Duration = Duration::from_secs(30);
+
+/// How long a command waits for its response, unless it waits for the user or configured otherwise
+pub(crate) const DEFAULT_RESPONSE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long `AUTHORIZE` waits for its response, as Discord only responds once the user answers its prompt
+const AUTHORIZE_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// How often a command waiting for its response checks whether it was cancelled
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Payload of the pings probing an idle connection, an empty object
+#[derive(Serialize)]
+struct Probe {}
+
+/// Length of [`Probe`] once serialized, `{}`
+const PROBE_LENGTH: usize = 2;
+
+/// How often an idle connection is written to by default, to tell whether it is still open
+pub(crate) const DEFAULT_WRITE_PROBE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Priority class of an outgoing message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    /// Sent in order with other messages
+    Normal,
+    /// Sent ahead of any queued [`Priority::Normal`] messages, e.g. clearing the activity
+    High,
+}
+
+/// Traffic and connection counters, for [`ConnectionStats`]
+struct Counters {
+    bytes_sent: AtomicU64,
+    frames_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    frames_received: AtomicU64,
+    connected_at: Mutex<Option<Instant>>,
+    activity_sent_at: Mutex<Option<SystemTime>>,
+}
+
+impl Counters {
+    fn new() -> Self {
+        Self {
+            bytes_sent: AtomicU64::new(0),
+            frames_sent: AtomicU64::new(0),
+            bytes_received: AtomicU64::new(0),
+            frames_received: AtomicU64::new(0),
+            connected_at: Mutex::new(None),
+            activity_sent_at: Mutex::new(None),
+        }
+    }
+
+    /// Counts a frame, including its header
+    fn count(bytes: &AtomicU64, frames: &AtomicU64, payload_len: usize) {
+        bytes.fetch_add(payload_len as u64 + 8, Ordering::Relaxed);
+        frames.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+struct QueuedCommand {
+    queued_at: Instant,
+    message: Message,
+    nonce: Option<String>,
+    priority: Priority,
+}
+
+/// Commands issued before the client is ready, to be sent once the handshake completes
+#[derive(Default)]
+struct OfflineQueue {
+    capacity: usize,
+    ttl: Duration,
+    messages: VecDeque<QueuedCommand>,
+}
 
 // TODO: Refactor connection manager
 #[derive(Clone)]
 pub struct Manager {
     connection: Arc<Option<Mutex<Socket>>>,
     client_id: u64,
-    outbound: (Rx, Tx),
-    inbound: (Rx, Tx),
+    outbound: (Lane, Sender<Outgoing>),
+    priority_outbound: (Lane, Sender<Outgoing>),
+    /// Commands waiting for their response, by nonce, each with the channel its response is delivered on
+    pending: Arc<Mutex<HashMap<String, Tx>>>,
     handshake_completed: bool,
     event_handler_registry: Arc<HandlerRegistry>,
     error_sleep: Duration,
     connection_attempts: Arc<Mutex<Option<usize>>>,
+    generation: Arc<AtomicU64>,
+    /// Whether the handshake has completed. Mirrored by [`crate::READY`] for [`crate::Client::is_ready`].
+    ready: Arc<AtomicBool>,
+    offline_queue: Arc<Mutex<OfflineQueue>>,
+    /// Nonces of queued commands, whose responses nobody is waiting for
+    detached_nonces: Arc<Mutex<HashSet<String>>>,
+    /// Commands the client sends on its own, such as flushing the offline queue, which go out ahead of the lanes.
+    /// They are sent from the connection thread, so they must not wait for room in lanes only that thread empties.
+    backlog: Arc<Mutex<VecDeque<Message>>>,
+    last_error: Arc<Mutex<Option<ErrorEvent>>>,
+    ready_info: Arc<Mutex<Option<ReadyEvent>>>,
+    waker: Arc<Mutex<Option<Arc<dyn Wake>>>>,
+    /// "Ask to Join" requests that have not been answered yet
+    join_requests: Arc<Mutex<Vec<JoinRequest>>>,
+    /// Whether to accept the sockets and handshakes of third-party RPC servers, such as arRPC
+    compatibility: Arc<AtomicBool>,
+    /// Directory containing the socket, overriding the platform's default
+    ipc_dir: Arc<Mutex<Option<PathBuf>>>,
+    rpc_version: Arc<Mutex<u32>>,
+    /// Whether to subscribe to events without being asked to, see [`Manager::subscribe_automatically`]
+    auto_subscribe: Arc<AtomicBool>,
+    /// Events subscribed to automatically, which are subscribed to again on every connection
+    auto_subscriptions: Arc<Mutex<HashSet<Event>>>,
+    /// Subscriptions made by the application, to unsubscribe from once their event has no handlers left
+    subscriptions: Arc<Mutex<HashSet<(Event, SubscriptionArgs)>>>,
+    /// Bridge to Discord used when running in WSL
+    #[cfg(feature = "wsl")]
+    bridge: Arc<Mutex<Option<Bridge>>>,
+    /// Report of the last failed connection attempt
+    diagnostics: Arc<Mutex<Option<ConnectDiagnostics>>>,
+    stats: Arc<Counters>,
+    state: Arc<Mutex<ConnectionState>>,
+    /// A message whose write failed, to be sent again once reconnected
+    unsent: Arc<Mutex<Option<Message>>>,
+    /// The last activity sent, to send again after resuming from sleep
+    sent_activity: Arc<Mutex<Option<SetActivityArgs>>>,
+    /// The activity Discord last acknowledged, as it echoed it back
+    shown_activity: Arc<Mutex<Option<Activity>>>,
+    /// How often an idle connection is written to, and how long writes may stall before it counts as lost
+    write_probe_interval: Arc<Mutex<Option<Duration>>>,
+    /// How long commands other than `AUTHORIZE` wait for their response
+    response_timeout: Arc<Mutex<Duration>>,
+    /// Access token of the last successful `AUTHENTICATE`, to authenticate with again on every connection
+    access_token: Arc<Mutex<Option<String>>>,
+    /// Nonce and token of the `AUTHENTICATE` sent on connecting, until Discord responds to it
+    reauthentication: Arc<Mutex<Option<(String, String)>>>,
+    /// Scopes of the last successful `AUTHENTICATE`, or `None` if they are not known
+    granted_scopes: Arc<Mutex<Option<HashSet<OAuthScope>>>>,
+    /// Called once the stored access token is rejected
+    token_expired_hook: Arc<Mutex<Option<Arc<TokenExpiredHook>>>>,
+    /// Generates the nonce of each command, or `None` for random UUIDs
+    nonce_generator: Arc<Mutex<Option<Arc<NonceGenerator>>>>,
 }
 
 impl Manager {
@@ -36,41 +213,722 @@ impl Manager {
         event_handler_registry: Arc<HandlerRegistry>,
         error_sleep: Duration,
         connection_attempts: Option<usize>,
+        capacities: ChannelCapacities,
     ) -> Self {
         let connection = Arc::new(None);
-        let (sender_o, receiver_o) = unbounded();
-        let (sender_i, receiver_i) = unbounded();
+        let (sender_o, receiver_o) = channel(capacities.outbound);
+
+        // Wakes the connection to unsubscribe from events that have no handlers left
+        let waker: Arc<Mutex<Option<Arc<dyn Wake>>>> = Arc::new(Mutex::new(None));
+        let emptied_waker = Arc::downgrade(&waker);
+        event_handler_registry.set_on_emptied(move || {
+            if let Some(waker) = emptied_waker.upgrade() {
+                if let Some(ref waker) = *waker.lock() {
+                    waker.wake();
+                }
+            }
+        });
+        let (sender_p, receiver_p) = channel(capacities.priority_outbound);
 
         Self {
             connection,
             client_id,
             handshake_completed: false,
-            inbound: (receiver_i, sender_i),
+            pending: Arc::new(Mutex::new(HashMap::new())),
             outbound: (receiver_o, sender_o),
+            priority_outbound: (receiver_p, sender_p),
             event_handler_registry,
             error_sleep,
             connection_attempts: Arc::new(Mutex::new(connection_attempts)),
+            generation: Arc::new(AtomicU64::new(0)),
+            ready: Arc::new(AtomicBool::new(false)),
+            offline_queue: Arc::new(Mutex::new(OfflineQueue::default())),
+            detached_nonces: Arc::new(Mutex::new(HashSet::new())),
+            backlog: Arc::new(Mutex::new(VecDeque::new())),
+            last_error: Arc::new(Mutex::new(None)),
+            ready_info: Arc::new(Mutex::new(None)),
+            waker,
+            join_requests: Arc::new(Mutex::new(Vec::new())),
+            compatibility: Arc::new(AtomicBool::new(false)),
+            ipc_dir: Arc::new(Mutex::new(None)),
+            rpc_version: Arc::new(Mutex::new(RPC_VERSION)),
+            auto_subscribe: Arc::new(AtomicBool::new(true)),
+            auto_subscriptions: Arc::new(Mutex::new(HashSet::new())),
+            subscriptions: Arc::new(Mutex::new(HashSet::new())),
+            #[cfg(feature = "wsl")]
+            bridge: Arc::new(Mutex::new(None)),
+            diagnostics: Arc::new(Mutex::new(None)),
+            stats: Arc::new(Counters::new()),
+            state: Arc::new(Mutex::new(ConnectionState::Disconnected)),
+            unsent: Arc::new(Mutex::new(None)),
+            sent_activity: Arc::new(Mutex::new(None)),
+            shown_activity: Arc::new(Mutex::new(None)),
+            write_probe_interval: Arc::new(Mutex::new(Some(DEFAULT_WRITE_PROBE_INTERVAL))),
+            response_timeout: Arc::new(Mutex::new(DEFAULT_RESPONSE_TIMEOUT)),
+            access_token: Arc::new(Mutex::new(None)),
+            reauthentication: Arc::new(Mutex::new(None)),
+            granted_scopes: Arc::new(Mutex::new(None)),
+            token_expired_hook: Arc::new(Mutex::new(None)),
+            nonce_generator: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Wakes the connection thread if it is waiting for incoming data
+    pub fn wake(&self) {
+        if let Some(ref waker) = *self.waker.lock() {
+            waker.wake();
+        }
+    }
+
+    /// The most recent error, either from failing to connect or reported by Discord
+    pub fn last_error(&self) -> Option<ErrorEvent> {
+        self.last_error.lock().clone()
+    }
+
+    /// The data of the most recent `READY` payload
+    pub fn ready_info(&self) -> Option<ReadyEvent> {
+        self.ready_info.lock().clone()
+    }
+
+    /// The current state of the connection
+    pub fn state(&self) -> ConnectionState {
+        *self.state.lock()
+    }
+
+    /// Moves to a new connection state, telling the state handlers if it differs from the current one
+    fn set_state(&self, new: ConnectionState, reason: StateChangeReason) {
+        let old = std::mem::replace(&mut *self.state.lock(), new);
+        if old == new {
+            return;
+        }
+
+        trace!(target: CONNECTION, "Connection state changed from {old:?} to {new:?}: {reason:?}");
+        self.event_handler_registry
+            .handle_state_change(StateChange { old, new, reason });
+    }
+
+    /// Passes an event on to the event handlers, keeping track of the state exposed by the manager
+    fn dispatch(&self, context: &EventContext) {
+        match context.event {
+            EventData::Error(ref error) => *self.last_error.lock() = Some(error.clone()),
+            EventData::ActivityJoinRequest(ref request) => {
+                if let Some(ref user) = request.user {
+                    self.track_join_request(user, context.received_at);
+                }
+            }
+            _ => {}
+        }
+
+        self.event_handler_registry
+            .handle(&context.clone().with_responder(self.clone()));
+    }
+
+    /// Dispatches an event as if Discord had sent it
+    #[cfg(feature = "test-util")]
+    pub fn simulate(&self, event: Event, data: JsonValue) {
+        self.dispatch(&EventContext::new(
+            event,
+            event.parse_data(data),
+            self.generation(),
+            None,
+        ));
+    }
+
+    fn track_join_request(&self, user: &PartialUser, received_at: SystemTime) {
+        let mut requests = self.join_requests.lock();
+
+        // A repeated request from the same user replaces the earlier one
+        requests.retain(|request| request.user.id != user.id);
+        requests.push(JoinRequest {
+            user: user.clone(),
+            received_at,
+            expires_at: received_at + JOIN_REQUEST_TIMEOUT,
+        });
+    }
+
+    /// The join requests that have not been answered or expired yet
+    pub fn join_requests(&self) -> Vec<JoinRequest> {
+        let mut requests = self.join_requests.lock();
+        let now = SystemTime::now();
+        requests.retain(|request| request.expires_at > now);
+        requests.clone()
+    }
+
+    /// Forgets the join request from the given user, once it has been answered
+    pub fn resolve_join_request(&self, user_id: Snowflake) {
+        self.join_requests
+            .lock()
+            .retain(|request| request.user.id != Some(user_id));
+    }
+
+    /// Configures the offline queue. A capacity of `0` disables it.
+    pub fn set_offline_queue(&self, capacity: usize, ttl: Duration) {
+        let mut queue = self.offline_queue.lock();
+        queue.capacity = capacity;
+        queue.ttl = ttl;
+        while queue.messages.len() > capacity {
+            self.drop_oldest(&mut queue);
+        }
+    }
+
+    fn drop_oldest(&self, queue: &mut OfflineQueue) {
+        if let Some(QueuedCommand {
+            nonce: Some(nonce), ..
+        }) = queue.messages.pop_front()
+        {
+            self.detached_nonces.lock().remove(&nonce);
+        }
+    }
+
+    /// Queues a message to be sent once the handshake completes.
+    ///
+    /// If the client became ready in the meantime, the message is handed back to be sent as usual instead,
+    /// as the queue may already have been flushed.
+    ///
+    /// # Errors
+    /// - [`DiscordError::NotStarted`] if the offline queue is disabled
+    pub fn enqueue(
+        &self,
+        message: Message,
+        nonce: Option<String>,
+        priority: Priority,
+    ) -> Result<Option<Message>> {
+        let mut queue = self.offline_queue.lock();
+        // Checked while holding the queue lock, which `set_ready` holds while flushing
+        if self.is_ready() {
+            return Ok(Some(message));
+        }
+
+        if queue.capacity == 0 {
+            return Err(DiscordError::NotStarted);
+        }
+
+        if queue.messages.len() >= queue.capacity {
+            warn!(target: CONNECTION, "Offline queue is full, dropping the oldest command");
+            self.drop_oldest(&mut queue);
+        }
+
+        if let Some(ref nonce) = nonce {
+            self.detached_nonces.lock().insert(nonce.clone());
+        }
+
+        queue.messages.push_back(QueuedCommand {
+            queued_at: Instant::now(),
+            message,
+            nonce,
+            priority,
+        });
+
+        Ok(None)
+    }
+
+    /// Marks the client as ready and flushes the offline queue, returning whether it was already ready.
+    ///
+    /// Both happen under the queue lock, so a command is either queued before the flush or sent directly.
+    fn set_ready(&self) -> bool {
+        let mut queue = self.offline_queue.lock();
+        let was_ready = self.ready.swap(true, Ordering::AcqRel);
+        crate::READY.store(true, Ordering::Release);
+        self.flush_offline_queue(&mut queue);
+
+        was_ready
+    }
+
+    fn flush_offline_queue(&self, queue: &mut OfflineQueue) {
+        let ttl = queue.ttl;
+        let (high, normal): (Vec<_>, Vec<_>) = queue
+            .messages
+            .drain(..)
+            .partition(|command| command.priority == Priority::High);
+
+        for command in high.into_iter().chain(normal) {
+            if command.queued_at.elapsed() > ttl {
+                trace!(target: CONNECTION, "Dropping expired queued command");
+                if let Some(ref nonce) = command.nonce {
+                    self.detached_nonces.lock().remove(nonce);
+                }
+                continue;
+            }
+
+            self.send_backlogged(command.message);
+        }
+    }
+
+    /// The report of the last failed connection attempt, or `None` if the last attempt succeeded
+    pub fn connect_diagnostics(&self) -> Option<ConnectDiagnostics> {
+        self.diagnostics.lock().clone()
+    }
+
+    /// Tries connecting to each candidate socket, without affecting the connection
+    pub fn diagnose(&self) -> ConnectDiagnostics {
+        Socket::diagnose(&self.connect_options())
+    }
+
+    /// Where the next connection looks for the socket
+    fn connect_options(&self) -> ConnectOptions {
+        ConnectOptions {
+            compatibility: self.compatibility.load(Ordering::Relaxed),
+            ipc_dir: self.ipc_dir.lock().clone(),
+            rpc_version: *self.rpc_version.lock(),
+        }
+    }
+
+    /// The directory the next connection looks for the socket in
+    pub fn ipc_dir(&self) -> PathBuf {
+        Socket::ipc_dir(&self.connect_options())
+    }
+
+    /// Overrides the directory containing the socket, or restores the default if `None`
+    pub fn set_ipc_dir(&self, ipc_dir: Option<PathBuf>) {
+        *self.ipc_dir.lock() = ipc_dir;
+    }
+
+    /// Sets the RPC version asked for in the handshake, which applies from the next connection
+    pub fn set_rpc_version(&self, version: u32) {
+        *self.rpc_version.lock() = version;
+    }
+
+    /// Sets how often an idle connection is written to, or stops probing it if `None`
+    pub fn set_write_probe_interval(&self, interval: Option<Duration>) {
+        *self.write_probe_interval.lock() = interval;
+    }
+
+    /// Sets how long commands other than `AUTHORIZE` wait for their response
+    pub fn set_response_timeout(&self, timeout: Duration) {
+        *self.response_timeout.lock() = timeout;
+    }
+
+    /// Enables or disables subscribing to events automatically
+    pub fn set_auto_subscribe(&self, enabled: bool) {
+        self.auto_subscribe.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Subscribes to `event` on this and every later connection, unless automatic subscriptions are disabled.
+    ///
+    /// Discord's response is not waited for, and a failed subscription is reported through the `Error` event.
+    pub fn subscribe_automatically(&self, event: Event) -> Result<()> {
+        if !self.auto_subscribe.load(Ordering::Relaxed)
+            || !self.auto_subscriptions.lock().insert(event)
+        {
+            return Ok(());
+        }
+
+        trace!(target: CONNECTION, "Subscribing to {event:?} automatically");
+        // Otherwise, the subscription is sent once connected
+        if self.is_ready() {
+            self.send_subscription(Command::Subscribe, event, SubscriptionArgs::new())?;
+        }
+
+        Ok(())
+    }
+
+    /// Keeps track of a subscription made by the application, so it can be undone once its event has no handlers left
+    pub fn track_subscription(&self, cmd: Command, event: Event, args: SubscriptionArgs) {
+        let mut subscriptions = self.subscriptions.lock();
+        match cmd {
+            Command::Subscribe => subscriptions.insert((event, args)),
+            _ => subscriptions.remove(&(event, args)),
+        };
+    }
+
+    /// Unsubscribes from the events whose last handler was removed
+    fn unsubscribe_emptied(&self) -> Result<()> {
+        for event in self.event_handler_registry.take_emptied() {
+            // A handler may have been registered again in the meantime
+            if self.event_handler_registry.count(event) > 0 {
+                continue;
+            }
+
+            let mut subscriptions = HashSet::new();
+            if self.auto_subscriptions.lock().remove(&event) {
+                subscriptions.insert(SubscriptionArgs::new());
+            }
+            self.subscriptions.lock().retain(|(subscribed, args)| {
+                let matches = *subscribed == event;
+                if matches {
+                    subscriptions.insert(args.clone());
+                }
+                !matches
+            });
+
+            // Subscriptions do not outlive the connection, so there is nothing to undo while disconnected
+            if !self.is_ready() {
+                continue;
+            }
+
+            for args in subscriptions {
+                trace!(target: CONNECTION, "Unsubscribing from {event:?}, as it has no handlers left");
+                self.send_subscription(Command::Unsubscribe, event, args)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sends a subscription command for `event`, dropping the response
+    fn send_subscription(&self, cmd: Command, event: Event, args: SubscriptionArgs) -> Result<()> {
+        let payload = self.payload(cmd, args, Some(event));
+        if let Some(ref nonce) = payload.nonce {
+            self.detached_nonces.lock().insert(nonce.clone());
+        }
+
+        self.send_backlogged(Message::new(OpCode::Frame, payload)?);
+        Ok(())
+    }
+
+    /// Enables or disables compatibility mode, which applies from the next connection
+    pub fn set_compatibility_mode(&self, enabled: bool) {
+        self.compatibility.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Sets the bridge to connect through when running in WSL, which applies from the next connection
+    #[cfg(feature = "wsl")]
+    pub fn set_wsl_bridge(&self, bridge: Option<Bridge>) {
+        *self.bridge.lock() = bridge;
+    }
+
+    /// Connects through the bridge if one is set and the process runs in WSL, or to Discord's own socket otherwise,
+    /// and performs the handshake
+    fn open(&self, options: &ConnectOptions) -> Result<(Socket, Message)> {
+        #[cfg(feature = "wsl")]
+        if let Some(bridge) = self.bridge.lock().clone().filter(|_| wsl::is_wsl()) {
+            trace!(target: CONNECTION, "Connecting through bridge {bridge}");
+            let mut connection = Socket::connect_bridge(&bridge).map_err(|why| {
+                DiscordError::DiscordNotFound(ConnectDiagnostics {
+                    failures: vec![ConnectFailure::new(PathBuf::from(bridge.to_string()), &why)],
+                    connected: None,
+                    sandbox: Some(Sandbox::Wsl),
+                })
+            })?;
+            let message = connection.handshake(self.client_id, options, PROBE_TIMEOUT)?;
+            return Ok((connection, message));
+        }
+
+        Socket::connect(options, self.client_id)
+    }
+
+    pub fn client_id(&self) -> u64 {
+        self.client_id
+    }
+
+    /// The number of commands waiting to be sent, either in the offline queue or to the connection thread
+    pub fn queued_commands(&self) -> usize {
+        self.offline_queue.lock().messages.len()
+            + self.backlog.lock().len()
+            + self.outbound.0.len()
+            + self.priority_outbound.0.len()
+    }
+
+    /// Whether the handshake has completed
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::Acquire)
+    }
+
+    /// Marks the client as no longer ready, so that commands are queued again
+    pub fn reset_ready(&self) {
+        let _queue = self.offline_queue.lock();
+        self.ready.store(false, Ordering::Release);
+        crate::READY.store(false, Ordering::Release);
+    }
+
+    /// Counters describing the connection so far
+    pub fn stats(&self) -> ConnectionStats {
+        ConnectionStats {
+            bytes_sent: self.stats.bytes_sent.load(Ordering::Relaxed),
+            frames_sent: self.stats.frames_sent.load(Ordering::Relaxed),
+            bytes_received: self.stats.bytes_received.load(Ordering::Relaxed),
+            frames_received: self.stats.frames_received.load(Ordering::Relaxed),
+            reconnects: self.generation().saturating_sub(1),
+            uptime: self
+                .stats
+                .connected_at
+                .lock()
+                .map(|connected_at| connected_at.elapsed()),
+            activity_sent_at: *self.stats.activity_sent_at.lock(),
+        }
+    }
+
+    /// Records that the activity was just sent
+    pub fn record_activity(&self, args: SetActivityArgs) {
+        *self.stats.activity_sent_at.lock() = Some(SystemTime::now());
+        *self.sent_activity.lock() = Some(args);
+    }
+
+    /// The activity last sent, or `None` if none was sent or it was cleared
+    pub fn intended_activity(&self) -> Option<Activity> {
+        self.sent_activity
+            .lock()
+            .as_ref()
+            .and_then(|args| args.activity().cloned())
+    }
+
+    /// The activity Discord last acknowledged, or `None` if it shows none
+    pub fn shown_activity(&self) -> Option<Activity> {
+        self.shown_activity.lock().clone()
+    }
+
+    /// Records the activity Discord echoed back in a response to `SET_ACTIVITY`, if `payload` is one
+    pub fn activity_acknowledged(&self, payload: &Payload<JsonValue>) {
+        if payload.cmd != Command::SetActivity || payload.evt == Some(Event::Error) {
+            return;
+        }
+
+        // Clearing the activity is acknowledged without data
+        *self.shown_activity.lock() = payload
+            .data
+            .clone()
+            .and_then(|data| serde_json::from_value(data).ok());
+    }
+
+    /// Sends the last activity again, dropping the response
+    fn resend_activity(&self) -> Result<()> {
+        let Some(args) = self.sent_activity.lock().clone() else {
+            return Ok(());
+        };
+
+        trace!(target: CONNECTION, "Sending the activity again");
+        let payload = self.payload(Command::SetActivity, args, None);
+        if let Some(ref nonce) = payload.nonce {
+            self.detached_nonces.lock().insert(nonce.clone());
+        }
+
+        self.send_backlogged(Message::new(OpCode::Frame, payload)?);
+        Ok(())
+    }
+
+    /// Sets the access token to authenticate with on every connection, or stops authenticating if `None`
+    pub fn set_access_token(&self, access_token: Option<String>) {
+        *self.access_token.lock() = access_token;
+    }
+
+    /// Sets the scopes the access token is authorized with, or `None` if they are not known
+    pub fn set_granted_scopes(&self, scopes: Option<HashSet<OAuthScope>>) {
+        *self.granted_scopes.lock() = scopes;
+    }
+
+    /// The scopes the access token is authorized with, or `None` if they are not known
+    pub fn granted_scopes(&self) -> Option<HashSet<OAuthScope>> {
+        self.granted_scopes.lock().clone()
+    }
+
+    /// Checks that the access token is authorized with every scope in `required`.
+    /// Passes if the granted scopes are not known, leaving it to Discord to check.
+    ///
+    /// # Errors
+    /// - [`DiscordError::MissingScope`] with the first scope that was not granted
+    pub fn check_scopes(&self, required: &[OAuthScope]) -> Result<()> {
+        let granted = self.granted_scopes.lock();
+        let Some(ref granted) = *granted else {
+            return Ok(());
+        };
+
+        match required.iter().find(|scope| !granted.contains(scope)) {
+            Some(&scope) => Err(DiscordError::MissingScope(scope)),
+            None => Ok(()),
+        }
+    }
+
+    /// Sets the hook called once the stored access token is rejected
+    pub fn set_token_expired_hook(&self, hook: Option<Arc<TokenExpiredHook>>) {
+        *self.token_expired_hook.lock() = hook;
+    }
+
+    /// Sets how the nonce of each command is generated, or `None` for random UUIDs
+    pub fn set_nonce_generator(&self, generator: Option<Arc<NonceGenerator>>) {
+        *self.nonce_generator.lock() = generator;
+    }
+
+    /// Creates the payload of a command, with a nonce from the configured generator
+    fn payload<T: Serialize>(&self, cmd: Command, args: T, evt: Option<Event>) -> Payload<T> {
+        let generator = self.nonce_generator.lock().clone();
+        Payload {
+            cmd,
+            args: Some(args),
+            data: None,
+            evt,
+            nonce: Some(generator.map_or_else(utils::nonce, |generator| generator())),
         }
     }
 
+    /// Authenticates with the stored access token, dropping the response
+    fn reauthenticate(&self) -> Result<()> {
+        let Some(access_token) = self.access_token.lock().clone() else {
+            return Ok(());
+        };
+
+        trace!(target: CONNECTION, "Authenticating again");
+        let args = AuthenticateArgs::new().access_token(access_token.clone());
+        let payload = self.payload(Command::Authenticate, args, None);
+        if let Some(ref nonce) = payload.nonce {
+            self.detached_nonces.lock().insert(nonce.clone());
+            *self.reauthentication.lock() = Some((nonce.clone(), access_token));
+        }
+
+        self.send_backlogged(Message::new(OpCode::Frame, payload)?);
+        Ok(())
+    }
+
+    /// Handles the response to the `AUTHENTICATE` sent on connecting, if `payload` is that response
+    ///
+    /// A rejected token is forgotten, unless it was replaced in the meantime, and the hook is called with the error.
+    fn reauthenticated(&self, payload: &Payload<JsonValue>) {
+        let mut reauthentication = self.reauthentication.lock();
+        let access_token = match reauthentication.take() {
+            Some((nonce, access_token)) if payload.nonce.as_ref() == Some(&nonce) => access_token,
+            other => {
+                *reauthentication = other;
+                return;
+            }
+        };
+        drop(reauthentication);
+
+        if payload.evt != Some(Event::Error) {
+            trace!(target: CONNECTION, "Authenticated again");
+            return;
+        }
+
+        warn!(target: CONNECTION, "The stored access token was rejected, continuing without authentication");
+        {
+            let mut stored = self.access_token.lock();
+            if stored.as_ref() == Some(&access_token) {
+                *stored = None;
+                *self.granted_scopes.lock() = None;
+            }
+        }
+
+        let error = payload
+            .data
+            .clone()
+            .and_then(|data| serde_json::from_value(data).ok())
+            .unwrap_or_default();
+        if let Some(hook) = self.token_expired_hook.lock().clone() {
+            // Run on its own thread, as the hook may well authenticate again, which waits for this one
+            utils::spawn("token-expired", move || hook(error));
+        }
+    }
+
+    /// The number of times a connection has been established
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Relaxed)
+    }
+
     pub fn start(&mut self, rx: Receiver<()>) -> std::thread::JoinHandle<()> {
         let mut manager_inner = self.clone();
         let error_sleep = self.error_sleep;
         let connection_attempts = self.connection_attempts.clone();
-        thread::spawn(move || {
+        let client_id = self.client_id;
+        utils::spawn("connection", move || {
+            let _span = info_span!(target: CONNECTION, "connection", client_id).entered();
             // TODO: Refactor so that JSON values are consistent across errors
             send_and_receive_loop(&mut manager_inner, &rx, error_sleep, &connection_attempts);
         })
     }
 
-    pub fn send(&self, message: Message) -> Result<()> {
-        self.outbound.1.send(message)?;
+    /// Queues a message in the lane for its priority, dropping it instead of writing it if `cancelled` is set before its turn comes
+    fn send(
+        &self,
+        message: Message,
+        priority: Priority,
+        cancelled: Option<&Arc<AtomicBool>>,
+    ) -> Result<()> {
+        let outgoing = Outgoing {
+            message,
+            cancelled: cancelled.cloned(),
+        };
+        let lane = match priority {
+            Priority::Normal => &self.outbound.1,
+            Priority::High => &self.priority_outbound.1,
+        };
+        lane.send(outgoing)
+            .map_err(|why| SendError(why.into_inner().message))?;
+
+        self.wake();
 
         Ok(())
     }
 
-    pub fn recv(&self) -> Result<Message> {
-        self.inbound.0.recv().map_err(DiscordError::from)
+    /// Sends a command the client issued on its own ahead of the lanes, without ever blocking
+    fn send_backlogged(&self, message: Message) {
+        self.backlog.lock().push_back(message);
+        self.wake();
+    }
+
+    /// Sends a command and waits for its response, or queues it if the client is not ready yet
+    pub fn execute<A, E>(
+        &self,
+        cmd: Command,
+        args: A,
+        evt: Option<Event>,
+        priority: Priority,
+    ) -> Result<Payload<E>>
+    where
+        A: Serialize + Send + Sync,
+        E: Serialize + DeserializeOwned + Send + Sync,
+    {
+        self.execute_unless_cancelled(cmd, args, evt, priority, None)
+    }
+
+    /// Like [`Manager::execute`], but gives up once `cancelled` is set, without sending the command if it was not sent yet.
+    ///
+    /// The flag is checked again just before the command is written, so a command cancelled while waiting in its lane
+    /// is dropped. Responses are matched with their command by nonce, so a response that arrives after giving up is dropped.
+    pub fn execute_unless_cancelled<A, E>(
+        &self,
+        cmd: Command,
+        args: A,
+        evt: Option<Event>,
+        priority: Priority,
+        cancelled: Option<&Arc<AtomicBool>>,
+    ) -> Result<Payload<E>>
+    where
+        A: Serialize + Send + Sync,
+        E: Serialize + DeserializeOwned + Send + Sync,
+    {
+        let is_cancelled = || cancelled.is_some_and(|cancelled| cancelled.load(Ordering::Acquire));
+        if is_cancelled() {
+            return Err(DiscordError::Cancelled);
+        }
+
+        let payload = self.payload(cmd, args, evt);
+
+        let nonce = payload.nonce.clone();
+        let mut message = Message::new(OpCode::Frame, payload)?;
+
+        if !self.is_ready() {
+            // The message is handed back if the client became ready in the meantime
+            let Some(unqueued) = self.enqueue(message, nonce.clone(), priority)? else {
+                trace!(target: CONNECTION, "Queued command: {:?}", cmd);
+                return Err(DiscordError::Queued);
+            };
+            message = unqueued;
+        }
+
+        trace!(target: CONNECTION, "Executing command: {:?}", cmd);
+
+        let nonce = into_error!(nonce, String::from("Commands always have a nonce"))?;
+        let (tx, rx) = bounded(1);
+        self.pending.lock().insert(nonce.clone(), tx);
+        let timeout = match cmd {
+            Command::Authorize => AUTHORIZE_TIMEOUT,
+            _ => *self.response_timeout.lock(),
+        };
+        let response = self
+            .send(message, priority, cancelled)
+            .and_then(|()| wait_for_response(cmd, timeout, &rx, cancelled.map(Arc::as_ref)));
+        self.pending.lock().remove(&nonce);
+        // Decoded loosely first, as the data of an `ERROR` is not the response the caller expects
+        let response: Payload<JsonValue> = codec::decode(&response?.payload)?;
+        if response.evt == Some(Event::Error) {
+            return Err(command_failed(cmd, response.data));
+        }
+
+        Ok(Payload {
+            cmd: response.cmd,
+            args: response.args.map(serde_json::from_value).transpose()?,
+            data: response.data.map(serde_json::from_value).transpose()?,
+            evt: response.evt,
+            nonce: response.nonce,
+        })
     }
 
     fn connect(&mut self) -> Result<()> {
@@ -78,38 +936,243 @@ impl Manager {
             return Ok(());
         }
 
-        trace!("Connecting");
+        trace!(target: CONNECTION, "Connecting");
+        self.set_state(ConnectionState::Connecting, StateChangeReason::Retrying);
 
-        let mut new_connection = Socket::connect()?;
+        let options = self.connect_options();
+        let (new_connection, msg) = match self.open(&options) {
+            Ok(connection) => connection,
+            Err(DiscordError::DiscordNotFound(diagnostics)) => {
+                *self.diagnostics.lock() = Some(diagnostics.clone());
+                return Err(DiscordError::DiscordNotFound(diagnostics));
+            }
+            Err(why) => return Err(why),
+        };
+        *self.diagnostics.lock() = None;
+        let waker = new_connection.waker()?;
+        let payload: Payload<JsonValue> = codec::decode(&msg.payload)?;
+        let generation = self.generation.fetch_add(1, Ordering::Relaxed) + 1;
+        let data = match payload.data {
+            Some(data) => Event::Ready.parse_data(data),
+            // Third-party servers may not describe themselves in the handshake response
+            None if options.compatibility => EventData::Unknown(JsonValue::Null),
+            None => into_error!(None)?,
+        };
+
+        if let EventData::Ready(ref ready) = data {
+            *self.ready_info.lock() = Some(ready.clone());
+        }
 
-        trace!("Performing handshake");
-        let msg = new_connection.handshake(self.client_id)?;
-        let payload: Payload<JsonValue> = serde_json::from_str(&msg.payload)?;
+        // Authentication does not carry over from previous connections either.
+        // It goes first, so that the commands and subscriptions that follow are authenticated.
+        self.reauthenticate()?;
 
         // TODO: Ensure it works without clone
-        // Only handle the ready event if the client was not already ready
-        if !crate::READY.load(std::sync::atomic::Ordering::Relaxed) {
-            trace!("Discord client is ready!");
-            crate::READY.store(true, Ordering::Relaxed);
+        // The client stops being ready on disconnecting, so the ready event is handled on every connection.
+        // Queued commands are flushed first, so they go out before anything sent by the handlers
+        if !self.set_ready() {
+            trace!(target: CONNECTION, "Discord client is ready!");
 
-            self.event_handler_registry.handle(
+            self.dispatch(&EventContext::new(
                 Event::Ready,
-                Event::Ready.parse_data(into_error!(payload.data)?),
-            );
+                data,
+                generation,
+                payload.nonce,
+            ));
         }
 
-        trace!("Handshake completed");
+        trace!(target: CONNECTION, "Handshake completed");
+
+        // Subscriptions do not carry over from previous connections
+        if self.auto_subscribe.load(Ordering::Relaxed) {
+            let events: Vec<_> = self.auto_subscriptions.lock().iter().copied().collect();
+            for event in events {
+                self.send_subscription(Command::Subscribe, event, SubscriptionArgs::new())?;
+            }
+        }
 
         self.connection = Arc::new(Some(Mutex::new(new_connection)));
+        *self.waker.lock() = waker;
+        *self.stats.connected_at.lock() = Some(Instant::now());
+        self.set_state(
+            ConnectionState::Connected,
+            StateChangeReason::HandshakeCompleted,
+        );
 
-        trace!("Connected");
+        trace!(target: CONNECTION, "Connected");
 
         Ok(())
     }
 
+    /// Deals with a message whose write failed, as the connection is lost
+    ///
+    /// Commands that are safe to send twice are kept to send again once reconnected.
+    /// Others fail, so whoever is waiting for their response is not left hanging.
+    fn write_failed(&self, message: Message) {
+        let Ok(payload) = codec::decode::<Payload<JsonValue>>(&message.payload) else {
+            return;
+        };
+
+        if can_resend(payload.cmd) {
+            trace!(target: CONNECTION, "Sending {:?} again once reconnected", payload.cmd);
+            *self.unsent.lock() = Some(message);
+            return;
+        }
+
+        warn!(target: CONNECTION, "Failed to send {:?}, as the connection was lost", payload.cmd);
+        let Some(nonce) = payload.nonce else {
+            return;
+        };
+        if self.detached_nonces.lock().remove(&nonce) {
+            return;
+        }
+        let Some(waiter) = self.pending.lock().remove(&nonce) else {
+            return;
+        };
+
+        let failure = Message::new(
+            OpCode::Frame,
+            Payload {
+                cmd: payload.cmd,
+                args: None,
+                data: Some(
+                    json!({ "message": "The connection was lost while sending the command" }),
+                ),
+                evt: Some(Event::Error),
+                nonce: Some(nonce),
+            },
+        );
+        // Each waiter has room for exactly one response, so this only fails once it stopped waiting
+        if let Ok(failure) = failure {
+            waiter.try_send(failure).ok();
+        }
+    }
+
+    /// Drops the connection if the system was suspended while it was up, returning whether it did
+    fn disconnect_if_resumed(&mut self, suspend: &mut SuspendDetector) -> bool {
+        // Only a connection that was up while suspended is suspect, the loop also stops while waiting to reconnect
+        let suspended = suspend.check(Instant::now(), SystemTime::now());
+        let Some(suspended) = suspended.filter(|_| self.connection.is_some()) else {
+            return false;
+        };
+
+        info!(target: CONNECTION, "Resumed after {suspended:?}, reconnecting");
+        self.disconnect();
+        self.set_state(ConnectionState::Disconnected, StateChangeReason::Resumed);
+        true
+    }
+
     fn disconnect(&mut self) {
+        // Commands are queued again until the next handshake, rather than sent to the lost connection
+        self.reset_ready();
         self.handshake_completed = false;
+        *self.stats.connected_at.lock() = None;
         self.connection = Arc::new(None);
+        *self.waker.lock() = None;
+        // Discord clears the activity once the connection is lost
+        *self.shown_activity.lock() = None;
+    }
+}
+
+/// Tells whether a connection is still open, as one Discord crashed on can stay open without ever becoming readable
+///
+/// The connection is written to whenever it has been idle for the probe interval,
+/// and counts as lost once a write has been stuck for that long, as nothing is reading the other end.
+struct Liveness {
+    last_probe: Instant,
+    stalled_since: Option<Instant>,
+}
+
+impl Liveness {
+    fn new() -> Self {
+        Self {
+            last_probe: Instant::now(),
+            stalled_since: None,
+        }
+    }
+
+    /// Probes the connection if it is due, failing with [`ErrorKind::TimedOut`] if writes have stalled
+    fn check(
+        &mut self,
+        manager: &Manager,
+        connection: &mut Socket,
+        interval: Duration,
+    ) -> Result<()> {
+        let now = Instant::now();
+        if connection.socket().1.has_pending_write() {
+            let stalled_since = *self.stalled_since.get_or_insert(now);
+            if now - stalled_since >= interval {
+                return Err(io::Error::new(
+                    ErrorKind::TimedOut,
+                    format!("writes have stalled for {:?}", now - stalled_since),
+                )
+                .into());
+            }
+
+            return Ok(());
+        }
+        self.stalled_since = None;
+
+        if now - self.last_probe < interval {
+            return Ok(());
+        }
+        self.last_probe = now;
+
+        trace!(target: CONNECTION, "Probing idle connection");
+        // Serialized straight into the frame, like the pings of `Connection::ping`
+        connection.send_payload(OpCode::Ping, &Probe {})?;
+        Counters::count(
+            &manager.stats.bytes_sent,
+            &manager.stats.frames_sent,
+            PROBE_LENGTH,
+        );
+        Ok(())
+    }
+}
+
+/// Notices the system resuming from sleep, after which the connection is often dead without having been closed
+///
+/// The connection loop never goes long without running, so either clock jumping forward between two of its turns means
+/// the system was suspended. Both are checked, as the monotonic clock stops while suspended on some platforms.
+struct SuspendDetector {
+    instant: Instant,
+    wall: SystemTime,
+}
+
+impl SuspendDetector {
+    fn new() -> Self {
+        Self {
+            instant: Instant::now(),
+            wall: SystemTime::now(),
+        }
+    }
+
+    /// Records a turn of the loop at the given time, returning how long the loop did not run for if the system was suspended
+    fn check(&mut self, instant: Instant, wall: SystemTime) -> Option<Duration> {
+        let gap = instant
+            .saturating_duration_since(self.instant)
+            .max(wall.duration_since(self.wall).unwrap_or_default());
+        self.instant = instant;
+        self.wall = wall;
+
+        (gap >= SUSPEND_THRESHOLD).then_some(gap)
+    }
+}
+
+/// Waits on a connection that has nothing left to read, then checks it is still open
+fn wait_while_idle(
+    manager: &Manager,
+    connection: &mut Socket,
+    liveness: &mut Liveness,
+) -> Result<()> {
+    if let Err(why) = connection.wait(IDLE_TIMEOUT) {
+        error!(target: CONNECTION, "Failed to wait for connection: {why}");
+        thread::sleep(time::Duration::from_millis(500));
+    }
+
+    match *manager.write_probe_interval.lock() {
+        Some(interval) => liveness.check(manager, connection, interval),
+        None => Ok(()),
     }
 }
 
@@ -119,101 +1182,1150 @@ fn send_and_receive_loop(
     err_sleep: Duration,
     connection_attempts: &Arc<Mutex<Option<usize>>>,
 ) {
-    trace!("Starting sender loop");
+    trace!(target: CONNECTION, "Starting sender loop");
+
+    // Ordered from highest to lowest priority
+    let outbound = [
+        manager.priority_outbound.0.clone(),
+        manager.outbound.0.clone(),
+    ];
 
-    let mut inbound = manager.inbound.1.clone();
-    let outbound = manager.outbound.0.clone();
+    manager.set_state(ConnectionState::Connecting, StateChangeReason::Started);
+    let mut liveness = Liveness::new();
+    let mut suspend = SuspendDetector::new();
+    let mut resumed = false;
 
     loop {
         if rx.try_recv().is_ok() {
+            manager.set_state(ConnectionState::Stopped, StateChangeReason::Shutdown);
             break;
         }
 
+        resumed |= manager.disconnect_if_resumed(&mut suspend);
+
+        if let Err(why) = manager.unsubscribe_emptied() {
+            error!(target: CONNECTION, "Failed to unsubscribe: {why}");
+        }
+
         let connection = manager.connection.clone();
 
         match *connection {
             Some(ref conn) => {
                 let mut connection = conn.lock();
-                match send_and_receive(
-                    &mut connection,
-                    &manager.event_handler_registry,
-                    &mut inbound,
-                    &outbound,
-                ) {
+                let result = match send_and_receive(manager, &mut connection, &outbound) {
+                    // Only wait once there is nothing left to read
+                    Err(ref why) if why.io_would_block() => {
+                        wait_while_idle(manager, &mut connection, &mut liveness)
+                    }
+                    result => result,
+                };
+
+                match result {
                     Err(DiscordError::IoError(ref err)) if err.kind() == ErrorKind::WouldBlock => {}
-                    Err(DiscordError::IoError(_) | DiscordError::ConnectionClosed) => {
+                    Err(
+                        ref why @ (DiscordError::IoError(_)
+                        | DiscordError::ConnectionClosed
+                        | DiscordError::Closed(_)),
+                    ) => {
                         manager.disconnect();
+                        if let DiscordError::Closed(_) = why {
+                            manager.dispatch(&EventContext::new(
+                                Event::Error,
+                                EventData::Error(error_event(why)),
+                                manager.generation(),
+                                None,
+                            ));
+                        }
+                        manager.set_state(
+                            ConnectionState::Disconnected,
+                            StateChangeReason::ConnectionLost(why.to_string()),
+                        );
                     }
-                    Err(DiscordError::TimeoutError(_)) => continue,
-                    Err(why) => trace!("discord error: {}", why),
+                    Err(DiscordError::TimeoutError(_)) => {}
+                    Err(why) => trace!(target: CONNECTION, "discord error: {}", why),
                     _ => {}
                 }
-
-                thread::sleep(time::Duration::from_millis(500));
             }
-            None => match manager.connect() {
-                Err(err) => {
-                    manager.event_handler_registry.handle(
-                        Event::Error,
-                        crate::models::EventData::Error(ErrorEvent {
-                            code: None,
-                            message: Some(err.to_string()),
-                        }),
+            None => {
+                if let Err(err) = manager.connect() {
+                    manager.set_state(
+                        ConnectionState::Disconnected,
+                        StateChangeReason::ConnectFailed(err.to_string()),
                     );
+                    manager.dispatch(&EventContext::new(
+                        Event::Error,
+                        EventData::Error(error_event(&err)),
+                        manager.generation(),
+                        None,
+                    ));
 
                     if err.should_break() {
+                        manager.set_state(ConnectionState::Stopped, StateChangeReason::GaveUp);
                         break;
                     }
-                    error!("Failed to connect: {:?}", err);
+                    error!(target: CONNECTION, "Failed to connect: {:?}", err);
 
                     let mut attempts = connection_attempts.lock();
                     if let Some(ref mut attempts) = *attempts {
                         if *attempts == 0 {
+                            manager.set_state(ConnectionState::Stopped, StateChangeReason::GaveUp);
                             break;
                         }
 
                         *attempts -= 1;
                     }
 
-                    thread::sleep(err_sleep);
+                    // Shutting down interrupts the wait
+                    if rx.recv_timeout(err_sleep).is_ok() {
+                        manager.set_state(ConnectionState::Stopped, StateChangeReason::Shutdown);
+                        break;
+                    }
+                } else {
+                    manager.handshake_completed = true;
+                    liveness = Liveness::new();
+
+                    // The activity is not shown while suspended, and does not carry over to the new connection
+                    if std::mem::take(&mut resumed) {
+                        if let Err(why) = manager.resend_activity() {
+                            error!(target: CONNECTION, "Failed to send the activity again: {why}");
+                        }
+                    }
                 }
-                _ => manager.handshake_completed = true,
-            },
+            }
         }
     }
 }
 
-fn send_and_receive(
-    connection: &mut Socket,
-    event_handler_registry: &Arc<HandlerRegistry>,
-    inbound: &mut Tx,
-    outbound: &Rx,
-) -> Result<()> {
-    while let Ok(msg) = outbound.try_recv() {
-        trace!("Sending message");
-        connection.send(&msg)?;
-        trace!("Sent message");
+/// The error event reporting a connection error, with Discord's close code if it closed the connection
+fn error_event(err: &DiscordError) -> ErrorEvent {
+    ErrorEvent {
+        code: match err {
+            DiscordError::Closed(ref event) => event.code,
+            _ => None,
+        },
+        message: Some(err.to_string()),
     }
+}
 
-    trace!("Receiving from connection");
-    let msg = connection.recv()?;
-    trace!("Received from connection");
+/// The error a command fails with, given the data of the `ERROR` Discord responded with
+fn command_failed(cmd: Command, data: Option<JsonValue>) -> DiscordError {
+    let error = data
+        .and_then(|data| serde_json::from_value::<ErrorEvent>(data).ok())
+        .unwrap_or_default();
+    let message = error.message.unwrap_or_default();
 
-    let payload: Payload<JsonValue> = serde_json::from_str(&msg.payload)?;
+    match cmd {
+        Command::Authenticate => DiscordError::AuthenticationFailed(message),
+        Command::Authorize => DiscordError::AuthorizationFailed(message),
+        _ => DiscordError::CommandFailed {
+            code: error.code,
+            message,
+        },
+    }
+}
+
+/// Whether sending the command twice has the same effect as sending it once
+fn can_resend(cmd: Command) -> bool {
+    matches!(
+        cmd,
+        Command::SetActivity | Command::Subscribe | Command::Unsubscribe
+    )
+}
+
+/// Waits for the response delivered on `rx`, giving up after `timeout` or once `cancelled` is set
+fn wait_for_response(
+    cmd: Command,
+    timeout: Duration,
+    rx: &Rx,
+    cancelled: Option<&AtomicBool>,
+) -> Result<Message> {
+    let deadline = Instant::now() + timeout;
+
+    loop {
+        let now = Instant::now();
+        if now >= deadline {
+            warn!(target: CONNECTION, "No response to {cmd:?} within {timeout:?}");
+            return Err(RecvTimeoutError::Timeout.into());
+        }
 
-    trace!("Received payload");
+        let wait = match cancelled {
+            Some(_) => CANCEL_POLL_INTERVAL.min(deadline - now),
+            None => deadline - now,
+        };
+        match rx.recv_timeout(wait) {
+            Ok(response) => return Ok(response),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return Err(DiscordError::ConnectionClosed),
+        }
+
+        if cancelled.is_some_and(|cancelled| cancelled.load(Ordering::Acquire)) {
+            return Err(DiscordError::Cancelled);
+        }
+    }
+}
 
-    if let Payload {
-        evt: Some(event), ..
-    } = &payload
+/// Takes the next message from the highest priority lane that has one, dropping those that were cancelled.
+///
+/// The higher priority lanes are checked again before every message, so they are never stuck behind a backlog.
+fn next_outbound(lanes: &[Lane]) -> Option<Message> {
+    lanes
+        .iter()
+        .find_map(|lane| {
+            lane.try_iter().find(|outgoing| {
+                let cancelled = outgoing.is_cancelled();
+                if cancelled {
+                    trace!(target: CONNECTION, "Dropping cancelled message");
+                }
+                !cancelled
+            })
+        })
+        .map(|outgoing| outgoing.message)
+}
+
+fn send_and_receive(manager: &Manager, connection: &mut Socket, outbound: &[Lane]) -> Result<()> {
+    // A message whose write failed goes out ahead of the rest, followed by those the client sent on its own
+    let mut unsent = manager.unsent.lock().take();
+    while let Some(msg) = unsent
+        .take()
+        .or_else(|| manager.backlog.lock().pop_front())
+        .or_else(|| next_outbound(outbound))
     {
-        trace!("Got event");
+        trace!(target: CONNECTION, "Sending message");
+        match connection.send(&msg) {
+            Ok(()) => {}
+            // None of the message was written, so it goes out first once the socket can take more,
+            // and receiving carries on in the meantime
+            Err(why) if why.io_would_block() => {
+                *manager.unsent.lock() = Some(msg);
+                break;
+            }
+            Err(why) => {
+                manager.write_failed(msg);
+                return Err(why);
+            }
+        }
+        Counters::count(
+            &manager.stats.bytes_sent,
+            &manager.stats.frames_sent,
+            msg.payload.len(),
+        );
+        trace!(target: CONNECTION, "Sent message");
+    }
+
+    trace!(target: CONNECTION, "Receiving from connection");
+    let msg = connection.recv()?;
+    Counters::count(
+        &manager.stats.bytes_received,
+        &manager.stats.frames_received,
+        msg.payload.len(),
+    );
+    trace!(target: CONNECTION, "Received from connection");
+
+    received(manager, msg)
+}
+
+/// Handles a message received from the connection, passing responses to the command waiting for them and events to the handlers
+fn received(manager: &Manager, msg: Message) -> Result<()> {
+    // The connection has already answered pings, and pongs only answer `Connection::ping`
+    match msg.opcode {
+        OpCode::Ping => {
+            trace!(target: CONNECTION, "Answered ping");
+            manager.event_handler_registry.handle_ping(&Ping {
+                payload: codec::decode(&msg.payload).unwrap_or(JsonValue::Null),
+                received_at: SystemTime::now(),
+            });
+            return Ok(());
+        }
+        OpCode::Pong => return Ok(()),
+        _ => {}
+    }
+
+    let payload: Payload<JsonValue> = codec::decode(&msg.payload)?;
+
+    trace!(target: CONNECTION, "Received payload");
+
+    // Responses to commands name the command, and carry an `ERROR` event if it failed
+    let detached = payload.cmd != Command::Dispatch
+        && payload
+            .nonce
+            .as_ref()
+            .is_some_and(|nonce| manager.detached_nonces.lock().remove(nonce));
+    if detached {
+        manager.reauthenticated(&payload);
+    }
+    manager.activity_acknowledged(&payload);
+    let event = match payload.evt {
+        Some(event) if payload.cmd == Command::Dispatch => Some(event),
+        // Nobody is waiting for the response to a detached command, so its failure is reported as an event
+        Some(Event::Error) if detached => Some(Event::Error),
+        _ => None,
+    };
+
+    if let Some(event) = event {
+        trace!(target: DISPATCH, "Got event");
         let event_data = event.parse_data(into_error!(payload.data.clone())?);
-        event_handler_registry.handle(*event, event_data);
+        let context = EventContext::new(
+            event,
+            event_data,
+            manager.generation(),
+            payload.nonce.clone(),
+        );
+        manager.dispatch(&match payload.cmd {
+            Command::Dispatch => context,
+            cmd => context.in_reply_to(cmd),
+        });
+    } else if detached {
+        trace!(target: CONNECTION, "Got response to queued command");
+    } else if let Some(waiter) = payload
+        .nonce
+        .as_ref()
+        .and_then(|nonce| manager.pending.lock().remove(nonce))
+    {
+        trace!(target: CONNECTION, "Got response");
+        // Each waiter has room for exactly one response, so this only fails once it stopped waiting
+        waiter.try_send(msg).ok();
     } else {
-        trace!("Got message");
-        inbound.send(msg)?;
+        trace!(target: CONNECTION, "Dropping a response nobody is waiting for");
     }
 
     Ok(())
 }
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use crate::models::ActivityJoinRequestEvent;
+
+    fn manager() -> Manager {
+        Manager::new(
+            1_003_450_375_732_482_138,
+            Arc::new(HandlerRegistry::new()),
+            Duration::from_secs(5),
+            None,
+            ChannelCapacities::default(),
+        )
+    }
+
+    fn message(n: u32) -> Message {
+        Message::new(OpCode::Frame, n).unwrap()
+    }
+
+    /// Waits for the next message the connection thread would send
+    fn sent_within(manager: &Manager, timeout: Duration) -> Message {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(message) = sent(manager) {
+                return message;
+            }
+            assert!(Instant::now() < deadline, "nothing was sent");
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    /// Answers `request` as Discord would, with an `ERROR` event if `data` has an error code
+    fn respond(manager: &Manager, request: &Message, data: JsonValue) {
+        let request: Payload<JsonValue> = codec::decode(&request.payload).unwrap();
+        let response = Payload {
+            cmd: request.cmd,
+            args: None,
+            evt: data.get("code").map(|_| Event::Error),
+            data: Some(data),
+            nonce: request.nonce,
+        };
+        received(manager, Message::new(OpCode::Frame, response).unwrap()).unwrap();
+    }
+
+    /// The next message the connection thread would send
+    fn sent(manager: &Manager) -> Option<Message> {
+        let lanes = [
+            manager.priority_outbound.0.clone(),
+            manager.outbound.0.clone(),
+        ];
+        let backlogged = manager.backlog.lock().pop_front();
+        backlogged.or_else(|| next_outbound(&lanes))
+    }
+
+    #[test]
+    fn cancelled_commands_are_not_sent() {
+        let manager = manager();
+        manager.set_ready();
+        let cancelled = Arc::new(AtomicBool::new(true));
+
+        let result = manager.execute_unless_cancelled::<_, JsonValue>(
+            Command::SetActivity,
+            0,
+            None,
+            Priority::Normal,
+            Some(&cancelled),
+        );
+        assert!(matches!(result, Err(DiscordError::Cancelled)));
+        assert!(sent(&manager).is_none());
+    }
+
+    #[test]
+    fn commands_cancelled_in_their_lane_are_dropped() {
+        let manager = manager();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        manager
+            .send(message(0), Priority::Normal, Some(&cancelled))
+            .unwrap();
+        manager.send(message(1), Priority::Normal, None).unwrap();
+
+        cancelled.store(true, Ordering::Release);
+        assert_eq!(sent(&manager), Some(message(1)));
+        assert_eq!(sent(&manager), None);
+    }
+
+    #[test]
+    fn cancelled_commands_stop_waiting() {
+        let manager = manager();
+        manager.set_ready();
+        let cancelled = Arc::new(AtomicBool::new(false));
+
+        thread::scope(|scope| {
+            let command = scope.spawn(|| {
+                manager.execute_unless_cancelled::<_, JsonValue>(
+                    Command::SetActivity,
+                    0,
+                    None,
+                    Priority::Normal,
+                    Some(&cancelled),
+                )
+            });
+
+            let request = sent_within(&manager, Duration::from_secs(1));
+            cancelled.store(true, Ordering::Release);
+            assert!(matches!(
+                command.join().unwrap(),
+                Err(DiscordError::Cancelled)
+            ));
+
+            // The late response is dropped, rather than given to the next command
+            respond(&manager, &request, json!({}));
+        });
+
+        assert!(manager.pending.lock().is_empty());
+    }
+
+    #[test]
+    fn responses_are_matched_by_nonce() {
+        let manager = manager();
+        manager.set_ready();
+
+        thread::scope(|scope| {
+            let command = |n: u32| {
+                let manager = &manager;
+                scope.spawn(move || {
+                    manager
+                        .execute::<_, JsonValue>(Command::GetGuild, n, None, Priority::Normal)
+                        .map(|response| response.data)
+                })
+            };
+            let first = command(1);
+            let first_request = sent_within(&manager, Duration::from_secs(1));
+            let second = command(2);
+            let second_request = sent_within(&manager, Duration::from_secs(1));
+
+            // Discord answers out of order
+            respond(&manager, &second_request, json!(2));
+            respond(&manager, &first_request, json!(1));
+
+            assert_eq!(first.join().unwrap().unwrap(), Some(json!(1)));
+            assert_eq!(second.join().unwrap().unwrap(), Some(json!(2)));
+        });
+    }
+
+    #[test]
+    fn subscribes_automatically_once() {
+        let manager = manager();
+
+        // Sent once connected
+        manager
+            .subscribe_automatically(Event::ActivityJoin)
+            .unwrap();
+        assert!(sent(&manager).is_none());
+
+        manager.set_ready();
+        manager
+            .subscribe_automatically(Event::ActivityJoin)
+            .unwrap();
+        manager
+            .subscribe_automatically(Event::ActivitySpectate)
+            .unwrap();
+
+        let subscription: Payload<JsonValue> =
+            codec::decode(&sent(&manager).unwrap().payload).unwrap();
+        assert_eq!(subscription.cmd, Command::Subscribe);
+        assert_eq!(subscription.evt, Some(Event::ActivitySpectate));
+        assert!(manager
+            .detached_nonces
+            .lock()
+            .contains(&subscription.nonce.unwrap()));
+        assert!(sent(&manager).is_none());
+
+        manager.set_auto_subscribe(false);
+        manager
+            .subscribe_automatically(Event::ActivityJoinRequest)
+            .unwrap();
+        assert!(sent(&manager).is_none());
+        assert_eq!(manager.auto_subscriptions.lock().len(), 2);
+    }
+
+    #[test]
+    fn error_responses_are_returned() {
+        let manager = manager();
+        manager.set_ready();
+
+        thread::scope(|scope| {
+            let command = scope.spawn(|| {
+                manager.execute::<_, JsonValue>(
+                    Command::Subscribe,
+                    SubscriptionArgs::new(),
+                    Some(Event::MessageCreate),
+                    Priority::Normal,
+                )
+            });
+
+            let request = sent_within(&manager, Duration::from_secs(1));
+            let error = json!({ "code": 4006, "message": "Not authenticated or invalid scope" });
+            respond(&manager, &request, error);
+            assert!(matches!(
+                command.join().unwrap(),
+                Err(DiscordError::CommandFailed { code: Some(4006), ref message })
+                    if message == "Not authenticated or invalid scope"
+            ));
+        });
+
+        assert_eq!(manager.last_error(), None);
+    }
+
+    #[test]
+    fn responses_time_out_as_configured() {
+        let manager = manager();
+        manager.set_ready();
+        manager.set_response_timeout(Duration::from_millis(50));
+
+        let started = Instant::now();
+        let result = manager.execute::<_, JsonValue>(
+            Command::Subscribe,
+            SubscriptionArgs::new(),
+            Some(Event::MessageCreate),
+            Priority::Normal,
+        );
+        assert!(matches!(result, Err(DiscordError::TimeoutError(_))));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn replies_carry_their_command() {
+        let frames = [
+            json!({
+                "cmd": "SET_ACTIVITY",
+                "evt": "ERROR",
+                "nonce": "queued",
+                "data": { "code": 4000, "message": "Invalid activity" },
+            }),
+            json!({ "cmd": "DISPATCH", "evt": "ERROR", "data": { "code": 1000 } }),
+        ];
+        let manager = manager();
+        manager
+            .detached_nonces
+            .lock()
+            .insert(String::from("queued"));
+        let (tx, rx) = unbounded();
+        let _handler = manager
+            .event_handler_registry
+            .register_wildcard(move |ctx| {
+                let reply = (ctx.reply_command(), ctx.reply_nonce().map(String::from));
+                tx.send(reply).ok();
+            });
+
+        for frame in frames {
+            received(&manager, Message::new(OpCode::Frame, frame).unwrap()).unwrap();
+        }
+
+        let reply = rx.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(
+            reply,
+            (Some(Command::SetActivity), Some(String::from("queued")))
+        );
+        assert_eq!(
+            rx.recv_timeout(Duration::from_secs(1)).unwrap(),
+            (None, None)
+        );
+    }
+
+    #[test]
+    #[cfg(all(unix, not(feature = "io-uring")))]
+    fn probes_idle_connections() {
+        use std::io::Read;
+
+        let (mut socket, mut stream) = crate::connection::socket_pair("probe-idle");
+
+        let manager = manager();
+        let mut liveness = Liveness::new();
+        let interval = Duration::from_millis(20);
+        liveness.check(&manager, &mut socket, interval).unwrap();
+
+        thread::sleep(interval);
+        liveness.check(&manager, &mut socket, interval).unwrap();
+        let mut frame = [0; 10];
+        stream.read_exact(&mut frame).unwrap();
+        assert_eq!(Message::decode(&frame).unwrap().opcode, OpCode::Ping);
+
+        // Nothing reads the rest, so the write stalls
+        let large = Message::new(OpCode::Frame, json!({ "data": "x".repeat(1 << 20) })).unwrap();
+        socket.send(&large).unwrap();
+        liveness.check(&manager, &mut socket, interval).unwrap();
+        thread::sleep(interval);
+        assert!(matches!(
+            liveness.check(&manager, &mut socket, interval),
+            Err(DiscordError::IoError(ref why)) if why.kind() == ErrorKind::TimedOut
+        ));
+    }
+
+    #[test]
+    fn detects_suspends() {
+        let mut suspend = SuspendDetector::new();
+        let (instant, wall) = (suspend.instant, suspend.wall);
+
+        assert_eq!(
+            suspend.check(instant + IDLE_TIMEOUT, wall + IDLE_TIMEOUT),
+            None
+        );
+
+        // The monotonic clock stops while suspended on Linux and macOS, but the wall clock does not
+        let resumed = wall + IDLE_TIMEOUT + Duration::from_secs(3600);
+        assert_eq!(
+            suspend.check(instant + IDLE_TIMEOUT, resumed),
+            Some(Duration::from_secs(3600))
+        );
+        assert_eq!(
+            suspend.check(instant + Duration::from_secs(65), resumed),
+            Some(Duration::from_secs(60))
+        );
+    }
+
+    #[test]
+    fn resends_the_last_activity() {
+        let manager = manager();
+        manager.resend_activity().unwrap();
+        assert!(sent(&manager).is_none());
+
+        let args = SetActivityArgs::new(|activity| activity.state("Resumed"));
+        manager.record_activity(args.clone());
+        manager.resend_activity().unwrap();
+
+        let resent = sent(&manager).unwrap();
+        let payload: Payload<SetActivityArgs> = codec::decode(&resent.payload).unwrap();
+        assert_eq!(payload.cmd, Command::SetActivity);
+        assert_eq!(payload.args, Some(args));
+        assert!(manager
+            .detached_nonces
+            .lock()
+            .contains(payload.nonce.as_ref().unwrap()));
+    }
+
+    #[test]
+    fn unsubscribes_once_handlers_are_gone() {
+        let manager = manager();
+        manager.set_ready();
+
+        let channel = SubscriptionArgs::new().channel_id(1);
+        manager.track_subscription(Command::Subscribe, Event::MessageCreate, channel.clone());
+        let handler = manager
+            .event_handler_registry
+            .register(Event::MessageCreate, |_| {});
+        let _ready = manager
+            .event_handler_registry
+            .register(Event::Ready, |_| {});
+
+        drop(handler);
+        manager.unsubscribe_emptied().unwrap();
+
+        let unsubscription: Payload<SubscriptionArgs> =
+            codec::decode(&sent(&manager).unwrap().payload).unwrap();
+        assert_eq!(unsubscription.cmd, Command::Unsubscribe);
+        assert_eq!(unsubscription.evt, Some(Event::MessageCreate));
+        assert_eq!(unsubscription.args, Some(channel));
+        assert!(sent(&manager).is_none());
+        assert!(manager.subscriptions.lock().is_empty());
+    }
+
+    #[test]
+    fn reauthenticates_with_the_stored_token() {
+        let manager = manager();
+        manager.reauthenticate().unwrap();
+        assert!(sent(&manager).is_none());
+
+        manager.set_access_token(Some(String::from("token")));
+        manager.reauthenticate().unwrap();
+        let request: Payload<AuthenticateArgs> =
+            codec::decode(&sent(&manager).unwrap().payload).unwrap();
+        assert_eq!(request.cmd, Command::Authenticate);
+        assert_eq!(request.args.unwrap().access_token.as_deref(), Some("token"));
+
+        manager.reauthenticated(&Payload {
+            cmd: Command::Authenticate,
+            args: None,
+            data: Some(json!({})),
+            evt: None,
+            nonce: request.nonce,
+        });
+        assert!(manager.reauthentication.lock().is_none());
+        assert_eq!(manager.access_token.lock().as_deref(), Some("token"));
+    }
+
+    #[test]
+    fn probes_are_counted_whole() {
+        assert_eq!(
+            serde_json::to_string(&Probe {}).unwrap().len(),
+            PROBE_LENGTH
+        );
+    }
+
+    #[test]
+    fn generates_nonces_with_the_configured_generator() {
+        let manager = manager();
+        let nonces = crate::nonce::Sequential::with_prefix("test");
+        manager.set_nonce_generator(Some(Arc::new(move || nonces.next())));
+        manager.set_access_token(Some(String::from("token")));
+
+        for expected in ["test-1", "test-2"] {
+            manager.reauthenticate().unwrap();
+            let request: Payload<JsonValue> =
+                codec::decode(&sent(&manager).unwrap().payload).unwrap();
+            assert_eq!(request.nonce.as_deref(), Some(expected));
+        }
+
+        manager.set_nonce_generator(None);
+        manager.reauthenticate().unwrap();
+        let request: Payload<JsonValue> = codec::decode(&sent(&manager).unwrap().payload).unwrap();
+        assert!(request.nonce.unwrap().parse::<uuid::Uuid>().is_ok());
+    }
+
+    #[test]
+    fn rejected_tokens_fail_authentication() {
+        let manager = manager();
+        manager.set_ready();
+
+        thread::scope(|scope| {
+            let command = scope.spawn(|| {
+                let args = AuthenticateArgs::new().access_token("expired");
+                manager.execute::<_, JsonValue>(Command::Authenticate, args, None, Priority::Normal)
+            });
+
+            let request = sent_within(&manager, Duration::from_secs(1));
+            respond(
+                &manager,
+                &request,
+                json!({ "code": 4009, "message": "Invalid access token" }),
+            );
+            assert!(matches!(
+                command.join().unwrap(),
+                Err(DiscordError::AuthenticationFailed(ref message)) if message == "Invalid access token"
+            ));
+        });
+    }
+
+    #[test]
+    fn authorization_does_not_hold_up_commands() {
+        let manager = manager();
+        manager.set_ready();
+
+        thread::scope(|scope| {
+            let authorize = scope.spawn(|| {
+                manager.execute::<_, JsonValue>(
+                    Command::Authorize,
+                    crate::models::AuthorizeArgs::new(1, [OAuthScope::Rpc]),
+                    None,
+                    Priority::Normal,
+                )
+            });
+            let authorization = sent_within(&manager, Duration::from_secs(1));
+
+            // The user has yet to answer the prompt
+            let command = scope.spawn(|| {
+                manager.execute::<_, JsonValue>(Command::GetGuilds, 0, None, Priority::Normal)
+            });
+            let request = sent_within(&manager, Duration::from_secs(1));
+            respond(&manager, &request, json!([]));
+            assert!(command.join().unwrap().is_ok());
+
+            respond(
+                &manager,
+                &authorization,
+                json!({ "code": 5000, "message": "OAuth2 Error: access_denied" }),
+            );
+            assert!(matches!(
+                authorize.join().unwrap(),
+                Err(DiscordError::AuthorizationFailed(ref message)) if message.contains("access_denied")
+            ));
+        });
+    }
+
+    #[test]
+    fn forgets_rejected_tokens() {
+        let manager = manager();
+        let (tx, rx) = bounded(1);
+        manager.set_token_expired_hook(Some(Arc::new(move |error| {
+            tx.send(error).ok();
+        })));
+        manager.set_access_token(Some(String::from("token")));
+        manager.reauthenticate().unwrap();
+        let request: Payload<JsonValue> = codec::decode(&sent(&manager).unwrap().payload).unwrap();
+
+        let rejection = |nonce| Payload {
+            cmd: Command::Authenticate,
+            args: None,
+            data: Some(json!({ "code": 4009, "message": "Invalid token" })),
+            evt: Some(Event::Error),
+            nonce,
+        };
+        manager.reauthenticated(&rejection(Some(String::from("other"))));
+        assert_eq!(manager.access_token.lock().as_deref(), Some("token"));
+
+        manager.reauthenticated(&rejection(request.nonce));
+        assert!(manager.access_token.lock().is_none());
+        let error = rx.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(error.message.as_deref(), Some("Invalid token"));
+    }
+
+    #[test]
+    fn checks_granted_scopes() {
+        let manager = manager();
+        manager.check_scopes(&[OAuthScope::Rpc]).unwrap();
+
+        manager.set_granted_scopes(Some(HashSet::from([OAuthScope::Rpc])));
+        manager.check_scopes(&[OAuthScope::Rpc]).unwrap();
+        assert!(matches!(
+            manager.check_scopes(Command::GetVoiceSettings.required_scopes()),
+            Err(DiscordError::MissingScope(OAuthScope::RpcVoiceRead))
+        ));
+    }
+
+    #[test]
+    fn offline_queue_is_disabled_by_default() {
+        let manager = manager();
+        assert!(matches!(
+            manager.enqueue(message(0), None, Priority::Normal),
+            Err(DiscordError::NotStarted)
+        ));
+    }
+
+    #[test]
+    fn offline_queue_flushes_in_order() {
+        let manager = manager();
+        manager.set_offline_queue(2, Duration::from_secs(60));
+
+        for n in 0..3 {
+            manager
+                .enqueue(message(n), Some(n.to_string()), Priority::Normal)
+                .unwrap();
+        }
+        manager.flush_offline_queue(&mut manager.offline_queue.lock());
+
+        let flushed: Vec<_> = std::iter::from_fn(|| sent(&manager)).collect();
+        assert_eq!(flushed, [message(1), message(2)]);
+        assert_eq!(manager.detached_nonces.lock().len(), 2);
+    }
+
+    #[test]
+    fn disconnecting_queues_commands_again() {
+        let mut manager = manager();
+        manager.set_offline_queue(1, Duration::from_secs(60));
+        manager.set_ready();
+        assert!(manager.is_ready());
+
+        manager.disconnect();
+        assert!(!manager.is_ready());
+        let result = manager.execute::<_, JsonValue>(Command::GetGuilds, 0, None, Priority::Normal);
+        assert!(matches!(result, Err(DiscordError::Queued)));
+        assert!(sent(&manager).is_none());
+
+        // Flushed once the next handshake completes
+        assert!(!manager.set_ready());
+        assert!(sent(&manager).is_some());
+    }
+
+    #[test]
+    fn offline_queue_flushes_past_channel_capacity() {
+        let manager = Manager::new(
+            1_003_450_375_732_482_138,
+            Arc::new(HandlerRegistry::new()),
+            Duration::from_secs(5),
+            None,
+            ChannelCapacities {
+                outbound: Some(1),
+                ..Default::default()
+            },
+        );
+        manager.set_offline_queue(4, Duration::from_secs(60));
+        for n in 0..3 {
+            manager.enqueue(message(n), None, Priority::Normal).unwrap();
+        }
+        manager.enqueue(message(3), None, Priority::High).unwrap();
+
+        // Nothing else empties the outbound channel, so this would block if the flush waited for room in it
+        manager.set_ready();
+
+        let flushed: Vec<_> = std::iter::from_fn(|| sent(&manager)).collect();
+        assert_eq!(flushed, [message(3), message(0), message(1), message(2)]);
+    }
+
+    #[test]
+    fn offline_queue_drops_expired_commands() {
+        let manager = manager();
+        manager.set_offline_queue(2, Duration::ZERO);
+
+        manager.enqueue(message(0), None, Priority::Normal).unwrap();
+        thread::sleep(Duration::from_millis(1));
+        manager.flush_offline_queue(&mut manager.offline_queue.lock());
+
+        assert!(sent(&manager).is_none());
+    }
+
+    #[test]
+    fn priority_messages_are_sent_first() {
+        let manager = manager();
+        manager.send(message(0), Priority::Normal, None).unwrap();
+        manager.send(message(1), Priority::High, None).unwrap();
+
+        let lanes = [
+            manager.priority_outbound.0.clone(),
+            manager.outbound.0.clone(),
+        ];
+        assert_eq!(next_outbound(&lanes), Some(message(1)));
+        assert_eq!(next_outbound(&lanes), Some(message(0)));
+        assert_eq!(next_outbound(&lanes), None);
+    }
+
+    #[test]
+    fn failed_writes_are_resent_or_fail() {
+        let manager = manager();
+
+        let activity = Payload::with_nonce(Command::SetActivity, Some(json!({})), None, None);
+        let activity = Message::new(OpCode::Frame, activity).unwrap();
+        manager.write_failed(activity.clone());
+        assert_eq!(*manager.unsent.lock(), Some(activity));
+
+        let invite =
+            Payload::with_nonce(Command::SendActivityJoinInvite, Some(json!({})), None, None);
+        let nonce = invite.nonce.clone();
+        let (tx, rx) = bounded(1);
+        manager.pending.lock().insert(nonce.clone().unwrap(), tx);
+        manager.write_failed(Message::new(OpCode::Frame, invite).unwrap());
+
+        let failure: Payload<JsonValue> = codec::decode(&rx.try_recv().unwrap().payload).unwrap();
+        assert_eq!(failure.evt, Some(Event::Error));
+        assert_eq!(failure.nonce, nonce);
+    }
+
+    #[test]
+    fn stats_count_traffic() {
+        let manager = manager();
+        assert_eq!(manager.stats(), ConnectionStats::default());
+
+        let message = Message::new(OpCode::Frame, json!({ "cmd": "SUBSCRIBE" })).unwrap();
+        Counters::count(
+            &manager.stats.bytes_sent,
+            &manager.stats.frames_sent,
+            message.payload.len(),
+        );
+        Counters::count(
+            &manager.stats.bytes_sent,
+            &manager.stats.frames_sent,
+            message.payload.len(),
+        );
+        manager.record_activity(SetActivityArgs::default());
+
+        let stats = manager.stats();
+        assert_eq!(stats.frames_sent, 2);
+        assert_eq!(stats.bytes_sent, 2 * (message.payload.len() as u64 + 8));
+        assert_eq!(stats.frames_received, 0);
+        assert_eq!(stats.uptime, None);
+        assert!(stats.activity_sent_at.is_some());
+    }
+
+    #[test]
+    fn repeated_states_are_not_reported() {
+        let manager = manager();
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let _state = manager
+            .event_handler_registry
+            .register_state_handler(move |change| tx.send(change).unwrap());
+
+        manager.set_state(ConnectionState::Connecting, StateChangeReason::Started);
+        manager.set_state(ConnectionState::Connecting, StateChangeReason::Retrying);
+        manager.set_state(
+            ConnectionState::Disconnected,
+            StateChangeReason::ConnectFailed(String::from("refused")),
+        );
+
+        let timeout = Duration::from_secs(1);
+        let change = rx.recv_timeout(timeout).unwrap();
+        assert_eq!(change.old, ConnectionState::Disconnected);
+        assert_eq!(change.new, ConnectionState::Connecting);
+        assert_eq!(change.reason, StateChangeReason::Started);
+        let change = rx.recv_timeout(timeout).unwrap();
+        assert_eq!(change.old, ConnectionState::Connecting);
+        assert_eq!(change.new, ConnectionState::Disconnected);
+        assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+        assert_eq!(manager.state(), ConnectionState::Disconnected);
+    }
+
+    #[test]
+    fn dispatch_records_last_error() {
+        let manager = manager();
+        assert_eq!(manager.last_error(), None);
+
+        let error = ErrorEvent::new().code(4000).message("Invalid Client ID");
+        manager.dispatch(&EventContext::new(
+            Event::Error,
+            EventData::Error(error.clone()),
+            0,
+            None,
+        ));
+
+        assert_eq!(manager.last_error(), Some(error));
+    }
+
+    #[test]
+    fn dispatch_tracks_join_requests() {
+        let manager = manager();
+
+        let request = |id: u64, received_at: SystemTime| {
+            let mut context = EventContext::new(
+                Event::ActivityJoinRequest,
+                EventData::ActivityJoinRequest(
+                    ActivityJoinRequestEvent::new().user(PartialUser::new().id(id)),
+                ),
+                0,
+                None,
+            );
+            context.received_at = received_at;
+            context
+        };
+
+        let now = SystemTime::now();
+        manager.dispatch(&request(1, now));
+        manager.dispatch(&request(2, now));
+        manager.dispatch(&request(1, now));
+        manager.dispatch(&request(3, now - JOIN_REQUEST_TIMEOUT));
+
+        let ids = |manager: &Manager| {
+            manager
+                .join_requests()
+                .into_iter()
+                .filter_map(|request| request.user.id.map(Snowflake::get))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(&manager), [2, 1]);
+
+        manager.resolve_join_request(Snowflake::new(2));
+        assert_eq!(ids(&manager), [1]);
+    }
+
+    #[test]
+    fn channel_capacities_bound_channels() {
+        let manager = Manager::new(
+            1_003_450_375_732_482_138,
+            Arc::new(HandlerRegistry::new()),
+            Duration::from_secs(5),
+            None,
+            ChannelCapacities {
+                outbound: Some(1),
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(manager.outbound.1.capacity(), Some(1));
+        assert_eq!(manager.priority_outbound.1.capacity(), None);
+    }
+
+    #[test]
+    fn handlers_can_send_commands() {
+        let manager = manager();
+        manager.set_ready();
+        let (tx, rx) = crossbeam_channel::unbounded();
+
+        let _ready = manager.event_handler_registry.register(Event::Ready, {
+            let manager = manager.clone();
+            move |_| {
+                let response =
+                    manager.execute::<_, JsonValue>(Command::GetGuild, 1, None, Priority::Normal);
+                tx.send(response.unwrap().data).unwrap();
+            }
+        });
+
+        thread::scope(|scope| {
+            // Another command is waiting for its response while the handler runs
+            let waiting = scope.spawn(|| {
+                manager.execute::<_, JsonValue>(Command::GetGuilds, 0, None, Priority::Normal)
+            });
+            let waiting_request = sent_within(&manager, Duration::from_secs(1));
+
+            manager.dispatch(&EventContext::new(
+                Event::Ready,
+                EventData::Unknown(JsonValue::Null),
+                0,
+                None,
+            ));
+
+            let request = sent_within(&manager, Duration::from_secs(1));
+            respond(&manager, &request, json!("guild"));
+            let timeout = Duration::from_secs(1);
+            assert_eq!(rx.recv_timeout(timeout).unwrap(), Some(json!("guild")));
+
+            respond(&manager, &waiting_request, json!([]));
+            assert!(waiting.join().unwrap().is_ok());
+        });
+    }
+}
+
+/// Run with `RUSTFLAGS="--cfg loom" cargo test --release --lib loom`
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use super::*;
+    use loom::thread;
+
+    fn manager() -> Manager {
+        let manager = Manager::new(
+            1_003_450_375_732_482_138,
+            Arc::new(HandlerRegistry::new()),
+            Duration::from_secs(5),
+            None,
+            ChannelCapacities::default(),
+        );
+        manager.set_offline_queue(1, Duration::from_secs(60));
+        manager
+    }
+
+    #[test]
+    fn queued_commands_are_never_stranded() {
+        loom::model(|| {
+            let manager = manager();
+            let message = Message::new(OpCode::Frame, 0).unwrap();
+
+            let enqueue = thread::spawn({
+                let manager = manager.clone();
+                let message = message.clone();
+                move || manager.enqueue(message, None, Priority::Normal).unwrap()
+            });
+
+            let was_ready = manager.set_ready();
+            let unqueued = enqueue.join().unwrap();
+
+            assert!(!was_ready);
+            assert!(manager.offline_queue.lock().messages.is_empty());
+            // The command is either flushed by `set_ready`, or handed back to be sent directly
+            match unqueued {
+                Some(unqueued) => {
+                    assert_eq!(unqueued, message);
+                    assert!(manager.backlog.lock().is_empty());
+                }
+                None => assert_eq!(manager.backlog.lock().pop_front(), Some(message)),
+            }
+        });
+    }
+}
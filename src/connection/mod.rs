@@ -1,13 +1,24 @@
 mod base;
 mod manager;
 
-pub use base::Connection;
-pub use manager::Manager;
+pub use crate::models::message::{Message, OpCode};
+pub use base::{Buffers, ConnectOptions, Connection, Wake, RPC_VERSION};
+pub(crate) use manager::{
+    Manager, Priority, DEFAULT_RESPONSE_TIMEOUT, DEFAULT_WRITE_PROBE_INTERVAL,
+};
 
 cfg_if::cfg_if! {
-    if #[cfg(unix)] {
+    if #[cfg(all(target_os = "linux", feature = "io-uring"))] {
+        mod unix;
+        mod uring;
+        pub use uring::Socket;
+        #[cfg(all(test, any(feature = "broker", feature = "wsl")))]
+        pub(crate) use unix::test_socket_path;
+    } else if #[cfg(unix)] {
         mod unix;
         pub use unix::Socket;
+        #[cfg(test)]
+        pub(crate) use unix::{socket_pair, test_socket_path};
     } else if #[cfg(windows)] {
         mod windows;
         pub use windows::Socket;
@@ -1,43 +1,210 @@
-use super::base::Connection;
-use crate::Result;
-use std::{env, net::Shutdown, os::unix::net::UnixStream, path::PathBuf, time};
+use super::base::{Buffers, Connection, Wake};
+#[cfg(feature = "wsl")]
+use crate::wsl::Bridge;
+use crate::{targets::CONNECTION, Result};
+use mio::{unix::SourceFd, Events, Interest, Poll, Token, Waker};
+use std::{
+    env,
+    io::{self, ErrorKind, Read, Write},
+    net::Shutdown,
+    os::unix::{
+        io::{AsRawFd, RawFd},
+        net::UnixStream,
+    },
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{self, Duration},
+};
 
+/// Environment variables naming the directory Discord creates its socket in, in order of preference
+const IPC_DIR_VARIABLES: [&str; 2] = ["XDG_RUNTIME_DIR", "TMPDIR"];
+/// Directory used when none of [`IPC_DIR_VARIABLES`] are set
+const FALLBACK_IPC_DIR: &str = "/tmp";
+
+const SOCKET: Token = Token(0);
+const WAKER: Token = Token(1);
+
+/// A stream to Discord's socket, or to a bridge relaying to it
+pub enum Stream {
+    /// Discord's own Unix socket
+    Unix(UnixStream),
+    /// A TCP connection to a bridge
+    #[cfg(feature = "wsl")]
+    Tcp(std::net::TcpStream),
+}
+
+macro_rules! delegate {
+    ($stream:expr, $inner:ident => $body:expr) => {
+        match $stream {
+            Stream::Unix($inner) => $body,
+            #[cfg(feature = "wsl")]
+            Stream::Tcp($inner) => $body,
+        }
+    };
+}
+
+impl Stream {
+    fn configure(&self) -> io::Result<()> {
+        delegate!(self, stream => {
+            stream.set_nonblocking(true)?;
+            stream.set_write_timeout(Some(time::Duration::from_secs(30)))?;
+            stream.set_read_timeout(Some(time::Duration::from_secs(30)))
+        })
+    }
+
+    fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        delegate!(self, stream => stream.shutdown(how))
+    }
+}
+
+impl AsRawFd for Stream {
+    fn as_raw_fd(&self) -> RawFd {
+        delegate!(self, stream => stream.as_raw_fd())
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        delegate!(self, stream => stream.read(buf))
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        delegate!(self, stream => stream.write(buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        delegate!(self, stream => stream.flush())
+    }
+}
+
+/// A connection to Discord's Unix socket, woken from other threads through a [`mio`] poll
 pub struct Socket {
-    socket: UnixStream,
+    stream: Stream,
+    buffers: Buffers,
+    poll: Poll,
+    events: Events,
+    /// What the socket is registered with `poll` for
+    interest: Interest,
+}
+
+impl Wake for Waker {
+    fn wake(&self) {
+        if let Err(why) = Waker::wake(self) {
+            error!(target: CONNECTION, "Failed to wake connection: {why}");
+        }
+    }
+}
+
+impl Socket {
+    fn from_stream(stream: Stream) -> Result<Self> {
+        stream.configure()?;
+
+        let poll = Poll::new()?;
+        poll.registry().register(
+            &mut SourceFd(&stream.as_raw_fd()),
+            SOCKET,
+            Interest::READABLE,
+        )?;
+
+        Ok(Self {
+            stream,
+            buffers: Buffers::default(),
+            poll,
+            events: Events::with_capacity(4),
+            interest: Interest::READABLE,
+        })
+    }
 }
 
 impl Connection for Socket {
-    type Socket = UnixStream;
+    type Socket = Stream;
+
+    fn connect_to(path: &Path) -> Result<Self> {
+        Self::from_stream(Stream::Unix(UnixStream::connect(path)?))
+    }
+
+    #[cfg(feature = "wsl")]
+    fn connect_bridge(bridge: &Bridge) -> Result<Self> {
+        let stream = match bridge {
+            Bridge::Unix(path) => Stream::Unix(UnixStream::connect(path)?),
+            Bridge::Tcp(addr) => Stream::Tcp(std::net::TcpStream::connect(addr)?),
+        };
 
-    fn connect() -> Result<Self> {
-        let connection_name = Self::socket_path(0);
-        let socket = UnixStream::connect(connection_name)?;
-        socket.set_nonblocking(true)?;
-        socket.set_write_timeout(Some(time::Duration::from_secs(30)))?;
-        socket.set_read_timeout(Some(time::Duration::from_secs(30)))?;
-        Ok(Self { socket })
+        Self::from_stream(stream)
     }
 
+    fn wait(&mut self, timeout: Duration) -> Result<()> {
+        // A frame the socket could not take all of is finished once it becomes writable
+        let interest = if self.buffers.has_pending_write() {
+            Interest::READABLE | Interest::WRITABLE
+        } else {
+            Interest::READABLE
+        };
+        if interest != self.interest {
+            self.poll.registry().reregister(
+                &mut SourceFd(&self.stream.as_raw_fd()),
+                SOCKET,
+                interest,
+            )?;
+            self.interest = interest;
+        }
+
+        match self.poll.poll(&mut self.events, Some(timeout)) {
+            Err(why) if why.kind() != ErrorKind::Interrupted => Err(why.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn waker(&self) -> Result<Option<Arc<dyn Wake>>> {
+        // Only a single waker may exist per `Poll`, so this is only called once per connection
+        let waker = Waker::new(self.poll.registry(), WAKER)?;
+        Ok(Some(Arc::new(waker)))
+    }
+
+    /// The first of `XDG_RUNTIME_DIR` and `TMPDIR` that is set, or `/tmp` otherwise.
+    ///
+    /// `XDG_RUNTIME_DIR` comes first as it is private to the user, whereas `/tmp` may hold another user's socket.
     fn ipc_path() -> PathBuf {
-        let tmp = env::var("XDG_RUNTIME_DIR")
-            .or_else(|_| env::var("TMPDIR"))
-            .or_else(|_| match env::temp_dir().to_str() {
-                None => Err("Failed to convert temp_dir"),
-                Some(tmp) => Ok(tmp.to_owned()),
-            })
-            .unwrap_or_else(|_| "/tmp".to_owned());
-        PathBuf::from(tmp)
+        IPC_DIR_VARIABLES
+            .iter()
+            .filter_map(env::var_os)
+            .find(|dir| !dir.is_empty())
+            .map_or_else(|| PathBuf::from(FALLBACK_IPC_DIR), PathBuf::from)
     }
 
-    fn socket(&mut self) -> &mut Self::Socket {
-        &mut self.socket
+    fn socket(&mut self) -> (&mut Self::Socket, &mut Buffers) {
+        (&mut self.stream, &mut self.buffers)
     }
 }
 
 impl Drop for Socket {
     fn drop(&mut self) {
-        if self.socket.shutdown(Shutdown::Both).is_err() {
-            error!("Failed to properly shut down socket");
+        if self.stream.shutdown(Shutdown::Both).is_err() {
+            error!(target: CONNECTION, "Failed to properly shut down socket");
         }
     }
 }
+
+/// A path in the temporary directory for a test's socket, unique to this process
+#[cfg(all(
+    test,
+    any(not(feature = "io-uring"), feature = "broker", feature = "wsl")
+))]
+pub(crate) fn test_socket_path(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("discord-{name}-{}", std::process::id()))
+}
+
+/// A [`Socket`] connected to the stream a test plays Discord's side of the connection on
+#[cfg(all(test, not(feature = "io-uring")))]
+pub(crate) fn socket_pair(name: &str) -> (Socket, UnixStream) {
+    use std::os::unix::net::UnixListener;
+
+    let path = test_socket_path(name);
+    let server = UnixListener::bind(&path).unwrap();
+    let socket = Socket::connect_to(&path).unwrap();
+    std::fs::remove_file(&path).ok();
+    let (stream, _) = server.accept().unwrap();
+    (socket, stream)
+}
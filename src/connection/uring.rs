@@ -0,0 +1,201 @@
+use super::{
+    base::{Buffers, Connection, Wake},
+    unix,
+};
+use crate::{targets::CONNECTION, Result};
+use crossbeam_channel::{bounded, select, unbounded, Receiver, Sender, TryRecvError};
+use std::{
+    io::{self, Read, Write},
+    net::Shutdown,
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+use tokio::sync::mpsc;
+use tracing::Instrument;
+
+const READ_CAPACITY: usize = 1024;
+
+/// A Unix socket driven by `io_uring` on a dedicated thread.
+///
+/// Reads and writes are exchanged with the ring thread over channels, so the socket itself never blocks.
+pub struct UringStream {
+    inbound: Receiver<Vec<u8>>,
+    outbound: mpsc::UnboundedSender<Vec<u8>>,
+    pending: Vec<u8>,
+}
+
+impl UringStream {
+    fn connect(path: PathBuf) -> io::Result<Self> {
+        let (inbound_tx, inbound) = unbounded();
+        let (outbound, outbound_rx) = mpsc::unbounded_channel();
+        let (connected_tx, connected_rx) = bounded(1);
+
+        thread::Builder::new()
+            .name("discord-presence-uring".to_owned())
+            .spawn(move || {
+                tokio_uring::start(run(&path, inbound_tx, outbound_rx, connected_tx));
+            })?;
+
+        connected_rx
+            .recv()
+            .map_err(|_| io::Error::other("io_uring thread exited"))??;
+
+        Ok(Self {
+            inbound,
+            outbound,
+            pending: Vec::new(),
+        })
+    }
+}
+
+async fn run(
+    path: &Path,
+    inbound: Sender<Vec<u8>>,
+    mut outbound: mpsc::UnboundedReceiver<Vec<u8>>,
+    connected: Sender<io::Result<()>>,
+) {
+    let stream = match tokio_uring::net::UnixStream::connect(path).await {
+        Ok(stream) => Rc::new(stream),
+        Err(why) => {
+            connected.send(Err(why)).ok();
+            return;
+        }
+    };
+    connected.send(Ok(())).ok();
+
+    let reader = tokio_uring::spawn({
+        let stream = stream.clone();
+        let span = debug_span!(target: CONNECTION, "reader");
+        async move {
+            loop {
+                let (res, buf) = stream.read(Vec::with_capacity(READ_CAPACITY)).await;
+                match res {
+                    // Dropping the sender reports the closed connection to the reading side
+                    Ok(0) => break,
+                    Ok(_) => {
+                        if inbound.send(buf).is_err() {
+                            break;
+                        }
+                    }
+                    Err(why) => {
+                        error!(target: CONNECTION, "Failed to read from io_uring socket: {why}");
+                        break;
+                    }
+                }
+            }
+        }
+        .instrument(span)
+    });
+
+    async {
+        while let Some(buf) = outbound.recv().await {
+            let (res, _) = stream.write_all(buf).await;
+            if let Err(why) = res {
+                error!(target: CONNECTION, "Failed to write to io_uring socket: {why}");
+                break;
+            }
+        }
+    }
+    .instrument(debug_span!(target: CONNECTION, "writer"))
+    .await;
+
+    // The socket was dropped, so wake the reader up and let the thread exit
+    if stream.shutdown(Shutdown::Both).is_err() {
+        error!(target: CONNECTION, "Failed to properly shut down socket");
+    }
+    reader.await.ok();
+}
+
+impl Read for UringStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pending.is_empty() {
+            match self.inbound.try_recv() {
+                Ok(chunk) => self.pending = chunk,
+                Err(TryRecvError::Empty) => return Err(io::ErrorKind::WouldBlock.into()),
+                Err(TryRecvError::Disconnected) => return Ok(0),
+            }
+        }
+
+        let n = buf.len().min(self.pending.len());
+        buf[..n].copy_from_slice(&self.pending[..n]);
+        self.pending.drain(..n);
+        Ok(n)
+    }
+}
+
+impl Write for UringStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.outbound
+            .send(buf.to_vec())
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+struct UringWaker(Sender<()>);
+
+impl Wake for UringWaker {
+    fn wake(&self) {
+        // A full channel means a wake up is already pending
+        self.0.try_send(()).ok();
+    }
+}
+
+/// A connection to Discord's Unix socket, driven by `io_uring`
+pub struct Socket {
+    stream: UringStream,
+    buffers: Buffers,
+    wake: (Sender<()>, Receiver<()>),
+}
+
+impl Connection for Socket {
+    type Socket = UringStream;
+
+    fn connect_to(path: &Path) -> Result<Self> {
+        let stream = UringStream::connect(path.to_owned())?;
+
+        Ok(Self {
+            stream,
+            buffers: Buffers::default(),
+            wake: bounded(1),
+        })
+    }
+
+    fn wait(&mut self, timeout: Duration) -> Result<()> {
+        if !self.stream.pending.is_empty() {
+            return Ok(());
+        }
+
+        select! {
+            recv(self.stream.inbound) -> chunk => {
+                // A closed channel is picked up by the next read
+                if let Ok(chunk) = chunk {
+                    self.stream.pending = chunk;
+                }
+            },
+            recv(self.wake.1) -> _ => {},
+            default(timeout) => {},
+        }
+
+        Ok(())
+    }
+
+    fn waker(&self) -> Result<Option<Arc<dyn Wake>>> {
+        Ok(Some(Arc::new(UringWaker(self.wake.0.clone()))))
+    }
+
+    fn ipc_path() -> PathBuf {
+        unix::Socket::ipc_path()
+    }
+
+    fn socket(&mut self) -> (&mut Self::Socket, &mut Buffers) {
+        (&mut self.stream, &mut self.buffers)
+    }
+}
@@ -1,29 +1,290 @@
-use super::base::Connection;
-use crate::Result;
-use named_pipe::PipeClient;
-use std::{path::PathBuf, time};
+use super::base::{Buffers, ConnectOptions, Connection, Wake, COMPAT_SOCKET_COUNT};
+use crate::{targets::CONNECTION, Result};
+use crossbeam_channel::{bounded, select, unbounded, Receiver, Sender, TryRecvError};
+use std::{
+    fs,
+    io::{self, Read, Write},
+    iter,
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::windows::named_pipe::{ClientOptions, NamedPipeClient},
+    runtime,
+    sync::mpsc,
+};
+use tracing::Instrument;
 
+/// Namespace packaged apps create their pipes in, when not given a full path
+const LOCAL_NAMESPACE: &str = "LOCAL";
+
+/// Error Windows reports when every instance of a pipe is connected to another client
+const ERROR_PIPE_BUSY: i32 = 231;
+
+/// How long to keep retrying a busy pipe before giving up on it
+const PIPE_BUSY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long to wait before the first retry of a busy pipe, doubling after each attempt
+const PIPE_BUSY_INITIAL_DELAY: Duration = Duration::from_millis(50);
+
+const READ_CAPACITY: usize = 1024;
+
+/// Connects to the pipe at `path`, retrying while it is busy.
+/// Must be called from within the pipe thread's runtime.
+///
+/// Discord only has a free pipe instance between accepting one client and creating the next instance,
+/// so a pipe that exists can briefly refuse connections, such as while another app is connecting.
+fn connect_pipe(path: &Path) -> io::Result<NamedPipeClient> {
+    let deadline = Instant::now() + PIPE_BUSY_TIMEOUT;
+    let mut delay = PIPE_BUSY_INITIAL_DELAY;
+
+    loop {
+        match ClientOptions::new().open(path) {
+            Err(why) if why.raw_os_error() == Some(ERROR_PIPE_BUSY) => {
+                let now = Instant::now();
+                if now >= deadline {
+                    return Err(why);
+                }
+
+                trace!(target: CONNECTION, "{} is busy, retrying in {delay:?}", path.display());
+                thread::sleep(delay.min(deadline - now));
+                delay *= 2;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// A named pipe opened for overlapped I/O, driven by a Tokio runtime on a dedicated thread.
+///
+/// Reads and writes are exchanged with the pipe thread over channels, so the pipe itself never blocks.
+pub struct PipeStream {
+    inbound: Receiver<Vec<u8>>,
+    outbound: mpsc::UnboundedSender<Vec<u8>>,
+    pending: Vec<u8>,
+}
+
+impl PipeStream {
+    fn connect(path: PathBuf) -> io::Result<Self> {
+        let (inbound_tx, inbound) = unbounded();
+        let (outbound, outbound_rx) = mpsc::unbounded_channel();
+        let (connected_tx, connected_rx) = bounded(1);
+
+        thread::Builder::new()
+            .name("discord-presence-pipe".to_owned())
+            .spawn(move || {
+                let runtime = match runtime::Builder::new_current_thread().enable_io().build() {
+                    Ok(runtime) => runtime,
+                    Err(why) => {
+                        connected_tx.send(Err(why)).ok();
+                        return;
+                    }
+                };
+
+                let pipe = {
+                    let _runtime = runtime.enter();
+                    connect_pipe(&path)
+                };
+                match pipe {
+                    Ok(pipe) => {
+                        connected_tx.send(Ok(())).ok();
+                        runtime.block_on(run(pipe, inbound_tx, outbound_rx));
+                    }
+                    Err(why) => {
+                        connected_tx.send(Err(why)).ok();
+                    }
+                }
+            })?;
+
+        connected_rx
+            .recv()
+            .map_err(|_| io::Error::other("named pipe thread exited"))??;
+
+        Ok(Self {
+            inbound,
+            outbound,
+            pending: Vec::new(),
+        })
+    }
+}
+
+async fn run(
+    pipe: NamedPipeClient,
+    inbound: Sender<Vec<u8>>,
+    mut outbound: mpsc::UnboundedReceiver<Vec<u8>>,
+) {
+    let (mut reader, mut writer) = tokio::io::split(pipe);
+
+    let reader = tokio::spawn(
+        async move {
+            loop {
+                let mut buf = vec![0; READ_CAPACITY];
+                match reader.read(&mut buf).await {
+                    // Dropping the sender reports the closed connection to the reading side
+                    Ok(0) => break,
+                    Ok(n) => {
+                        buf.truncate(n);
+                        if inbound.send(buf).is_err() {
+                            break;
+                        }
+                    }
+                    Err(why) => {
+                        error!(target: CONNECTION, "Failed to read from named pipe: {why}");
+                        break;
+                    }
+                }
+            }
+        }
+        .instrument(debug_span!(target: CONNECTION, "reader")),
+    );
+
+    async {
+        while let Some(buf) = outbound.recv().await {
+            if let Err(why) = writer.write_all(&buf).await {
+                error!(target: CONNECTION, "Failed to write to named pipe: {why}");
+                break;
+            }
+        }
+    }
+    .instrument(debug_span!(target: CONNECTION, "writer"))
+    .await;
+
+    // The socket was dropped, so stop reading and let the pipe close with the runtime
+    reader.abort();
+}
+
+impl Read for PipeStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pending.is_empty() {
+            match self.inbound.try_recv() {
+                Ok(chunk) => self.pending = chunk,
+                Err(TryRecvError::Empty) => return Err(io::ErrorKind::WouldBlock.into()),
+                Err(TryRecvError::Disconnected) => return Ok(0),
+            }
+        }
+
+        let n = buf.len().min(self.pending.len());
+        buf[..n].copy_from_slice(&self.pending[..n]);
+        self.pending.drain(..n);
+        Ok(n)
+    }
+}
+
+impl Write for PipeStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.outbound
+            .send(buf.to_vec())
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+struct PipeWaker(Sender<()>);
+
+impl Wake for PipeWaker {
+    fn wake(&self) {
+        // A full channel means a wake up is already pending
+        self.0.try_send(()).ok();
+    }
+}
+
+/// A connection to Discord's named pipe
 pub struct Socket {
-    socket: PipeClient,
+    stream: PipeStream,
+    buffers: Buffers,
+    wake: (Sender<()>, Receiver<()>),
 }
 
 impl Connection for Socket {
-    type Socket = PipeClient;
+    type Socket = PipeStream;
+
+    fn connect_to(path: &Path) -> Result<Self> {
+        let stream = PipeStream::connect(path.to_owned())?;
+
+        Ok(Self {
+            stream,
+            buffers: Buffers::default(),
+            wake: bounded(1),
+        })
+    }
+
+    fn wait(&mut self, timeout: Duration) -> Result<()> {
+        if !self.stream.pending.is_empty() {
+            return Ok(());
+        }
 
-    fn connect() -> Result<Self> {
-        let connection_name = Self::socket_path(0);
-        let mut socket = PipeClient::connect(connection_name)?;
-        // Discord rate limit timeout is 15 seconds, so 16 should account for that
-        socket.set_write_timeout(Some(time::Duration::from_secs(16)));
-        socket.set_read_timeout(Some(time::Duration::from_secs(16)));
-        Ok(Self { socket })
+        select! {
+            recv(self.stream.inbound) -> chunk => {
+                // A closed channel is picked up by the next read
+                if let Ok(chunk) = chunk {
+                    self.stream.pending = chunk;
+                }
+            },
+            recv(self.wake.1) -> _ => {},
+            default(timeout) => {},
+        }
+
+        Ok(())
+    }
+
+    fn waker(&self) -> Result<Option<Arc<dyn Wake>>> {
+        Ok(Some(Arc::new(PipeWaker(self.wake.0.clone()))))
     }
 
     fn ipc_path() -> PathBuf {
         PathBuf::from(r"\\.\pipe\")
     }
 
-    fn socket(&mut self) -> &mut Self::Socket {
-        &mut self.socket
+    /// Discord installed from the Microsoft Store runs in an app container, so its pipe is created
+    /// in the container's namespace rather than at the root of the pipe filesystem.
+    /// Those pipes are found by listing every pipe, and are tried after the usual location.
+    fn socket_paths(options: &ConnectOptions) -> Vec<PathBuf> {
+        let count = if options.compatibility {
+            COMPAT_SOCKET_COUNT
+        } else {
+            1
+        };
+        let ipc_dir = Self::ipc_dir(options);
+        let namespaced = namespaced_pipes();
+
+        (0..count)
+            .flat_map(|n| {
+                let socket_path = format!("discord-ipc-{n}");
+
+                let packaged = namespaced
+                    .iter()
+                    .filter(|pipe| pipe.ends_with(&socket_path))
+                    .cloned()
+                    .collect::<Vec<_>>();
+
+                iter::once(ipc_dir.join(&socket_path))
+                    .chain(packaged)
+                    .chain(iter::once(ipc_dir.join(LOCAL_NAMESPACE).join(&socket_path)))
+            })
+            .collect()
+    }
+
+    fn socket(&mut self) -> (&mut Self::Socket, &mut Buffers) {
+        (&mut self.stream, &mut self.buffers)
     }
 }
+
+/// Pipes created within a namespace, such as `Sessions\1\AppContainerNamedObjects\<SID>\discord-ipc-0`
+fn namespaced_pipes() -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(Socket::ipc_path()) else {
+        return Vec::new();
+    };
+
+    entries
+        .filter_map(std::result::Result::ok)
+        .filter(|entry| entry.file_name().to_string_lossy().contains('\\'))
+        .map(|entry| entry.path())
+        .collect()
+}
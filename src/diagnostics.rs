@@ -0,0 +1,209 @@
+use std::{
+    env,
+    fmt::{self, Display},
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use crate::DiscordError;
+
+/// A sandbox the application runs in, which may hide Discord's socket from it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Sandbox {
+    /// Flatpak
+    Flatpak,
+    /// Snap
+    Snap,
+    /// A Microsoft Store (MSIX) package
+    MicrosoftStore,
+    /// Windows Subsystem for Linux, where Discord runs on the Windows side
+    Wsl,
+}
+
+impl Sandbox {
+    /// Detects the sandbox the current process runs in, if any
+    #[must_use]
+    pub fn detect() -> Option<Self> {
+        if env::var_os("FLATPAK_ID").is_some() || Path::new("/.flatpak-info").exists() {
+            return Some(Self::Flatpak);
+        }
+
+        if env::var_os("SNAP").is_some() {
+            return Some(Self::Snap);
+        }
+
+        if is_wsl() {
+            return Some(Self::Wsl);
+        }
+
+        // Packaged apps are installed under `WindowsApps`
+        let packaged = cfg!(windows)
+            && env::current_exe().is_ok_and(|exe| {
+                exe.components()
+                    .any(|component| component.as_os_str() == "WindowsApps")
+            });
+        if packaged {
+            return Some(Self::MicrosoftStore);
+        }
+
+        None
+    }
+
+    fn hint(self) -> &'static str {
+        match self {
+            Self::Flatpak => "The app runs in Flatpak, which needs `--filesystem=xdg-run/discord-ipc-0` to reach Discord",
+            Self::Snap => "The app runs in a Snap, which may not be allowed to reach Discord's socket",
+            Self::MicrosoftStore => "The app is packaged for the Microsoft Store, which may not be allowed to reach Discord's pipe",
+            Self::Wsl => "The app runs in WSL, which cannot reach Discord's pipe on Windows without a bridge",
+        }
+    }
+}
+
+/// Whether the current process runs in Windows Subsystem for Linux
+pub(crate) fn is_wsl() -> bool {
+    env::var_os("WSL_DISTRO_NAME").is_some()
+        || fs::read_to_string("/proc/sys/kernel/osrelease")
+            .is_ok_and(|release| is_wsl_release(&release))
+}
+
+/// WSL kernels are built by Microsoft, and say so in their release
+fn is_wsl_release(release: &str) -> bool {
+    release.to_lowercase().contains("microsoft")
+}
+
+/// A socket that could not be connected to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectFailure {
+    /// Path of the socket or named pipe
+    pub path: PathBuf,
+    /// Kind of the error, or [`ErrorKind::Other`] if it was not an IO error
+    pub kind: ErrorKind,
+    /// Description of the error
+    pub message: String,
+}
+
+impl ConnectFailure {
+    pub(crate) fn new(path: PathBuf, error: &DiscordError) -> Self {
+        let (kind, message) = match error {
+            DiscordError::IoError(ref why) => (why.kind(), why.to_string()),
+            _ => (ErrorKind::Other, error.to_string()),
+        };
+
+        Self {
+            path,
+            kind,
+            message,
+        }
+    }
+}
+
+/// Report of an attempt to connect to Discord, explaining why it failed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConnectDiagnostics {
+    /// The sockets that could not be connected to, in the order they were tried
+    pub failures: Vec<ConnectFailure>,
+    /// The socket that was connected to, if any
+    pub connected: Option<PathBuf>,
+    /// The sandbox the application runs in, if any
+    pub sandbox: Option<Sandbox>,
+}
+
+impl ConnectDiagnostics {
+    /// Suggests what the user can do to fix the connection, if it failed
+    #[must_use]
+    pub fn hint(&self) -> Option<&'static str> {
+        if self.connected.is_some() {
+            return None;
+        }
+
+        if let Some(sandbox) = self.sandbox {
+            return Some(sandbox.hint());
+        }
+
+        let any = |kind| self.failures.iter().any(|failure| failure.kind == kind);
+        let hint = if any(ErrorKind::PermissionDenied) {
+            "Discord's socket could not be accessed, check Discord runs as the same user"
+        } else if any(ErrorKind::ConnectionRefused) {
+            "Discord's socket exists but is not accepting connections, try restarting Discord"
+        } else {
+            "Discord does not seem to be running"
+        };
+
+        Some(hint)
+    }
+}
+
+impl Display for ConnectDiagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(ref path) = self.connected {
+            return write!(f, "connected to {}", path.display());
+        }
+
+        write!(f, "tried {} sockets", self.failures.len())?;
+        for failure in &self.failures {
+            write!(f, "; {}: {}", failure.path.display(), failure.message)?;
+        }
+
+        if let Some(hint) = self.hint() {
+            write!(f, ". {hint}")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn failure(kind: ErrorKind) -> ConnectFailure {
+        ConnectFailure::new(
+            PathBuf::from("/run/user/1000/discord-ipc-0"),
+            &DiscordError::IoError(kind.into()),
+        )
+    }
+
+    #[test]
+    fn hints_at_cause() {
+        let mut diagnostics = ConnectDiagnostics {
+            failures: vec![failure(ErrorKind::NotFound)],
+            ..ConnectDiagnostics::default()
+        };
+        assert_eq!(
+            diagnostics.hint(),
+            Some("Discord does not seem to be running")
+        );
+
+        diagnostics
+            .failures
+            .push(failure(ErrorKind::PermissionDenied));
+        assert!(diagnostics.hint().unwrap().contains("same user"));
+
+        diagnostics.sandbox = Some(Sandbox::Flatpak);
+        assert!(diagnostics.hint().unwrap().contains("Flatpak"));
+
+        diagnostics.connected = Some(PathBuf::from("/run/user/1000/discord-ipc-1"));
+        assert_eq!(diagnostics.hint(), None);
+    }
+
+    #[test]
+    fn detects_wsl_kernel() {
+        assert!(is_wsl_release("5.15.153.1-microsoft-standard-WSL2"));
+        assert!(is_wsl_release("4.4.0-19041-Microsoft"));
+        assert!(!is_wsl_release("6.8.0-45-generic"));
+    }
+
+    #[test]
+    fn lists_failures() {
+        let diagnostics = ConnectDiagnostics {
+            failures: vec![failure(ErrorKind::NotFound)],
+            ..ConnectDiagnostics::default()
+        };
+
+        let report = diagnostics.to_string();
+        assert!(report.starts_with("tried 1 sockets; /run/user/1000/discord-ipc-0: "));
+        assert!(report.ends_with("Discord does not seem to be running"));
+    }
+}
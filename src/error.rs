@@ -6,7 +6,10 @@ use std::{
     sync::mpsc::{RecvError as ChannelRecv, RecvTimeoutError as ChannelTimeout},
 };
 
-use crate::models::Message;
+use crate::{
+    diagnostics::ConnectDiagnostics,
+    models::{CloseCode, CloseEvent, Message, OAuthScope},
+};
 
 /// Error types from Discord
 #[derive(Debug, thiserror::Error)]
@@ -36,6 +39,22 @@ pub enum DiscordError {
     #[error("Error parsing Json")]
     /// Json Error
     JsonError(#[from] JsonError),
+    #[cfg(feature = "simd-json")]
+    #[error("Error parsing Json")]
+    /// Json Error from `simd-json`
+    SimdJsonError(#[from] simd_json::Error),
+    #[cfg(feature = "gateway")]
+    #[error("Gateway connection failed: {0}")]
+    /// Gateway connection error, boxed as it is much larger than the other variants
+    GatewayError(Box<tungstenite::Error>),
+    #[cfg(feature = "exit-hook")]
+    #[error("Failed to install the exit hook: {0}")]
+    /// Exit hook error, such as another Ctrl+C handler already being installed
+    ExitHookError(#[from] ctrlc::Error),
+    #[cfg(feature = "keyring")]
+    #[error("Failed to access the credential store: {0}")]
+    /// Credential store error, such as the store being locked or unavailable
+    KeyringError(#[from] keyring::Error),
     #[error("A thread ran into an error. See logs for more info.")]
     /// A thread ran into an error
     ThreadError,
@@ -48,9 +67,35 @@ pub enum DiscordError {
     #[error("Error subscribing to an event")]
     /// Subscription Joining Error
     SubscriptionFailed,
+    #[error("Discord rejected the command: {message}")]
+    /// Discord responded to a command with an error
+    CommandFailed {
+        /// Discord's error code, such as 4000 for invalid arguments
+        code: Option<u32>,
+        /// Discord's description of the error
+        message: String,
+    },
     #[error("Connection was closed prematurely")]
     /// Connection Closing error
     ConnectionClosed,
+    #[error("Discord closed the connection: {0}")]
+    /// Discord closed the connection, saying why
+    Closed(CloseEvent),
+    #[error("Could not connect to Discord: {0}")]
+    /// None of the candidate sockets could be connected to
+    DiscordNotFound(ConnectDiagnostics),
+    #[error("Discord does not know the client ID {0}. Check that it is the application ID from the developer portal")]
+    /// Discord rejected the handshake, as no application has the client ID
+    InvalidClientId(u64),
+    #[error("{0:?} is not a valid client ID. Use the application ID from the developer portal")]
+    /// The client ID does not look like a Discord ID, so it was rejected before connecting
+    MalformedClientId(String),
+    #[error("Unsupported RPC protocol: {0}")]
+    /// Discord rejected the handshake, as it does not support the RPC version
+    UnsupportedProtocol(String),
+    #[error("Unexpected handshake response: {0}")]
+    /// The server did not respond to the handshake as expected, which may not happen again on the next attempt
+    UnexpectedHandshake(String),
     #[error("Connection has not been started")]
     /// Connection has not been started
     NotStarted,
@@ -60,11 +105,48 @@ pub enum DiscordError {
     /// No changes were made to the event handler
     #[error("No changes were made to the event handler. This can usually be ignored")]
     NoChangesMade,
+    /// The activity is identical to the last one sent
+    #[error("The activity is identical to the last one sent, so it was not sent again. This can usually be ignored")]
+    Unchanged,
+    /// The activity update was deferred by the debounce window
+    #[error("The activity update was deferred, and will be sent once the debounce window ends. This can usually be ignored")]
+    Debounced,
+    /// The command was queued, and will be sent once the client is ready
+    #[error("The command was queued, and will be sent once the client is ready. This can usually be ignored")]
+    Queued,
+    /// The command was cancelled before it was sent, or while waiting for its response
+    #[error("The command was cancelled")]
+    Cancelled,
+    /// The activity would be rejected by Discord, or shown wrongly
+    #[error("Invalid activity: {0}")]
+    InvalidActivity(String),
+    /// Discord rejected the access token passed to [`crate::Client::authenticate`]
+    #[error("Discord rejected the access token: {0}")]
+    AuthenticationFailed(String),
+    /// The user declined to authorize the application, or Discord rejected the request
+    #[error("Authorization failed: {0}")]
+    AuthorizationFailed(String),
+    /// The access token is not authorized with a scope the command or event requires
+    #[error("The access token lacks the {0} scope")]
+    MissingScope(OAuthScope),
+    /// The payload is too large to fit in a single frame
+    #[error("Payload of {0} bytes is too large to fit in a frame")]
+    PayloadTooLarge(usize),
+    /// The event is not a join request, or does not say who sent it
+    #[error("The event is not a join request")]
+    NotAJoinRequest,
     #[error("Could not safely shut down client. Thread is in use.")]
     /// RPC thread is in use
     ThreadInUse,
 }
 
+#[cfg(feature = "gateway")]
+impl From<tungstenite::Error> for DiscordError {
+    fn from(why: tungstenite::Error) -> Self {
+        Self::GatewayError(Box::new(why))
+    }
+}
+
 impl DiscordError {
     #[must_use]
     /// Tell whether an [`IoError`] would block the connection
@@ -80,6 +162,14 @@ impl DiscordError {
     pub fn should_break(&self) -> bool {
         match self {
             Self::IoError(ref err) => err.kind() == std::io::ErrorKind::ConnectionRefused,
+            // Matches the error of the last socket tried
+            Self::DiscordNotFound(ref diagnostics) => diagnostics
+                .failures
+                .last()
+                .is_some_and(|failure| failure.kind == std::io::ErrorKind::ConnectionRefused),
+            // Connecting again would be closed the same way
+            Self::Closed(ref event) => event.close_code().is_some_and(CloseCode::is_fatal),
+            Self::InvalidClientId(_) | Self::UnsupportedProtocol(_) => true,
             _ => false,
         }
     }
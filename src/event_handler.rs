@@ -1,36 +1,324 @@
-use std::{collections::HashMap, sync::Arc};
-use std::{sync::Weak, thread};
+use std::sync::Weak;
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::Display,
+    panic::{catch_unwind, AssertUnwindSafe},
+    sync::Arc,
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
 
-use parking_lot::RwLock;
+use arc_swap::ArcSwap;
 
-use crate::models::{Event, EventData};
+use serde_json::Value;
+
+use crate::{
+    connection::{Manager, Priority},
+    models::{payload::Payload, Command, ErrorEvent, Event, EventData, SendActivityJoinInviteArgs},
+    sync::Mutex,
+    targets::DISPATCH,
+    utils, DiscordError, Result,
+};
 
 /// Event handler callback
 pub type Handler = dyn Fn(Context) + 'static + Send + Sync;
 
-type HandlerList = Vec<Arc<Handler>>;
+/// Connection state change callback
+pub type StateHandler = dyn Fn(StateChange) + 'static + Send + Sync;
+
+/// Server ping callback
+pub type PingHandler = dyn Fn(Ping) + 'static + Send + Sync;
+
+/// Slow handler callback
+pub type SlowHandlerHook = dyn Fn(&SlowHandler) + 'static + Send + Sync;
+
+/// How long an event handler may run before it is reported as slow, unless changed with
+/// [`crate::Client::set_slow_handler_threshold`]
+pub const DEFAULT_SLOW_HANDLER_THRESHOLD: Duration = Duration::from_secs(1);
+
+/// An event handler that ran for longer than the slow handler threshold, passed to [`crate::Client::on_slow_handler`]
+///
+/// A handler is reported once while it is still running past the threshold, and again once it finishes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlowHandler {
+    /// The event the handler was called for
+    pub event: Event,
+    /// Whether the handler listens for all events, rather than just this one
+    pub wildcard: bool,
+    /// The label supplied when registering the handler, if any
+    pub label: Option<String>,
+    /// How long the handler ran for, or has been running for so far if it is still running
+    pub elapsed: Duration,
+    /// Whether the handler was still running when it was reported
+    pub running: bool,
+}
+
+/// How the handlers for an event are run, set with [`crate::Client::set_dispatch_mode`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[non_exhaustive]
+pub enum DispatchMode {
+    /// Every handler runs on a thread of its own, so handlers may run at the same time and finish in any order
+    #[default]
+    Concurrent,
+    /// Handlers run one after another on a single thread per event, in the order they were registered.
+    /// Each event is only passed on once the handlers are done with the one before it.
+    Sequential,
+}
+
+/// A ping from Discord, passed to [`crate::Client::on_ping`] once it has been answered
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ping {
+    /// The payload of the ping, which is echoed back in the pong
+    pub payload: Value,
+    /// When the ping was received
+    pub received_at: SystemTime,
+}
+
+/// State of the client's connection to Discord
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[non_exhaustive]
+pub enum ConnectionState {
+    /// Not connected, either because the client has not started yet or because the connection failed
+    #[default]
+    Disconnected,
+    /// Connecting and handshaking with Discord
+    Connecting,
+    /// Connected, with the handshake completed
+    Connected,
+    /// No longer trying to connect, as the client was shut down or gave up
+    Stopped,
+}
+
+/// Why the connection state changed
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum StateChangeReason {
+    /// The client was started
+    Started,
+    /// The client is trying again after the previous connection failed or was lost
+    Retrying,
+    /// The handshake with Discord completed
+    HandshakeCompleted,
+    /// Connecting failed, with the error
+    ConnectFailed(String),
+    /// The connection was lost, with the error
+    ConnectionLost(String),
+    /// The client gave up connecting, as it ran out of attempts or Discord rejected it
+    GaveUp,
+    /// The client was shut down
+    Shutdown,
+    /// The system resumed from sleep, so the client is reconnecting, as the connection is unlikely to have survived
+    Resumed,
+}
+
+/// A transition between two connection states, passed to [`crate::Client::on_state_change`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateChange {
+    /// The state before the change
+    pub old: ConnectionState,
+    /// The state after the change
+    pub new: ConnectionState,
+    /// Why the state changed
+    pub reason: StateChangeReason,
+}
+
+#[derive(Clone)]
+struct HandlerEntry {
+    handler: Arc<Handler>,
+    label: Option<String>,
+}
+
+type HandlerList = Vec<HandlerEntry>;
+
+/// The handler a [`Context`] was passed to, so it can remove itself
+#[derive(Clone)]
+struct HandlerRef {
+    /// `None` if the handler listens for all events
+    event: Option<Event>,
+    registry: Weak<HandlerRegistry>,
+    handler: Weak<Handler>,
+}
+
+impl std::fmt::Debug for HandlerRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HandlerRef")
+            .field("event", &self.event)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The connection a [`Context`] was received on, so commands can be sent in response
+#[derive(Clone)]
+struct Responder(Manager);
+
+impl std::fmt::Debug for Responder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Responder").finish_non_exhaustive()
+    }
+}
 
 #[derive(Debug, Clone)]
 /// Event context
 pub struct Context {
+    /// The event that was fired
+    pub kind: Event,
     /// Event data
     pub event: EventData,
+    /// When the event was received
+    pub received_at: SystemTime,
+    /// The connection generation the event was received on.
+    ///
+    /// This is incremented every time the client (re)connects to Discord,
+    /// so it can be compared against [`crate::Client::connection_generation`] to discard stale events.
+    pub generation: u64,
+    /// The nonce of the payload that carried the event, if any
+    pub nonce: Option<String>,
+    /// The command the payload was a response to, or `None` if Discord dispatched it
+    reply_to: Option<Command>,
+    handler: Option<HandlerRef>,
+    responder: Option<Responder>,
 }
 
 impl Context {
-    pub(crate) fn new(event: EventData) -> Self {
-        Self { event }
+    pub(crate) fn new(
+        kind: Event,
+        event: EventData,
+        generation: u64,
+        nonce: Option<String>,
+    ) -> Self {
+        Self {
+            kind,
+            event,
+            received_at: SystemTime::now(),
+            generation,
+            nonce,
+            reply_to: None,
+            handler: None,
+            responder: None,
+        }
+    }
+
+    /// Marks the event as carried by the response to `command`, rather than dispatched by Discord
+    pub(crate) fn in_reply_to(mut self, command: Command) -> Self {
+        self.reply_to = Some(command);
+        self
+    }
+
+    /// The nonce of the command whose failure this `ERROR` event reports
+    ///
+    /// Only the responses nobody waits for are dispatched as events, and only when the command failed: those to
+    /// commands queued while disconnected, and to commands the client sends on its own, such as resubscribing after
+    /// reconnecting. Responses to other commands are returned to the caller instead, so this is `None` for them, and
+    /// for every event Discord dispatched on its own.
+    #[must_use]
+    pub fn reply_nonce(&self) -> Option<&str> {
+        self.reply_to.and(self.nonce.as_deref())
+    }
+
+    /// The command whose failure this `ERROR` event reports. See [`Context::reply_nonce`] for when this is set.
+    #[must_use]
+    pub fn reply_command(&self) -> Option<Command> {
+        self.reply_to
+    }
+
+    /// Attaches the connection the context was received on, so handlers can respond through it
+    pub(crate) fn with_responder(mut self, manager: Manager) -> Self {
+        self.responder = Some(Responder(manager));
+        self
+    }
+
+    /// Accepts the join request this context was created for, inviting the requesting user to the game
+    ///
+    /// # Errors
+    /// - [`DiscordError::NotAJoinRequest`] if the event is not [`Event::ActivityJoinRequest`]
+    /// - [`DiscordError::NotStarted`] if the context was not received by a client
+    /// - See [`DiscordError`] for more info
+    pub fn accept(&self) -> Result<Payload<Value>> {
+        self.respond_to_join_request(Command::SendActivityJoinInvite)
+    }
+
+    /// Declines the join request this context was created for
+    ///
+    /// # Errors
+    /// - [`DiscordError::NotAJoinRequest`] if the event is not [`Event::ActivityJoinRequest`]
+    /// - [`DiscordError::NotStarted`] if the context was not received by a client
+    /// - See [`DiscordError`] for more info
+    pub fn decline(&self) -> Result<Payload<Value>> {
+        self.respond_to_join_request(Command::CloseActivityRequest)
+    }
+
+    fn respond_to_join_request(&self, cmd: Command) -> Result<Payload<Value>> {
+        let EventData::ActivityJoinRequest(ref event) = self.event else {
+            return Err(DiscordError::NotAJoinRequest);
+        };
+        let Some(user_id) = event.user.as_ref().and_then(|user| user.id) else {
+            return Err(DiscordError::NotAJoinRequest);
+        };
+        let Some(Responder(ref manager)) = self.responder else {
+            return Err(DiscordError::NotStarted);
+        };
+
+        // Both commands take the same arguments
+        let args = SendActivityJoinInviteArgs { user_id };
+        let response = manager.execute(cmd, args, None, Priority::Normal)?;
+
+        manager.resolve_join_request(user_id);
+        Ok(response)
+    }
+
+    /// Removes the handler this context was passed to from the registry,
+    /// so it is not called for any further events.
+    ///
+    /// Returns `false` if the handler had already been removed.
+    ///
+    /// The handle returned when registering the handler can still be dropped as usual afterwards.
+    // The return value is informational, removing the handler is the point
+    #[allow(clippy::must_use_candidate)]
+    pub fn remove_handler(&self) -> bool {
+        let Some(HandlerRef {
+            event,
+            registry,
+            handler,
+        }) = &self.handler
+        else {
+            return false;
+        };
+
+        match (registry.upgrade(), handler.upgrade()) {
+            (Some(registry), Some(handler)) => match event {
+                Some(event) => registry.remove(*event, &handler).is_ok(),
+                None => registry.remove_wildcard(&handler).is_ok(),
+            },
+            _ => false,
+        }
     }
 }
 
-type Handlers = RwLock<HashMap<Event, HandlerList>>;
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Information about a registered event listener
+pub struct HandlerInfo {
+    /// The event the listener is registered for, or `None` if it listens for all events
+    pub event: Option<Event>,
+    /// The label supplied when registering the listener, if any
+    pub label: Option<String>,
+}
+
+/// The listener an [`EventCallbackHandle`] belongs to
+enum Listener {
+    Event {
+        /// `None` if the handler listens for all events
+        event: Option<Event>,
+        handler: Weak<Handler>,
+    },
+    StateChange(Weak<StateHandler>),
+    Ping(Weak<PingHandler>),
+}
 
 #[must_use = "event listeners will be immediately dropped if the handle is not kept. Use `.persist` to stop them from being removed."]
 /// Handle to an event listener
 pub struct EventCallbackHandle {
-    event: Event,
+    listener: Listener,
     registry: Weak<HandlerRegistry>,
-    handler: Weak<Handler>,
 }
 
 impl EventCallbackHandle {
@@ -48,57 +336,685 @@ impl EventCallbackHandle {
 impl Drop for EventCallbackHandle {
     fn drop(&mut self) {
         // if the registry or this event handler has already been dropped, there's no reason to try and do it again
-        if let (Some(registry), Some(handler)) = (self.registry.upgrade(), self.handler.upgrade()) {
-            let handler = registry.remove(self.event, &handler);
-            if handler.is_err() {
-                error!("Failed to remove event handler. This can usually be ignored.");
-            }
+        let Some(registry) = self.registry.upgrade() else {
+            return;
+        };
+        let removed = match self.listener {
+            Listener::Event { event, ref handler } => match (event, handler.upgrade()) {
+                (Some(event), Some(handler)) => registry.remove(event, &handler).is_ok(),
+                (None, Some(handler)) => registry.remove_wildcard(&handler).is_ok(),
+                (_, None) => return,
+            },
+            Listener::StateChange(ref handler) => match handler.upgrade() {
+                Some(handler) => registry.remove_state_handler(&handler).is_ok(),
+                None => return,
+            },
+            Listener::Ping(ref handler) => match handler.upgrade() {
+                Some(handler) => registry.remove_ping_handler(&handler).is_ok(),
+                None => return,
+            },
+        };
+        if !removed {
+            error!(target: DISPATCH, "Failed to remove event handler. This can usually be ignored.");
+        }
+    }
+}
+
+#[must_use = "event listeners will be immediately dropped if the group is not kept. Use `.persist` to stop them from being removed."]
+#[derive(Default)]
+/// A group of event listeners that are all removed together once the group is dropped
+pub struct HandlerGroup {
+    handles: Vec<EventCallbackHandle>,
+}
+
+impl HandlerGroup {
+    /// Creates an empty group
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an event listener to the group
+    pub fn push(&mut self, handle: EventCallbackHandle) {
+        self.handles.push(handle);
+    }
+
+    /// Adds an event listener to the group, returning the group
+    pub fn with(mut self, handle: EventCallbackHandle) -> Self {
+        self.push(handle);
+        self
+    }
+
+    #[must_use]
+    /// The number of event listeners in the group
+    pub fn len(&self) -> usize {
+        self.handles.len()
+    }
+
+    #[must_use]
+    /// Whether the group contains no event listeners
+    pub fn is_empty(&self) -> bool {
+        self.handles.is_empty()
+    }
+
+    /// Immediately removes all event listeners in the group from the registry, keeping the (now empty) group.
+    pub fn clear(&mut self) {
+        self.handles.clear();
+    }
+
+    /// Immediately drops the group, thus removing all of its event listeners from the registry.
+    pub fn remove(self) {
+        drop(self);
+    }
+
+    /// "Forgets" all event listeners in the group. See [`EventCallbackHandle::persist`].
+    pub fn persist(self) {
+        self.handles
+            .into_iter()
+            .for_each(EventCallbackHandle::persist);
+    }
+}
+
+impl Extend<EventCallbackHandle> for HandlerGroup {
+    fn extend<T: IntoIterator<Item = EventCallbackHandle>>(&mut self, iter: T) {
+        self.handles.extend(iter);
+    }
+}
+
+impl FromIterator<EventCallbackHandle> for HandlerGroup {
+    fn from_iter<T: IntoIterator<Item = EventCallbackHandle>>(iter: T) -> Self {
+        Self {
+            handles: iter.into_iter().collect(),
         }
     }
 }
 
+/// The registered handlers, which are replaced as a whole whenever they change
+#[derive(Clone, Default)]
+struct HandlerTable {
+    handlers: HashMap<Event, HandlerList>,
+    wildcard_handlers: HandlerList,
+    state_handlers: Vec<Arc<StateHandler>>,
+    ping_handlers: Vec<Arc<PingHandler>>,
+    /// Events whose handlers are not run concurrently
+    dispatch_modes: HashMap<Event, DispatchMode>,
+}
+
+/// Registry of event handlers
+///
+/// Dispatching only loads the current [`HandlerTable`], so it never waits on registrations.
+/// Changes copy the table and swap the copy in, retrying if another change got there first.
 pub(crate) struct HandlerRegistry {
-    handlers: Handlers,
+    table: ArcSwap<HandlerTable>,
+    state_changes: Mutex<StateChangeQueue>,
+    /// Events whose last handler was removed, until the connection unsubscribes from them
+    emptied: Mutex<Vec<Event>>,
+    /// Called once an event has no handlers left, to wake the connection
+    on_emptied: Mutex<Option<Box<dyn Fn() + Send + Sync>>>,
+    slow_handlers: Mutex<SlowHandlerDetection>,
+    /// Events waiting to be passed to the handlers of events dispatched sequentially
+    sequential: Mutex<HashMap<Event, DispatchQueue>>,
+}
+
+/// Events waiting to be passed on, along with the handlers registered when they were received
+#[derive(Default)]
+struct DispatchQueue {
+    pending: VecDeque<(Context, HandlerList)>,
+    /// Whether a thread is currently passing the pending events on
+    draining: bool,
+}
+
+impl std::fmt::Debug for HandlerRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let table = self.table.load();
+        let handlers: HashMap<_, _> = table
+            .handlers
+            .iter()
+            .map(|(event, entries)| (event, entries.len()))
+            .collect();
+
+        f.debug_struct("HandlerRegistry")
+            .field("handlers", &handlers)
+            .field("wildcard_handlers", &table.wildcard_handlers.len())
+            .field("state_handlers", &table.state_handlers.len())
+            .field("ping_handlers", &table.ping_handlers.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// How long handlers may run for, and who to tell when they take longer
+struct SlowHandlerDetection {
+    /// `None` if handlers are not timed
+    threshold: Option<Duration>,
+    hook: Option<Arc<SlowHandlerHook>>,
+    /// Handlers that are currently running, watched so they are reported without waiting for them to finish
+    running: HashMap<u64, RunningHandler>,
+    next_id: u64,
+    /// Whether a thread is currently watching the running handlers
+    watching: bool,
+}
+
+impl Default for SlowHandlerDetection {
+    fn default() -> Self {
+        Self {
+            threshold: Some(DEFAULT_SLOW_HANDLER_THRESHOLD),
+            hook: None,
+            running: HashMap::new(),
+            next_id: 0,
+            watching: false,
+        }
+    }
+}
+
+/// A handler being watched, and whether it was already reported as still running
+struct RunningHandler {
+    handler: SlowHandler,
+    started: Instant,
+    reported: bool,
+}
+
+/// Stops watching a handler once it returns or panics
+struct Watched<'a> {
+    registry: &'a Weak<HandlerRegistry>,
+    id: u64,
+}
+
+impl Drop for Watched<'_> {
+    fn drop(&mut self) {
+        if let Some(registry) = self.registry.upgrade() {
+            registry.slow_handlers.lock().running.remove(&self.id);
+        }
+    }
+}
+
+/// State changes waiting to be passed to the state handlers
+#[derive(Default)]
+struct StateChangeQueue {
+    pending: VecDeque<StateChange>,
+    /// Whether a thread is currently passing the pending changes on
+    draining: bool,
 }
 
 impl HandlerRegistry {
     pub fn new() -> Self {
         Self {
-            handlers: RwLock::new(HashMap::new()),
+            table: ArcSwap::from_pointee(HandlerTable::default()),
+            state_changes: Mutex::default(),
+            emptied: Mutex::default(),
+            on_emptied: Mutex::default(),
+            slow_handlers: Mutex::default(),
+            sequential: Mutex::default(),
         }
     }
 
+    /// Sets how the handlers for `event` are run
+    pub fn set_dispatch_mode(&self, event: Event, mode: DispatchMode) {
+        self.update(|table| match mode {
+            DispatchMode::Concurrent => {
+                table.dispatch_modes.remove(&event);
+            }
+            mode => {
+                table.dispatch_modes.insert(event, mode);
+            }
+        });
+    }
+
+    /// How the handlers for `event` are run
+    pub fn dispatch_mode(&self, event: Event) -> DispatchMode {
+        self.table
+            .load()
+            .dispatch_modes
+            .get(&event)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Sets how long event handlers may run before they are reported as slow, or stops timing them if `None`
+    pub fn set_slow_handler_threshold(&self, threshold: Option<Duration>) {
+        self.slow_handlers.lock().threshold = threshold;
+    }
+
+    /// Sets the function called with every slow event handler, or removes it if `None`
+    pub fn set_slow_handler_hook(&self, hook: Option<Arc<SlowHandlerHook>>) {
+        self.slow_handlers.lock().hook = hook;
+    }
+
+    /// Sets the function called once an event has no handlers left
+    pub fn set_on_emptied<F>(&self, f: F)
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        *self.on_emptied.lock() = Some(Box::new(f));
+    }
+
+    /// Takes the events whose last handler was removed since the last call
+    pub fn take_emptied(&self) -> Vec<Event> {
+        std::mem::take(&mut *self.emptied.lock())
+    }
+
+    /// Records that `event` was emptied if it has no handlers left
+    fn note_if_emptied(&self, event: Event) {
+        if self.count(event) > 0 {
+            return;
+        }
+
+        let mut emptied = self.emptied.lock();
+        if !emptied.contains(&event) {
+            emptied.push(event);
+        }
+        drop(emptied);
+
+        if let Some(ref on_emptied) = *self.on_emptied.lock() {
+            on_emptied();
+        }
+    }
+
+    /// Applies `f` to a copy of the handler table and swaps it in, returning the result of the successful attempt
+    fn update<R: Default>(&self, mut f: impl FnMut(&mut HandlerTable) -> R) -> R {
+        let mut result = R::default();
+        self.table.rcu(|table| {
+            let mut table = HandlerTable::clone(table);
+            result = f(&mut table);
+            table
+        });
+        result
+    }
+
+    fn insert(
+        self: &Arc<Self>,
+        event: Option<Event>,
+        label: Option<String>,
+        handler: Arc<Handler>,
+    ) -> EventCallbackHandle {
+        let callback_handle = EventCallbackHandle {
+            listener: Listener::Event {
+                event,
+                handler: Arc::downgrade(&handler),
+            },
+            registry: Arc::downgrade(self),
+        };
+
+        let entry = HandlerEntry { handler, label };
+        self.update(|table| match event {
+            Some(event) => table.handlers.entry(event).or_default().push(entry.clone()),
+            None => table.wildcard_handlers.push(entry.clone()),
+        });
+
+        callback_handle
+    }
+
     pub fn register<F>(self: &Arc<Self>, event: Event, handler: F) -> EventCallbackHandle
     where
         F: Fn(Context) + Send + Sync + 'static,
     {
-        let handler: Arc<Handler> = Arc::new(handler);
+        self.insert(Some(event), None, Arc::new(handler))
+    }
+
+    /// Registers a handler with a label, which is reported by [`HandlerRegistry::info`]
+    pub fn register_labeled<S, F>(
+        self: &Arc<Self>,
+        event: Event,
+        label: S,
+        handler: F,
+    ) -> EventCallbackHandle
+    where
+        S: Into<String>,
+        F: Fn(Context) + Send + Sync + 'static,
+    {
+        self.insert(Some(event), Some(label.into()), Arc::new(handler))
+    }
+
+    /// Registers a handler whose errors are passed on to the [`Event::Error`] handlers
+    pub fn register_fallible<F, E>(
+        self: &Arc<Self>,
+        event: Event,
+        handler: F,
+    ) -> EventCallbackHandle
+    where
+        F: Fn(Context) -> std::result::Result<(), E> + Send + Sync + 'static,
+        E: Display,
+    {
+        let registry = Arc::downgrade(self);
+        self.register(event, move |context| {
+            let generation = context.generation;
+            if let Err(why) = handler(context) {
+                if let Some(registry) = registry.upgrade() {
+                    registry.handle_handler_error(event, generation, &why);
+                }
+            }
+        })
+    }
+
+    /// Passes the error returned by a handler on to the [`Event::Error`] handlers.
+    ///
+    /// Errors returned by [`Event::Error`] handlers are only logged, so a failing error handler does not call itself forever.
+    fn handle_handler_error(self: &Arc<Self>, event: Event, generation: u64, why: &dyn Display) {
+        let message = format!("Handler for {event:?} failed: {why}");
+        error!(target: DISPATCH, "{message}");
+
+        if event != Event::Error {
+            let error = ErrorEvent::new().message(message);
+            self.handle(&Context::new(
+                Event::Error,
+                EventData::Error(error),
+                generation,
+                None,
+            ));
+        }
+    }
+
+    /// Registers a handler that is only called when `filter` returns `true` for the event context
+    pub fn register_filtered<P, F>(
+        self: &Arc<Self>,
+        event: Event,
+        filter: P,
+        handler: F,
+    ) -> EventCallbackHandle
+    where
+        P: Fn(&Context) -> bool + Send + Sync + 'static,
+        F: Fn(Context) + Send + Sync + 'static,
+    {
+        self.register(event, move |context| {
+            if filter(&context) {
+                handler(context);
+            }
+        })
+    }
+
+    /// Registers a handler that is called for every event
+    pub fn register_wildcard<F>(self: &Arc<Self>, handler: F) -> EventCallbackHandle
+    where
+        F: Fn(Context) + Send + Sync + 'static,
+    {
+        self.insert(None, None, Arc::new(handler))
+    }
+
+    /// Registers a handler that is called whenever the connection state changes
+    pub fn register_state_handler<F>(self: &Arc<Self>, handler: F) -> EventCallbackHandle
+    where
+        F: Fn(StateChange) + Send + Sync + 'static,
+    {
+        let handler: Arc<StateHandler> = Arc::new(handler);
         let callback_handle = EventCallbackHandle {
-            event,
+            listener: Listener::StateChange(Arc::downgrade(&handler)),
             registry: Arc::downgrade(self),
-            handler: Arc::downgrade(&handler),
         };
 
-        let mut event_handlers = self.handlers.write();
-        let event_handler = event_handlers.entry(event).or_default();
-        event_handler.push(handler);
+        self.update(|table| table.state_handlers.push(handler.clone()));
 
         callback_handle
     }
 
+    /// Registers a handler that is called whenever Discord pings the connection
+    pub fn register_ping_handler<F>(self: &Arc<Self>, handler: F) -> EventCallbackHandle
+    where
+        F: Fn(Ping) + Send + Sync + 'static,
+    {
+        let handler: Arc<PingHandler> = Arc::new(handler);
+        let callback_handle = EventCallbackHandle {
+            listener: Listener::Ping(Arc::downgrade(&handler)),
+            registry: Arc::downgrade(self),
+        };
+
+        self.update(|table| table.ping_handlers.push(handler.clone()));
+
+        callback_handle
+    }
+
+    /// Passes a ping on to the ping handlers, each on a thread of its own
+    pub fn handle_ping(&self, ping: &Ping) {
+        for handler in &self.table.load().ping_handlers {
+            let handler = handler.clone();
+            let ping = ping.clone();
+            utils::spawn("ping", move || handler(ping));
+        }
+    }
+
+    /// Passes a state change on to the state handlers
+    ///
+    /// Changes are queued and passed on by a single thread at a time, so handlers see them in the order they happened
+    /// without holding up the connection thread.
+    pub fn handle_state_change(self: &Arc<Self>, change: StateChange) {
+        let mut queue = self.state_changes.lock();
+        queue.pending.push_back(change);
+        if queue.draining {
+            return;
+        }
+        queue.draining = true;
+        drop(queue);
+
+        let registry = self.clone();
+        utils::spawn("state", move || loop {
+            let mut queue = registry.state_changes.lock();
+            let Some(change) = queue.pending.pop_front() else {
+                queue.draining = false;
+                break;
+            };
+            drop(queue);
+
+            for handler in &registry.table.load().state_handlers {
+                // Caught so the queue keeps draining, as it would otherwise be marked as draining forever
+                if catch_unwind(AssertUnwindSafe(|| handler(change.clone()))).is_err() {
+                    error!(target: DISPATCH, "State handler panicked on {:?}", change.new);
+                }
+            }
+        });
+    }
+
     // TODO: Replace data type with stronger types
-    pub fn handle(&self, event: Event, data: EventData) {
-        let handlers = self.handlers.read();
-        if let Some(handlers) = handlers.get(&event) {
-            let context = Context::new(data);
+    pub fn handle(self: &Arc<Self>, context: &Context) {
+        // The loaded table is a snapshot, so handlers are free to register or remove others
+        let table = self.table.load();
+        let kind = context.kind;
+        let handlers = table.handlers.get(&kind).cloned().unwrap_or_default();
+
+        match table.dispatch_modes.get(&kind).copied().unwrap_or_default() {
+            DispatchMode::Concurrent => {
+                for entry in handlers {
+                    self.spawn_handler(Some(kind), entry, context.clone());
+                }
+            }
+            DispatchMode::Sequential => self.dispatch_sequentially(context.clone(), handlers),
+        }
+
+        // Handlers for all events are run concurrently, so one slow event does not hold up the others
+        for entry in &table.wildcard_handlers {
+            self.spawn_handler(None, entry.clone(), context.clone());
+        }
+    }
+
+    /// Runs a handler on a thread of its own
+    fn spawn_handler(
+        self: &Arc<Self>,
+        event: Option<Event>,
+        entry: HandlerEntry,
+        context: Context,
+    ) {
+        let registry = Arc::downgrade(self);
+        utils::spawn("handler", move || {
+            Self::run_handler(&registry, event, entry, context);
+        });
+    }
+
+    /// Queues an event to be passed to `handlers` one after another, after any events of the same kind before it
+    fn dispatch_sequentially(self: &Arc<Self>, context: Context, handlers: HandlerList) {
+        let kind = context.kind;
+        let mut queues = self.sequential.lock();
+        let queue = queues.entry(kind).or_default();
+        queue.pending.push_back((context, handlers));
+        if queue.draining {
+            return;
+        }
+        queue.draining = true;
+        drop(queues);
+
+        let registry = Arc::downgrade(self);
+        utils::spawn("dispatch", move || loop {
+            let Some(strong) = registry.upgrade() else {
+                break;
+            };
+            let mut queues = strong.sequential.lock();
+            let queue = queues.entry(kind).or_default();
+            let Some((context, handlers)) = queue.pending.pop_front() else {
+                queue.draining = false;
+                break;
+            };
+            drop(queues);
+            drop(strong);
 
-            for handler in handlers {
-                let handler = handler.clone();
+            for entry in handlers {
                 let context = context.clone();
-                thread::spawn(move || {
-                    handler(context);
-                });
+                // Caught so the queue keeps draining, as it would otherwise be marked as draining forever
+                if catch_unwind(AssertUnwindSafe(|| {
+                    Self::run_handler(&registry, Some(kind), entry, context);
+                }))
+                .is_err()
+                {
+                    error!(target: DISPATCH, "Handler for {kind:?} panicked");
+                }
             }
+        });
+    }
+
+    /// Runs a handler on the current thread, timing how long it takes
+    fn run_handler(
+        registry: &Weak<Self>,
+        event: Option<Event>,
+        entry: HandlerEntry,
+        mut context: Context,
+    ) {
+        let HandlerEntry { handler, label } = entry;
+        let kind = context.kind;
+        let _span =
+            debug_span!(target: DISPATCH, "handler", event = ?kind, label = label.as_deref())
+                .entered();
+        context.handler = Some(HandlerRef {
+            event,
+            registry: registry.clone(),
+            handler: Arc::downgrade(&handler),
+        });
+
+        let started = Instant::now();
+        let report = SlowHandler {
+            event: kind,
+            wildcard: event.is_none(),
+            label,
+            elapsed: Duration::ZERO,
+            running: false,
+        };
+        let watched = registry
+            .upgrade()
+            .and_then(|strong| strong.watch(registry, report.clone(), started));
+        handler(context);
+        drop(watched);
+
+        if let Some(registry) = registry.upgrade() {
+            registry.check_duration(&SlowHandler {
+                elapsed: started.elapsed(),
+                ..report
+            });
+        }
+    }
+
+    /// Watches a running handler, so it is reported once it runs past the threshold even if it never returns
+    fn watch<'a>(
+        self: &Arc<Self>,
+        registry: &'a Weak<Self>,
+        handler: SlowHandler,
+        started: Instant,
+    ) -> Option<Watched<'a>> {
+        let mut slow_handlers = self.slow_handlers.lock();
+        slow_handlers.threshold?;
+
+        let id = slow_handlers.next_id;
+        slow_handlers.next_id += 1;
+        slow_handlers.running.insert(
+            id,
+            RunningHandler {
+                handler,
+                started,
+                reported: false,
+            },
+        );
+
+        if !slow_handlers.watching {
+            slow_handlers.watching = true;
+            let registry = registry.clone();
+            utils::spawn("watchdog", move || Self::watchdog(&registry));
+        }
+
+        Some(Watched { registry, id })
+    }
+
+    /// Reports handlers still running past the threshold, until none are left running
+    fn watchdog(registry: &Weak<Self>) {
+        loop {
+            let Some(strong) = registry.upgrade() else {
+                break;
+            };
+            let mut slow_handlers = strong.slow_handlers.lock();
+            let Some(threshold) = slow_handlers
+                .threshold
+                .filter(|_| !slow_handlers.running.is_empty())
+            else {
+                slow_handlers.watching = false;
+                break;
+            };
+
+            let mut overdue = Vec::new();
+            for running in slow_handlers.running.values_mut() {
+                let elapsed = running.started.elapsed();
+                if !running.reported && elapsed > threshold {
+                    running.reported = true;
+                    overdue.push(SlowHandler {
+                        elapsed,
+                        running: true,
+                        ..running.handler.clone()
+                    });
+                }
+            }
+            drop(slow_handlers);
+
+            for handler in &overdue {
+                strong.report_slow(handler, threshold);
+            }
+            drop(strong);
+
+            // Polled at a fraction of the threshold, so handlers are reported soon after running past it
+            thread::sleep((threshold / 4).max(Duration::from_millis(1)));
+        }
+    }
+
+    /// Reports a handler that finished running if it took longer than the threshold
+    fn check_duration(&self, handler: &SlowHandler) {
+        let threshold = self.slow_handlers.lock().threshold;
+        if let Some(threshold) = threshold.filter(|threshold| handler.elapsed > *threshold) {
+            self.report_slow(handler, threshold);
+        }
+    }
+
+    /// Logs a slow handler and passes it to the hook
+    fn report_slow(&self, handler: &SlowHandler, threshold: Duration) {
+        let hook = self.slow_handlers.lock().hook.clone();
+
+        let label = handler.label.as_deref().unwrap_or("unlabeled");
+        if handler.running {
+            warn!(
+                target: DISPATCH,
+                "Handler for {:?} ({label}) is still running after {:?}, longer than the {threshold:?} threshold",
+                handler.event,
+                handler.elapsed
+            );
+        } else {
+            warn!(
+                target: DISPATCH,
+                "Handler for {:?} ({label}) took {:?}, longer than the {threshold:?} threshold",
+                handler.event,
+                handler.elapsed
+            );
+        }
+
+        if let Some(hook) = hook {
+            hook(handler);
         }
     }
 
@@ -112,18 +1028,104 @@ impl HandlerRegistry {
         event: Event,
         target: &Arc<Handler>,
     ) -> crate::Result<Arc<Handler>> {
-        let mut handlers = self.handlers.write();
-        if let Some(handlers) = handlers.get_mut(&event) {
-            if let Some(index) = handlers
-                .iter()
-                .position(|handler| Arc::ptr_eq(handler, target))
-            {
-                return Ok(handlers.remove(index));
-            }
+        let removed = self
+            .update(|table| {
+                let handlers = table.handlers.get_mut(&event)?;
+                let index = handlers
+                    .iter()
+                    .position(|entry| Arc::ptr_eq(&entry.handler, target))?;
+                Some(handlers.remove(index).handler)
+            })
+            .ok_or(crate::DiscordError::NoChangesMade)?;
+
+        self.note_if_emptied(event);
+        Ok(removed)
+    }
+
+    /// The number of handlers registered for the given event, excluding wildcard handlers
+    pub fn count(&self, event: Event) -> usize {
+        self.table.load().handlers.get(&event).map_or(0, Vec::len)
+    }
+
+    /// Information about every registered handler, including wildcard handlers
+    pub fn info(&self) -> Vec<HandlerInfo> {
+        let table = self.table.load();
+
+        let handlers = table.handlers.iter().flat_map(|(event, entries)| {
+            entries.iter().map(|entry| HandlerInfo {
+                event: Some(*event),
+                label: entry.label.clone(),
+            })
+        });
+        let wildcard_handlers = table.wildcard_handlers.iter().map(|entry| HandlerInfo {
+            event: None,
+            label: entry.label.clone(),
+        });
+
+        handlers.chain(wildcard_handlers).collect()
+    }
+
+    /// Removes all handlers for the given event from the registry, returning how many were removed
+    pub fn clear(&self, event: Event) -> usize {
+        let cleared = self.update(|table| {
+            table
+                .handlers
+                .remove(&event)
+                .map_or(0, |handlers| handlers.len())
+        });
+
+        if cleared > 0 {
+            self.note_if_emptied(event);
         }
+        cleared
+    }
+
+    /// Removes a state handler from the registry, if it exists
+    ///
+    /// # Errors
+    /// - Returns an error if no changes were made to the registry. See [`HandlerRegistry::remove`].
+    pub fn remove_state_handler(
+        &self,
+        target: &Arc<StateHandler>,
+    ) -> crate::Result<Arc<StateHandler>> {
+        self.update(|table| remove_handler(&mut table.state_handlers, target))
+            .ok_or(crate::DiscordError::NoChangesMade)
+    }
 
-        Err(crate::DiscordError::NoChangesMade)
+    /// Removes a ping handler from the registry, if it exists
+    ///
+    /// # Errors
+    /// - Returns an error if no changes were made to the registry. See [`HandlerRegistry::remove`].
+    pub fn remove_ping_handler(
+        &self,
+        target: &Arc<PingHandler>,
+    ) -> crate::Result<Arc<PingHandler>> {
+        self.update(|table| remove_handler(&mut table.ping_handlers, target))
+            .ok_or(crate::DiscordError::NoChangesMade)
     }
+
+    /// Removes a wildcard handler from the registry, if it exists
+    ///
+    /// # Errors
+    /// - Returns an error if no changes were made to the registry. See [`HandlerRegistry::remove`].
+    pub fn remove_wildcard(self: &Arc<Self>, target: &Arc<Handler>) -> crate::Result<Arc<Handler>> {
+        self.update(|table| {
+            let handlers = &mut table.wildcard_handlers;
+            let index = handlers
+                .iter()
+                .position(|entry| Arc::ptr_eq(&entry.handler, target))?;
+            Some(handlers.remove(index).handler)
+        })
+        .ok_or(crate::DiscordError::NoChangesMade)
+    }
+}
+
+/// Removes `target` from a list of handlers that are not tied to an event
+fn remove_handler<T: ?Sized>(handlers: &mut Vec<Arc<T>>, target: &Arc<T>) -> Option<Arc<T>> {
+    let index = handlers
+        .iter()
+        .position(|handler| Arc::ptr_eq(handler, target))?;
+    Some(handlers.remove(index))
 }
 
 #[cfg(test)]
@@ -139,7 +1141,8 @@ mod tests {
         let _ready2 = registry.register(Event::Ready, |_| unimplemented!());
         let _error = registry.register(Event::Error, |_| unimplemented!());
 
-        let handlers = registry.handlers.read();
+        let table = registry.table.load();
+        let handlers = &table.handlers;
         assert_eq!(handlers.len(), 2);
         assert_eq!(handlers[&Event::Ready].len(), 2);
         assert_eq!(handlers[&Event::Error].len(), 1);
@@ -157,7 +1160,8 @@ mod tests {
         }
         // _ready2 is automatically removed
 
-        let handlers = registry.handlers.read();
+        let table = registry.table.load();
+        let handlers = &table.handlers;
         assert_eq!(handlers.len(), 2);
         assert_eq!(handlers[&Event::Ready].len(), 1);
         assert_eq!(handlers[&Event::Error].len(), 1);
@@ -176,8 +1180,480 @@ mod tests {
         }
         // _ready2 is not automatically removed
 
-        let handlers = registry.handlers.read();
+        let table = registry.table.load();
+        let handlers = &table.handlers;
         assert_eq!(handlers.len(), 1);
         assert_eq!(handlers[&Event::Ready].len(), 1);
     }
+
+    #[test]
+    fn filtered_handlers_skip_unmatched_events() {
+        use crate::models::ActivityJoinEvent;
+
+        let registry = Arc::new(HandlerRegistry::new());
+        let (tx, rx) = crossbeam_channel::unbounded();
+
+        let _join = registry.register_filtered(
+            Event::ActivityJoin,
+            |ctx| matches!(&ctx.event, EventData::ActivityJoin(e) if e.secret.as_deref() == Some("b")),
+            move |ctx| tx.send(ctx).unwrap(),
+        );
+
+        for secret in ["a", "b"] {
+            registry.handle(&Context::new(
+                Event::ActivityJoin,
+                EventData::ActivityJoin(ActivityJoinEvent::new().secret(secret)),
+                0,
+                None,
+            ));
+        }
+
+        let ctx = rx
+            .recv_timeout(std::time::Duration::from_secs(1))
+            .expect("Filtered handler was not called");
+        assert_eq!(
+            ctx.event,
+            EventData::ActivityJoin(ActivityJoinEvent::new().secret("b"))
+        );
+        assert!(rx
+            .recv_timeout(std::time::Duration::from_millis(100))
+            .is_err());
+    }
+
+    #[test]
+    fn wildcard_handlers_receive_all_events() {
+        let registry = Arc::new(HandlerRegistry::new());
+        let (tx, rx) = crossbeam_channel::unbounded();
+
+        let wildcard = registry.register_wildcard(move |ctx| tx.send(ctx.kind).unwrap());
+
+        for event in [Event::Ready, Event::SpeakingStart] {
+            registry.handle(&Context::new(
+                event,
+                EventData::Unknown(serde_json::Value::Null),
+                0,
+                None,
+            ));
+        }
+
+        let timeout = std::time::Duration::from_secs(1);
+        let mut kinds = vec![
+            rx.recv_timeout(timeout).unwrap(),
+            rx.recv_timeout(timeout).unwrap(),
+        ];
+        kinds.sort_by_key(|kind| *kind == Event::SpeakingStart);
+        assert_eq!(kinds, [Event::Ready, Event::SpeakingStart]);
+
+        drop(wildcard);
+        assert!(registry.table.load().wildcard_handlers.is_empty());
+    }
+
+    #[test]
+    fn state_handlers_are_called_in_order() {
+        let registry = Arc::new(HandlerRegistry::new());
+        let (tx, rx) = crossbeam_channel::unbounded();
+
+        let handle = registry.register_state_handler(move |change| tx.send(change.new).unwrap());
+
+        let states = [
+            ConnectionState::Connecting,
+            ConnectionState::Connected,
+            ConnectionState::Disconnected,
+        ];
+        for new in states {
+            registry.handle_state_change(StateChange {
+                old: ConnectionState::Disconnected,
+                new,
+                reason: StateChangeReason::Started,
+            });
+        }
+
+        let timeout = std::time::Duration::from_secs(1);
+        for state in states {
+            assert_eq!(rx.recv_timeout(timeout).unwrap(), state);
+        }
+
+        drop(handle);
+        assert!(registry.table.load().state_handlers.is_empty());
+    }
+
+    #[test]
+    fn state_handlers_survive_panics() {
+        let registry = Arc::new(HandlerRegistry::new());
+        let (tx, rx) = crossbeam_channel::unbounded();
+
+        let handle = registry.register_state_handler(move |change| {
+            assert_ne!(change.new, ConnectionState::Connecting, "handler failed");
+            tx.send(change.new).unwrap();
+        });
+
+        for new in [ConnectionState::Connecting, ConnectionState::Connected] {
+            registry.handle_state_change(StateChange {
+                old: ConnectionState::Disconnected,
+                new,
+                reason: StateChangeReason::Started,
+            });
+        }
+
+        let timeout = std::time::Duration::from_secs(1);
+        assert_eq!(
+            rx.recv_timeout(timeout).unwrap(),
+            ConnectionState::Connected
+        );
+
+        // Changes after the panic are still passed on
+        registry.handle_state_change(StateChange {
+            old: ConnectionState::Connected,
+            new: ConnectionState::Disconnected,
+            reason: StateChangeReason::Started,
+        });
+        assert_eq!(
+            rx.recv_timeout(timeout).unwrap(),
+            ConnectionState::Disconnected
+        );
+        drop(handle);
+    }
+
+    #[test]
+    fn ping_handlers_receive_pings() {
+        let registry = Arc::new(HandlerRegistry::new());
+        let (tx, rx) = crossbeam_channel::unbounded();
+
+        let handle = registry.register_ping_handler(move |ping| tx.send(ping.payload).unwrap());
+
+        registry.handle_ping(&Ping {
+            payload: serde_json::json!({ "n": 1 }),
+            received_at: SystemTime::now(),
+        });
+        let payload = rx.recv_timeout(std::time::Duration::from_secs(1)).unwrap();
+        assert_eq!(payload, serde_json::json!({ "n": 1 }));
+
+        drop(handle);
+        assert!(registry.table.load().ping_handlers.is_empty());
+    }
+
+    #[test]
+    fn handler_errors_are_passed_on() {
+        let registry = Arc::new(HandlerRegistry::new());
+
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let _error = registry.register_fallible(Event::Error, move |ctx| {
+            let EventData::Error(ref event) = ctx.event else {
+                unreachable!()
+            };
+            tx.send(event.message.clone()).unwrap();
+            Err("the error handler failed too")
+        });
+        let _join = registry.register_fallible(Event::ActivityJoin, |_| Err("no such lobby"));
+
+        registry.handle(&Context::new(
+            Event::ActivityJoin,
+            EventData::ActivityJoin(crate::models::ActivityJoinEvent::new()),
+            0,
+            None,
+        ));
+
+        let message = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(
+            message.as_deref(),
+            Some("Handler for ActivityJoin failed: no such lobby")
+        );
+        assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+    }
+
+    #[test]
+    fn sequential_handlers_run_in_order() {
+        use crate::models::ActivityJoinEvent;
+
+        let registry = Arc::new(HandlerRegistry::new());
+        registry.set_dispatch_mode(Event::ActivityJoin, DispatchMode::Sequential);
+        assert_eq!(
+            registry.dispatch_mode(Event::ActivityJoin),
+            DispatchMode::Sequential
+        );
+
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let _handlers: HandlerGroup = (0..3u64)
+            .map(|i| {
+                let tx = tx.clone();
+                registry.register(Event::ActivityJoin, move |ctx| {
+                    // Earlier handlers take longer, so they would finish last if run concurrently
+                    thread::sleep(Duration::from_millis(30 - i * 10));
+                    let EventData::ActivityJoin(ref event) = ctx.event else {
+                        unreachable!()
+                    };
+                    tx.send((event.secret.clone(), i)).unwrap();
+                })
+            })
+            .collect();
+
+        for secret in ["a", "b"] {
+            registry.handle(&Context::new(
+                Event::ActivityJoin,
+                EventData::ActivityJoin(ActivityJoinEvent::new().secret(secret)),
+                0,
+                None,
+            ));
+        }
+
+        let calls: Vec<_> = (0..6)
+            .map(|_| rx.recv_timeout(Duration::from_secs(5)).unwrap())
+            .collect();
+        let expected: Vec<_> = ["a", "b"]
+            .into_iter()
+            .flat_map(|secret| (0..3).map(move |i| (Some(secret.to_string()), i)))
+            .collect();
+        assert_eq!(calls, expected);
+    }
+
+    #[test]
+    fn sequential_handlers_survive_panics() {
+        use crate::models::ActivityJoinEvent;
+
+        let registry = Arc::new(HandlerRegistry::new());
+        registry.set_dispatch_mode(Event::ActivityJoin, DispatchMode::Sequential);
+
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let _join = registry.register(Event::ActivityJoin, move |ctx| {
+            let EventData::ActivityJoin(ref event) = ctx.event else {
+                unreachable!()
+            };
+            assert_ne!(event.secret.as_deref(), Some("a"), "handler failed");
+            tx.send(event.secret.clone()).unwrap();
+        });
+
+        for secret in ["a", "b", "c"] {
+            registry.handle(&Context::new(
+                Event::ActivityJoin,
+                EventData::ActivityJoin(ActivityJoinEvent::new().secret(secret)),
+                0,
+                None,
+            ));
+        }
+
+        for secret in ["b", "c"] {
+            let received = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+            assert_eq!(received.as_deref(), Some(secret));
+        }
+    }
+
+    #[test]
+    fn slow_handlers_are_reported() {
+        use crate::models::ActivityJoinEvent;
+
+        let registry = Arc::new(HandlerRegistry::new());
+        registry.set_slow_handler_threshold(Some(Duration::from_millis(20)));
+
+        let (tx, rx) = crossbeam_channel::unbounded();
+        registry.set_slow_handler_hook(Some(Arc::new(move |handler: &SlowHandler| {
+            tx.send(handler.clone()).unwrap();
+        })));
+
+        let _fast = registry.register_labeled(Event::ActivityJoin, "fast", |_| {});
+        let _slow = registry.register_labeled(Event::ActivityJoin, "slow", |_| {
+            thread::sleep(Duration::from_millis(50));
+        });
+
+        registry.handle(&Context::new(
+            Event::ActivityJoin,
+            EventData::ActivityJoin(ActivityJoinEvent::new()),
+            0,
+            None,
+        ));
+
+        // Reported while still running, and again once finished
+        let running = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(running.running);
+        assert!(running.elapsed > Duration::from_millis(20));
+
+        let slow = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(slow.event, Event::ActivityJoin);
+        assert!(!slow.wildcard);
+        assert!(!slow.running);
+        assert_eq!(slow.label.as_deref(), Some("slow"));
+        assert!(slow.elapsed >= Duration::from_millis(50));
+        assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+    }
+
+    #[test]
+    fn stuck_handlers_are_reported() {
+        let registry = Arc::new(HandlerRegistry::new());
+        registry.set_slow_handler_threshold(Some(Duration::from_millis(20)));
+
+        let (tx, rx) = crossbeam_channel::unbounded();
+        registry.set_slow_handler_hook(Some(Arc::new(move |handler: &SlowHandler| {
+            tx.send(handler.clone()).unwrap();
+        })));
+
+        let (release, released) = crossbeam_channel::bounded::<()>(0);
+        let _stuck = registry.register_labeled(Event::Ready, "stuck", move |_| {
+            let _ = released.recv();
+        });
+
+        registry.handle(&Context::new(
+            Event::Ready,
+            EventData::Unknown(Value::Null),
+            0,
+            None,
+        ));
+
+        let stuck = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(stuck.running);
+        assert_eq!(stuck.label.as_deref(), Some("stuck"));
+        // Only reported once while running
+        assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+
+        drop(release);
+        assert!(!rx.recv_timeout(Duration::from_secs(5)).unwrap().running);
+    }
+
+    #[test]
+    fn removing_the_last_handler_empties_the_event() {
+        let registry = Arc::new(HandlerRegistry::new());
+        let (tx, rx) = crossbeam_channel::unbounded();
+        registry.set_on_emptied(move || tx.send(()).unwrap());
+
+        let ready1 = registry.register(Event::Ready, |_| unimplemented!());
+        let ready2 = registry.register(Event::Ready, |_| unimplemented!());
+        let _error = registry.register(Event::Error, |_| unimplemented!());
+
+        drop(ready1);
+        assert!(registry.take_emptied().is_empty());
+
+        drop(ready2);
+        assert_eq!(registry.take_emptied(), [Event::Ready]);
+        assert!(rx.try_recv().is_ok());
+
+        assert_eq!(registry.clear(Event::Error), 1);
+        assert_eq!(registry.take_emptied(), [Event::Error]);
+    }
+
+    #[test]
+    fn clear_event_handlers() {
+        let registry = Arc::new(HandlerRegistry::new());
+        let ready1 = registry.register(Event::Ready, |_| unimplemented!());
+        let _ready2 = registry.register(Event::Ready, |_| unimplemented!());
+        let _error = registry.register(Event::Error, |_| unimplemented!());
+
+        assert_eq!(registry.clear(Event::Ready), 2);
+        assert_eq!(registry.clear(Event::Ready), 0);
+
+        // Dropping a handle to an already cleared handler is a no-op
+        drop(ready1);
+
+        let table = registry.table.load();
+        let handlers = &table.handlers;
+        assert_eq!(handlers.len(), 1);
+        assert_eq!(handlers[&Event::Error].len(), 1);
+    }
+
+    #[test]
+    fn handler_groups_remove_together() {
+        let registry = Arc::new(HandlerRegistry::new());
+        let _error = registry.register(Event::Error, |_| unimplemented!());
+
+        {
+            let group: HandlerGroup = [Event::Ready, Event::Error]
+                .into_iter()
+                .map(|event| registry.register(event, |_| unimplemented!()))
+                .collect();
+            assert_eq!(group.len(), 2);
+        }
+
+        let table = registry.table.load();
+        let handlers = &table.handlers;
+        assert!(handlers[&Event::Ready].is_empty());
+        assert_eq!(handlers[&Event::Error].len(), 1);
+    }
+
+    #[test]
+    fn inspect_registered_handlers() {
+        let registry = Arc::new(HandlerRegistry::new());
+        let _ready = registry.register_labeled(Event::Ready, "plugin", |_| unimplemented!());
+        let _error = registry.register(Event::Error, |_| unimplemented!());
+        let _wildcard = registry.register_wildcard(|_| unimplemented!());
+
+        assert_eq!(registry.count(Event::Ready), 1);
+        assert_eq!(registry.count(Event::ActivityJoin), 0);
+
+        let info = registry.info();
+        assert_eq!(info.len(), 3);
+        assert!(info.contains(&HandlerInfo {
+            event: Some(Event::Ready),
+            label: Some(String::from("plugin")),
+        }));
+        assert!(info.contains(&HandlerInfo {
+            event: None,
+            label: None,
+        }));
+    }
+
+    #[test]
+    fn handlers_can_modify_registry() {
+        let registry = Arc::new(HandlerRegistry::new());
+        let (tx, rx) = crossbeam_channel::unbounded();
+
+        let _ready = registry.register(Event::Ready, {
+            let registry = registry.clone();
+            move |_| {
+                let error = registry.register(Event::Error, |_| unimplemented!());
+                tx.send(registry.count(Event::Error)).unwrap();
+                drop(error);
+                tx.send(registry.count(Event::Error)).unwrap();
+            }
+        });
+
+        registry.handle(&Context::new(
+            Event::Ready,
+            EventData::Unknown(serde_json::Value::Null),
+            0,
+            None,
+        ));
+
+        let timeout = std::time::Duration::from_secs(1);
+        assert_eq!(rx.recv_timeout(timeout).unwrap(), 1);
+        assert_eq!(rx.recv_timeout(timeout).unwrap(), 0);
+    }
+
+    #[test]
+    fn handlers_can_remove_themselves() {
+        let registry = Arc::new(HandlerRegistry::new());
+        let (tx, rx) = crossbeam_channel::unbounded();
+
+        let _ready = registry.register(Event::Ready, move |ctx| {
+            tx.send(ctx.remove_handler()).unwrap();
+            tx.send(ctx.remove_handler()).unwrap();
+        });
+
+        registry.handle(&Context::new(
+            Event::Ready,
+            EventData::Unknown(serde_json::Value::Null),
+            0,
+            None,
+        ));
+
+        let timeout = std::time::Duration::from_secs(1);
+        assert!(rx.recv_timeout(timeout).unwrap());
+        assert!(!rx.recv_timeout(timeout).unwrap());
+        assert_eq!(registry.count(Event::Ready), 0);
+    }
+
+    #[test]
+    fn only_join_requests_can_be_answered() {
+        let context = Context::new(Event::Ready, EventData::Unknown(Value::Null), 0, None);
+        assert!(matches!(
+            context.accept(),
+            Err(DiscordError::NotAJoinRequest)
+        ));
+
+        let request = crate::models::ActivityJoinRequestEvent::new()
+            .user(crate::models::PartialUser::new().id(225_528_876_536_414_208));
+        let context = Context::new(
+            Event::ActivityJoinRequest,
+            EventData::ActivityJoinRequest(request),
+            0,
+            None,
+        );
+        assert!(matches!(context.decline(), Err(DiscordError::NotStarted)));
+    }
 }
@@ -0,0 +1,280 @@
+use std::{
+    io::ErrorKind,
+    net::TcpStream,
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crossbeam_channel::{unbounded, Receiver, Sender, TryRecvError};
+use serde_json::{json, Value};
+use tungstenite::{
+    handshake::HandshakeError, stream::MaybeTlsStream, Message as WsMessage, WebSocket,
+};
+
+use crate::{models::Activity, targets::GATEWAY, Client, DiscordError, Result};
+
+const GATEWAY_HOST: &str = "gateway.discord.gg";
+const GATEWAY_URL: &str = "wss://gateway.discord.gg/?v=10&encoding=json";
+
+/// How long a read waits before the connection thread checks for heartbeats and outgoing updates
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+const OP_DISPATCH: u64 = 0;
+const OP_HEARTBEAT: u64 = 1;
+const OP_IDENTIFY: u64 = 2;
+const OP_PRESENCE_UPDATE: u64 = 3;
+const OP_RECONNECT: u64 = 7;
+const OP_INVALID_SESSION: u64 = 9;
+const OP_HELLO: u64 = 10;
+
+type Socket = WebSocket<MaybeTlsStream<TcpStream>>;
+
+/// Sets and clears the activity, either through the local Discord client or the gateway
+///
+/// Lets the same code set presence on a desktop with [`Client`], and headless with [`Gateway`].
+pub trait ActivitySink {
+    /// Sets the activity
+    ///
+    /// # Errors
+    /// - The activity could not be sent
+    fn set_activity(&mut self, activity: Activity) -> Result<()>;
+
+    /// Clears the activity
+    ///
+    /// # Errors
+    /// - The activity could not be cleared
+    fn clear_activity(&mut self) -> Result<()>;
+}
+
+impl ActivitySink for Client {
+    fn set_activity(&mut self, activity: Activity) -> Result<()> {
+        self.set_activity_struct(activity).map(|_| ())
+    }
+
+    fn clear_activity(&mut self) -> Result<()> {
+        Client::clear_activity(self).map(|_| ())
+    }
+}
+
+/// Sets the presence of a bot through a gateway connection, for use without a local Discord client
+///
+/// Bots can only show the name, type and state of an activity, so the details become the name,
+/// and the rest of the activity is ignored. An activity with just a state is shown as a custom status.
+///
+/// The connection is not resumed if Discord closes it, and is closed when the `Gateway` is dropped.
+pub struct Gateway {
+    /// Dropped to stop the connection thread
+    outbound: Option<Sender<Value>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Gateway {
+    /// Connects to the gateway and identifies with the given bot token
+    ///
+    /// # Errors
+    /// - The connection or handshake failed
+    pub fn connect(token: &str) -> Result<Self> {
+        let stream = TcpStream::connect((GATEWAY_HOST, 443))?;
+        // Socket options are shared between clones, so the timeout can be set once the handshake is done
+        let timeout_handle = stream.try_clone()?;
+
+        let (mut socket, _) =
+            tungstenite::client_tls(GATEWAY_URL, stream).map_err(|why| match why {
+                HandshakeError::Failure(why) => DiscordError::from(why),
+                // The stream blocks until the handshake completes, so it is never interrupted
+                HandshakeError::Interrupted(_) => DiscordError::ConnectionClosed,
+            })?;
+        timeout_handle.set_read_timeout(Some(POLL_INTERVAL))?;
+
+        let heartbeat_interval = read_hello(&mut socket)?;
+        trace!(target: GATEWAY, "Heartbeating every {heartbeat_interval:?}");
+
+        send(
+            &mut socket,
+            &json!({
+                "op": OP_IDENTIFY,
+                "d": {
+                    "token": token,
+                    "intents": 0,
+                    "properties": {
+                        "os": std::env::consts::OS,
+                        "browser": "discord-presence",
+                        "device": "discord-presence",
+                    },
+                },
+            }),
+        )?;
+
+        let (outbound, rx) = unbounded();
+        let thread = thread::Builder::new()
+            .name("discord-presence-gateway".to_owned())
+            .spawn(move || run(socket, &rx, heartbeat_interval))?;
+
+        Ok(Self {
+            outbound: Some(outbound),
+            thread: Some(thread),
+        })
+    }
+
+    fn update_presence(&self, activities: &[Value]) -> Result<()> {
+        let Some(ref outbound) = self.outbound else {
+            return Err(DiscordError::ConnectionClosed);
+        };
+
+        outbound
+            .send(json!({
+                "op": OP_PRESENCE_UPDATE,
+                "d": {
+                    "since": null,
+                    "activities": activities,
+                    "status": "online",
+                    "afk": false,
+                },
+            }))
+            .map_err(|_| DiscordError::ConnectionClosed)
+    }
+}
+
+impl ActivitySink for Gateway {
+    fn set_activity(&mut self, activity: Activity) -> Result<()> {
+        self.update_presence(&[gateway_activity(&activity)])
+    }
+
+    fn clear_activity(&mut self) -> Result<()> {
+        self.update_presence(&[])
+    }
+}
+
+impl Drop for Gateway {
+    fn drop(&mut self) {
+        self.outbound = None;
+
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+    }
+}
+
+/// Converts an activity into the subset of it bots can show
+fn gateway_activity(activity: &Activity) -> Value {
+    #[cfg(feature = "activity_type")]
+    let kind = activity._type.map_or(0, |kind| kind as u8);
+    #[cfg(not(feature = "activity_type"))]
+    let kind = 0;
+
+    match activity.details {
+        Some(ref details) => json!({ "name": details, "type": kind, "state": activity.state }),
+        // Custom statuses show the state on its own
+        None => json!({
+            "name": "Custom Status",
+            "type": 4,
+            "state": activity.state.as_deref().unwrap_or_default(),
+        }),
+    }
+}
+
+fn read_hello(socket: &mut Socket) -> Result<Duration> {
+    loop {
+        let Some(payload) = read(socket)? else {
+            continue;
+        };
+
+        if payload["op"].as_u64() == Some(OP_HELLO) {
+            let interval = payload["d"]["heartbeat_interval"]
+                .as_u64()
+                .ok_or(DiscordError::Conversion)?;
+            return Ok(Duration::from_millis(interval));
+        }
+    }
+}
+
+fn run(mut socket: Socket, outbound: &Receiver<Value>, heartbeat_interval: Duration) {
+    let mut sequence = Value::Null;
+    let mut next_heartbeat = Instant::now() + heartbeat_interval;
+
+    loop {
+        if Instant::now() >= next_heartbeat {
+            next_heartbeat = Instant::now() + heartbeat_interval;
+            if let Err(why) = send(&mut socket, &json!({ "op": OP_HEARTBEAT, "d": sequence })) {
+                error!(target: GATEWAY, "Failed to send heartbeat: {why}");
+                break;
+            }
+        }
+
+        match outbound.try_recv() {
+            Ok(payload) => {
+                if let Err(why) = send(&mut socket, &payload) {
+                    error!(target: GATEWAY, "Failed to update presence: {why}");
+                    break;
+                }
+            }
+            Err(TryRecvError::Disconnected) => break,
+            Err(TryRecvError::Empty) => {}
+        }
+
+        match read(&mut socket) {
+            Ok(Some(payload)) => match payload["op"].as_u64() {
+                Some(OP_DISPATCH) => sequence = payload["s"].clone(),
+                // Discord can ask for a heartbeat straight away
+                Some(OP_HEARTBEAT) => next_heartbeat = Instant::now(),
+                Some(OP_RECONNECT | OP_INVALID_SESSION) => {
+                    error!(target: GATEWAY, "Discord closed the gateway session");
+                    break;
+                }
+                _ => {}
+            },
+            Ok(None) => {}
+            Err(why) => {
+                error!(target: GATEWAY, "Gateway connection failed: {why}");
+                break;
+            }
+        }
+    }
+
+    socket.close(None).ok();
+    socket.flush().ok();
+}
+
+/// Reads a payload, or `None` if nothing arrived before the read timed out
+fn read(socket: &mut Socket) -> Result<Option<Value>> {
+    match socket.read() {
+        Ok(WsMessage::Text(text)) => Ok(Some(serde_json::from_str(&text)?)),
+        Ok(WsMessage::Close(_)) => Err(DiscordError::ConnectionClosed),
+        Ok(_) => Ok(None),
+        Err(tungstenite::Error::Io(ref why))
+            if matches!(why.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) =>
+        {
+            Ok(None)
+        }
+        Err(why) => Err(why.into()),
+    }
+}
+
+fn send(socket: &mut Socket, payload: &Value) -> Result<()> {
+    trace!(target: GATEWAY, "Sending op {}", payload["op"]);
+    socket
+        .send(WsMessage::Text(payload.to_string()))
+        .map_err(DiscordError::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn details_become_the_name() {
+        let activity = Activity::new().details("Ranked").state("In a match");
+
+        let value = gateway_activity(&activity);
+        assert_eq!(value["name"], "Ranked");
+        assert_eq!(value["state"], "In a match");
+        assert_eq!(value["type"], 0);
+    }
+
+    #[test]
+    fn state_becomes_custom_status() {
+        let value = gateway_activity(&Activity::new().state("Online"));
+        assert_eq!(value["type"], 4);
+        assert_eq!(value["state"], "Online");
+    }
+}
@@ -10,8 +10,15 @@
 
 //! A Rust library that allows the developer to interact with the Discord Presence API with ease
 
+#[cfg(not(loom))]
 pub(crate) static READY: AtomicBool = AtomicBool::new(false);
 
+// Loom atomics cannot be created in a `const` context, and are reset between model runs
+#[cfg(loom)]
+loom::lazy_static! {
+    pub(crate) static ref READY: AtomicBool = AtomicBool::new(false);
+}
+
 // Cannot remove this *macro_use*, would break derive inside of macros
 #[macro_use]
 extern crate serde;
@@ -21,18 +28,44 @@ extern crate tracing;
 
 #[macro_use]
 mod macros;
+/// Sharing one connection to Discord between several processes
+#[cfg(all(unix, feature = "broker"))]
+#[cfg_attr(docsrs, doc(cfg(all(unix, feature = "broker"))))]
+pub mod broker;
 /// A client for the Discord Presence API
 pub mod client;
-mod connection;
+mod codec;
+/// The IPC connection to Discord, for building on its framing without the client's event handling
+pub mod connection;
+/// Reports on why connecting to Discord failed
+pub mod diagnostics;
 /// Errors that can occur when interacting with the Discord Presence API
 pub mod error;
 /// Event handlers
 pub mod event_handler;
+/// Presence through a bot's gateway connection, for use without a local Discord client
+#[cfg(feature = "gateway")]
+#[cfg_attr(docsrs, doc(cfg(feature = "gateway")))]
+pub mod gateway;
 /// Models for discord activity
 pub mod models;
+/// Generating the nonces commands are matched with their responses by
+pub mod nonce;
+/// Helpers that build activities for common kinds of applications
+pub mod presence;
+mod sync;
+mod targets;
+/// Keeping `OAuth2` tokens in the credential store of the OS
+#[cfg(feature = "keyring")]
+#[cfg_attr(docsrs, doc(cfg(feature = "keyring")))]
+pub mod token_store;
 mod utils;
+/// Reaching Discord on Windows from inside WSL
+#[cfg(feature = "wsl")]
+#[cfg_attr(docsrs, doc(cfg(feature = "wsl")))]
+pub mod wsl;
 
-use std::sync::atomic::AtomicBool;
+use sync::AtomicBool;
 
 pub use client::Client;
 pub use error::{DiscordError, Result};
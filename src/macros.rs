@@ -22,7 +22,10 @@ macro_rules! builder_func {
 
     [ $name:ident, String $(=> if feature = $feature:tt)? ] => {
         $(#[cfg(feature = $feature)])?
-        #[doc = builder_func_doc!(Stringish)]
+        #[doc = concat!(
+            "Instantiates the current struct with the given value.\n\n",
+            "Accepts anything that converts into a [`String`], such as `&str`, `String` or `Cow<str>`."
+        )]
         #[must_use]
         pub fn $name<S>(mut self, value: S) -> Self
             where S: Into<String>
@@ -31,6 +34,20 @@ macro_rules! builder_func {
         }
     };
 
+    [ $name:ident, Snowflake $(=> if feature = $feature:tt)? ] => {
+        $(#[cfg(feature = $feature)])?
+        #[doc = concat!(
+            "Instantiates the current struct with the given ID.\n\n",
+            "Accepts a [`Snowflake`](crate::models::Snowflake) or a `u64`."
+        )]
+        #[must_use]
+        pub fn $name<I>(mut self, id: I) -> Self
+            where I: Into<crate::models::Snowflake>
+        {
+            self.$name = Some(id.into()); self
+        }
+    };
+
     [ $name:ident, $type:ty $(=> if feature = $feature:tt)? ] => {
         $(#[cfg(feature = $feature)])?
         #[doc = builder_func_doc!($type)]
@@ -78,8 +95,21 @@ macro_rules! into_error {
     };
 }
 
+macro_rules! builder_debug_field {
+    [ $debug:ident, $self:ident, $field:tt, plain ] => {
+        $debug.field(stringify!($field), &$self.$field);
+    };
+
+    [ $debug:ident, $self:ident, $field:tt, redact ] => {
+        $debug.field(
+            stringify!($field),
+            &$self.$field.as_ref().map(|_| crate::utils::REDACTED),
+        );
+    };
+}
+
 macro_rules! builder {
-    [ @st ( $name:ident $field:tt: $type:tt alias = $alias:tt $(=> if feature = $feature:tt)?, $($rest:tt)* ) -> ( $($out:tt)* ) ] => {
+    [ @st ( $name:ident $field:tt: $type:tt alias = $alias:tt $(=> if feature = $feature:tt)?, $($rest:tt)* ) -> ( $($out:tt)* ) [ $($dbg:tt)* ] ] => {
         builder![ @st
             ( $name $($rest)* ) -> (
                 $($out)*
@@ -87,43 +117,77 @@ macro_rules! builder {
                 #[doc = concat!("Optional " , stringify!($field), " field")]
                 #[serde(skip_serializing_if = "Option::is_none", rename = $alias)]
                 pub $field: Option<$type>,
-            )
+            ) [ $($dbg)* { $field plain $(#[cfg(feature = $feature)])? } ]
+        ];
+    };
+
+    [ @st ( $name:ident $field:tt: $type:tt redact alias = $alias:tt, $($rest:tt)* ) -> ( $($out:tt)* ) [ $($dbg:tt)* ] ] => {
+        builder![ @st
+            ( $name $($rest)* ) -> (
+                $($out)*
+                #[doc = concat!("Optional " , stringify!($field), " field")]
+                #[serde(skip_serializing_if = "Option::is_none", rename = $alias)]
+                pub $field: Option<$type>,
+            ) [ $($dbg)* { $field redact } ]
         ];
     };
 
-    [ @st ( $name:ident $field:tt: $type:tt func $(=> if feature = $feature:tt)?, $($rest:tt)* ) -> ( $($out:tt)* ) ] => {
-        builder![ @st ( $name $field: $type, $($rest)* ) -> ( $($out)* ) ];
+    [ @st ( $name:ident $field:tt: $type:tt func $(=> if feature = $feature:tt)?, $($rest:tt)* ) -> ( $($out:tt)* ) [ $($dbg:tt)* ] ] => {
+        builder![ @st ( $name $field: $type, $($rest)* ) -> ( $($out)* ) [ $($dbg)* ] ];
+    };
+
+    // Secret fields are masked in the `Debug` output
+    [ @st ( $name:ident $field:ident: $type:tt redact, $($rest:tt)* ) -> ( $($out:tt)* ) [ $($dbg:tt)* ] ] => {
+        builder![ @st
+            ( $name $($rest)* ) -> (
+                $($out)*
+                #[doc = concat!("Optional " , stringify!($field), " field")]
+                #[serde(skip_serializing_if = "Option::is_none")]
+                pub $field: Option<$type>,
+            ) [ $($dbg)* { $field redact } ]
+        ];
     };
 
     // TODO: Make this more applicable for other types than just buttons
     // Currently the implementation here only works for buttons, thanks to the deserialize_with attribute
-    [ @st ( $name:ident $field:ident: $type:ty as array, $($rest:tt)* ) -> ( $($out:tt)* ) ] => {
+    [ @st ( $name:ident $field:ident: $type:ty as array, $($rest:tt)* ) -> ( $($out:tt)* ) [ $($dbg:tt)* ] ] => {
         builder![ @st
             ( $name $($rest)* ) -> (
                 $($out)*
                 #[doc = concat!("Optional ", stringify!($field), " field")]
                 #[serde(default, skip_serializing_if = "Vec::is_empty", deserialize_with = "serialize_activity_button")]
                 pub $field: Vec<$type>,
-            )
+            ) [ $($dbg)* { $field plain } ]
         ];
     };
 
 
-    [ @st ( $name:ident $field:ident: $type:ty $(=> if feature = $feature:tt)?, $($rest:tt)* ) -> ( $($out:tt)* ) ] => {
+    [ @st ( $name:ident $field:ident: $type:ty $(=> if feature = $feature:tt)?, $($rest:tt)* ) -> ( $($out:tt)* ) [ $($dbg:tt)* ] ] => {
         builder![ @st
             ( $name $($rest)* ) -> (
                 $($out)*
                 #[doc = concat!("Optional " , stringify!($field), " field")]
                 #[serde(skip_serializing_if = "Option::is_none")]
                 pub $field: Option<$type>,
-            )
+            ) [ $($dbg)* { $field plain } ]
         ];
     };
 
-    [ @st ( $name:ident ) -> ( $($out:tt)* ) ] => {
+    [ @st ( $name:ident ) -> ( $($out:tt)* ) [ $( { $dfield:tt $mode:ident $(#[$meta:meta])* } )* ] ] => {
         #[doc = concat!(stringify!($name), " struct")]
-        #[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize, Hash, Eq)]
+        #[derive(Clone, Default, PartialEq, Deserialize, Serialize, Hash, Eq)]
         pub struct $name { $($out)* }
+
+        impl std::fmt::Debug for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                let mut debug = f.debug_struct(stringify!($name));
+                $(
+                    $(#[$meta])*
+                    builder_debug_field!(debug, self, $dfield, $mode);
+                )*
+                debug.finish()
+            }
+        }
     };
 
     [ @im ( $name:ident $field:ident: $type:tt func $(=> if feature = $feature:tt)?, $($rest:tt)* ) -> ( $($out:tt)* ) ] => {
@@ -138,6 +202,10 @@ macro_rules! builder {
         builder![ @im ( $name $field: $type $(=> if feature = $feature)?, $($rest)* ) -> ( $($out)* ) ];
     };
 
+    [ @im ( $name:ident $field:ident: $type:tt redact $(alias = $modifier:tt)?, $($rest:tt)* ) -> ( $($out:tt)* ) ] => {
+        builder![ @im ( $name $field: $type, $($rest)* ) -> ( $($out)* ) ];
+    };
+
     [ @im ( $name:ident $field:ident: $type:tt $(=> if feature = $feature:tt)?, $($rest:tt)* ) -> ( $($out:tt)* ) ] => {
         builder![ @im ( $name $($rest)* ) -> ( builder_func![$field, $type $(=> if feature = $feature)?]; $($out)* ) ];
     };
@@ -155,7 +223,7 @@ macro_rules! builder {
     };
 
     [ $name:ident $($body:tt)* ] => {
-        builder![@st ( $name $($body)* ) -> () ];
+        builder![@st ( $name $($body)* ) -> () [] ];
         builder![@im ( $name $($body)* ) -> () ];
     }
 }
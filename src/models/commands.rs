@@ -1,10 +1,166 @@
-use super::events::PartialUser;
+use super::{events::PartialUser, Snowflake};
+
+macro_rules! oauth_scopes {
+    ( $( $(#[$meta:meta])* $variant:ident = $name:literal, )* ) => {
+        /// `OAuth2` scopes, which the user grants the application through [`Command::Authorize`](super::Command::Authorize)
+        ///
+        /// Some commands and events require scopes, see [`Command::required_scopes`](super::Command::required_scopes).
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+        #[non_exhaustive]
+        pub enum OAuthScope {
+            $(
+                $(#[$meta])*
+                #[serde(rename = $name)]
+                $variant,
+            )*
+            /// A scope this version of the library does not know about
+            #[serde(other, rename = "unknown")]
+            Unknown,
+        }
+
+        impl OAuthScope {
+            #[must_use]
+            /// The name of the scope, as Discord knows it
+            pub const fn as_str(self) -> &'static str {
+                match self {
+                    $( Self::$variant => $name, )*
+                    Self::Unknown => "unknown",
+                }
+            }
+        }
+    };
+}
+
+oauth_scopes! {
+    /// Read the user's activities
+    ActivitiesRead = "activities.read",
+    /// Update the user's activity
+    ActivitiesWrite = "activities.write",
+    /// Read the application's build data
+    ApplicationsBuildsRead = "applications.builds.read",
+    /// Upload builds of the application
+    ApplicationsBuildsUpload = "applications.builds.upload",
+    /// Use the application's commands
+    ApplicationsCommands = "applications.commands",
+    /// Update the application's commands
+    ApplicationsCommandsUpdate = "applications.commands.update",
+    /// Read the user's entitlements to the application
+    ApplicationsEntitlements = "applications.entitlements",
+    /// Update the application's store data
+    ApplicationsStoreUpdate = "applications.store.update",
+    /// Add a bot to a guild
+    Bot = "bot",
+    /// Read the user's third-party connections
+    Connections = "connections",
+    /// See the user's direct messages
+    DmChannelsRead = "dm_channels.read",
+    /// Read the user's email address
+    Email = "email",
+    /// Join the user to group direct messages
+    GdmJoin = "gdm.join",
+    /// List the guilds the user is in
+    Guilds = "guilds",
+    /// Join the user to guilds
+    GuildsJoin = "guilds.join",
+    /// Read the user's member data in guilds
+    GuildsMembersRead = "guilds.members.read",
+    /// Read the user's account, without their email address
+    Identify = "identify",
+    /// Read messages in the channels the user can see
+    MessagesRead = "messages.read",
+    /// Read the user's friends
+    RelationshipsRead = "relationships.read",
+    /// Control the user's Discord client
+    Rpc = "rpc",
+    /// Update the user's activity through RPC
+    RpcActivitiesWrite = "rpc.activities.write",
+    /// Receive the user's notifications
+    RpcNotificationsRead = "rpc.notifications.read",
+    /// Read the user's voice settings
+    RpcVoiceRead = "rpc.voice.read",
+    /// Change the user's voice settings
+    RpcVoiceWrite = "rpc.voice.write",
+    /// Read the user's video settings
+    RpcVideoRead = "rpc.video.read",
+    /// Change the user's video settings
+    RpcVideoWrite = "rpc.video.write",
+    /// Read the user's screen sharing settings
+    RpcScreenshareRead = "rpc.screenshare.read",
+    /// Change the user's screen sharing settings
+    RpcScreenshareWrite = "rpc.screenshare.write",
+    /// Join the user to voice channels
+    Voice = "voice",
+    /// Create a webhook in a channel the user picks
+    WebhookIncoming = "webhook.incoming",
+}
+
+impl std::fmt::Display for OAuthScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
 
 builder! {SubscriptionArgs
-    secret: String,     // Activity{Join,Spectate}
+    secret: String redact, // Activity{Join,Spectate}
     user: PartialUser,  // ActivityJoinRequest
+    // Message{Create,Update,Delete}, Speaking{Start,Stop}, VoiceState{Create,Update,Delete}
+    channel_id: Snowflake,
+    guild_id: Snowflake, // GuildStatus
 }
 
 builder! {Subscription
     evt: String,
 }
+
+builder! {AuthenticateArgs
+    access_token: String redact,
+}
+
+/// Response to [`Command::Authenticate`](super::Command::Authenticate)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AuthenticateResponse {
+    /// The user the access token belongs to
+    pub user: Option<PartialUser>,
+    /// The scopes the access token was authorized with
+    #[serde(default)]
+    pub scopes: Vec<OAuthScope>,
+    /// When the access token expires, as an ISO 8601 timestamp
+    pub expires: Option<String>,
+    /// The application the access token was issued to
+    pub application: Option<OAuthApplication>,
+}
+
+builder! {OAuthApplication
+    id: Snowflake,
+    name: String,
+    description: String,
+    icon: String,
+}
+
+/// Args to ask the user to authorize the application, see [`Command::Authorize`](super::Command::Authorize)
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AuthorizeArgs {
+    /// The application to authorize
+    pub client_id: Snowflake,
+    /// The scopes to ask the user for
+    pub scopes: Vec<OAuthScope>,
+}
+
+impl AuthorizeArgs {
+    #[must_use]
+    /// Create a new `AuthorizeArgs`
+    pub fn new<I, S>(client_id: I, scopes: S) -> Self
+    where
+        I: Into<Snowflake>,
+        S: IntoIterator<Item = OAuthScope>,
+    {
+        Self {
+            client_id: client_id.into(),
+            scopes: scopes.into_iter().collect(),
+        }
+    }
+}
+
+builder! {AuthorizeResponse
+    code: String redact,
+}
@@ -0,0 +1,61 @@
+use super::Snowflake;
+
+builder! {Sku
+    id:             Snowflake,
+    application_id: Snowflake,
+    name:           String,
+    sku_type:       u32 alias = "type",
+    price:          SkuPrice,
+}
+
+builder! {SkuPrice
+    amount:   u64,
+    currency: String,
+}
+
+builder! {Entitlement
+    id:               Snowflake,
+    sku_id:           Snowflake,
+    application_id:   Snowflake,
+    user_id:          Snowflake,
+    entitlement_type: u32 alias = "type",
+    consumed:         bool,
+}
+
+impl Entitlement {
+    #[must_use]
+    /// Whether the entitlement grants the SKU, which consumable entitlements stop doing once used up
+    pub fn grants(&self, sku_id: Snowflake) -> bool {
+        self.sku_id == Some(sku_id) && self.consumed != Some(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_parse_skus_and_entitlements() {
+        let skus: Vec<Sku> = serde_json::from_value(serde_json::json!([{
+            "id": "53908232506183680",
+            "application_id": "461618159171141643",
+            "name": "Soundtrack",
+            "type": 2,
+            "price": { "amount": 499, "currency": "usd" },
+        }]))
+        .unwrap();
+        assert_eq!(skus[0].sku_type, Some(2));
+        assert_eq!(
+            skus[0].price.as_ref().and_then(|price| price.amount),
+            Some(499)
+        );
+
+        let entitlements: Vec<Entitlement> = serde_json::from_value(serde_json::json!([
+            { "id": "1", "sku_id": "53908232506183680", "type": 1 },
+            { "id": "2", "sku_id": "53908232506183681", "type": 1, "consumed": true },
+        ]))
+        .unwrap();
+        assert!(entitlements[0].grants(Snowflake::new(53_908_232_506_183_680)));
+        assert!(!entitlements[1].grants(Snowflake::new(53_908_232_506_183_681)));
+    }
+}
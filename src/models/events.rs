@@ -1,3 +1,8 @@
+use super::Snowflake;
+
+#[cfg(feature = "voice")]
+use serde_json::Number;
+
 builder! {ReadyEvent
     v:      u32,
     config: RpcServerConfiguration,
@@ -9,15 +14,394 @@ builder! {ErrorEvent
     message: String,
 }
 
+builder! {CloseEvent
+    code: u32,
+    message: String,
+}
+
+impl CloseEvent {
+    /// The meaning of the close code, if it is one Discord is known to send
+    #[must_use]
+    pub fn close_code(&self) -> Option<CloseCode> {
+        self.code.and_then(CloseCode::from_code)
+    }
+}
+
+impl std::fmt::Display for CloseEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.message, self.close_code()) {
+            (Some(message), _) => f.write_str(message)?,
+            (None, Some(code)) => write!(f, "{code}")?,
+            (None, None) => f.write_str("no reason given")?,
+        }
+
+        match self.code {
+            Some(code) => write!(f, " (code {code})"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Codes Discord sends when closing the connection, as part of a [`CloseEvent`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum CloseCode {
+    /// The connection was closed normally
+    Normal,
+    /// The client ID is not a valid application
+    InvalidClientId,
+    /// The origin of the connection is not allowed
+    InvalidOrigin,
+    /// Too many connections or commands were made
+    RateLimited,
+    /// The access token was revoked
+    TokenRevoked,
+    /// The RPC version is not supported
+    InvalidVersion,
+    /// The encoding is not supported
+    InvalidEncoding,
+}
+
+impl CloseCode {
+    /// Looks up a close code, returning `None` for codes Discord is not known to send
+    #[must_use]
+    pub fn from_code(code: u32) -> Option<Self> {
+        match code {
+            1000 => Some(Self::Normal),
+            4000 => Some(Self::InvalidClientId),
+            4001 => Some(Self::InvalidOrigin),
+            4002 => Some(Self::RateLimited),
+            4003 => Some(Self::TokenRevoked),
+            4004 => Some(Self::InvalidVersion),
+            4005 => Some(Self::InvalidEncoding),
+            _ => None,
+        }
+    }
+
+    /// Whether connecting again with the same settings is bound to be closed the same way
+    #[must_use]
+    pub fn is_fatal(self) -> bool {
+        matches!(
+            self,
+            Self::InvalidClientId
+                | Self::InvalidOrigin
+                | Self::InvalidVersion
+                | Self::InvalidEncoding
+        )
+    }
+}
+
+impl std::fmt::Display for CloseCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Normal => "closed normally",
+            Self::InvalidClientId => "invalid client ID",
+            Self::InvalidOrigin => "invalid origin",
+            Self::RateLimited => "rate limited",
+            Self::TokenRevoked => "token revoked",
+            Self::InvalidVersion => "invalid RPC version",
+            Self::InvalidEncoding => "invalid encoding",
+        })
+    }
+}
+
 builder! {RpcServerConfiguration
     cdn_host: String,
     api_endpoint: String,
     environment: String,
 }
 
+impl RpcServerConfiguration {
+    /// Base URL of Discord's CDN, such as `https://cdn.discordapp.com`
+    #[must_use]
+    pub fn cdn_url(&self) -> Option<String> {
+        self.cdn_host.as_ref().map(|host| format!("https://{host}"))
+    }
+
+    /// Base URL of Discord's API, such as `https://discord.com/api`
+    ///
+    /// Discord reports the endpoint without a scheme, so HTTPS is assumed.
+    #[must_use]
+    pub fn api_url(&self) -> Option<String> {
+        self.api_endpoint.as_ref().map(|endpoint| {
+            if endpoint.contains("://") {
+                endpoint.clone()
+            } else {
+                format!("https://{}", endpoint.trim_start_matches('/'))
+            }
+        })
+    }
+}
+
 builder! {PartialUser
-    id:            String,
+    id:            Snowflake,
     username:      String,
     discriminator: String,
     avatar:        String,
+    locale:        String,
+}
+
+/// Discord's CDN, which serves avatars
+const CDN_URL: &str = "https://cdn.discordapp.com";
+
+/// Image format of an avatar, for [`PartialUser::avatar_url`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum AvatarFormat {
+    /// PNG
+    #[default]
+    Png,
+    /// JPEG
+    Jpeg,
+    /// WebP
+    WebP,
+    /// GIF, which is only available for animated avatars. Other avatars fall back to PNG.
+    Gif,
+}
+
+impl AvatarFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Jpeg => "jpg",
+            Self::WebP => "webp",
+            Self::Gif => "gif",
+        }
+    }
+}
+
+impl PartialUser {
+    /// URL of the user's avatar on Discord's CDN, or of their default avatar if they have not set one
+    ///
+    /// The size is rounded to a power of two between 16 and 4096, as only those are served.
+    /// Default avatars are only served as PNG, regardless of `format`.
+    ///
+    /// Returns `None` if the user's ID is unknown.
+    #[must_use]
+    pub fn avatar_url(&self, size: u16, format: AvatarFormat) -> Option<String> {
+        let id = self.id?;
+        let size = size.clamp(16, 4096).next_power_of_two().min(4096);
+
+        let Some(ref avatar) = self.avatar else {
+            return Some(format!(
+                "{CDN_URL}/embed/avatars/{}.png?size={size}",
+                self.default_avatar_index()?
+            ));
+        };
+
+        // Animated avatars are marked by their hash
+        let format = match format {
+            AvatarFormat::Gif if !avatar.starts_with("a_") => AvatarFormat::Png,
+            format => format,
+        };
+
+        Some(format!(
+            "{CDN_URL}/avatars/{id}/{avatar}.{}?size={size}",
+            format.extension()
+        ))
+    }
+
+    /// Index of the default avatar, which is picked from the discriminator, or from the ID for users without one
+    fn default_avatar_index(&self) -> Option<u64> {
+        match self.discriminator.as_deref() {
+            Some(discriminator) if discriminator != "0" => discriminator
+                .parse::<u64>()
+                .ok()
+                .map(|discriminator| discriminator % 5),
+            _ => Some((self.id?.get() >> 22) % 6),
+        }
+    }
+}
+
+builder! {GuildStatusEvent
+    guild:  PartialGuild,
+    online: u32,
+}
+
+builder! {PartialGuild
+    id:       Snowflake,
+    name:     String,
+    icon_url: String,
+}
+
+builder! {GuildCreateEvent
+    id:   Snowflake,
+    name: String,
+}
+
+builder! {ChannelCreateEvent
+    id:           Snowflake,
+    name:         String,
+    channel_type: u32 alias = "type",
+}
+
+#[cfg(feature = "voice")]
+builder! {VoiceChannelSelectEvent
+    channel_id: Snowflake,
+    guild_id:   Snowflake,
+}
+
+#[cfg(feature = "messages")]
+builder! {MessageEvent
+    channel_id: Snowflake,
+    message:    ChannelMessage,
+}
+
+#[cfg(any(feature = "messages", feature = "notifications"))]
+builder! {ChannelMessage
+    id:               Snowflake,
+    blocked:          bool,
+    bot:              bool,
+    content:          String,
+    nick:             String,
+    author_color:     String,
+    edited_timestamp: String,
+    timestamp:        String,
+    tts:              bool,
+    mention_everyone: bool,
+    pinned:           bool,
+    author:           PartialUser,
+    message_type:     u32 alias = "type",
+}
+
+#[cfg(feature = "voice")]
+builder! {SpeakingEvent
+    user_id:    Snowflake,
+    channel_id: Snowflake,
+}
+
+#[cfg(feature = "voice")]
+builder! {VoiceStateEvent
+    voice_state: VoiceState,
+    user:        PartialUser,
+    nick:        String,
+    volume:      Number,
+    mute:        bool,
+    pan:         Pan,
+}
+
+#[cfg(feature = "voice")]
+builder! {VoiceState
+    mute:      bool,
+    deaf:      bool,
+    self_mute: bool,
+    self_deaf: bool,
+    suppress:  bool,
+}
+
+#[cfg(feature = "voice")]
+builder! {Pan
+    left:  Number,
+    right: Number,
+}
+
+#[cfg(feature = "notifications")]
+builder! {NotificationEvent
+    channel_id: Snowflake,
+    message:    ChannelMessage,
+    icon_url:   String,
+    title:      String,
+    body:       String,
+}
+
+#[cfg(feature = "voice")]
+type VoicePings = Vec<VoicePing>;
+
+#[cfg(feature = "voice")]
+builder! {VoiceConnectionStatusEvent
+    state:        VoiceConnectionState,
+    hostname:     String,
+    pings:        VoicePings,
+    average_ping: Number,
+    last_ping:    Number,
+}
+
+#[cfg(feature = "voice")]
+builder! {VoicePing
+    time:  u64,
+    value: u32,
+}
+
+#[cfg(feature = "voice")]
+type AudioDevices = Vec<AudioDevice>;
+
+// Also the data of `VOICE_SETTINGS_UPDATE`
+#[cfg(feature = "voice")]
+builder! {VoiceSettings
+    input:                  VoiceSettingsIo,
+    output:                 VoiceSettingsIo,
+    mode:                   VoiceSettingsMode,
+    automatic_gain_control: bool,
+    echo_cancellation:      bool,
+    noise_suppression:      bool,
+    qos:                    bool,
+    silence_warning:        bool,
+    deaf:                   bool,
+    mute:                   bool,
+}
+
+#[cfg(feature = "voice")]
+builder! {VoiceSettingsIo
+    device_id:         String,
+    volume:            Number,
+    available_devices: AudioDevices,
+}
+
+#[cfg(feature = "voice")]
+builder! {AudioDevice
+    id:   String,
+    name: String,
+}
+
+#[cfg(feature = "voice")]
+builder! {VoiceSettingsMode
+    mode_type:      String alias = "type",
+    auto_threshold: bool,
+    threshold:      Number,
+    delay:          Number,
+}
+
+#[cfg(feature = "voice")]
+impl VoiceSettingsIo {
+    #[must_use]
+    /// The devices that can be picked, or an empty slice if Discord did not list them
+    pub fn devices(&self) -> &[AudioDevice] {
+        self.available_devices.as_deref().unwrap_or_default()
+    }
+
+    #[must_use]
+    /// The device in use, if it is one of [`VoiceSettingsIo::devices`]
+    pub fn selected_device(&self) -> Option<&AudioDevice> {
+        let device_id = self.device_id.as_ref()?;
+        self.devices()
+            .iter()
+            .find(|device| device.id.as_ref() == Some(device_id))
+    }
+}
+
+/// State of the client's voice connection, as reported by [`VoiceConnectionStatusEvent`]
+#[cfg(feature = "voice")]
+#[cfg_attr(docsrs, doc(cfg(feature = "voice")))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum VoiceConnectionState {
+    /// TCP disconnected
+    Disconnected,
+    /// Waiting for voice endpoint
+    AwaitingEndpoint,
+    /// TCP authenticating
+    Authenticating,
+    /// TCP connecting
+    Connecting,
+    /// TCP connected
+    Connected,
+    /// TCP connected, Voice disconnected
+    VoiceDisconnected,
+    /// TCP connected, Voice connecting
+    VoiceConnecting,
+    /// TCP connected, Voice connected
+    VoiceConnected,
+    /// No route to host
+    NoRoute,
+    /// WebRTC ice checking
+    IceChecking,
 }
@@ -1,9 +1,37 @@
-use crate::{DiscordError, Result};
+use crate::{utils, DiscordError, Result};
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use bytes::BytesMut;
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
 use serde::Serialize;
-use std::io::{Read, Write};
+use std::{
+    fmt,
+    io::{self, Read, Write},
+};
+
+/// Length of the opcode and payload length preceding every payload
+const HEADER_LENGTH: usize = 8;
+
+/// Largest payload accepted from the other end, well above anything Discord sends,
+/// so a corrupt or hostile header cannot make the reader allocate gigabytes
+pub(crate) const MAX_PAYLOAD_LENGTH: usize = 16 * 1024 * 1024;
+
+/// Reads the payload length from a frame header, rejecting lengths above [`MAX_PAYLOAD_LENGTH`]
+///
+/// The rest of the stream cannot be read once a frame is rejected, so this fails with an I/O error,
+/// which drops the connection.
+pub(crate) fn payload_length(header: [u8; HEADER_LENGTH]) -> Result<usize> {
+    let len = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+    if len > MAX_PAYLOAD_LENGTH {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Frame of {len} bytes is larger than the {MAX_PAYLOAD_LENGTH} byte limit"),
+        )
+        .into());
+    }
+
+    Ok(len)
+}
 
 /// Codes for payload types
 #[derive(Debug, Copy, Clone, PartialEq, Eq, FromPrimitive)]
@@ -22,7 +50,7 @@ pub enum OpCode {
 }
 
 /// Message struct for the Discord RPC
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(PartialEq, Eq, Clone)]
 pub struct Message {
     /// The payload type for this `Message`
     pub opcode: OpCode,
@@ -30,6 +58,16 @@ pub struct Message {
     pub payload: String,
 }
 
+// Messages are logged at the frame level, so secrets in the payload have to be masked
+impl fmt::Debug for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Message")
+            .field("opcode", &self.opcode)
+            .field("payload", &utils::redact_json(&self.payload))
+            .finish()
+    }
+}
+
 impl Message {
     /// Create a new `Message`
     ///
@@ -49,34 +87,103 @@ impl Message {
     ///
     /// # Errors
     /// - Failed to write to the buffer
-    ///
-    /// # Panics
-    /// - The payload length is not a 32 bit number
+    /// - The payload length does not fit in a 32 bit number
     pub fn encode(&self) -> Result<Vec<u8>> {
         let mut bytes: Vec<u8> = vec![];
 
-        let payload_length = u32::try_from(self.payload.len()).expect("32-bit payload length");
+        self.encode_into(&mut bytes)?;
+
+        Ok(bytes)
+    }
+
+    /// Encode message, appending it to an existing buffer
+    ///
+    /// # Errors
+    /// - Failed to write to the buffer
+    /// - The payload length does not fit in a 32 bit number
+    pub fn encode_into(&self, bytes: &mut Vec<u8>) -> Result<()> {
+        let payload_length = u32::try_from(self.payload.len())
+            .map_err(|_| DiscordError::PayloadTooLarge(self.payload.len()))?;
 
+        bytes.reserve(8 + self.payload.len());
         bytes.write_u32::<LittleEndian>(self.opcode as u32)?;
         bytes.write_u32::<LittleEndian>(payload_length)?;
         bytes.write_all(self.payload.as_bytes())?;
 
-        Ok(bytes)
+        Ok(())
+    }
+
+    /// Serialize a payload straight into a frame appended to `bytes`, without building a `Message` first
+    ///
+    /// Produces the same bytes as [`Message::new`] followed by [`Message::encode_into`], with one less copy of the payload.
+    /// Only frames the connection thread writes itself can be serialized this way, such as the handshake and pings.
+    /// Commands are serialized into a [`Message`] on the calling thread, as they are handed over to the connection
+    /// thread to be written.
+    ///
+    /// # Errors
+    /// - Could not serialize the payload
+    /// - The payload length does not fit in a 32 bit number
+    pub fn encode_payload_into<T>(opcode: OpCode, payload: &T, bytes: &mut Vec<u8>) -> Result<()>
+    where
+        T: Serialize,
+    {
+        let header = bytes.len();
+
+        // The length is patched in once the payload has been written
+        bytes.write_u32::<LittleEndian>(opcode as u32)?;
+        bytes.write_u32::<LittleEndian>(0)?;
+        serde_json::to_writer(&mut *bytes, payload)?;
+
+        let payload_length = bytes.len() - header - 8;
+        let payload_length = u32::try_from(payload_length)
+            .map_err(|_| DiscordError::PayloadTooLarge(payload_length))?;
+        bytes[header + 4..header + 8].copy_from_slice(&payload_length.to_le_bytes());
+
+        Ok(())
     }
 
     /// Decode message
     ///
+    /// Only the payload length given in the header is read, any bytes after it are ignored.
+    ///
     /// # Errors
-    /// - Failed to read from buffer
+    /// - Failed to read from buffer, or it is shorter than the payload length
+    /// - The opcode is unknown
     pub fn decode(mut bytes: &[u8]) -> Result<Self> {
         let opcode =
             OpCode::from_u32(bytes.read_u32::<LittleEndian>()?).ok_or(DiscordError::Conversion)?;
         let len = bytes.read_u32::<LittleEndian>()? as usize;
+        // Only allocated for once the payload is known to be there
+        let mut bytes = bytes
+            .get(..len)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
         let mut payload = String::with_capacity(len);
         bytes.read_to_string(&mut payload)?;
 
         Ok(Self { opcode, payload })
     }
+
+    /// Decode the first message in `buffer` and remove it, if the whole frame has been received
+    ///
+    /// Sockets may split a frame across reads or return several frames at once,
+    /// so bytes are collected into `buffer` until a frame is complete, and any that follow it are kept.
+    ///
+    /// # Errors
+    /// - The opcode is unknown, or the payload is not valid UTF-8
+    /// - The payload is longer than [`MAX_PAYLOAD_LENGTH`], as an I/O error
+    pub fn decode_buffered(buffer: &mut BytesMut) -> Result<Option<Self>> {
+        let Some(header) = buffer.get(..HEADER_LENGTH) else {
+            return Ok(None);
+        };
+
+        let len = payload_length(header.try_into().map_err(|_| DiscordError::Conversion)?)?;
+        if buffer.len() < HEADER_LENGTH + len {
+            return Ok(None);
+        }
+
+        let frame = buffer.split_to(HEADER_LENGTH + len);
+        Self::decode(&frame).map(Some)
+    }
 }
 
 #[cfg(test)]
@@ -103,4 +210,61 @@ mod tests {
         assert_eq!(OpCode::from_u32(4), Some(OpCode::Pong));
         assert_eq!(OpCode::from_u32(5), None);
     }
+
+    #[test]
+    fn rejects_oversized_frames() {
+        let mut header = [0; HEADER_LENGTH];
+        header[4..].copy_from_slice(&u32::MAX.to_le_bytes());
+
+        let mut buffer = BytesMut::from(&header[..]);
+        assert!(matches!(
+            Message::decode_buffered(&mut buffer),
+            Err(DiscordError::IoError(why)) if why.kind() == io::ErrorKind::InvalidData
+        ));
+        // Short frames are still a decoding error rather than a huge allocation
+        assert!(Message::decode(&header).is_err());
+    }
+
+    #[test]
+    fn decodes_split_and_joined_frames() {
+        let first = Message::new(OpCode::Frame, Something { empty: true }).unwrap();
+        let second = Message::new(OpCode::Ping, Something { empty: false }).unwrap();
+        let mut bytes = first.encode().unwrap();
+        second.encode_into(&mut bytes).unwrap();
+
+        let mut buffer = BytesMut::new();
+        let mut decoded = vec![];
+        // Feed the frames a few bytes at a time, as a slow socket would
+        for chunk in bytes.chunks(5) {
+            buffer.extend_from_slice(chunk);
+            while let Some(message) = Message::decode_buffered(&mut buffer).unwrap() {
+                decoded.push(message);
+            }
+        }
+
+        assert_eq!(decoded, [first, second]);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn rejects_truncated_frames() {
+        let encoded = Message::new(OpCode::Frame, Something { empty: true })
+            .and_then(|msg| msg.encode())
+            .unwrap();
+        assert!(Message::decode(&encoded[..encoded.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn encodes_payload_directly() {
+        let payload = Something { empty: true };
+        let expected = Message::new(OpCode::Frame, &payload)
+            .and_then(|msg| msg.encode())
+            .expect("Failed to encode message");
+
+        let mut bytes = vec![];
+        Message::encode_payload_into(OpCode::Frame, &payload, &mut bytes)
+            .expect("Failed to encode payload");
+
+        assert_eq!(bytes, expected);
+    }
 }
@@ -1,5 +1,9 @@
 /// The Discord commands module
 pub mod commands;
+/// SKUs and entitlements of the Discord store
+#[cfg(feature = "commerce")]
+#[cfg_attr(docsrs, doc(cfg(feature = "commerce")))]
+pub mod commerce;
 /// The events module
 pub mod events;
 /// The module to handle messages
@@ -8,12 +12,17 @@ pub mod message;
 pub mod payload;
 /// The rich presence module
 pub mod rich_presence;
+/// Discord IDs
+pub mod snowflake;
+
+pub use snowflake::Snowflake;
 
 use quork::traits::list::ListVariants;
 
 /// Different Discord commands
 #[derive(Debug, PartialEq, Eq, Copy, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[non_exhaustive]
 pub enum Command {
     /// Dispatch something to Discord
     Dispatch,
@@ -29,14 +38,67 @@ pub enum Command {
     SendActivityJoinInvite,
     /// Close the invite to join a game
     CloseActivityRequest,
+    /// Accept an invite to join a game, sent by another user
+    AcceptActivityInvite,
+    /// Authenticate with an access token
+    Authenticate,
+    /// Get a guild the user is in
+    GetGuild,
+    /// Get every guild the user is in
+    GetGuilds,
+    /// Get a channel the user can see
+    GetChannel,
+    /// Get the channels of a guild
+    GetChannels,
+    /// Change the voice settings of another user
+    SetUserVoiceSettings,
+    /// Join or leave a voice channel
+    SelectVoiceChannel,
+    /// Get the voice channel the user is in
+    GetSelectedVoiceChannel,
+    /// Join or leave a text channel
+    SelectTextChannel,
+    /// Get the user's voice settings
+    GetVoiceSettings,
+    /// Change the user's voice settings
+    SetVoiceSettings,
+    /// Tell Discord about certified audio devices
+    SetCertifiedDevices,
+    /// Get the SKUs of the application in the Discord store
+    GetSkus,
+    /// Get the user's entitlements to the application's SKUs
+    GetEntitlements,
+}
+
+impl Command {
+    #[must_use]
+    /// The `OAuth2` scopes the access token has to be authorized with to send the command
+    ///
+    /// The scopes to subscribe to an event are given by [`Event::required_scopes`].
+    pub fn required_scopes(self) -> &'static [OAuthScope] {
+        match self {
+            Self::GetVoiceSettings => &[OAuthScope::RpcVoiceRead],
+            Self::SetVoiceSettings => &[OAuthScope::RpcVoiceWrite],
+            Self::GetGuild
+            | Self::GetGuilds
+            | Self::GetChannel
+            | Self::GetChannels
+            | Self::SetUserVoiceSettings
+            | Self::SelectVoiceChannel
+            | Self::GetSelectedVoiceChannel
+            | Self::SelectTextChannel => &[OAuthScope::Rpc],
+            _ => &[],
+        }
+    }
 }
 
 // NOTE: ListVariants is required to bevy-discord-rpc
 /// Discord events
 #[derive(Debug, PartialEq, Eq, Deserialize, Serialize, Copy, Clone, Hash, ListVariants)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[non_exhaustive]
 pub enum Event {
-    /// [`Event::Ready`] event, fired when the client is ready, but not if an error occurs
+    /// [`Event::Ready`] event, fired each time the client connects, but not if an error occurs
     Ready,
     /// [`Event::Error`] event, overrides the `Ready` event
     Error,
@@ -46,11 +108,93 @@ pub enum Event {
     ActivitySpectate,
     /// [`Event::ActivityJoinRequest`] event, fired when the client receives a join request
     ActivityJoinRequest,
+    /// [`Event::ActivityInvite`] event, fired when another user invites the current user to join their game
+    ActivityInvite,
+    /// [`Event::MessageCreate`] event, fired when a message is created in a subscribed channel
+    MessageCreate,
+    /// [`Event::MessageUpdate`] event, fired when a message is updated in a subscribed channel
+    MessageUpdate,
+    /// [`Event::MessageDelete`] event, fired when a message is deleted in a subscribed channel
+    MessageDelete,
+    /// [`Event::SpeakingStart`] event, fired when a user in a subscribed voice channel starts speaking
+    SpeakingStart,
+    /// [`Event::SpeakingStop`] event, fired when a user in a subscribed voice channel stops speaking
+    SpeakingStop,
+    /// [`Event::VoiceStateCreate`] event, fired when a user joins a subscribed voice channel
+    VoiceStateCreate,
+    /// [`Event::VoiceStateUpdate`] event, fired when the voice state of a user in a subscribed voice channel changes
+    VoiceStateUpdate,
+    /// [`Event::VoiceStateDelete`] event, fired when a user leaves a subscribed voice channel
+    VoiceStateDelete,
+    /// [`Event::NotificationCreate`] event, fired when the client receives a notification
+    NotificationCreate,
+    /// [`Event::VoiceConnectionStatus`] event, fired when the client's voice connection status changes
+    VoiceConnectionStatus,
+    /// [`Event::GuildStatus`] event, fired when a subscribed guild's status changes
+    GuildStatus,
+    /// [`Event::GuildCreate`] event, fired when the user joins a guild
+    GuildCreate,
+    /// [`Event::ChannelCreate`] event, fired when a channel is created that the user can see
+    ChannelCreate,
+    /// [`Event::VoiceChannelSelect`] event, fired when the user joins or leaves a voice channel
+    VoiceChannelSelect,
+    /// [`Event::VoiceSettingsUpdate`] event, fired when the user's voice settings change
+    VoiceSettingsUpdate,
 }
 
 impl Event {
+    /// Every event, in the order they are declared
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use discord_presence::Event;
+    /// let subscribable = Event::all().filter(|event| event.is_subscribable());
+    /// assert_eq!(subscribable.count(), Event::all().count() - 2);
+    /// ```
+    pub fn all() -> impl Iterator<Item = Self> {
+        Self::VARIANTS.into_iter()
+    }
+
+    #[must_use]
+    /// Whether the event has to be subscribed to. [`Event::Ready`] and [`Event::Error`] are sent without subscribing.
+    pub fn is_subscribable(self) -> bool {
+        !matches!(self, Self::Ready | Self::Error)
+    }
+
+    #[must_use]
+    /// The `OAuth2` scopes the access token has to be authorized with to subscribe to the event
+    pub fn required_scopes(self) -> &'static [OAuthScope] {
+        match self {
+            Self::MessageCreate | Self::MessageUpdate | Self::MessageDelete => {
+                &[OAuthScope::Rpc, OAuthScope::MessagesRead]
+            }
+            Self::NotificationCreate => &[OAuthScope::RpcNotificationsRead],
+            Self::VoiceSettingsUpdate => &[OAuthScope::RpcVoiceRead],
+            Self::SpeakingStart
+            | Self::SpeakingStop
+            | Self::VoiceStateCreate
+            | Self::VoiceStateUpdate
+            | Self::VoiceStateDelete
+            | Self::VoiceConnectionStatus
+            | Self::GuildStatus
+            | Self::GuildCreate
+            | Self::ChannelCreate
+            | Self::VoiceChannelSelect => &[OAuthScope::Rpc],
+            Self::Ready
+            | Self::Error
+            | Self::ActivityJoin
+            | Self::ActivitySpectate
+            | Self::ActivityJoinRequest
+            | Self::ActivityInvite => &[],
+        }
+    }
+
     #[must_use]
     /// Parse event data from a [`JsonValue`]
+    ///
+    /// Events of a command family that was compiled out (see the `messages`, `notifications` and `voice` features)
+    /// are returned as [`EventData::Unknown`].
     pub fn parse_data(self, data: JsonValue) -> EventData {
         match self {
             Event::Ready => serde_json::from_value(data.clone())
@@ -72,12 +216,93 @@ impl Event {
             Event::ActivityJoinRequest => serde_json::from_value(data.clone())
                 .map(EventData::ActivityJoinRequest)
                 .unwrap_or(EventData::Unknown(data)),
+
+            Event::ActivityInvite => serde_json::from_value(data.clone())
+                .map(|invite| EventData::ActivityInvite(Box::new(invite)))
+                .unwrap_or(EventData::Unknown(data)),
+
+            #[cfg(feature = "messages")]
+            Event::MessageCreate => serde_json::from_value(data.clone())
+                .map(EventData::MessageCreate)
+                .unwrap_or(EventData::Unknown(data)),
+
+            #[cfg(feature = "messages")]
+            Event::MessageUpdate => serde_json::from_value(data.clone())
+                .map(EventData::MessageUpdate)
+                .unwrap_or(EventData::Unknown(data)),
+
+            #[cfg(feature = "messages")]
+            Event::MessageDelete => serde_json::from_value(data.clone())
+                .map(EventData::MessageDelete)
+                .unwrap_or(EventData::Unknown(data)),
+
+            #[cfg(feature = "voice")]
+            Event::SpeakingStart => serde_json::from_value(data.clone())
+                .map(EventData::SpeakingStart)
+                .unwrap_or(EventData::Unknown(data)),
+
+            #[cfg(feature = "voice")]
+            Event::SpeakingStop => serde_json::from_value(data.clone())
+                .map(EventData::SpeakingStop)
+                .unwrap_or(EventData::Unknown(data)),
+
+            #[cfg(feature = "voice")]
+            Event::VoiceStateCreate => serde_json::from_value(data.clone())
+                .map(EventData::VoiceStateCreate)
+                .unwrap_or(EventData::Unknown(data)),
+
+            #[cfg(feature = "voice")]
+            Event::VoiceStateUpdate => serde_json::from_value(data.clone())
+                .map(EventData::VoiceStateUpdate)
+                .unwrap_or(EventData::Unknown(data)),
+
+            #[cfg(feature = "voice")]
+            Event::VoiceStateDelete => serde_json::from_value(data.clone())
+                .map(EventData::VoiceStateDelete)
+                .unwrap_or(EventData::Unknown(data)),
+
+            #[cfg(feature = "notifications")]
+            Event::NotificationCreate => serde_json::from_value(data.clone())
+                .map(EventData::NotificationCreate)
+                .unwrap_or(EventData::Unknown(data)),
+
+            #[cfg(feature = "voice")]
+            Event::VoiceConnectionStatus => serde_json::from_value(data.clone())
+                .map(EventData::VoiceConnectionStatus)
+                .unwrap_or(EventData::Unknown(data)),
+
+            Event::GuildStatus => serde_json::from_value(data.clone())
+                .map(EventData::GuildStatus)
+                .unwrap_or(EventData::Unknown(data)),
+
+            Event::GuildCreate => serde_json::from_value(data.clone())
+                .map(EventData::GuildCreate)
+                .unwrap_or(EventData::Unknown(data)),
+
+            Event::ChannelCreate => serde_json::from_value(data.clone())
+                .map(EventData::ChannelCreate)
+                .unwrap_or(EventData::Unknown(data)),
+
+            #[cfg(feature = "voice")]
+            Event::VoiceChannelSelect => serde_json::from_value(data.clone())
+                .map(EventData::VoiceChannelSelect)
+                .unwrap_or(EventData::Unknown(data)),
+
+            #[cfg(feature = "voice")]
+            Event::VoiceSettingsUpdate => serde_json::from_value(data.clone())
+                .map(EventData::VoiceSettingsUpdate)
+                .unwrap_or(EventData::Unknown(data)),
+
+            // Events whose models were compiled out, see the crate features
+            #[allow(unreachable_patterns)]
+            _ => EventData::Unknown(data),
         }
     }
 }
 
 #[derive(Debug, PartialEq, Eq, Deserialize, Serialize, Clone)]
 /// Internal data for the [`Event`] enum
+#[non_exhaustive]
 pub enum EventData {
     /// [`EventData::Ready`] event data
     Ready(ReadyEvent),
@@ -89,11 +314,57 @@ pub enum EventData {
     ActivitySpectate(ActivitySpectateEvent),
     /// [`EventData::ActivityJoinRequest`] event data
     ActivityJoinRequest(ActivityJoinRequestEvent),
+    /// [`EventData::ActivityInvite`] event data
+    ActivityInvite(Box<ActivityInviteEvent>),
+    /// [`EventData::MessageCreate`] event data
+    #[cfg(feature = "messages")]
+    MessageCreate(MessageEvent),
+    /// [`EventData::MessageUpdate`] event data
+    #[cfg(feature = "messages")]
+    MessageUpdate(MessageEvent),
+    /// [`EventData::MessageDelete`] event data
+    #[cfg(feature = "messages")]
+    MessageDelete(MessageEvent),
+    /// [`EventData::SpeakingStart`] event data
+    #[cfg(feature = "voice")]
+    SpeakingStart(SpeakingEvent),
+    /// [`EventData::SpeakingStop`] event data
+    #[cfg(feature = "voice")]
+    SpeakingStop(SpeakingEvent),
+    /// [`EventData::VoiceStateCreate`] event data
+    #[cfg(feature = "voice")]
+    VoiceStateCreate(VoiceStateEvent),
+    /// [`EventData::VoiceStateUpdate`] event data
+    #[cfg(feature = "voice")]
+    VoiceStateUpdate(VoiceStateEvent),
+    /// [`EventData::VoiceStateDelete`] event data
+    #[cfg(feature = "voice")]
+    VoiceStateDelete(VoiceStateEvent),
+    /// [`EventData::NotificationCreate`] event data
+    #[cfg(feature = "notifications")]
+    NotificationCreate(NotificationEvent),
+    /// [`EventData::VoiceConnectionStatus`] event data
+    #[cfg(feature = "voice")]
+    VoiceConnectionStatus(VoiceConnectionStatusEvent),
+    /// [`EventData::GuildStatus`] event data
+    GuildStatus(GuildStatusEvent),
+    /// [`EventData::GuildCreate`] event data
+    GuildCreate(GuildCreateEvent),
+    /// [`EventData::ChannelCreate`] event data
+    ChannelCreate(ChannelCreateEvent),
+    /// [`EventData::VoiceChannelSelect`] event data
+    #[cfg(feature = "voice")]
+    VoiceChannelSelect(VoiceChannelSelectEvent),
+    /// [`EventData::VoiceSettingsUpdate`] event data
+    #[cfg(feature = "voice")]
+    VoiceSettingsUpdate(VoiceSettings),
     /// [`EventData::Unknown`] event data
     Unknown(JsonValue),
 }
 
 pub use commands::*;
+#[cfg(feature = "commerce")]
+pub use commerce::*;
 pub use events::*;
 pub use message::{Message, OpCode};
 
@@ -103,11 +374,296 @@ use serde_json::Value as JsonValue;
 /// Prelude for all Discord RPC types
 pub mod prelude {
     pub use super::commands::{Subscription, SubscriptionArgs};
-    pub use super::events::{ErrorEvent, ReadyEvent};
+    #[cfg(any(feature = "messages", feature = "notifications"))]
+    pub use super::events::ChannelMessage;
+    #[cfg(feature = "messages")]
+    pub use super::events::MessageEvent;
+    #[cfg(feature = "notifications")]
+    pub use super::events::NotificationEvent;
+    #[cfg(feature = "voice")]
+    pub use super::events::{
+        AudioDevice, SpeakingEvent, VoiceChannelSelectEvent, VoiceConnectionState,
+        VoiceConnectionStatusEvent, VoiceSettings, VoiceState, VoiceStateEvent,
+    };
+    pub use super::events::{
+        ChannelCreateEvent, ErrorEvent, GuildCreateEvent, GuildStatusEvent, PartialGuild,
+        ReadyEvent,
+    };
     pub use super::rich_presence::{
-        ActivityJoinEvent, ActivityJoinRequestEvent, ActivitySpectateEvent,
-        CloseActivityRequestArgs, SendActivityJoinInviteArgs, SetActivityArgs,
+        AcceptActivityInviteArgs, ActivityInviteEvent, ActivityJoinEvent, ActivityJoinRequestEvent,
+        ActivitySpectateEvent, CloseActivityRequestArgs, SendActivityJoinInviteArgs,
+        SetActivityArgs,
     };
+    pub use super::snowflake::Snowflake;
     pub use super::Command;
     pub use super::Event;
 }
+
+#[cfg(all(test, any(feature = "messages", feature = "voice")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_parse_ready_config() {
+        let data = serde_json::json!({
+            "v": 1,
+            "config": {
+                "cdn_host": "cdn.discordapp.com",
+                "api_endpoint": "//discord.com/api",
+                "environment": "production",
+            },
+        });
+
+        let EventData::Ready(event) = Event::Ready.parse_data(data) else {
+            panic!("Failed to parse ready event");
+        };
+
+        let config = event.config.expect("Missing config");
+        assert_eq!(config.environment.as_deref(), Some("production"));
+        assert_eq!(
+            config.cdn_url().as_deref(),
+            Some("https://cdn.discordapp.com")
+        );
+        assert_eq!(config.api_url().as_deref(), Some("https://discord.com/api"));
+    }
+
+    #[test]
+    fn commands_use_protocol_names() {
+        let names = [
+            (Command::SetCertifiedDevices, "SET_CERTIFIED_DEVICES"),
+            (
+                Command::GetSelectedVoiceChannel,
+                "GET_SELECTED_VOICE_CHANNEL",
+            ),
+            (Command::SendActivityJoinInvite, "SEND_ACTIVITY_JOIN_INVITE"),
+        ];
+        for (command, name) in names {
+            assert_eq!(serde_json::to_value(command).unwrap(), name);
+        }
+    }
+
+    #[test]
+    fn can_parse_guild_and_channel_events() {
+        let event: Event = serde_json::from_value(serde_json::json!("GUILD_STATUS")).unwrap();
+        let data = serde_json::json!({
+            "guild": { "id": "199737254929760256", "name": "test server", "icon_url": null },
+            "online": 42,
+        });
+        let EventData::GuildStatus(status) = event.parse_data(data) else {
+            panic!("Failed to parse guild status event");
+        };
+        assert_eq!(status.online, Some(42));
+        assert_eq!(
+            status.guild.and_then(|guild| guild.id),
+            Some(Snowflake::new(199_737_254_929_760_256))
+        );
+
+        let data = serde_json::json!({ "id": "199737254929760257", "name": "general", "type": 0 });
+        let EventData::ChannelCreate(channel) = Event::ChannelCreate.parse_data(data) else {
+            panic!("Failed to parse channel create event");
+        };
+        assert_eq!(channel.name.as_deref(), Some("general"));
+        assert_eq!(channel.channel_type, Some(0));
+    }
+
+    #[test]
+    fn builds_avatar_urls() {
+        let user = PartialUser::new()
+            .id(190_320_984_123_768_832)
+            .avatar("a_0123456789abcdef");
+
+        assert_eq!(
+            user.avatar_url(100, AvatarFormat::Gif).as_deref(),
+            Some("https://cdn.discordapp.com/avatars/190320984123768832/a_0123456789abcdef.gif?size=128")
+        );
+
+        let user = user.avatar("0123456789abcdef");
+        assert_eq!(
+            user.avatar_url(5000, AvatarFormat::Gif).as_deref(),
+            Some("https://cdn.discordapp.com/avatars/190320984123768832/0123456789abcdef.png?size=4096")
+        );
+
+        let user = PartialUser::new()
+            .id(190_320_984_123_768_832)
+            .discriminator("7479");
+        assert_eq!(
+            user.avatar_url(64, AvatarFormat::WebP).as_deref(),
+            Some("https://cdn.discordapp.com/embed/avatars/4.png?size=64")
+        );
+
+        let user = user.discriminator("0");
+        let index = (190_320_984_123_768_832_u64 >> 22) % 6;
+        assert_eq!(
+            user.avatar_url(64, AvatarFormat::Png),
+            Some(format!(
+                "https://cdn.discordapp.com/embed/avatars/{index}.png?size=64"
+            ))
+        );
+        assert_eq!(PartialUser::new().avatar_url(64, AvatarFormat::Png), None);
+    }
+
+    #[cfg(feature = "messages")]
+    #[test]
+    fn can_parse_message_event() {
+        let data = serde_json::json!({
+            "channel_id": "199737254929760256",
+            "message": {
+                "id": "199743874640379904",
+                "content": "test",
+                "tts": false,
+                "author": {
+                    "id": "190320984123768832",
+                    "username": "test 2",
+                    "discriminator": "7479",
+                },
+                "type": 0,
+            },
+        });
+
+        let EventData::MessageCreate(event) = Event::MessageCreate.parse_data(data) else {
+            panic!("Failed to parse message event");
+        };
+
+        assert_eq!(
+            event.channel_id,
+            Some(Snowflake::new(199_737_254_929_760_256))
+        );
+
+        let message = event.message.expect("Missing message");
+        assert_eq!(message.content.as_deref(), Some("test"));
+        assert_eq!(message.message_type, Some(0));
+        assert_eq!(
+            message.author.and_then(|author| author.username).as_deref(),
+            Some("test 2")
+        );
+    }
+
+    #[cfg(feature = "voice")]
+    #[test]
+    fn can_parse_voice_state_event() {
+        let data = serde_json::json!({
+            "voice_state": {
+                "mute": false,
+                "deaf": false,
+                "self_mute": true,
+                "self_deaf": false,
+                "suppress": false,
+            },
+            "user": {
+                "id": "190320984123768832",
+                "username": "test 2",
+                "discriminator": "7479",
+            },
+            "nick": "test user 2",
+            "volume": 110,
+            "mute": false,
+            "pan": {
+                "left": 1.0,
+                "right": 1.0,
+            },
+        });
+
+        let EventData::VoiceStateUpdate(event) = Event::VoiceStateUpdate.parse_data(data) else {
+            panic!("Failed to parse voice state event");
+        };
+
+        assert_eq!(event.nick.as_deref(), Some("test user 2"));
+        assert_eq!(event.volume.and_then(|v| v.as_u64()), Some(110));
+        assert_eq!(
+            event.voice_state.and_then(|state| state.self_mute),
+            Some(true)
+        );
+        assert_eq!(event.pan.and_then(|pan| pan.left?.as_f64()), Some(1.0));
+    }
+
+    #[cfg(feature = "voice")]
+    #[test]
+    fn can_parse_voice_connection_status_event() {
+        let data = serde_json::json!({
+            "state": "VOICE_CONNECTED",
+            "hostname": "some-server.discord.gg",
+            "pings": [{ "time": 1_600_000_000_000_u64, "value": 18 }],
+            "average_ping": 18.5,
+            "last_ping": 18,
+        });
+
+        let EventData::VoiceConnectionStatus(event) = Event::VoiceConnectionStatus.parse_data(data)
+        else {
+            panic!("Failed to parse voice connection status event");
+        };
+
+        assert_eq!(event.state, Some(VoiceConnectionState::VoiceConnected));
+        assert_eq!(event.pings.map(|pings| pings.len()), Some(1));
+        assert_eq!(event.average_ping.and_then(|v| v.as_f64()), Some(18.5));
+    }
+
+    #[cfg(feature = "voice")]
+    #[test]
+    fn can_parse_voice_settings() {
+        let data = serde_json::json!({
+            "input": {
+                "available_devices": [
+                    { "id": "default", "name": "Default" },
+                    { "id": "{0.0.1.00000000}.{a1b2}", "name": "Microphone (USB Audio)" },
+                ],
+                "device_id": "{0.0.1.00000000}.{a1b2}",
+                "volume": 49.5,
+            },
+            "output": { "device_id": "default", "volume": 100 },
+            "mode": { "type": "VOICE_ACTIVITY", "auto_threshold": true, "threshold": -46, "delay": 1 },
+            "mute": false,
+        });
+
+        let settings: VoiceSettings = serde_json::from_value(data).unwrap();
+        let input = settings.input.unwrap();
+        assert_eq!(input.devices().len(), 2);
+        assert_eq!(
+            input
+                .selected_device()
+                .and_then(|device| device.name.as_deref()),
+            Some("Microphone (USB Audio)")
+        );
+
+        let output = settings.output.unwrap();
+        assert!(output.devices().is_empty());
+        assert!(output.selected_device().is_none());
+        assert_eq!(
+            settings.mode.and_then(|mode| mode.mode_type).as_deref(),
+            Some("VOICE_ACTIVITY")
+        );
+
+        let data = serde_json::json!({ "mode": { "type": "VOICE_ACTIVITY" }, "mute": true });
+        let EventData::VoiceSettingsUpdate(settings) = Event::VoiceSettingsUpdate.parse_data(data)
+        else {
+            panic!("Failed to parse voice settings update event");
+        };
+        assert_eq!(settings.mute, Some(true));
+    }
+
+    #[test]
+    fn parses_oauth_scopes() {
+        let response: AuthenticateResponse = serde_json::from_value(serde_json::json!({
+            "scopes": ["rpc", "rpc.voice.read", "some.future.scope"],
+            "expires": "2026-10-23T12:00:00.000000+00:00",
+        }))
+        .unwrap();
+
+        assert_eq!(
+            response.scopes,
+            [
+                OAuthScope::Rpc,
+                OAuthScope::RpcVoiceRead,
+                OAuthScope::Unknown
+            ]
+        );
+        assert_eq!(OAuthScope::RpcVoiceRead.to_string(), "rpc.voice.read");
+        assert_eq!(
+            serde_json::to_value(AuthorizeArgs::new(1, [OAuthScope::MessagesRead])).unwrap(),
+            serde_json::json!({ "client_id": "1", "scopes": ["messages.read"] })
+        );
+        assert_eq!(
+            Event::MessageCreate.required_scopes(),
+            [OAuthScope::Rpc, OAuthScope::MessagesRead]
+        );
+    }
+}
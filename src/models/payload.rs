@@ -1,5 +1,5 @@
 use super::{Command, Event, Message};
-use crate::utils;
+use crate::{utils, DiscordError};
 use serde::{de::DeserializeOwned, Serialize};
 
 /// The Discord client payload
@@ -44,11 +44,13 @@ where
     }
 }
 
-impl<T> From<Message> for Payload<T>
+impl<T> TryFrom<Message> for Payload<T>
 where
     T: Serialize + DeserializeOwned,
 {
-    fn from(message: Message) -> Self {
-        serde_json::from_str(&message.payload).unwrap()
+    type Error = DiscordError;
+
+    fn try_from(message: Message) -> Result<Self, Self::Error> {
+        crate::codec::decode(&message.payload)
     }
 }
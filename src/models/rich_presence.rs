@@ -1,15 +1,15 @@
 use std::default::Default;
 
-use serde::Deserializer;
+use serde::{Deserialize, Deserializer};
 
 #[cfg(feature = "activity_type")]
 use serde_repr::{Deserialize_repr, Serialize_repr};
 
-use super::events::PartialUser;
+use super::{events::PartialUser, Snowflake};
 use crate::utils;
 
 /// Args to set Discord activity
-#[derive(Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
 pub struct SetActivityArgs {
     pid: u32,
 
@@ -30,6 +30,40 @@ impl SetActivityArgs {
     }
 }
 
+impl SetActivityArgs {
+    /// The activity to set, or `None` if it is being cleared
+    pub(crate) fn activity(&self) -> Option<&Activity> {
+        self.activity.as_ref()
+    }
+
+    /// Applies a function to the activity, if any
+    pub(crate) fn map_activity<F>(mut self, f: F) -> Self
+    where
+        F: FnOnce(Activity) -> Activity,
+    {
+        self.activity = self.activity.map(f);
+        self
+    }
+
+    /// Applies a fallible function to the activity, if any
+    pub(crate) fn try_map_activity<F>(mut self, f: F) -> crate::Result<Self>
+    where
+        F: FnOnce(Activity) -> crate::Result<Activity>,
+    {
+        self.activity = self.activity.map(f).transpose()?;
+        Ok(self)
+    }
+}
+
+impl From<Activity> for SetActivityArgs {
+    fn from(activity: Activity) -> Self {
+        Self {
+            pid: utils::pid(),
+            activity: Some(activity),
+        }
+    }
+}
+
 impl Default for SetActivityArgs {
     fn default() -> Self {
         Self {
@@ -40,21 +74,51 @@ impl Default for SetActivityArgs {
 }
 
 /// Args to invite a player to join a game
-#[derive(Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
 pub struct SendActivityJoinInviteArgs {
     /// The user to invite
-    pub user_id: String,
+    pub user_id: Snowflake,
 }
 
 /// The args to close an activity request
 pub type CloseActivityRequestArgs = SendActivityJoinInviteArgs;
 
+/// The kind of [`ActivityInviteEvent`] that invites the user to join
+pub const ACTIVITY_INVITE_JOIN: u32 = 1;
+
+/// Args to accept an invite to join a game, as received in an [`ActivityInviteEvent`]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct AcceptActivityInviteArgs {
+    /// The kind of invite, see [`ACTIVITY_INVITE_JOIN`]
+    #[serde(rename = "type")]
+    pub invite_type: u32,
+    /// The user who sent the invite
+    pub user_id: Snowflake,
+    /// The channel the invite was sent in
+    pub channel_id: Snowflake,
+    /// The message carrying the invite
+    pub message_id: Snowflake,
+}
+
+impl AcceptActivityInviteArgs {
+    #[must_use]
+    /// Create the args to accept the invite, or `None` if the event does not say who sent it or in which message
+    pub fn from_invite(invite: &ActivityInviteEvent) -> Option<Self> {
+        Some(Self {
+            invite_type: invite.invite_type.unwrap_or(ACTIVITY_INVITE_JOIN),
+            user_id: invite.user.as_ref()?.id?,
+            channel_id: invite.channel_id?,
+            message_id: invite.message_id?,
+        })
+    }
+}
+
 impl SendActivityJoinInviteArgs {
     #[must_use]
     /// Create a new `SendActivityJoinInviteArgs`
-    pub fn new(user_id: u64) -> Self {
+    pub fn new<I: Into<Snowflake>>(user_id: I) -> Self {
         Self {
-            user_id: user_id.to_string(),
+            user_id: user_id.into(),
         }
     }
 }
@@ -69,7 +133,7 @@ impl SendActivityJoinInviteArgs {
 #[cfg_attr(docsrs, doc(cfg(feature = "activity_type")))]
 #[repr(u8)]
 #[non_exhaustive]
-#[derive(Clone, Debug, PartialEq, Eq, Deserialize_repr, Serialize_repr, Hash)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize_repr, Serialize_repr, Hash)]
 pub enum ActivityType {
     /// Playing a game
     Playing = 0,
@@ -82,17 +146,25 @@ pub enum ActivityType {
 }
 
 builder! {ActivityJoinEvent
-    secret: String,
+    secret: String redact,
 }
 
 builder! {ActivitySpectateEvent
-    secret: String,
+    secret: String redact,
 }
 
 builder! {ActivityJoinRequestEvent
     user: PartialUser,
 }
 
+builder! {ActivityInviteEvent
+    invite_type: u32 alias = "type",
+    user:        PartialUser,
+    activity:    Activity,
+    channel_id:  Snowflake,
+    message_id:  Snowflake,
+}
+
 builder! {Activity
     state: String,
     details: String,
@@ -105,11 +177,381 @@ builder! {Activity
     buttons: ActivityButton as array,
 }
 
+impl Activity {
+    /// Parses an `Activity` from JSON, e.g. a user-editable presence definition
+    ///
+    /// Buttons may either be full objects, or just their labels, as returned by Discord.
+    ///
+    /// # Errors
+    /// - The JSON is invalid, or does not describe an activity
+    pub fn from_json(json: &str) -> crate::Result<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+}
+
+impl From<&Activity> for Activity {
+    fn from(activity: &Activity) -> Self {
+        activity.clone()
+    }
+}
+
+/// Maximum length of Discord's activity text fields, in characters
+const MAX_TEXT_LENGTH: usize = 128;
+/// Maximum length of an activity button's label, in characters
+const MAX_BUTTON_LABEL_LENGTH: usize = 32;
+/// Maximum number of buttons Discord shows on an activity
+const MAX_BUTTONS: usize = 2;
+/// Maximum length of an activity secret, in characters
+const MAX_SECRET_LENGTH: usize = 128;
+
+impl Activity {
+    /// Truncates the text fields to Discord's limits, ending them with an ellipsis if they were too long.
+    ///
+    /// Useful when presence text comes from arbitrary user input (e.g. song titles),
+    /// which Discord would otherwise reject. Call this after setting the fields.
+    #[must_use]
+    pub fn truncate_fields(mut self) -> Self {
+        let text_fields = [&mut self.state, &mut self.details];
+        let asset_fields = self
+            .assets
+            .iter_mut()
+            .flat_map(|assets| [&mut assets.large_text, &mut assets.small_text]);
+
+        for field in text_fields.into_iter().chain(asset_fields).flatten() {
+            utils::truncate(field, MAX_TEXT_LENGTH);
+        }
+
+        for label in self.buttons.iter_mut().filter_map(|b| b.label.as_mut()) {
+            utils::truncate(label, MAX_BUTTON_LABEL_LENGTH);
+        }
+
+        self
+    }
+
+    /// Translates the text fields, looking up each field's text as a key in the given locale.
+    ///
+    /// Fields the localizer returns `None` for are left as they are, so untranslated text still shows.
+    /// This lets presence text be written as keys (e.g. `menu.main`), which the app translates.
+    #[must_use]
+    pub fn localize<F>(mut self, locale: &str, localizer: F) -> Self
+    where
+        F: Fn(&str, &str) -> Option<String>,
+    {
+        let text_fields = [&mut self.state, &mut self.details];
+        let asset_fields = self
+            .assets
+            .iter_mut()
+            .flat_map(|assets| [&mut assets.large_text, &mut assets.small_text]);
+        let labels = self.buttons.iter_mut().map(|button| &mut button.label);
+
+        for field in text_fields
+            .into_iter()
+            .chain(asset_fields)
+            .chain(labels)
+            .flatten()
+        {
+            if let Some(text) = localizer(field, locale) {
+                *field = text;
+            }
+        }
+
+        self
+    }
+
+    /// Checks that Discord would show the activity as intended, fixing what can be fixed.
+    /// Every activity is checked like this before it is sent.
+    ///
+    /// Timestamps given in seconds are converted to milliseconds. See [`ActivityTimestamps::normalized`].
+    /// Combinations Discord drops the activity for are rejected, rather than the activity silently not showing:
+    /// - Buttons alongside secrets, as buttons replace the "Ask to Join" and "Spectate" buttons
+    /// - More than two buttons, or a button without both a label and a URL
+    /// - A join secret without a party ID, which Discord needs to tell who is in the party
+    /// - Empty secrets, secrets longer than 128 characters, or a spectate secret equal to the join secret,
+    ///   which would let spectators join
+    /// - A spectate secret on an activity that is explicitly not an instance, as only instances can be spectated
+    /// - A party with more members than its maximum size
+    ///
+    /// # Errors
+    /// - [`DiscordError::InvalidActivity`](crate::DiscordError::InvalidActivity) describing the first problem found
+    pub fn validated(mut self) -> crate::Result<Self> {
+        let invalid = |reason: &str| Err(crate::DiscordError::InvalidActivity(reason.to_owned()));
+
+        self.timestamps = self
+            .timestamps
+            .map(ActivityTimestamps::normalized)
+            .transpose()?;
+
+        let secrets = self.secrets.as_ref();
+        let has_secrets = secrets.is_some_and(|secrets| {
+            secrets.join.is_some() || secrets.spectate.is_some() || secrets.game.is_some()
+        });
+        if has_secrets && !self.buttons.is_empty() {
+            return invalid("an activity cannot have both buttons and secrets");
+        }
+        if self.buttons.len() > MAX_BUTTONS {
+            return invalid("an activity cannot have more than two buttons");
+        }
+        if self
+            .buttons
+            .iter()
+            .any(|button| button.label.is_none() || button.url.is_none())
+        {
+            return invalid("every button needs both a label and a URL");
+        }
+
+        let party = self.party.as_ref();
+        if secrets.is_some_and(|secrets| secrets.join.is_some())
+            && party.map_or(true, |party| party.id.is_none())
+        {
+            return invalid("a join secret needs a party ID");
+        }
+        if let Some(secrets) = secrets {
+            if [&secrets.join, &secrets.spectate, &secrets.game]
+                .into_iter()
+                .any(|secret| secret.as_deref() == Some(""))
+            {
+                return invalid("secrets cannot be empty");
+            }
+            if [&secrets.join, &secrets.spectate, &secrets.game]
+                .into_iter()
+                .flatten()
+                .any(|secret| secret.chars().count() > MAX_SECRET_LENGTH)
+            {
+                return invalid("secrets cannot be longer than 128 characters");
+            }
+            if secrets.spectate.is_some() && secrets.spectate == secrets.join {
+                return invalid("the spectate secret must differ from the join secret");
+            }
+            if secrets.spectate.is_some() && self.instance == Some(false) {
+                return invalid("a spectate secret needs the activity to be an instance");
+            }
+        }
+        if let Some((current, max)) = party.and_then(|party| party.size) {
+            if current > max {
+                return invalid("the party has more members than its maximum size");
+            }
+        }
+
+        Ok(self)
+    }
+
+    /// Whether the activity looks the same as `other` on the user's profile, as far as can be told from
+    /// the fields Discord shows as they were sent: the state, details, timestamps and party size
+    ///
+    /// Used to compare an activity with the one Discord echoed back, which has fields of its own filled in.
+    #[must_use]
+    pub fn shows_same_as(&self, other: &Activity) -> bool {
+        let timestamps = |activity: &Activity| {
+            activity
+                .timestamps
+                .as_ref()
+                .map(|timestamps| (timestamps.start, timestamps.end))
+        };
+        let party_size = |activity: &Activity| activity.party.as_ref().and_then(|party| party.size);
+
+        self.state == other.state
+            && self.details == other.details
+            && timestamps(self) == timestamps(other)
+            && party_size(self) == party_size(other)
+    }
+
+    /// Sets up a session that others can spectate, but not join
+    ///
+    /// Sets the spectate secret and marks the activity as an instance, which Discord needs to offer spectating.
+    /// A party is not needed. The join secret is removed, as it would make the session joinable,
+    /// and so are buttons, which would replace the "Spectate" button.
+    #[must_use]
+    pub fn spectate_only<S: Into<String>>(mut self, secret: S) -> Self {
+        let secrets = self.secrets.get_or_insert_with(ActivitySecrets::default);
+        secrets.join = None;
+        secrets.spectate = Some(secret.into());
+        self.instance = Some(true);
+        self.buttons.clear();
+        self
+    }
+
+    #[must_use]
+    /// Whether the activity can be spectated, but not joined
+    pub fn is_spectate_only(&self) -> bool {
+        self.secrets
+            .as_ref()
+            .is_some_and(|secrets| secrets.spectate.is_some() && secrets.join.is_none())
+    }
+
+    fn party_size(&mut self) -> &mut (u32, u32) {
+        self.party
+            .get_or_insert_with(ActivityParty::default)
+            .size
+            .get_or_insert((0, 0))
+    }
+
+    /// Sets the current size of the party, raising the maximum size to match if needed
+    #[must_use]
+    pub fn party_current(mut self, current: u32) -> Self {
+        let size = self.party_size();
+        *size = (current, size.1.max(current));
+        self
+    }
+
+    /// Sets the maximum size of the party, lowering the current size to match if needed
+    #[must_use]
+    pub fn party_max(mut self, max: u32) -> Self {
+        let size = self.party_size();
+        *size = (size.0.min(max), max);
+        self
+    }
+
+    /// Increments the current size of the party, if it is not already full
+    #[must_use]
+    pub fn increment_party(mut self) -> Self {
+        let size = self.party_size();
+        if size.0 < size.1 {
+            size.0 += 1;
+        }
+        self
+    }
+
+    /// Decrements the current size of the party, if it is not already empty
+    #[must_use]
+    pub fn decrement_party(mut self) -> Self {
+        let size = self.party_size();
+        size.0 = size.0.saturating_sub(1);
+        self
+    }
+}
+
 builder! {ActivityTimestamps
     start: u64,
     end: u64,
 }
 
+/// Timestamps below this are taken to be in seconds, as in milliseconds they would be in April 1970
+const SECONDS_THRESHOLD: u64 = 10_000_000_000;
+/// The last millisecond of the year 9999, past which a timestamp is more likely in micro- or nanoseconds
+const MAX_TIMESTAMP: u64 = 253_402_300_799_999;
+
+impl ActivityTimestamps {
+    /// Checks that the timestamps make sense, converting any given in seconds to the milliseconds Discord expects.
+    ///
+    /// Timestamps in seconds would otherwise be shown as a date in 1970, counting tens of thousands of days.
+    ///
+    /// # Errors
+    /// - [`DiscordError::InvalidActivity`](crate::DiscordError::InvalidActivity) if a timestamp is zero,
+    ///   too large to be in milliseconds, or the start is after the end
+    pub fn normalized(mut self) -> crate::Result<Self> {
+        self.start = self
+            .start
+            .map(|start| normalize_timestamp("start", start))
+            .transpose()?;
+        self.end = self
+            .end
+            .map(|end| normalize_timestamp("end", end))
+            .transpose()?;
+
+        match (self.start, self.end) {
+            (Some(start), Some(end)) if start > end => Err(crate::DiscordError::InvalidActivity(
+                format!("the start timestamp {start} is after the end timestamp {end}"),
+            )),
+            _ => Ok(self),
+        }
+    }
+}
+
+/// Converts a timestamp in seconds to milliseconds, rejecting those that cannot be either
+fn normalize_timestamp(name: &str, timestamp: u64) -> crate::Result<u64> {
+    if timestamp == 0 {
+        Err(crate::DiscordError::InvalidActivity(format!(
+            "the {name} timestamp is zero"
+        )))
+    } else if timestamp < SECONDS_THRESHOLD {
+        Ok(timestamp * 1000)
+    } else if timestamp > MAX_TIMESTAMP {
+        Err(crate::DiscordError::InvalidActivity(format!(
+            "the {name} timestamp {timestamp} is too large, timestamps are in milliseconds"
+        )))
+    } else {
+        Ok(timestamp)
+    }
+}
+
+#[cfg(feature = "chrono")]
+#[cfg_attr(docsrs, doc(cfg(feature = "chrono")))]
+impl ActivityTimestamps {
+    fn from_chrono<Tz: chrono::TimeZone>(datetime: &chrono::DateTime<Tz>) -> u64 {
+        // Timestamps before the unix epoch are not supported by Discord
+        u64::try_from(datetime.timestamp_millis()).unwrap_or(0)
+    }
+
+    fn to_chrono(millis: u64) -> Option<chrono::DateTime<chrono::Utc>> {
+        use chrono::TimeZone;
+
+        chrono::Utc
+            .timestamp_millis_opt(i64::try_from(millis).ok()?)
+            .single()
+    }
+
+    /// Instantiates the current struct with the given [`chrono::DateTime`] as the start time.
+    #[must_use]
+    pub fn start_datetime<Tz: chrono::TimeZone>(self, start: &chrono::DateTime<Tz>) -> Self {
+        self.start(Self::from_chrono(start))
+    }
+
+    /// Instantiates the current struct with the given [`chrono::DateTime`] as the end time.
+    #[must_use]
+    pub fn end_datetime<Tz: chrono::TimeZone>(self, end: &chrono::DateTime<Tz>) -> Self {
+        self.end(Self::from_chrono(end))
+    }
+
+    /// The start time as a [`chrono::DateTime`]
+    #[must_use]
+    pub fn start_as_datetime(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.start.and_then(Self::to_chrono)
+    }
+
+    /// The end time as a [`chrono::DateTime`]
+    #[must_use]
+    pub fn end_as_datetime(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.end.and_then(Self::to_chrono)
+    }
+}
+
+#[cfg(feature = "time")]
+#[cfg_attr(docsrs, doc(cfg(feature = "time")))]
+impl ActivityTimestamps {
+    fn from_time(datetime: time::OffsetDateTime) -> u64 {
+        // Timestamps before the unix epoch are not supported by Discord
+        u64::try_from(datetime.unix_timestamp_nanos() / 1_000_000).unwrap_or(0)
+    }
+
+    fn to_time(millis: u64) -> Option<time::OffsetDateTime> {
+        time::OffsetDateTime::from_unix_timestamp_nanos(i128::from(millis) * 1_000_000).ok()
+    }
+
+    /// Instantiates the current struct with the given [`time::OffsetDateTime`] as the start time.
+    #[must_use]
+    pub fn start_offset_datetime(self, start: time::OffsetDateTime) -> Self {
+        self.start(Self::from_time(start))
+    }
+
+    /// Instantiates the current struct with the given [`time::OffsetDateTime`] as the end time.
+    #[must_use]
+    pub fn end_offset_datetime(self, end: time::OffsetDateTime) -> Self {
+        self.end(Self::from_time(end))
+    }
+
+    /// The start time as a [`time::OffsetDateTime`]
+    #[must_use]
+    pub fn start_as_offset_datetime(&self) -> Option<time::OffsetDateTime> {
+        self.start.and_then(Self::to_time)
+    }
+
+    /// The end time as a [`time::OffsetDateTime`]
+    #[must_use]
+    pub fn end_as_offset_datetime(&self) -> Option<time::OffsetDateTime> {
+        self.end.and_then(Self::to_time)
+    }
+}
+
 builder! {ActivityAssets
     large_image: String,
     large_text: String,
@@ -120,53 +562,70 @@ builder! {ActivityAssets
 builder! {ActivityParty
     id: String,
     size: (u32, u32),
+    privacy: PartyPrivacy,
+}
+
+/// Who can join a party without asking first
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(into = "u8", try_from = "u8")]
+pub enum PartyPrivacy {
+    /// Players have to ask to join
+    Private = 0,
+    /// Players can join directly, such as friends in the same voice channel
+    Public = 1,
+}
+
+impl From<PartyPrivacy> for u8 {
+    fn from(privacy: PartyPrivacy) -> Self {
+        privacy as u8
+    }
+}
+
+impl TryFrom<u8> for PartyPrivacy {
+    type Error = crate::DiscordError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::Private),
+            1 => Ok(Self::Public),
+            _ => Err(crate::DiscordError::Conversion),
+        }
+    }
 }
 
 builder! {ActivitySecrets
-    join: String,
-    spectate: String,
-    game: String alias = "match",
+    join: String redact,
+    spectate: String redact,
+    game: String redact alias = "match",
 }
 
 // pub type ActivityButtons = Vec<ActivityButton>;
 
-// A probably overcomplicated way to convert the array of strings returned by Discord, into buttons
+// Discord returns buttons as an array of their labels, while they are sent as full objects.
+// Accept both, so that activities round-trip losslessly.
 fn serialize_activity_button<'de, D>(data: D) -> Result<Vec<ActivityButton>, D::Error>
 where
     D: Deserializer<'de>,
 {
-    use serde::de;
-    use std::fmt;
-
-    struct JsonStringVisitor;
-
-    impl<'de> de::Visitor<'de> for JsonStringVisitor {
-        type Value = Vec<ActivityButton>;
-
-        fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
-            formatter.write_str("a string containing the label for the button")
-        }
-
-        fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
-        where
-            A: de::SeqAccess<'de>,
-        {
-            let mut buttons = vec![];
-
-            while let Ok(Some(label)) = seq.next_element::<String>() {
-                let button = ActivityButton {
-                    label: Some(label.clone()),
-                    url: None,
-                };
-
-                buttons.push(button);
-            }
-
-            Ok(buttons)
-        }
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum ButtonRepr {
+        Label(String),
+        Button(ActivityButton),
     }
 
-    data.deserialize_any(JsonStringVisitor)
+    let buttons = Vec::<ButtonRepr>::deserialize(data)?
+        .into_iter()
+        .map(|button| match button {
+            ButtonRepr::Label(label) => ActivityButton {
+                label: Some(label),
+                url: None,
+            },
+            ButtonRepr::Button(button) => button,
+        })
+        .collect();
+
+    Ok(buttons)
 }
 
 builder! {ActivityButton
@@ -179,8 +638,78 @@ builder! {ActivityButton
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::models::{Event, EventData};
     use serde_json;
 
+    #[test]
+    fn accepts_activity_invites() {
+        let data = serde_json::json!({
+            "type": 1,
+            "user": { "id": "175928847299117063", "username": "test" },
+            "activity": { "state": "In a group", "party": { "id": "party-1", "size": [2, 4] } },
+            "channel_id": "199737254929760256",
+            "message_id": "199737254929760257",
+        });
+        let EventData::ActivityInvite(invite) = Event::ActivityInvite.parse_data(data) else {
+            panic!("Failed to parse activity invite event");
+        };
+        assert_eq!(
+            invite
+                .activity
+                .as_ref()
+                .and_then(|activity| activity.state.as_deref()),
+            Some("In a group")
+        );
+
+        let args = AcceptActivityInviteArgs::from_invite(&invite).unwrap();
+        assert_eq!(
+            serde_json::to_value(args).unwrap(),
+            serde_json::json!({
+                "type": ACTIVITY_INVITE_JOIN,
+                "user_id": "175928847299117063",
+                "channel_id": "199737254929760256",
+                "message_id": "199737254929760257",
+            })
+        );
+
+        let invite = ActivityInviteEvent {
+            message_id: None,
+            ..*invite
+        };
+        assert!(AcceptActivityInviteArgs::from_invite(&invite).is_none());
+    }
+
+    #[test]
+    fn normalizes_timestamps() {
+        let timestamps = ActivityTimestamps::new()
+            .start(1_700_000_000)
+            .end(1_700_000_060_000)
+            .normalized()
+            .unwrap();
+        assert_eq!(timestamps.start, Some(1_700_000_000_000));
+        assert_eq!(timestamps.end, Some(1_700_000_060_000));
+
+        let invalid = [
+            ActivityTimestamps::new().start(0),
+            ActivityTimestamps::new().end(1_700_000_000_000_000),
+            ActivityTimestamps::new()
+                .start(1_700_000_060)
+                .end(1_700_000_000),
+        ];
+        for timestamps in invalid {
+            assert!(matches!(
+                timestamps.normalized(),
+                Err(crate::DiscordError::InvalidActivity(_))
+            ));
+        }
+
+        let activity = Activity::new().timestamps(|t| t.start(1_700_000_000));
+        assert_eq!(
+            activity.validated().unwrap().timestamps.unwrap().start,
+            Some(1_700_000_000_000)
+        );
+    }
+
     #[test]
     fn can_serialize_full_activity() {
         let expected = include_str!("../../tests/fixtures/activity_full.json");
@@ -208,12 +737,207 @@ mod tests {
         assert_eq!(parsed_expected, activity);
     }
 
+    #[test]
+    fn redacts_secrets_in_debug_output() {
+        let activity = Activity::new()
+            .state("rusting")
+            .secrets(|s| s.join("025ed05c71f639de8bfaa0d679d7c94b2fdce12f"));
+        let debug = format!("{activity:?}");
+
+        assert!(debug.contains("rusting"));
+        assert!(debug.contains(crate::utils::REDACTED));
+        assert!(!debug.contains("025ed05c71f639de8bfaa0d679d7c94b2fdce12f"));
+    }
+
+    #[test]
+    fn can_truncate_fields() {
+        let activity = Activity::new()
+            .state("a".repeat(200))
+            .details("short")
+            .assets(|a| a.large_text("ü".repeat(129)))
+            .append_buttons(|b| b.label("b".repeat(33)))
+            .truncate_fields();
+
+        assert_eq!(activity.state.unwrap().chars().count(), MAX_TEXT_LENGTH);
+        assert_eq!(activity.details.as_deref(), Some("short"));
+
+        let large_text = activity.assets.unwrap().large_text.unwrap();
+        assert_eq!(large_text.chars().count(), MAX_TEXT_LENGTH);
+        assert!(large_text.ends_with('…'));
+
+        let label = activity.buttons[0].label.as_ref().unwrap();
+        assert_eq!(label.chars().count(), MAX_BUTTON_LABEL_LENGTH);
+    }
+
+    #[test]
+    fn keeps_party_size_consistent() {
+        let size = |activity: &Activity| activity.party.as_ref().and_then(|p| p.size);
+
+        let activity = Activity::new().party_max(4).party_current(2);
+        assert_eq!(size(&activity), Some((2, 4)));
+
+        let activity = activity
+            .increment_party()
+            .increment_party()
+            .increment_party();
+        assert_eq!(size(&activity), Some((4, 4)));
+
+        let activity = activity.party_max(3);
+        assert_eq!(size(&activity), Some((3, 3)));
+
+        let activity = activity.party_current(5);
+        assert_eq!(size(&activity), Some((5, 5)));
+
+        let activity = Activity::new().decrement_party();
+        assert_eq!(size(&activity), Some((0, 0)));
+    }
+
+    #[test]
+    fn rejects_illegal_combinations() {
+        let button = |b: ActivityButton| b.label("Click Me!").url("https://example.com");
+        let invalid = [
+            Activity::new()
+                .append_buttons(button)
+                .secrets(|s| s.spectate("secret")),
+            Activity::new()
+                .append_buttons(button)
+                .append_buttons(button)
+                .append_buttons(button),
+            Activity::new().append_buttons(|b| b.label("Click Me!")),
+            Activity::new().secrets(|s| s.join("secret")),
+            Activity::new().party(|p| p.id("party").size((5, 4))),
+            Activity::new().secrets(|s| s.spectate("")),
+            Activity::new()
+                .party(|p| p.id("party"))
+                .secrets(|s| s.join("secret").spectate("secret")),
+            Activity::new()
+                .instance(false)
+                .secrets(|s| s.spectate("secret")),
+            Activity::new().secrets(|s| s.game("a".repeat(129))),
+        ];
+        for activity in invalid {
+            assert!(matches!(
+                activity.validated(),
+                Err(crate::DiscordError::InvalidActivity(_))
+            ));
+        }
+
+        let valid = Activity::new()
+            .party(|p| p.id("party").size((1, 4)))
+            .secrets(|s| s.join("secret"));
+        assert!(valid.validated().is_ok());
+    }
+
+    #[test]
+    fn builds_spectate_only_activities() {
+        let activity = Activity::new()
+            .party(|p| p.id("party").size((2, 2)))
+            .secrets(|s| s.join("join").game("match"))
+            .append_buttons(|b| b.label("Click Me!").url("https://example.com"))
+            .spectate_only("spectate");
+
+        assert!(activity.is_spectate_only());
+        assert_eq!(activity.instance, Some(true));
+        assert!(activity.buttons.is_empty());
+        let secrets = activity.secrets.as_ref().unwrap();
+        assert_eq!(secrets.join, None);
+        assert_eq!(secrets.spectate.as_deref(), Some("spectate"));
+        assert_eq!(secrets.game.as_deref(), Some("match"));
+        assert!(activity.validated().is_ok());
+
+        let joinable = Activity::new()
+            .party(|p| p.id("party"))
+            .secrets(|s| s.join("join").spectate("spectate"));
+        assert!(!joinable.is_spectate_only());
+    }
+
+    #[test]
+    fn can_round_trip_activity() {
+        let activity = Activity::new()
+            .state("rusting")
+            .timestamps(|t| t.start(1000))
+            .party(|p| p.id("party").size((1, 4)))
+            .append_buttons(|b| b.label("Click Me!").url("https://example.com"))
+            .secrets(|s| s.game("4b2fdce12f639de8bfa7e3591b71a0d679d7c93f"));
+
+        let json = serde_json::to_string(&activity).expect("Failed to serialize into String");
+        let parsed = Activity::from_json(&json).expect("Failed to parse activity");
+
+        assert_eq!(activity, parsed);
+    }
+
+    #[test]
+    fn equal_activities_hash_equally() {
+        use std::{
+            collections::hash_map::DefaultHasher,
+            hash::{Hash, Hasher},
+        };
+
+        let hash = |activity: &Activity| {
+            let mut hasher = DefaultHasher::new();
+            activity.hash(&mut hasher);
+            hasher.finish()
+        };
+
+        let build = || {
+            Activity::new()
+                .state("rusting")
+                .party_max(4)
+                .party_current(1)
+        };
+
+        assert_eq!(build(), build());
+        assert_eq!(hash(&build()), hash(&build()));
+        assert_ne!(hash(&build()), hash(&build().details("different")));
+    }
+
     #[test]
     fn can_serialize_empty_activity() {
         let activity = Activity::new();
         let json = serde_json::to_string(&activity).expect("Failed to serialize into String");
         assert_eq![json, "{}"];
     }
+
+    #[test]
+    fn string_setters_accept_string_like_values() {
+        use std::borrow::Cow;
+
+        let details = format!("Level {}", 3);
+        let activity = Activity::new()
+            .state(Cow::Borrowed("rusting"))
+            .details(&details)
+            .assets(|a| a.large_image(String::from("ferris")));
+
+        assert_eq!(activity.state.as_deref(), Some("rusting"));
+        assert_eq!(activity.details, Some(details));
+        assert_eq!(
+            activity.assets.and_then(|a| a.large_image).as_deref(),
+            Some("ferris")
+        );
+    }
+
+    #[test]
+    fn localizes_text_fields() {
+        let activity = Activity::new()
+            .state("menu.main")
+            .details("Untranslated")
+            .assets(|a| a.large_text("map.forest"))
+            .append_buttons(|b| b.label("button.join").url("https://example.com"))
+            .localize("fr", |key, locale| match (key, locale) {
+                ("menu.main", "fr") => Some("Menu principal".to_owned()),
+                ("map.forest", "fr") => Some("Forêt".to_owned()),
+                ("button.join", "fr") => Some("Rejoindre".to_owned()),
+                _ => None,
+            });
+
+        assert_eq!(activity.state.as_deref(), Some("Menu principal"));
+        assert_eq!(activity.details.as_deref(), Some("Untranslated"));
+        assert_eq!(
+            activity.assets.unwrap().large_text.as_deref(),
+            Some("Forêt")
+        );
+        assert_eq!(activity.buttons[0].label.as_deref(), Some("Rejoindre"));
+    }
 }
 
 #[cfg(test)]
@@ -229,3 +953,36 @@ mod activity_type_tests {
         assert_eq![json, r#"{"type":3}"#];
     }
 }
+
+#[cfg(test)]
+#[cfg(feature = "chrono")]
+mod chrono_tests {
+    use super::*;
+
+    #[test]
+    fn can_convert_chrono_timestamps() {
+        use chrono::TimeZone;
+
+        let start = chrono::Utc.timestamp_millis_opt(1_700_000_000_123).unwrap();
+        let timestamps = ActivityTimestamps::new().start_datetime(&start);
+
+        assert_eq!(timestamps.start, Some(1_700_000_000_123));
+        assert_eq!(timestamps.start_as_datetime(), Some(start));
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "time")]
+mod time_tests {
+    use super::*;
+
+    #[test]
+    fn can_convert_time_timestamps() {
+        let start =
+            time::OffsetDateTime::from_unix_timestamp_nanos(1_700_000_000_123_000_000).unwrap();
+        let timestamps = ActivityTimestamps::new().start_offset_datetime(start);
+
+        assert_eq!(timestamps.start, Some(1_700_000_000_123));
+        assert_eq!(timestamps.start_as_offset_datetime(), Some(start));
+    }
+}
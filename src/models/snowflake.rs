@@ -0,0 +1,144 @@
+use std::{
+    fmt,
+    num::ParseIntError,
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+/// Milliseconds between the Unix epoch and the first second of 2015, which Discord's IDs count from
+const DISCORD_EPOCH: u64 = 1_420_070_400_000;
+
+/// A Discord ID, such as that of a user, channel or message
+///
+/// Discord sends IDs as strings, as they do not fit in a JavaScript number, so they are serialized as strings.
+/// Both strings and numbers are accepted when deserializing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Snowflake(u64);
+
+impl Snowflake {
+    /// Wraps a raw ID
+    #[must_use]
+    pub const fn new(id: u64) -> Self {
+        Self(id)
+    }
+
+    /// The raw ID
+    #[must_use]
+    pub const fn get(self) -> u64 {
+        self.0
+    }
+
+    /// When the ID was created, which is encoded in its upper 42 bits
+    #[must_use]
+    pub fn created_at(self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis((self.0 >> 22) + DISCORD_EPOCH)
+    }
+
+    /// Whether the ID could have been made by Discord, which is not the case if it has no creation time,
+    /// or was created more than a day from now
+    #[must_use]
+    pub fn is_plausible(self) -> bool {
+        self.0 >> 22 > 0
+            && self.created_at() < SystemTime::now() + Duration::from_secs(24 * 60 * 60)
+    }
+}
+
+impl From<u64> for Snowflake {
+    fn from(id: u64) -> Self {
+        Self(id)
+    }
+}
+
+impl From<Snowflake> for u64 {
+    fn from(id: Snowflake) -> Self {
+        id.0
+    }
+}
+
+impl FromStr for Snowflake {
+    type Err = ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse().map(Self)
+    }
+}
+
+impl fmt::Display for Snowflake {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl Serialize for Snowflake {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Snowflake {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl de::Visitor<'_> for Visitor {
+            type Value = Snowflake;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a Discord ID, as a string or a number")
+            }
+
+            fn visit_u64<E: de::Error>(self, id: u64) -> Result<Self::Value, E> {
+                Ok(Snowflake(id))
+            }
+
+            fn visit_i64<E: de::Error>(self, id: i64) -> Result<Self::Value, E> {
+                u64::try_from(id)
+                    .map(Snowflake)
+                    .map_err(|_| E::invalid_value(de::Unexpected::Signed(id), &self))
+            }
+
+            fn visit_str<E: de::Error>(self, id: &str) -> Result<Self::Value, E> {
+                id.parse()
+                    .map_err(|_| E::invalid_value(de::Unexpected::Str(id), &self))
+            }
+        }
+
+        deserializer.deserialize_any(Visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_creation_time() {
+        let id = Snowflake::new(175_928_847_299_117_063);
+        let created_at = id.created_at().duration_since(UNIX_EPOCH).unwrap();
+        assert_eq!(created_at.as_millis(), 1_462_015_105_796);
+    }
+
+    #[test]
+    fn checks_plausibility() {
+        assert!(Snowflake::new(1_003_450_375_732_482_138).is_plausible());
+        assert!(!Snowflake::new(0).is_plausible());
+        assert!(!Snowflake::new(12345).is_plausible());
+        assert!(!Snowflake::new(u64::MAX).is_plausible());
+    }
+
+    #[test]
+    fn accepts_strings_and_numbers() {
+        let from_str: Snowflake = serde_json::from_str(r#""175928847299117063""#).unwrap();
+        let from_number: Snowflake = serde_json::from_str("175928847299117063").unwrap();
+        assert_eq!(from_str, Snowflake::new(175_928_847_299_117_063));
+        assert_eq!(from_number, from_str);
+        assert!(serde_json::from_str::<Snowflake>(r#""not an id""#).is_err());
+        assert!(serde_json::from_str::<Snowflake>("-1").is_err());
+
+        assert_eq!(
+            serde_json::to_string(&from_str).unwrap(),
+            r#""175928847299117063""#
+        );
+    }
+}
@@ -0,0 +1,68 @@
+use uuid::Uuid;
+
+use crate::sync::{AtomicU64, Ordering};
+
+/// Generates the nonce of each command, which Discord echoes back in its response. See [`Client::set_nonce_generator`](crate::Client::set_nonce_generator).
+///
+/// Every nonce has to be unique among the commands waiting for a response, or responses are given to the wrong command.
+pub type NonceGenerator = dyn Fn() -> String + Send + Sync;
+
+/// A random, version 4 UUID, which is what nonces are by default
+#[must_use]
+pub fn uuid_v4() -> String {
+    Uuid::new_v4().to_string()
+}
+
+/// A version 7 UUID, which starts with the time it was generated, so nonces sort in the order commands were sent
+#[must_use]
+pub fn uuid_v7() -> String {
+    Uuid::now_v7().to_string()
+}
+
+/// Numbers commands in the order they are sent, prefixed with the ID of the process, such as `4242-17`
+///
+/// # Examples
+///
+/// ```
+/// # use discord_presence::nonce::Sequential;
+/// let nonces = Sequential::new();
+/// let pid = std::process::id();
+///
+/// assert_eq!(nonces.next(), format!("{pid}-1"));
+/// assert_eq!(nonces.next(), format!("{pid}-2"));
+/// ```
+#[derive(Debug)]
+pub struct Sequential {
+    prefix: String,
+    sent: AtomicU64,
+}
+
+impl Sequential {
+    /// Create a new `Sequential` generator, prefixing nonces with the ID of the process
+    #[must_use]
+    pub fn new() -> Self {
+        Self::with_prefix(std::process::id().to_string())
+    }
+
+    /// Create a new `Sequential` generator, prefixing nonces with the given text instead of the process ID
+    #[must_use]
+    pub fn with_prefix<S: Into<String>>(prefix: S) -> Self {
+        Self {
+            prefix: prefix.into(),
+            sent: AtomicU64::new(0),
+        }
+    }
+
+    /// The nonce of the next command
+    #[must_use]
+    pub fn next(&self) -> String {
+        let n = self.sent.fetch_add(1, Ordering::Relaxed) + 1;
+        format!("{}-{n}", self.prefix)
+    }
+}
+
+impl Default for Sequential {
+    fn default() -> Self {
+        Self::new()
+    }
+}
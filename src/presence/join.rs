@@ -0,0 +1,187 @@
+use std::sync::Arc;
+
+use crate::{
+    event_handler::{Context as EventContext, EventCallbackHandle},
+    models::{ActivitySecrets, EventData, PartialUser},
+    sync::Mutex,
+    targets::DISPATCH,
+    utils, Client,
+};
+
+/// Separates the token from the connection details in a join secret
+const SEPARATOR: char = ':';
+
+type RequestFilter = dyn Fn(&PartialUser) -> bool + Send + Sync;
+
+/// How to connect to a peer's game, decoded from the join secret of their activity
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ConnectInfo {
+    /// Connection details supplied to [`JoinFlow::host`], such as a server address or lobby ID
+    pub connect: String,
+    /// Token to present to the host, which it checks with [`JoinFlow::verify`]
+    pub token: String,
+}
+
+impl ConnectInfo {
+    /// Parses a join secret generated by [`JoinFlow::host`]
+    #[must_use]
+    pub fn from_secret(secret: &str) -> Option<Self> {
+        let (token, connect) = secret.split_once(SEPARATOR)?;
+
+        if token.is_empty() || connect.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            connect: connect.to_owned(),
+            token: token.to_owned(),
+        })
+    }
+
+    fn to_secret(&self) -> String {
+        format!("{}{SEPARATOR}{}", self.token, self.connect)
+    }
+}
+
+/// Wires Discord's "Ask to Join" flow together, so games only deal in connection details
+///
+/// - [`JoinFlow::host`] generates the join secret to put in the activity
+/// - `ACTIVITY_JOIN` and `ACTIVITY_JOIN_REQUEST` are subscribed to automatically for its handlers,
+///   unless that is turned off with [`Client::set_auto_subscribe`]
+/// - Join requests are accepted, or declined if rejected by [`JoinFlow::filter_requests`]
+/// - Joining a peer's game calls the `on_peer_join` handler with their [`ConnectInfo`]
+///
+/// The joining player presents the token from [`ConnectInfo`] to the host over the game's own connection,
+/// where [`JoinFlow::verify`] checks it was issued for the current session.
+///
+/// The handlers are removed when the flow is dropped.
+pub struct JoinFlow {
+    hosted: Arc<Mutex<Option<ConnectInfo>>>,
+    filter: Arc<Mutex<Option<Box<RequestFilter>>>>,
+    _handles: Vec<EventCallbackHandle>,
+}
+
+impl JoinFlow {
+    /// Starts the flow, calling `on_peer_join` whenever the player joins someone else's game
+    #[must_use]
+    pub fn new<F>(client: &Client, on_peer_join: F) -> Self
+    where
+        F: Fn(ConnectInfo) + Send + Sync + 'static,
+    {
+        let filter: Arc<Mutex<Option<Box<RequestFilter>>>> = Arc::new(Mutex::new(None));
+
+        let on_join = client.on_activity_join(move |ctx| {
+            let EventData::ActivityJoin(ref event) = ctx.event else {
+                return;
+            };
+
+            if let Some(info) = event.secret.as_deref().and_then(ConnectInfo::from_secret) {
+                on_peer_join(info);
+            } else {
+                warn!(target: DISPATCH, "Ignoring join with an invalid secret");
+            }
+        });
+
+        let on_request = client.on_activity_join_request({
+            let filter = filter.clone();
+            move |ctx| respond(&filter, &ctx)
+        });
+
+        Self {
+            hosted: Arc::new(Mutex::new(None)),
+            filter,
+            _handles: vec![on_join, on_request],
+        }
+    }
+
+    /// Sets which join requests are accepted. By default, every request is accepted.
+    pub fn filter_requests<F>(&self, filter: F)
+    where
+        F: Fn(&PartialUser) -> bool + Send + Sync + 'static,
+    {
+        *self.filter.lock() = Some(Box::new(filter));
+    }
+
+    /// Starts hosting a session, returning the secrets to set on the activity
+    ///
+    /// Each call generates a new token, so peers holding a secret from an earlier session fail verification.
+    /// The activity also needs a party for Discord to show the "Ask to Join" button.
+    pub fn host<S: Into<String>>(&self, connect: S) -> ActivitySecrets {
+        let info = ConnectInfo {
+            connect: connect.into(),
+            token: utils::nonce(),
+        };
+        let secret = info.to_secret();
+
+        *self.hosted.lock() = Some(info);
+        ActivitySecrets::new().join(secret)
+    }
+
+    /// Stops hosting, so no token passes verification
+    pub fn stop_hosting(&self) {
+        *self.hosted.lock() = None;
+    }
+
+    /// Checks a token presented by a joining peer was issued for the session being hosted
+    #[must_use]
+    pub fn verify(&self, token: &str) -> bool {
+        self.hosted
+            .lock()
+            .as_ref()
+            .is_some_and(|info| info.token == token)
+    }
+}
+
+fn respond(filter: &Mutex<Option<Box<RequestFilter>>>, ctx: &EventContext) {
+    let EventData::ActivityJoinRequest(ref event) = ctx.event else {
+        return;
+    };
+    let Some(user) = event.user.as_ref() else {
+        return;
+    };
+
+    let accept = filter.lock().as_ref().map_or(true, |filter| filter(user));
+    let result = if accept { ctx.accept() } else { ctx.decline() };
+
+    if let Err(why) = result {
+        error!(target: DISPATCH, "Failed to respond to join request: {why}");
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secret_round_trips() {
+        let info = ConnectInfo {
+            connect: "127.0.0.1:7777".to_owned(),
+            token: "token".to_owned(),
+        };
+
+        assert_eq!(ConnectInfo::from_secret(&info.to_secret()), Some(info));
+        assert_eq!(ConnectInfo::from_secret("no separator"), None);
+        assert_eq!(ConnectInfo::from_secret(":127.0.0.1:7777"), None);
+    }
+
+    #[test]
+    fn verifies_hosted_token() {
+        // Built directly, as other tests may mark the client ready
+        let flow = JoinFlow {
+            hosted: Arc::new(Mutex::new(None)),
+            filter: Arc::new(Mutex::new(None)),
+            _handles: Vec::new(),
+        };
+
+        let secret = flow.host("lobby").join.unwrap();
+        let info = ConnectInfo::from_secret(&secret).unwrap();
+        assert_eq!(info.connect, "lobby");
+        assert!(flow.verify(&info.token));
+
+        flow.host("lobby");
+        assert!(!flow.verify(&info.token));
+
+        flow.stop_hosting();
+        assert!(!flow.verify(""));
+    }
+}
@@ -0,0 +1,159 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "activity_type")]
+use crate::models::ActivityType;
+use crate::models::{Activity, ActivityAssets, ActivityTimestamps};
+
+/// Metadata of the track that is currently playing
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct TrackMetadata {
+    /// Title of the track
+    pub title: String,
+    /// Artist of the track
+    pub artist: Option<String>,
+    /// Album the track is on
+    pub album: Option<String>,
+    /// URL of the album art, or the key of an uploaded art asset
+    pub art_url: Option<String>,
+    /// How far into the track playback is
+    pub position: Option<Duration>,
+    /// Total length of the track
+    pub duration: Option<Duration>,
+}
+
+impl TrackMetadata {
+    /// Creates metadata for a track with the given title
+    pub fn new<S: Into<String>>(title: S) -> Self {
+        Self {
+            title: title.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Builds a listening activity for the track, as of now
+    ///
+    /// - The title is shown as the details, and the artist as the state
+    /// - The album art is the large image, with the album as its text
+    /// - The timestamps are derived from the position and duration, so Discord shows the time elapsed and remaining
+    ///
+    /// Text is truncated to Discord's limits. With the `activity_type` feature, the activity type is set to [`ActivityType::Listening`].
+    ///
+    /// Call this again whenever playback is seeked, paused or resumed, as the timestamps are only correct while playing.
+    #[must_use]
+    pub fn to_activity(&self) -> Activity {
+        self.to_activity_at(SystemTime::now())
+    }
+
+    fn to_activity_at(&self, now: SystemTime) -> Activity {
+        let mut activity = Activity::new().details(&self.title);
+
+        if let Some(ref artist) = self.artist {
+            activity = activity.state(artist);
+        }
+
+        if self.art_url.is_some() || self.album.is_some() {
+            activity.assets = Some(ActivityAssets {
+                large_image: self.art_url.clone(),
+                large_text: self.album.clone(),
+                ..ActivityAssets::default()
+            });
+        }
+
+        if let Some(start) = self.started_at(now) {
+            activity.timestamps = Some(ActivityTimestamps {
+                start: Some(start),
+                end: self.duration.map(|duration| start + millis(duration)),
+            });
+        }
+
+        #[cfg(feature = "activity_type")]
+        {
+            activity = activity._type(ActivityType::Listening);
+        }
+
+        activity.truncate_fields()
+    }
+
+    /// When the track would have started playing, in milliseconds since the unix epoch
+    fn started_at(&self, now: SystemTime) -> Option<u64> {
+        // Without either, there is no time to show
+        if self.position.is_none() && self.duration.is_none() {
+            return None;
+        }
+
+        let now = now.duration_since(UNIX_EPOCH).ok()?;
+        Some(millis(
+            now.saturating_sub(self.position.unwrap_or_default()),
+        ))
+    }
+}
+
+impl From<&TrackMetadata> for Activity {
+    fn from(track: &TrackMetadata) -> Self {
+        track.to_activity()
+    }
+}
+
+fn millis(duration: Duration) -> u64 {
+    u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000_000;
+
+    fn now() -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(NOW)
+    }
+
+    #[test]
+    fn maps_track_to_activity() {
+        let track = TrackMetadata {
+            artist: Some("Ferris".to_owned()),
+            album: Some("Rusting".to_owned()),
+            art_url: Some("https://example.com/art.png".to_owned()),
+            position: Some(Duration::from_secs(30)),
+            duration: Some(Duration::from_secs(200)),
+            ..TrackMetadata::new("Crab Rave")
+        };
+
+        let activity = track.to_activity_at(now());
+
+        assert_eq!(activity.details.as_deref(), Some("Crab Rave"));
+        assert_eq!(activity.state.as_deref(), Some("Ferris"));
+
+        let assets = activity.assets.unwrap();
+        assert_eq!(
+            assets.large_image.as_deref(),
+            Some("https://example.com/art.png")
+        );
+        assert_eq!(assets.large_text.as_deref(), Some("Rusting"));
+
+        let timestamps = activity.timestamps.unwrap();
+        assert_eq!(timestamps.start, Some(NOW - 30_000));
+        assert_eq!(timestamps.end, Some(NOW + 170_000));
+    }
+
+    #[test]
+    fn omits_unknown_fields() {
+        let activity = TrackMetadata::new("Crab Rave").to_activity_at(now());
+
+        assert_eq!(activity.state, None);
+        assert_eq!(activity.assets, None);
+        assert_eq!(activity.timestamps, None);
+    }
+
+    #[test]
+    fn counts_up_without_duration() {
+        let track = TrackMetadata {
+            position: Some(Duration::from_secs(5)),
+            ..TrackMetadata::new("Live stream")
+        };
+
+        let timestamps = track.to_activity_at(now()).timestamps.unwrap();
+        assert_eq!(timestamps.start, Some(NOW - 5_000));
+        assert_eq!(timestamps.end, None);
+    }
+}
@@ -0,0 +1,26 @@
+/// Discord's "Ask to Join" flow for multiplayer games
+pub mod join;
+/// Presence for music players and other media
+pub mod media;
+/// Parties that keep the activity up to date as players come and go
+pub mod party;
+/// Presence for game sessions that move between menus, queues and matches
+pub mod session;
+/// Signing join and spectate secrets, so forged ones are rejected
+#[cfg(feature = "signed-secrets")]
+#[cfg_attr(docsrs, doc(cfg(feature = "signed-secrets")))]
+pub mod signing;
+/// Elapsed time that can be paused and resumed
+pub mod stopwatch;
+
+use crate::{models::Activity, Client, DiscordError, Result};
+
+/// Sends an activity, treating updates that were deferred, skipped or queued as sent
+fn send(client: &mut Client, activity: Activity) -> Result<()> {
+    match client.set_activity_struct(activity) {
+        Ok(_) | Err(DiscordError::Debounced | DiscordError::Unchanged | DiscordError::Queued) => {
+            Ok(())
+        }
+        Err(why) => Err(why),
+    }
+}
@@ -0,0 +1,252 @@
+use std::num::NonZeroU32;
+
+use crate::{
+    models::{Activity, ActivityParty, ActivitySecrets, PartyPrivacy},
+    Client, Result,
+};
+
+/// A party of players, which keeps the client's activity in sync with its members
+///
+/// Every change to the party re-sends the activity, with its party block and join secret updated:
+///
+/// - The party size follows the number of members
+/// - The join secret is only shown while the local player leads the party and it has space left,
+///   so Discord stops offering to join once it is full
+///
+/// Updates that are deferred by [`Client::set_activity_debounce`] or unchanged are not treated as errors.
+pub struct Party {
+    client: Client,
+    activity: Activity,
+    id: String,
+    max_size: NonZeroU32,
+    privacy: PartyPrivacy,
+    local_user: String,
+    /// User IDs of the members, with the leader first
+    members: Vec<String>,
+    join_secret: Option<String>,
+}
+
+impl Party {
+    /// Creates a party led by the local user, who is its only member
+    ///
+    /// Nothing is sent until the party first changes, or [`Party::update`] is called.
+    /// `max_size` counts the local user, so a party always has room for them.
+    pub fn new<I, U>(
+        client: Client,
+        id: I,
+        max_size: NonZeroU32,
+        privacy: PartyPrivacy,
+        local_user: U,
+    ) -> Self
+    where
+        I: Into<String>,
+        U: Into<String>,
+    {
+        let local_user = local_user.into();
+
+        Self {
+            client,
+            activity: Activity::new(),
+            id: id.into(),
+            max_size,
+            privacy,
+            members: vec![local_user.clone()],
+            local_user,
+            join_secret: None,
+        }
+    }
+
+    /// The ID of the party
+    #[must_use]
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// The user IDs of the members, with the leader first
+    #[must_use]
+    pub fn members(&self) -> &[String] {
+        &self.members
+    }
+
+    /// The user ID of the party leader
+    #[must_use]
+    pub fn leader(&self) -> &str {
+        &self.members[0]
+    }
+
+    /// Whether the party has no space left
+    #[must_use]
+    pub fn is_full(&self) -> bool {
+        self.members.len() >= self.max_size.get() as usize
+    }
+
+    /// Sets the rest of the activity, such as its details and state, which the party block is added to
+    ///
+    /// # Errors
+    /// - See [`Party::update`]
+    pub fn set_activity(&mut self, activity: Activity) -> Result<()> {
+        self.activity = activity;
+        self.update()
+    }
+
+    /// Sets the secret other players use to join the party, or removes it if `None`
+    ///
+    /// # Errors
+    /// - See [`Party::update`]
+    pub fn set_join_secret<S: Into<String>>(&mut self, secret: Option<S>) -> Result<()> {
+        self.join_secret = secret.map(Into::into);
+        self.update()
+    }
+
+    /// Adds a member to the party, returning `false` if they were already a member or the party is full
+    ///
+    /// # Errors
+    /// - See [`Party::update`]
+    pub fn add_member<S: Into<String>>(&mut self, user_id: S) -> Result<bool> {
+        let user_id = user_id.into();
+
+        if self.is_full() || self.members.contains(&user_id) {
+            return Ok(false);
+        }
+
+        self.members.push(user_id);
+        self.update()?;
+        Ok(true)
+    }
+
+    /// Removes a member from the party, returning `false` if they were not a member
+    ///
+    /// The local user cannot be removed. If the leader leaves, the next member to have joined leads the party.
+    ///
+    /// # Errors
+    /// - See [`Party::update`]
+    pub fn remove_member(&mut self, user_id: &str) -> Result<bool> {
+        if user_id == self.local_user {
+            return Ok(false);
+        }
+
+        let Some(index) = self.members.iter().position(|member| member == user_id) else {
+            return Ok(false);
+        };
+
+        self.members.remove(index);
+        self.update()?;
+        Ok(true)
+    }
+
+    /// Makes a member the leader of the party, returning `false` if they are not a member
+    ///
+    /// # Errors
+    /// - See [`Party::update`]
+    pub fn promote(&mut self, user_id: &str) -> Result<bool> {
+        let Some(index) = self.members.iter().position(|member| member == user_id) else {
+            return Ok(false);
+        };
+
+        let leader = self.members.remove(index);
+        self.members.insert(0, leader);
+        self.update()?;
+        Ok(true)
+    }
+
+    /// Sends the activity with the current state of the party
+    ///
+    /// # Errors
+    /// - See [`Client::set_activity_struct`]
+    pub fn update(&mut self) -> Result<()> {
+        let activity = self.activity();
+        super::send(&mut self.client, activity)
+    }
+
+    /// Builds the activity with the current state of the party
+    #[must_use]
+    pub fn activity(&self) -> Activity {
+        let mut activity = self.activity.clone();
+
+        let size = u32::try_from(self.members.len()).unwrap_or(u32::MAX);
+        activity.party = Some(ActivityParty {
+            id: Some(self.id.clone()),
+            size: Some((size, self.max_size.get())),
+            privacy: Some(self.privacy),
+        });
+
+        let joinable = self.leader() == self.local_user && !self.is_full();
+        let join = self.join_secret.clone().filter(|_| joinable);
+
+        let secrets = activity
+            .secrets
+            .get_or_insert_with(ActivitySecrets::default);
+        secrets.join = join;
+        if *secrets == ActivitySecrets::default() {
+            activity.secrets = None;
+        }
+
+        activity
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn party(max_size: u32) -> Party {
+        let client = Client::new(0);
+        client.set_offline_queue(16, Duration::from_secs(60));
+
+        let max_size = NonZeroU32::new(max_size).unwrap();
+        let mut party = Party::new(client, "party", max_size, PartyPrivacy::Private, "local");
+        party.set_join_secret(Some("join")).unwrap();
+        party
+    }
+
+    fn join_secret(party: &Party) -> Option<String> {
+        party.activity().secrets.and_then(|secrets| secrets.join)
+    }
+
+    #[test]
+    fn size_follows_members() {
+        let mut party = party(3);
+
+        assert!(party.add_member("a").unwrap());
+        assert!(!party.add_member("a").unwrap());
+
+        let block = party.activity().party.unwrap();
+        assert_eq!(block.id.as_deref(), Some("party"));
+        assert_eq!(block.size, Some((2, 3)));
+        assert_eq!(block.privacy, Some(PartyPrivacy::Private));
+
+        assert!(party.remove_member("a").unwrap());
+        assert!(!party.remove_member("local").unwrap());
+        assert_eq!(party.activity().party.unwrap().size, Some((1, 3)));
+    }
+
+    #[test]
+    fn join_secret_hidden_when_full() {
+        let mut party = party(2);
+        assert_eq!(join_secret(&party).as_deref(), Some("join"));
+
+        party.add_member("a").unwrap();
+        assert!(party.is_full());
+        assert_eq!(join_secret(&party), None);
+        assert!(!party.add_member("b").unwrap());
+
+        party.remove_member("a").unwrap();
+        assert_eq!(join_secret(&party).as_deref(), Some("join"));
+    }
+
+    #[test]
+    fn only_leader_shows_join_secret() {
+        let mut party = party(4);
+        party.add_member("a").unwrap();
+
+        assert!(party.promote("a").unwrap());
+        assert_eq!(party.leader(), "a");
+        assert_eq!(join_secret(&party), None);
+
+        party.remove_member("a").unwrap();
+        assert_eq!(party.leader(), "local");
+        assert_eq!(join_secret(&party).as_deref(), Some("join"));
+    }
+}
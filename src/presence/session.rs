@@ -0,0 +1,350 @@
+use std::{
+    sync::Arc,
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    models::{Activity, ActivitySecrets, ActivityTimestamps},
+    sync::Mutex,
+    targets::CONNECTION,
+    utils, Client, Result,
+};
+
+/// Minimum time between activity updates.
+///
+/// Discord allows five updates every 20 seconds, so spacing them evenly never hits the limit.
+pub const UPDATE_INTERVAL: Duration = Duration::from_secs(4);
+
+/// Data about a match in progress
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct MatchInfo {
+    /// Game mode or map being played, shown as the details
+    pub mode: String,
+    /// Progress of the match, such as the score, shown as the state
+    pub status: Option<String>,
+    /// Current and maximum size of the player's party
+    pub party_size: Option<(u32, u32)>,
+    /// Secret other players can use to join the party
+    pub join_secret: Option<String>,
+    /// Secret other players can use to spectate the match
+    pub spectate_secret: Option<String>,
+}
+
+impl MatchInfo {
+    /// Creates data for a match of the given mode
+    pub fn new<S: Into<String>>(mode: S) -> Self {
+        Self {
+            mode: mode.into(),
+            ..Self::default()
+        }
+    }
+}
+
+/// A stage of a game session
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SessionState {
+    /// The player is in the main menu
+    MainMenu,
+    /// The player is queueing for a match
+    Queue {
+        /// Game mode being queued for
+        mode: String,
+    },
+    /// The player is in a match
+    Match(MatchInfo),
+    /// The player is looking at the results of a match
+    Results {
+        /// Outcome of the match, such as "Victory" or the final score
+        summary: String,
+    },
+}
+
+/// Keeps the activity of a game session in sync with its state
+///
+/// Game integrations only report state transitions, and the activity is derived from them:
+///
+/// - Each state shows the time elapsed since it was entered
+/// - Matches are given their own party ID, so join and spectate secrets are always paired with a party,
+///   and the party and secrets are cleared once the match ends
+/// - Updates are sent at most once every [`UPDATE_INTERVAL`]. Those made sooner are deferred until it has passed,
+///   and only the latest of them is sent
+///
+/// The text shown for each state is in English, and can be translated with [`Client::set_localizer`].
+///
+/// The spacing only covers the session's own updates, and leaves the client's settings unchanged.
+pub struct SessionPresence {
+    client: Client,
+    state: SessionState,
+    since: SystemTime,
+    party_id: String,
+    spacing: Arc<Mutex<Spacing>>,
+}
+
+/// When the session last sent its activity, and the update deferred until [`UPDATE_INTERVAL`] has passed
+#[derive(Default)]
+struct Spacing {
+    last_sent: Option<Instant>,
+    pending: Option<Activity>,
+}
+
+impl SessionPresence {
+    /// Creates a session in the main menu
+    ///
+    /// No activity is sent until the first transition or [`SessionPresence::update`].
+    #[must_use]
+    pub fn new(client: Client) -> Self {
+        Self {
+            client,
+            state: SessionState::MainMenu,
+            since: SystemTime::now(),
+            party_id: utils::nonce(),
+            spacing: Arc::new(Mutex::new(Spacing::default())),
+        }
+    }
+
+    /// The current state of the session
+    #[must_use]
+    pub fn state(&self) -> &SessionState {
+        &self.state
+    }
+
+    /// Moves the session to a new state, and updates the activity
+    ///
+    /// Entering [`SessionState::Match`] always starts a new match, with a new party and elapsed time.
+    /// Use [`SessionPresence::update_match`] to change the data of the current match.
+    ///
+    /// # Errors
+    /// - See [`SessionPresence::update`]
+    pub fn transition(&mut self, state: SessionState) -> Result<()> {
+        if matches!(state, SessionState::Match(_)) {
+            self.party_id = utils::nonce();
+        }
+
+        self.state = state;
+        self.since = SystemTime::now();
+        self.update()
+    }
+
+    /// Changes the data of the match in progress, and updates the activity
+    ///
+    /// Does nothing outside of a match.
+    ///
+    /// # Errors
+    /// - See [`SessionPresence::update`]
+    pub fn update_match<F>(&mut self, func: F) -> Result<()>
+    where
+        F: FnOnce(&mut MatchInfo),
+    {
+        let SessionState::Match(ref mut info) = self.state else {
+            return Ok(());
+        };
+
+        func(info);
+        self.update()
+    }
+
+    /// Sends the activity for the current state, or defers it if the last one was sent within [`UPDATE_INTERVAL`]
+    ///
+    /// Deferred, unchanged and queued updates are not treated as errors, as they are sent or skipped as needed.
+    /// Deferred updates that fail to send are logged instead.
+    ///
+    /// # Errors
+    /// - See [`Client::set_activity_struct`]
+    pub fn update(&mut self) -> Result<()> {
+        let activity = self.activity();
+        let mut spacing = self.spacing.lock();
+
+        let elapsed = spacing.last_sent.map(|last_sent| last_sent.elapsed());
+        if let Some(elapsed) = elapsed.filter(|elapsed| *elapsed < UPDATE_INTERVAL) {
+            // Only schedule a flush if one is not already pending
+            if spacing.pending.replace(activity).is_none() {
+                let mut client = self.client.detached();
+                let shared = self.spacing.clone();
+
+                utils::spawn("session", move || {
+                    thread::sleep(UPDATE_INTERVAL.saturating_sub(elapsed));
+
+                    let activity = {
+                        let mut spacing = shared.lock();
+                        spacing.last_sent = Some(Instant::now());
+                        spacing.pending.take()
+                    };
+
+                    if let Some(activity) = activity {
+                        if let Err(why) = super::send(&mut client, activity) {
+                            error!(target: CONNECTION, "Failed to send deferred session activity: {why}");
+                        }
+                    }
+                });
+            }
+
+            return Ok(());
+        }
+
+        // A deferred update is older than this one, so the flush must not send it afterwards
+        spacing.pending = None;
+        spacing.last_sent = Some(Instant::now());
+        drop(spacing);
+
+        super::send(&mut self.client, activity)
+    }
+
+    /// Builds the activity for the current state
+    #[must_use]
+    pub fn activity(&self) -> Activity {
+        let mut activity = match self.state {
+            SessionState::MainMenu => Activity::new().details("In the main menu"),
+            SessionState::Queue { ref mode } => Activity::new().details(mode).state("In queue"),
+            SessionState::Match(ref info) => self.match_activity(info),
+            SessionState::Results { ref summary } => {
+                Activity::new().details("Match results").state(summary)
+            }
+        };
+
+        let start = self.since.duration_since(UNIX_EPOCH).unwrap_or_default();
+        activity.timestamps = Some(ActivityTimestamps {
+            start: Some(u64::try_from(start.as_millis()).unwrap_or(u64::MAX)),
+            end: None,
+        });
+
+        activity.truncate_fields()
+    }
+
+    fn match_activity(&self, info: &MatchInfo) -> Activity {
+        let mut activity = Activity::new()
+            .details(&info.mode)
+            .party(|party| party.id(&self.party_id));
+
+        if let Some(ref status) = info.status {
+            activity = activity.state(status);
+        }
+
+        if let Some((current, max)) = info.party_size {
+            activity = activity.party_max(max).party_current(current);
+        }
+
+        match (&info.join_secret, &info.spectate_secret) {
+            (None, None) => {}
+            (None, Some(spectate)) => activity = activity.spectate_only(spectate),
+            (join, spectate) => {
+                activity.secrets = Some(ActivitySecrets {
+                    join: join.clone(),
+                    spectate: spectate.clone(),
+                    ..ActivitySecrets::default()
+                });
+            }
+        }
+
+        activity
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+
+    fn session() -> SessionPresence {
+        let client = Client::new(0);
+        client.set_offline_queue(16, Duration::from_secs(60));
+        SessionPresence::new(client)
+    }
+
+    #[test]
+    fn match_has_party_and_secrets() {
+        let mut session = session();
+
+        let info = MatchInfo {
+            status: Some("3 - 1".to_owned()),
+            party_size: Some((2, 4)),
+            join_secret: Some("join".to_owned()),
+            ..MatchInfo::new("Ranked")
+        };
+        session.transition(SessionState::Match(info)).unwrap();
+
+        let activity = session.activity();
+        assert_eq!(activity.details.as_deref(), Some("Ranked"));
+        assert_eq!(activity.state.as_deref(), Some("3 - 1"));
+
+        let party = activity.party.unwrap();
+        assert!(party.id.is_some());
+        assert_eq!(party.size, Some((2, 4)));
+        assert_eq!(activity.secrets.unwrap().join.as_deref(), Some("join"));
+    }
+
+    #[test]
+    fn party_is_cleared_after_match() {
+        let mut session = session();
+
+        let info = MatchInfo {
+            join_secret: Some("join".to_owned()),
+            ..MatchInfo::new("Ranked")
+        };
+        session.transition(SessionState::Match(info)).unwrap();
+        session
+            .transition(SessionState::Results {
+                summary: "Victory".to_owned(),
+            })
+            .unwrap();
+
+        let activity = session.activity();
+        assert_eq!(activity.state.as_deref(), Some("Victory"));
+        assert_eq!(activity.party, None);
+        assert_eq!(activity.secrets, None);
+    }
+
+    #[test]
+    fn match_updates_keep_party_and_start() {
+        let mut session = session();
+
+        session
+            .transition(SessionState::Match(MatchInfo::new("Ranked")))
+            .unwrap();
+        let before = session.activity();
+
+        session
+            .update_match(|info| info.status = Some("1 - 0".to_owned()))
+            .unwrap();
+        let after = session.activity();
+
+        assert_eq!(after.state.as_deref(), Some("1 - 0"));
+        assert_eq!(after.party, before.party);
+        assert_eq!(after.timestamps, before.timestamps);
+
+        session
+            .transition(SessionState::Match(MatchInfo::new("Ranked")))
+            .unwrap();
+        assert_ne!(session.activity().party, before.party);
+    }
+
+    #[test]
+    fn updates_are_spaced_out() {
+        let mut session = session();
+
+        session.update().unwrap();
+        assert!(session.spacing.lock().pending.is_none());
+
+        session
+            .transition(SessionState::Queue {
+                mode: "Ranked".to_owned(),
+            })
+            .unwrap();
+        session
+            .transition(SessionState::Match(MatchInfo::new("Ranked")))
+            .unwrap();
+
+        // Only the latest deferred update is sent once the interval has passed
+        let pending = session.spacing.lock().pending.clone();
+        assert_eq!(pending, Some(session.activity()));
+    }
+
+    #[test]
+    fn update_match_outside_match_is_ignored() {
+        let mut session = session();
+
+        session
+            .update_match(|info| info.mode = "Casual".to_owned())
+            .unwrap();
+        assert_eq!(session.state(), &SessionState::MainMenu);
+    }
+}
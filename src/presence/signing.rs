@@ -0,0 +1,199 @@
+use std::fmt::Write;
+
+use ring::hmac;
+
+use crate::{
+    event_handler::Context as EventContext,
+    models::{ActivitySecrets, EventData},
+    targets::DISPATCH,
+};
+
+/// Separates the payload from its signature in a signed secret
+const SEPARATOR: char = '.';
+
+/// Longest payload that still fits in Discord's 128 character limit for secrets once signed,
+/// as signing adds a separator and 64 hexadecimal characters
+pub const MAX_PAYLOAD_LENGTH: usize = 63;
+
+/// What a secret lets its holder do, which is signed along with it so one kind cannot pass for the other
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SecretKind {
+    /// A join secret, as received by [`Client::on_activity_join`](crate::Client::on_activity_join)
+    Join,
+    /// A spectate secret, as received by [`Client::on_activity_spectate`](crate::Client::on_activity_spectate)
+    Spectate,
+}
+
+impl SecretKind {
+    fn tag(self) -> &'static [u8] {
+        match self {
+            Self::Join => b"join\0",
+            Self::Spectate => b"spectate\0",
+        }
+    }
+}
+
+/// Signs join and spectate secrets with an application key, and verifies them once received
+///
+/// Anyone can put any secret in their activity, so a handler trusting the secret it receives can be sent
+/// to a forged lobby or server. Secrets signed when generated are checked with HMAC-SHA256 before use instead.
+///
+/// The key ships with every copy of the game, so this stops secrets made up by anyone who has not extracted it
+/// from the game, not a player determined to forge them. Signed secrets are 65 characters longer than their
+/// payload, so payloads longer than [`MAX_PAYLOAD_LENGTH`] make activities that fail validation.
+///
+/// # Examples
+///
+/// ```
+/// # use discord_presence::{models::ActivitySecrets, presence::signing::{SecretKind, SecretSigner}};
+/// let signer = SecretSigner::new(b"a key shipped with the game");
+///
+/// let secrets = signer.sign_secrets(ActivitySecrets::new().spectate("lobby-42"));
+/// let received = secrets.spectate.unwrap();
+///
+/// assert_eq!(signer.verify(SecretKind::Spectate, &received), Some("lobby-42"));
+/// assert_eq!(signer.verify(SecretKind::Join, &received), None);
+/// ```
+pub struct SecretSigner {
+    key: hmac::Key,
+}
+
+impl SecretSigner {
+    /// Create a new `SecretSigner` with the given key, which every instance of the game has to share
+    #[must_use]
+    pub fn new(key: &[u8]) -> Self {
+        Self {
+            key: hmac::Key::new(hmac::HMAC_SHA256, key),
+        }
+    }
+
+    /// Signs the payload of a secret, returning the secret to put in the activity
+    ///
+    /// The secret is 65 characters longer than the payload, so keep payloads within [`MAX_PAYLOAD_LENGTH`].
+    #[must_use]
+    pub fn sign(&self, kind: SecretKind, payload: &str) -> String {
+        let tag = hmac::sign(&self.key, &Self::message(kind, payload));
+
+        let mut secret = String::with_capacity(payload.len() + 1 + tag.as_ref().len() * 2);
+        secret.push_str(payload);
+        secret.push(SEPARATOR);
+        for byte in tag.as_ref() {
+            // Writing to a `String` never fails
+            let _ = write!(secret, "{byte:02x}");
+        }
+
+        secret
+    }
+
+    /// Signs the join and spectate secrets. The match secret is left as it is, as it is never received back.
+    #[must_use]
+    pub fn sign_secrets(&self, mut secrets: ActivitySecrets) -> ActivitySecrets {
+        secrets.join = secrets.join.map(|join| self.sign(SecretKind::Join, &join));
+        secrets.spectate = secrets
+            .spectate
+            .map(|spectate| self.sign(SecretKind::Spectate, &spectate));
+        secrets
+    }
+
+    /// Checks a received secret was signed with the key for the given kind, returning its payload if so
+    #[must_use]
+    pub fn verify<'a>(&self, kind: SecretKind, secret: &'a str) -> Option<&'a str> {
+        let (payload, signature) = secret.rsplit_once(SEPARATOR)?;
+        let signature = decode_hex(signature)?;
+
+        hmac::verify(&self.key, &Self::message(kind, payload), &signature)
+            .ok()
+            .map(|()| payload)
+    }
+
+    /// Checks the secret of an `ACTIVITY_JOIN` or `ACTIVITY_SPECTATE` event, returning its payload if it was signed
+    ///
+    /// Returns `None` for other events, and for secrets that are missing or forged.
+    #[must_use]
+    pub fn verify_event<'a>(&self, ctx: &'a EventContext) -> Option<&'a str> {
+        let (kind, secret) = match ctx.event {
+            EventData::ActivityJoin(ref event) => (SecretKind::Join, event.secret.as_deref()?),
+            EventData::ActivitySpectate(ref event) => {
+                (SecretKind::Spectate, event.secret.as_deref()?)
+            }
+            _ => return None,
+        };
+
+        let payload = self.verify(kind, secret);
+        if payload.is_none() {
+            warn!(target: DISPATCH, "Rejecting a {kind:?} secret with an invalid signature");
+        }
+
+        payload
+    }
+
+    fn message(kind: SecretKind, payload: &str) -> Vec<u8> {
+        [kind.tag(), payload.as_bytes()].concat()
+    }
+}
+
+impl std::fmt::Debug for SecretSigner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SecretSigner").finish_non_exhaustive()
+    }
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 || !hex.is_ascii() {
+        return None;
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{models::ActivitySpectateEvent, Event};
+
+    #[test]
+    fn rejects_forged_secrets() {
+        let signer = SecretSigner::new(b"key");
+        let secret = signer.sign(SecretKind::Join, "10.0.0.1:7777.lobby");
+
+        assert_eq!(
+            signer.verify(SecretKind::Join, &secret),
+            Some("10.0.0.1:7777.lobby")
+        );
+        assert_eq!(signer.verify(SecretKind::Spectate, &secret), None);
+        assert_eq!(
+            SecretSigner::new(b"other").verify(SecretKind::Join, &secret),
+            None
+        );
+
+        // Signed payloads fit in Discord's limit for secrets up to the maximum length
+        let longest = signer.sign(SecretKind::Join, &"a".repeat(MAX_PAYLOAD_LENGTH));
+        assert_eq!(longest.len(), 128);
+
+        let forged = secret.replacen("10.0.0.1", "10.6.6.6", 1);
+        assert_eq!(signer.verify(SecretKind::Join, &forged), None);
+        assert_eq!(signer.verify(SecretKind::Join, "10.0.0.1:7777"), None);
+        assert_eq!(signer.verify(SecretKind::Join, "lobby.zz"), None);
+    }
+
+    #[test]
+    fn verifies_event_secrets() {
+        let signer = SecretSigner::new(b"key");
+        let secrets = signer.sign_secrets(ActivitySecrets::new().spectate("lobby"));
+        let ctx = |secret: &str| {
+            EventContext::new(
+                Event::ActivitySpectate,
+                EventData::ActivitySpectate(ActivitySpectateEvent::new().secret(secret)),
+                1,
+                None,
+            )
+        };
+
+        let received = ctx(secrets.spectate.as_deref().unwrap());
+        assert_eq!(signer.verify_event(&received), Some("lobby"));
+        assert_eq!(signer.verify_event(&ctx("lobby")), None);
+    }
+}
@@ -0,0 +1,145 @@
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::models::ActivityTimestamps;
+
+/// Tracks the time spent on an activity, which can be paused and resumed
+///
+/// Discord counts elapsed time up from a start timestamp, and cannot show a stopped timer.
+/// The stopwatch shifts the start timestamp forward by the time spent paused,
+/// so the elapsed time carries on where it left off when resumed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Stopwatch {
+    /// Time counted before the stopwatch was last resumed
+    elapsed: Duration,
+    /// When the stopwatch was last resumed, or `None` while paused
+    resumed_at: Option<Instant>,
+}
+
+impl Stopwatch {
+    /// Creates a stopwatch that starts counting from now
+    #[must_use]
+    pub fn start() -> Self {
+        Self {
+            elapsed: Duration::ZERO,
+            resumed_at: Some(Instant::now()),
+        }
+    }
+
+    /// Creates a paused stopwatch, which starts counting once resumed
+    #[must_use]
+    pub fn paused() -> Self {
+        Self::default()
+    }
+
+    /// Whether the stopwatch is paused
+    #[must_use]
+    pub fn is_paused(&self) -> bool {
+        self.resumed_at.is_none()
+    }
+
+    /// Stops counting, until [`Stopwatch::resume`] is called. Does nothing if already paused.
+    pub fn pause(&mut self) {
+        if let Some(resumed_at) = self.resumed_at.take() {
+            self.elapsed += resumed_at.elapsed();
+        }
+    }
+
+    /// Carries on counting from where the stopwatch was paused. Does nothing if already running.
+    pub fn resume(&mut self) {
+        if self.resumed_at.is_none() {
+            self.resumed_at = Some(Instant::now());
+        }
+    }
+
+    /// Sets the elapsed time back to zero, keeping the stopwatch running or paused
+    pub fn reset(&mut self) {
+        self.elapsed = Duration::ZERO;
+        if self.resumed_at.is_some() {
+            self.resumed_at = Some(Instant::now());
+        }
+    }
+
+    /// The time counted so far
+    #[must_use]
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed_at(Instant::now())
+    }
+
+    fn elapsed_at(&self, now: Instant) -> Duration {
+        let running = self.resumed_at.map_or(Duration::ZERO, |resumed_at| {
+            now.saturating_duration_since(resumed_at)
+        });
+
+        self.elapsed + running
+    }
+
+    /// The timestamps that show the elapsed time, as of now
+    ///
+    /// Returns `None` while paused, as Discord cannot show a stopped timer, so the timer is hidden instead.
+    /// The start timestamp changes after every pause, so call this again whenever the activity is sent.
+    #[must_use]
+    pub fn timestamps(&self) -> Option<ActivityTimestamps> {
+        self.timestamps_at(SystemTime::now(), Instant::now())
+    }
+
+    fn timestamps_at(&self, now: SystemTime, instant: Instant) -> Option<ActivityTimestamps> {
+        if self.is_paused() {
+            return None;
+        }
+
+        let start = now
+            .checked_sub(self.elapsed_at(instant))?
+            .duration_since(UNIX_EPOCH)
+            .ok()?;
+
+        Some(ActivityTimestamps::new().start(u64::try_from(start.as_millis()).unwrap_or(u64::MAX)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000_000;
+
+    #[test]
+    fn start_accounts_for_pauses() {
+        let now = Instant::now();
+        let Some(resumed_at) = now.checked_sub(Duration::from_secs(10)) else {
+            // The clock has not been running long enough on this platform
+            return;
+        };
+
+        let stopwatch = Stopwatch {
+            elapsed: Duration::from_secs(5),
+            resumed_at: Some(resumed_at),
+        };
+        assert_eq!(stopwatch.elapsed_at(now), Duration::from_secs(15));
+
+        let timestamps = stopwatch
+            .timestamps_at(UNIX_EPOCH + Duration::from_millis(NOW), now)
+            .unwrap();
+        assert_eq!(timestamps.start, Some(NOW - 15_000));
+        assert_eq!(timestamps.end, None);
+    }
+
+    #[test]
+    fn paused_keeps_elapsed_time() {
+        let mut stopwatch = Stopwatch::start();
+        stopwatch.pause();
+        let elapsed = stopwatch.elapsed();
+
+        assert!(stopwatch.is_paused());
+        assert_eq!(stopwatch.timestamps(), None);
+        assert_eq!(stopwatch.elapsed(), elapsed);
+
+        stopwatch.resume();
+        assert!(!stopwatch.is_paused());
+        assert!(stopwatch.elapsed() >= elapsed);
+        assert!(stopwatch.timestamps().is_some());
+
+        stopwatch.reset();
+        stopwatch.pause();
+        assert!(stopwatch.elapsed() < Duration::from_secs(1));
+    }
+}
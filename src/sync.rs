@@ -0,0 +1,41 @@
+//! Synchronization primitives, swapped out for [`loom`]'s when testing under `--cfg loom`
+//!
+//! Only the state shared between the client and the connection thread goes through here,
+//! so that loom can explore how the two interleave.
+//!
+//! `parking_lot` is used for locks by default, falling back to `std` when its feature is disabled.
+
+/// Wraps a poisoning mutex in the `parking_lot` API
+#[cfg(any(loom, not(feature = "parking_lot")))]
+macro_rules! poison_free_mutex {
+    [ $($path:ident)::+ ] => {
+        #[derive(Debug, Default)]
+        pub(crate) struct Mutex<T>($($path)::+::Mutex<T>);
+
+        impl<T> Mutex<T> {
+            pub(crate) fn new(value: T) -> Self {
+                Self($($path)::+::Mutex::new(value))
+            }
+
+            pub(crate) fn lock(&self) -> $($path)::+::MutexGuard<'_, T> {
+                // A panicking event handler should not take the client down with it, so poisoning is ignored
+                self.0
+                    .lock()
+                    .unwrap_or_else(std::sync::PoisonError::into_inner)
+            }
+        }
+    };
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(loom)] {
+        pub(crate) use loom::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+        poison_free_mutex![loom::sync];
+    } else if #[cfg(feature = "parking_lot")] {
+        pub(crate) use parking_lot::Mutex;
+        pub(crate) use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+    } else {
+        pub(crate) use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+        poison_free_mutex![std::sync];
+    }
+}
@@ -0,0 +1,17 @@
+//! Tracing targets, so each subsystem's logs can be filtered separately,
+//! e.g. `RUST_LOG=discord_presence::dispatch=trace`
+//!
+//! With the `log` feature, the same targets are used for the `log` records emitted when no `tracing` subscriber is installed.
+
+/// Connecting, reconnecting, and sending and receiving frames
+pub(crate) const CONNECTION: &str = "discord_presence::connection";
+/// Passing events on to the event handlers
+pub(crate) const DISPATCH: &str = "discord_presence::dispatch";
+/// Decoding payloads
+pub(crate) const CODEC: &str = "discord_presence::codec";
+/// The raw contents of every frame, with secrets redacted. Only emitted with the `frame-logging` feature.
+#[cfg(feature = "frame-logging")]
+pub(crate) const FRAMES: &str = "discord_presence::frames";
+/// The bot gateway connection. Only emitted with the `gateway` feature.
+#[cfg(feature = "gateway")]
+pub(crate) const GATEWAY: &str = "discord_presence::gateway";
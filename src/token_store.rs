@@ -0,0 +1,164 @@
+use std::time::SystemTime;
+
+use keyring::Entry;
+
+use crate::{utils::REDACTED, DiscordError, Result};
+
+/// Service name the tokens are stored under by default
+pub const DEFAULT_SERVICE: &str = "discord-presence";
+
+/// `OAuth2` tokens of the user, as returned by Discord's token endpoint
+#[derive(Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct OAuthTokens {
+    /// Token passed to [`Client::authenticate`](crate::Client::authenticate)
+    pub access_token: String,
+    /// Token to get a new access token with once it expires
+    pub refresh_token: Option<String>,
+    /// When the access token expires
+    pub expires_at: Option<SystemTime>,
+}
+
+impl OAuthTokens {
+    #[must_use]
+    /// Create new `OAuthTokens` with the given access token
+    pub fn new<S: Into<String>>(access_token: S) -> Self {
+        Self {
+            access_token: access_token.into(),
+            refresh_token: None,
+            expires_at: None,
+        }
+    }
+
+    #[must_use]
+    /// Whether the access token has expired, as far as is known
+    pub fn is_expired(&self) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| expires_at <= SystemTime::now())
+    }
+}
+
+impl std::fmt::Debug for OAuthTokens {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OAuthTokens")
+            .field("access_token", &REDACTED)
+            .field(
+                "refresh_token",
+                &self.refresh_token.as_ref().map(|_| REDACTED),
+            )
+            .field("expires_at", &self.expires_at)
+            .finish()
+    }
+}
+
+/// Persists the `OAuth2` tokens of each application between runs
+pub trait TokenStore: Send + Sync {
+    /// Loads the tokens of the application, or `None` if none are stored
+    ///
+    /// # Errors
+    /// - The store could not be read
+    fn load(&self, client_id: u64) -> Result<Option<OAuthTokens>>;
+
+    /// Stores the tokens of the application, replacing any stored before
+    ///
+    /// # Errors
+    /// - The store could not be written to
+    fn save(&self, client_id: u64, tokens: &OAuthTokens) -> Result<()>;
+
+    /// Removes the tokens of the application, if any are stored
+    ///
+    /// # Errors
+    /// - The store could not be written to
+    fn clear(&self, client_id: u64) -> Result<()>;
+}
+
+/// Stores tokens in the credential store of the OS, such as the Keychain on macOS,
+/// the Credential Manager on Windows and the Secret Service on Linux
+///
+/// Each application's tokens are stored as a single secret, under the service name and the client ID as the user name.
+#[derive(Debug, Clone)]
+pub struct KeyringStore {
+    service: String,
+}
+
+impl KeyringStore {
+    #[must_use]
+    /// Create a new `KeyringStore`, storing tokens under [`DEFAULT_SERVICE`]
+    pub fn new() -> Self {
+        Self::with_service(DEFAULT_SERVICE)
+    }
+
+    #[must_use]
+    /// Create a new `KeyringStore`, storing tokens under the given service name
+    pub fn with_service<S: Into<String>>(service: S) -> Self {
+        Self {
+            service: service.into(),
+        }
+    }
+
+    fn entry(&self, client_id: u64) -> Result<Entry> {
+        Ok(Entry::new(&self.service, &client_id.to_string())?)
+    }
+}
+
+impl Default for KeyringStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TokenStore for KeyringStore {
+    fn load(&self, client_id: u64) -> Result<Option<OAuthTokens>> {
+        match self.entry(client_id)?.get_password() {
+            Ok(secret) => Ok(Some(serde_json::from_str(&secret)?)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(why) => Err(DiscordError::from(why)),
+        }
+    }
+
+    fn save(&self, client_id: u64, tokens: &OAuthTokens) -> Result<()> {
+        let secret = serde_json::to_string(tokens)?;
+        Ok(self.entry(client_id)?.set_password(&secret)?)
+    }
+
+    fn clear(&self, client_id: u64) -> Result<()> {
+        match self.entry(client_id)?.delete_password() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(why) => Err(DiscordError::from(why)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn tokens_round_trip() {
+        let tokens = OAuthTokens {
+            refresh_token: Some(String::from("refresh")),
+            expires_at: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000)),
+            ..OAuthTokens::new("access")
+        };
+
+        let secret = serde_json::to_string(&tokens).unwrap();
+        assert_eq!(
+            serde_json::from_str::<OAuthTokens>(&secret).unwrap(),
+            tokens
+        );
+        assert!(tokens.is_expired());
+        assert!(!OAuthTokens::new("access").is_expired());
+    }
+
+    #[test]
+    fn debug_redacts_tokens() {
+        let tokens = OAuthTokens {
+            refresh_token: Some(String::from("refresh")),
+            ..OAuthTokens::new("access")
+        };
+
+        let debug = format!("{tokens:?}");
+        assert!(!debug.contains("access\""));
+        assert!(!debug.contains("refresh\""));
+    }
+}
@@ -1,9 +1,124 @@
-use uuid::Uuid;
+use std::thread::{self, JoinHandle};
+
+use serde_json::Value;
+use tracing::Span;
+
+/// Placeholder for secrets in `Debug` and trace output
+pub const REDACTED: &str = "[redacted]";
+
+/// Keys of JSON payloads whose values are secrets
+const SECRET_KEYS: &[&str] = &["secret", "secrets", "access_token", "refresh_token"];
 
 pub fn pid() -> u32 {
     std::process::id()
 }
 
 pub fn nonce() -> String {
-    Uuid::new_v4().to_string()
+    crate::nonce::uuid_v4()
+}
+
+/// Spawns an internal thread named `discord-presence-{name}`, so it can be told apart in debuggers and profilers.
+///
+/// The thread runs inside the span it was spawned from, so its logs keep their context.
+///
+/// # Panics
+/// - If the thread could not be spawned, like [`std::thread::spawn`]
+pub fn spawn<F, T>(name: &str, f: F) -> JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let span = Span::current();
+    thread::Builder::new()
+        .name(format!("discord-presence-{name}"))
+        .spawn(move || span.in_scope(f))
+        .expect("failed to spawn thread")
+}
+
+/// Truncates a string to at most `max` characters, replacing the last character with an ellipsis if it was too long.
+///
+/// Counts `char`s rather than bytes, so it never splits a character.
+pub fn truncate(value: &mut String, max: usize) {
+    if max == 0 {
+        value.clear();
+    } else if let Some((index, _)) = value.char_indices().nth(max) {
+        // Make room for the ellipsis
+        let index = value[..index].char_indices().last().map_or(0, |(i, _)| i);
+        value.truncate(index);
+        value.push('…');
+    }
+}
+
+/// Masks any secrets in a JSON payload, so it can be safely logged.
+///
+/// Payloads that are not valid JSON are returned as-is.
+pub fn redact_json(payload: &str) -> String {
+    match serde_json::from_str::<Value>(payload) {
+        Ok(mut value) => {
+            redact_value(&mut value, false);
+            value.to_string()
+        }
+        Err(_) => payload.to_owned(),
+    }
+}
+
+fn redact_value(value: &mut Value, secret: bool) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                redact_value(value, secret || SECRET_KEYS.contains(&key.as_str()));
+            }
+        }
+        Value::Array(values) => {
+            for value in values {
+                redact_value(value, secret);
+            }
+        }
+        Value::Null => {}
+        value if secret => *value = Value::from(REDACTED),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_spawned_threads() {
+        let name = spawn("test", || thread::current().name().map(ToOwned::to_owned))
+            .join()
+            .unwrap();
+        assert_eq!(name.as_deref(), Some("discord-presence-test"));
+    }
+
+    #[test]
+    fn truncates_at_char_boundaries() {
+        let mut short = String::from("héllo");
+        truncate(&mut short, 5);
+        assert_eq!(short, "héllo");
+
+        let mut long = String::from("ünïcödé");
+        truncate(&mut long, 4);
+        assert_eq!(long, "ünï…");
+    }
+
+    #[test]
+    fn redacts_secrets_in_json() {
+        let payload = serde_json::json!({
+            "cmd": "SET_ACTIVITY",
+            "args": {
+                "activity": {
+                    "state": "rusting",
+                    "secrets": { "join": "025ed05c71f639de8bfaa0d679d7c94b2fdce12f" },
+                },
+            },
+        });
+
+        let redacted: Value = serde_json::from_str(&redact_json(&payload.to_string())).unwrap();
+
+        assert_eq!(redacted["args"]["activity"]["state"], "rusting");
+        assert_eq!(redacted["args"]["activity"]["secrets"]["join"], REDACTED);
+        assert_eq!(redact_json("not json"), "not json");
+    }
 }
@@ -0,0 +1,69 @@
+use std::{
+    fmt::{self, Display},
+    net::SocketAddr,
+    path::PathBuf,
+};
+
+/// Where to reach Discord from inside WSL, which cannot see the named pipe Discord creates on Windows
+///
+/// The bridge relays IPC frames to Discord's pipe unchanged. It can be a helper run on the Windows side,
+/// or a relay such as `socat` and `npiperelay`:
+///
+/// ```sh
+/// socat UNIX-LISTEN:/tmp/discord-bridge,fork EXEC:"npiperelay.exe //./pipe/discord-ipc-0"
+/// ```
+///
+/// Set with [`Client::set_wsl_bridge`](crate::Client::set_wsl_bridge).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Bridge {
+    /// A Unix socket within WSL, forwarded to Windows
+    Unix(PathBuf),
+    /// A TCP listener, usually on the Windows host
+    Tcp(SocketAddr),
+}
+
+impl Display for Bridge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unix(path) => write!(f, "{}", path.display()),
+            Self::Tcp(addr) => write!(f, "tcp://{addr}"),
+        }
+    }
+}
+
+/// Whether the current process runs in Windows Subsystem for Linux, where a [`Bridge`] is needed
+#[must_use]
+pub fn is_wsl() -> bool {
+    crate::diagnostics::is_wsl()
+}
+
+#[cfg(all(test, unix, not(loom)))]
+mod tests {
+    use std::os::unix::net::UnixListener;
+
+    use super::*;
+    use crate::connection::{test_socket_path, Connection, Socket};
+
+    #[test]
+    fn connects_through_unix_bridge() {
+        let path = test_socket_path("bridge");
+        let _listener = UnixListener::bind(&path).unwrap();
+
+        let connection = Socket::connect_bridge(&Bridge::Unix(path.clone()));
+        std::fs::remove_file(&path).ok();
+        assert!(connection.is_ok());
+    }
+
+    #[test]
+    #[cfg(not(feature = "io-uring"))]
+    fn connects_through_tcp_bridge() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let bridge = Bridge::Tcp(listener.local_addr().unwrap());
+
+        assert_eq!(
+            bridge.to_string(),
+            format!("tcp://{}", listener.local_addr().unwrap())
+        );
+        assert!(Socket::connect_bridge(&bridge).is_ok());
+    }
+}